@@ -0,0 +1,26 @@
+#!/bin/sh
+# Build the image of the `mkfs_vfat_image` test with the real tools (dosfstools, mtools):
+#   ./make_test_image.sh
+#   FAT32_TEST_IMAGE=target/fat32-test.img cargo test -p fat32 -- --ignored
+set -e
+
+IMAGE=${1:-target/fat32-test.img}
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+
+mkdir -p "$(dirname "$IMAGE")"
+rm -f "$IMAGE"
+mkfs.vfat -C -F 32 -n MATIAOS "$IMAGE" 65536 > /dev/null
+
+printf 'hello FAT32\n' > "$TMP/README.TXT"
+printf 'arm_64bit=1\n' > "$TMP/config.txt"
+# same content as the tests' pattern(100_000, 1)
+python3 -c 'import sys; sys.stdout.buffer.write(bytes((i * 31 + 1) & 0xFF for i in range(100000)))' \
+    > "$TMP/long"
+
+mcopy -i "$IMAGE" "$TMP/README.TXT" ::README.TXT
+mcopy -i "$IMAGE" "$TMP/long" "::A long file name.txt"
+mmd -i "$IMAGE" ::boot
+mcopy -i "$IMAGE" "$TMP/config.txt" ::boot/config.txt
+
+echo "$IMAGE"
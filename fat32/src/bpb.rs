@@ -0,0 +1,103 @@
+//! Boot sector and BIOS parameter block of a FAT32 volume.
+
+use crate::{Error, SECTOR_SIZE};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+/// The FAT32 filesystem type string of the extended BPB. Informative only, but every formatter
+/// writes it, so it tells a boot sector from an MBR.
+const FS_TYPE_FAT32: &[u8; 8] = b"FAT32   ";
+
+fn u16_at(sector: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([sector[offset], sector[offset + 1]])
+}
+
+fn u32_at(sector: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        sector[offset],
+        sector[offset + 1],
+        sector[offset + 2],
+        sector[offset + 3],
+    ])
+}
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+/// The fields of the BPB we need
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BiosParameterBlock {
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub num_fats: u32,
+    pub total_sectors: u32,
+    /// Sectors per FAT
+    pub fat_size: u32,
+    pub root_cluster: u32,
+    pub volume_label: [u8; 11],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl BiosParameterBlock {
+    /// True if the sector looks like a FAT32 boot sector
+    pub fn is_fat32_boot_sector(sector: &[u8; SECTOR_SIZE]) -> bool {
+        // x86 jump to the boot code: EB xx 90 or E9 xx xx
+        let jump = sector[0] == 0xEB && sector[2] == 0x90 || sector[0] == 0xE9;
+
+        jump && &sector[82..90] == FS_TYPE_FAT32
+    }
+
+    /// Parse and sanity check the BPB
+    pub fn parse(sector: &[u8; SECTOR_SIZE]) -> Result<Self, Error> {
+        if sector[510..] != [0x55, 0xAA] {
+            return Err(Error::NoFat32Volume);
+        }
+
+        if usize::from(u16_at(sector, 11)) != SECTOR_SIZE {
+            return Err(Error::Unsupported("Sector size other than 512 bytes"));
+        }
+
+        let sectors_per_cluster = u32::from(sector[13]);
+        if !sectors_per_cluster.is_power_of_two() {
+            return Err(Error::Corrupted("Invalid sectors per cluster"));
+        }
+
+        let reserved_sectors = u32::from(u16_at(sector, 14));
+        let num_fats = u32::from(sector[16]);
+        if reserved_sectors == 0 || num_fats == 0 {
+            return Err(Error::Corrupted("Invalid BPB"));
+        }
+
+        // FAT12/16 have a fixed size root directory and a 16 bit FAT size, FAT32 has neither
+        let root_entry_count = u16_at(sector, 17);
+        let fat_size_16 = u16_at(sector, 22);
+        let fat_size = u32_at(sector, 36);
+        if root_entry_count != 0 || fat_size_16 != 0 || fat_size == 0 {
+            return Err(Error::NoFat32Volume);
+        }
+
+        let total_sectors = match u16_at(sector, 19) {
+            0 => u32_at(sector, 32),
+            x => u32::from(x),
+        };
+
+        let mut volume_label = [0; 11];
+        volume_label.copy_from_slice(&sector[71..82]);
+
+        Ok(Self {
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            total_sectors,
+            fat_size,
+            root_cluster: u32_at(sector, 44),
+            volume_label,
+        })
+    }
+}
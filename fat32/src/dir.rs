@@ -0,0 +1,283 @@
+//! Directory entries and long file names.
+
+use core::{char, str};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+/// Attribute bits
+mod attr {
+    pub const HIDDEN: u8 = 0x02;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    /// READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID
+    pub const LONG_NAME: u8 = 0x0F;
+}
+
+/// NT reserved byte: the short name's base/extension are stored upper case but shown lower case
+const NT_LOWERCASE_BASE: u8 = 0x08;
+const NT_LOWERCASE_EXT: u8 = 0x10;
+
+/// First byte of a directory entry
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+/// A name really starting with 0xE5 is stored as 0x05
+const ENTRY_KANJI_E5: u8 = 0x05;
+
+/// Long name entries: last one flag, and the characters held by each entry
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_SEQUENCE_MASK: u8 = 0x1F;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const LFN_MAX_ENTRIES: usize = 20;
+/// Byte offsets of the UTF-16 characters in a long name entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The short name checksum every long name entry carries
+fn short_name_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+/// Longest file name, in UTF-8 bytes (255 UTF-16 characters)
+pub const MAX_NAME_LEN: usize = 255 * 3;
+
+/// Size of an on-disk directory entry
+pub(crate) const DIR_ENTRY_SIZE: usize = 32;
+
+/// A file or a directory
+#[derive(Clone)]
+pub struct DirEntry {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    short_name: [u8; 12],
+    short_name_len: usize,
+    attributes: u8,
+    first_cluster: u32,
+    size: u32,
+}
+
+/// Long name entries seen so far, waiting for their short name entry
+pub(crate) struct LongName {
+    chars: [u16; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+    /// Sequence number of the next expected entry, 0 once complete
+    next: u8,
+    checksum: u8,
+    valid: bool,
+}
+
+/// What a raw directory entry turned out to be. No allocator to box the entry, it is moved once.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Parsed {
+    /// No more entries in this directory
+    End,
+    /// Deleted, volume label or part of a long name
+    Skip,
+    Entry(DirEntry),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl DirEntry {
+    /// The root directory. It has no entry of its own.
+    pub(crate) fn root(cluster: u32) -> Self {
+        let mut entry = Self {
+            name: [0; MAX_NAME_LEN],
+            name_len: 1,
+            short_name: [0; 12],
+            short_name_len: 1,
+            attributes: attr::DIRECTORY,
+            first_cluster: cluster,
+            size: 0,
+        };
+        entry.name[0] = b'/';
+        entry.short_name[0] = b'/';
+
+        entry
+    }
+
+    /// Long name if there is one, short name otherwise
+    pub fn name(&self) -> &str {
+        // only ever filled with UTF-8
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+
+    /// The 8.3 name
+    pub fn short_name(&self) -> &str {
+        str::from_utf8(&self.short_name[..self.short_name_len]).unwrap_or("?")
+    }
+
+    /// Is it a directory
+    pub fn is_dir(&self) -> bool {
+        self.attributes & attr::DIRECTORY != 0
+    }
+
+    /// Is it hidden
+    pub fn is_hidden(&self) -> bool {
+        self.attributes & attr::HIDDEN != 0
+    }
+
+    /// Size in bytes, 0 for directories
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// First cluster of the data, 0 for an empty file
+    pub fn first_cluster(&self) -> u32 {
+        self.first_cluster
+    }
+
+    /// FAT names are case insensitive, and both the long and the short name work
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        let eq = |a: &str, b: &str| {
+            a.len() == b.len()
+                && a.chars()
+                    .zip(b.chars())
+                    .all(|(x, y)| x.to_lowercase().eq(y.to_lowercase()))
+        };
+
+        eq(self.name(), name) || eq(self.short_name(), name)
+    }
+
+    pub(crate) fn is_dot(&self) -> bool {
+        matches!(self.short_name(), "." | "..")
+    }
+}
+
+impl LongName {
+    pub const fn new() -> Self {
+        Self {
+            chars: [0; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+            next: 0,
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    /// Long name entries come last one first, down to sequence number 1
+    fn push(&mut self, raw: &[u8]) {
+        let sequence = raw[0] & LFN_SEQUENCE_MASK;
+
+        if raw[0] & LFN_LAST_ENTRY != 0 {
+            self.valid = sequence != 0 && usize::from(sequence) <= LFN_MAX_ENTRIES;
+            self.checksum = raw[13];
+            self.chars.fill(0xFFFF);
+        } else if !self.valid || sequence != self.next || raw[13] != self.checksum {
+            // orphan, out of order or from another file
+            self.valid = false;
+        }
+
+        if !self.valid {
+            return;
+        }
+
+        let start = (usize::from(sequence) - 1) * LFN_CHARS_PER_ENTRY;
+        for (i, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.chars[start + i] = u16::from_le_bytes([raw[*offset], raw[offset + 1]]);
+        }
+        self.next = sequence - 1;
+    }
+
+    /// The name put together, if it belongs to this short name. Fill `name` with the UTF-8 bytes.
+    fn take(&mut self, short_name: &[u8], name: &mut [u8; MAX_NAME_LEN]) -> Option<usize> {
+        let valid =
+            self.valid && self.next == 0 && short_name_checksum(short_name) == self.checksum;
+        self.valid = false;
+
+        if !valid {
+            return None;
+        }
+
+        // NUL terminated, unless it fills its entries exactly. The rest is 0xFFFF padding.
+        let units = self
+            .chars
+            .iter()
+            .copied()
+            .take_while(|&c| c != 0 && c != 0xFFFF);
+        let mut len = 0;
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            if len + c.len_utf8() > MAX_NAME_LEN {
+                return None;
+            }
+            len += c.encode_utf8(&mut name[len..]).len();
+        }
+
+        (len != 0).then_some(len)
+    }
+}
+
+/// Parse one 32 bytes directory entry
+pub(crate) fn parse(raw: &[u8], long_name: &mut LongName) -> Parsed {
+    match raw[0] {
+        ENTRY_END => return Parsed::End,
+        ENTRY_DELETED => {
+            long_name.valid = false;
+            return Parsed::Skip;
+        }
+        _ => (),
+    }
+
+    let attributes = raw[11];
+    if attributes & attr::LONG_NAME == attr::LONG_NAME {
+        long_name.push(raw);
+        return Parsed::Skip;
+    }
+    if attributes & attr::VOLUME_ID != 0 {
+        long_name.valid = false;
+        return Parsed::Skip;
+    }
+
+    let mut short = [0; 11];
+    short.copy_from_slice(&raw[..11]);
+
+    let mut entry = DirEntry {
+        name: [0; MAX_NAME_LEN],
+        name_len: 0,
+        short_name: [0; 12],
+        short_name_len: 0,
+        attributes,
+        first_cluster: u32::from(u16::from_le_bytes([raw[20], raw[21]])) << 16
+            | u32::from(u16::from_le_bytes([raw[26], raw[27]])),
+        size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+    };
+
+    if short[0] == ENTRY_KANJI_E5 {
+        short[0] = ENTRY_DELETED;
+    }
+
+    // "BASE    EXT" -> "base.ext", with the NT case bits. Non ASCII (code page) bytes become '_'.
+    let case = raw[12];
+    let mut put = |bytes: &[u8], lowercase: bool| {
+        for &c in bytes.iter().take_while(|&&c| c != b' ') {
+            let c = if c.is_ascii() { c } else { b'_' };
+            entry.short_name[entry.short_name_len] =
+                if lowercase { c.to_ascii_lowercase() } else { c };
+            entry.short_name_len += 1;
+        }
+    };
+    put(&short[..8], case & NT_LOWERCASE_BASE != 0);
+    if short[8] != b' ' {
+        put(b".", false);
+        put(&short[8..], case & NT_LOWERCASE_EXT != 0);
+    }
+
+    match long_name.take(&raw[..11], &mut entry.name) {
+        Some(len) => entry.name_len = len,
+        None => {
+            entry.name[..entry.short_name_len]
+                .copy_from_slice(&entry.short_name[..entry.short_name_len]);
+            entry.name_len = entry.short_name_len;
+        }
+    }
+
+    Parsed::Entry(entry)
+}
@@ -0,0 +1,98 @@
+//! Read-only FAT32 filesystem.
+//!
+//! `no_std` and allocation free, so the kernel can use it as is. The volume is read through the
+//! [`BlockDevice`] trait, one 512 bytes sector at a time:
+//! - the MBR partition table is parsed to find the first FAT32 partition (images made by
+//!   `mkfs.vfat` without a partition table work too),
+//! - the BPB (BIOS parameter block) gives the volume layout,
+//! - files and directories are cluster chains in the FAT,
+//! - long file names (VFAT) are put back together from their directory entries.
+//!
+//! The tests run on the host: `cargo test -p fat32`.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+mod bpb;
+mod dir;
+mod mbr;
+mod volume;
+
+#[cfg(test)]
+mod tests;
+
+use core::fmt;
+
+pub use dir::{DirEntry, MAX_NAME_LEN};
+pub use mbr::{Partition, PartitionTable};
+pub use volume::{DirIter, Volume};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Sector size. The only one we support, and the one of every SD card.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Where the filesystem lives (an SD card, an image in memory...)
+pub trait BlockDevice {
+    /// Read `buf.len() / SECTOR_SIZE` sectors, starting from sector `lba`.
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str>;
+}
+
+/// Filesystem errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The block device failed
+    Device(&'static str),
+    /// Neither the first sector nor a partition holds a FAT32 volume
+    NoFat32Volume,
+    /// A valid FAT32 volume we don't handle
+    Unsupported(&'static str),
+    /// The on-disk structures don't make sense
+    Corrupted(&'static str),
+    /// No such file or directory
+    NotFound,
+    /// A path component is a file
+    NotADirectory,
+    /// Expected a file, got a directory
+    IsADirectory,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T: BlockDevice + ?Sized> BlockDevice for &T {
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        (**self).read_blocks(lba, buf)
+    }
+}
+
+impl Error {
+    /// Short description, for the kernel's `&'static str` errors
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Device(e) => e,
+            Error::NoFat32Volume => "No FAT32 volume",
+            Error::Unsupported(e) => e,
+            Error::Corrupted(e) => e,
+            Error::NotFound => "No such file or directory",
+            Error::NotADirectory => "Not a directory",
+            Error::IsADirectory => "Is a directory",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device(e) => write!(f, "device error: {}", e),
+            Error::Unsupported(e) => write!(f, "unsupported: {}", e),
+            Error::Corrupted(e) => write!(f, "corrupted filesystem: {}", e),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! MBR partition table.
+
+use crate::SECTOR_SIZE;
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+const PARTITION_TABLE_OFFSET: usize = 446;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const NUM_PARTITIONS: usize = 4;
+const SIGNATURE_OFFSET: usize = 510;
+const SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Partition types of a FAT32 partition (CHS and LBA addressing)
+const TYPE_FAT32_CHS: u8 = 0x0B;
+const TYPE_FAT32_LBA: u8 = 0x0C;
+
+/// Status byte of a bootable partition
+const STATUS_BOOTABLE: u8 = 0x80;
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+/// A primary partition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Partition {
+    /// Partition type
+    pub kind: u8,
+    /// Active flag
+    pub bootable: bool,
+    /// First sector
+    pub start_lba: u32,
+    /// Size in sectors
+    pub num_sectors: u32,
+}
+
+/// The four primary partitions of an MBR
+#[derive(Clone, Copy, Debug)]
+pub struct PartitionTable {
+    partitions: [Option<Partition>; NUM_PARTITIONS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Partition {
+    /// True for the FAT32 partition types
+    pub fn is_fat32(&self) -> bool {
+        matches!(self.kind, TYPE_FAT32_CHS | TYPE_FAT32_LBA)
+    }
+}
+
+impl PartitionTable {
+    /// Parse the first sector of a disk. `None` if it is not an MBR.
+    pub fn parse(sector: &[u8; SECTOR_SIZE]) -> Option<Self> {
+        if sector[SIGNATURE_OFFSET..] != SIGNATURE {
+            return None;
+        }
+
+        let mut partitions = [None; NUM_PARTITIONS];
+        for (i, partition) in partitions.iter_mut().enumerate() {
+            let entry = &sector[PARTITION_TABLE_OFFSET + i * PARTITION_ENTRY_SIZE..]
+                [..PARTITION_ENTRY_SIZE];
+            let status = entry[0];
+
+            // Only 0x00 and 0x80 are valid, anything else is not a partition table at all
+            if status != 0 && status != STATUS_BOOTABLE {
+                return None;
+            }
+
+            let kind = entry[4];
+            let start_lba = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]);
+            let num_sectors = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+
+            // type 0 is an unused entry
+            if kind != 0 && num_sectors != 0 {
+                *partition = Some(Partition {
+                    kind,
+                    bootable: status == STATUS_BOOTABLE,
+                    start_lba,
+                    num_sectors,
+                });
+            }
+        }
+
+        Some(Self { partitions })
+    }
+
+    /// The used entries
+    pub fn partitions(&self) -> impl Iterator<Item = &Partition> {
+        self.partitions.iter().filter_map(|x| x.as_ref())
+    }
+}
@@ -0,0 +1,340 @@
+//! Host tests, against images built in memory and, optionally, one made by `mkfs.vfat`
+//! (see `make_test_image.sh`).
+
+mod image;
+
+use std::{string::String, vec, vec::Vec};
+
+use crate::{BlockDevice, DirEntry, Error, PartitionTable, Volume, SECTOR_SIZE};
+use image::{Builder, Image, PARTITION_START};
+
+/// The smallest proper FAT32 volume has 65525 clusters
+const CLUSTERS: u32 = 65525;
+
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect()
+}
+
+fn names<D: BlockDevice>(volume: &Volume<D>, path: &str) -> Vec<String> {
+    let dir = volume.find(path).unwrap();
+    volume
+        .read_dir(&dir)
+        .unwrap()
+        .map(|x| String::from(x.unwrap().name()))
+        .collect()
+}
+
+fn read_all<D: BlockDevice>(volume: &Volume<D>, file: &DirEntry) -> Vec<u8> {
+    let mut content = vec![0; file.size() as usize + 100];
+    let len = volume.read(file, 0, &mut content).unwrap();
+    content.truncate(len);
+
+    content
+}
+
+/// A bit of everything
+fn sample(with_mbr: bool) -> Image {
+    let mut builder = Builder::new(CLUSTERS, 1, with_mbr);
+    let root = builder.root();
+
+    builder.add_file(root, "README.TXT", b"hello FAT32\n");
+    builder.add_file(root, "A long file name.txt", &pattern(1500, 1));
+    builder.add_file(root, "EMPTY", b"");
+    let boot = builder.add_dir(root, "BOOT");
+    let overlays = builder.add_dir(boot, "overlays");
+    builder.add_file(overlays, "héllo wörld.dtbo", b"unicode");
+    builder.fragment = true;
+    builder.add_file(boot, "KERNEL8.IMG", &pattern(10_000, 7));
+
+    builder.build()
+}
+
+#[test]
+fn partition_table() {
+    let image = sample(true);
+    let mut sector = [0; SECTOR_SIZE];
+    image.read_blocks(0, &mut sector).unwrap();
+
+    let table = PartitionTable::parse(&sector).unwrap();
+    let partitions: Vec<_> = table.partitions().collect();
+    assert_eq!(partitions.len(), 1);
+    assert!(partitions[0].is_fat32());
+    assert!(partitions[0].bootable);
+    assert_eq!(partitions[0].start_lba, PARTITION_START);
+
+    sector[510] = 0;
+    assert!(PartitionTable::parse(&sector).is_none());
+}
+
+#[test]
+fn open_superfloppy_and_partition() {
+    for with_mbr in [false, true] {
+        let image = sample(with_mbr);
+        let volume = Volume::open(&image).unwrap();
+
+        assert_eq!(volume.label(), "TESTVOL");
+        assert_eq!(volume.cluster_size(), 512);
+        assert_eq!(
+            read_all(&volume, &volume.find("/README.TXT").unwrap()),
+            b"hello FAT32\n"
+        );
+    }
+}
+
+#[test]
+fn not_fat32() {
+    let image = Image {
+        data: vec![0; 64 * SECTOR_SIZE],
+    };
+
+    assert_eq!(Volume::open(&image).err(), Some(Error::NoFat32Volume));
+}
+
+#[test]
+fn list_root() {
+    let image = sample(false);
+    let volume = Volume::open(&image).unwrap();
+
+    assert_eq!(
+        names(&volume, "/"),
+        ["README.TXT", "A long file name.txt", "EMPTY", "BOOT"]
+    );
+    assert_eq!(names(&volume, "/BOOT"), ["overlays", "KERNEL8.IMG"]);
+    assert_eq!(names(&volume, "/boot/overlays"), ["héllo wörld.dtbo"]);
+}
+
+#[test]
+fn entries() {
+    let image = sample(false);
+    let volume = Volume::open(&image).unwrap();
+
+    let file = volume.find("/A long file name.txt").unwrap();
+    assert!(!file.is_dir());
+    assert_eq!(file.size(), 1500);
+    assert_eq!(file.short_name(), "LONGNA~1.TXT");
+
+    let dir = volume.find("/boot/overlays").unwrap();
+    assert!(dir.is_dir());
+    assert_eq!(dir.size(), 0);
+
+    // short names work too, case insensitive
+    assert_eq!(
+        volume.find("/longna~1.txt").unwrap().name(),
+        "A long file name.txt"
+    );
+    assert_eq!(volume.find("/A LONG FILE NAME.TXT").unwrap().size(), 1500);
+}
+
+#[test]
+fn paths() {
+    let image = sample(true);
+    let volume = Volume::open(&image).unwrap();
+
+    assert!(volume.find("").unwrap().is_dir());
+    assert_eq!(
+        volume.find("/").unwrap().first_cluster(),
+        volume.root().first_cluster()
+    );
+    assert_eq!(
+        volume.find("//BOOT/./overlays/").unwrap().name(),
+        "overlays"
+    );
+    assert_eq!(
+        volume.find("/BOOT/overlays/../KERNEL8.IMG").unwrap().size(),
+        10_000
+    );
+    assert_eq!(volume.find("/BOOT/../README.TXT").unwrap().size(), 12);
+    assert_eq!(volume.find("/../README.TXT").unwrap().size(), 12);
+
+    let boot = volume.find("/boot").unwrap();
+    assert_eq!(volume.lookup(&boot, "kernel8.img").unwrap().size(), 10_000);
+    let overlays = volume.lookup(&boot, "overlays").unwrap();
+    assert_eq!(
+        volume.lookup(&overlays, "..").unwrap().first_cluster(),
+        boot.first_cluster()
+    );
+    assert_eq!(volume.lookup(&boot, "..").unwrap().name(), "/");
+
+    assert_eq!(volume.find("/nope").err(), Some(Error::NotFound));
+    assert_eq!(volume.find("/BOOT/nope/x").err(), Some(Error::NotFound));
+    assert_eq!(
+        volume.find("/README.TXT/x").err(),
+        Some(Error::NotADirectory)
+    );
+}
+
+#[test]
+fn read_files() {
+    let image = sample(false);
+    let volume = Volume::open(&image).unwrap();
+
+    let file = volume.find("/A long file name.txt").unwrap();
+    assert_eq!(read_all(&volume, &file), pattern(1500, 1));
+
+    let empty = volume.find("/EMPTY").unwrap();
+    assert_eq!(empty.first_cluster(), 0);
+    assert!(read_all(&volume, &empty).is_empty());
+
+    let unicode = volume.find("/BOOT/overlays/héllo wörld.dtbo").unwrap();
+    assert_eq!(read_all(&volume, &unicode), b"unicode");
+
+    let dir = volume.find("/BOOT").unwrap();
+    assert_eq!(
+        volume.read(&dir, 0, &mut [0; 4]).err(),
+        Some(Error::IsADirectory)
+    );
+    assert_eq!(volume.read_dir(&file).err(), Some(Error::NotADirectory));
+}
+
+#[test]
+fn read_at_offsets() {
+    let image = sample(false);
+    let volume = Volume::open(&image).unwrap();
+    let file = volume.find("/BOOT/KERNEL8.IMG").unwrap();
+    let expected = pattern(10_000, 7);
+
+    // unaligned, across sectors (= clusters, fragmented) and up to the end
+    for (offset, len) in [
+        (0, 10),
+        (500, 30),
+        (511, 1026),
+        (1024, 512),
+        (9_990, 100),
+        (3, 10_000),
+    ] {
+        let mut buf = vec![0; len];
+        let read = volume.read(&file, offset as u64, &mut buf).unwrap();
+        let end = (offset + len).min(expected.len());
+        assert_eq!(read, end - offset);
+        assert_eq!(buf[..read], expected[offset..end]);
+    }
+
+    assert_eq!(volume.read(&file, 10_000, &mut [0; 4]).unwrap(), 0);
+    assert_eq!(volume.read(&file, 20_000, &mut [0; 4]).unwrap(), 0);
+}
+
+#[test]
+fn multi_sector_clusters() {
+    let mut builder = Builder::new(CLUSTERS, 8, false);
+    let root = builder.root();
+    builder.fragment = true;
+    builder.add_file(root, "BIG.BIN", &pattern(40_000, 3));
+    let image = builder.build();
+
+    let volume = Volume::open(&image).unwrap();
+    assert_eq!(volume.cluster_size(), 4096);
+
+    let file = volume.find("/big.bin").unwrap();
+    assert_eq!(read_all(&volume, &file), pattern(40_000, 3));
+
+    let mut buf = vec![0; 5000];
+    assert_eq!(volume.read(&file, 4000, &mut buf).unwrap(), 5000);
+    assert_eq!(buf, pattern(40_000, 3)[4000..9000]);
+}
+
+#[test]
+fn large_directory() {
+    // 16 entries per cluster, every file takes 3 of them
+    let mut builder = Builder::new(CLUSTERS, 1, false);
+    let root = builder.root();
+    let dir = builder.add_dir(root, "many");
+    let expected: Vec<String> = (0..50)
+        .map(|i| std::format!("file number {:02}.txt", i))
+        .collect();
+    for name in &expected {
+        builder.add_file(dir, name, name.as_bytes());
+    }
+    let image = builder.build();
+    let volume = Volume::open(&image).unwrap();
+
+    assert_eq!(names(&volume, "/many"), expected);
+    let last = volume.find("/many/file number 49.txt").unwrap();
+    assert_eq!(read_all(&volume, &last), b"file number 49.txt");
+}
+
+#[test]
+fn deleted_and_orphan_entries() {
+    let mut builder = Builder::new(CLUSTERS, 1, false);
+    let root = builder.root();
+
+    let mut deleted = [0u8; 32];
+    deleted[..11].copy_from_slice(b"\xE5ONE    TXT");
+    builder.push_raw(root, &deleted);
+
+    // a long name entry whose checksum doesn't match the short name following it
+    let mut orphan = [0u8; 32];
+    orphan[0] = 0x41;
+    orphan[11] = 0x0F;
+    orphan[13] = 0x42;
+    orphan[1] = b'x';
+    builder.push_raw(root, &orphan);
+
+    let mut label = [0u8; 32];
+    label[..11].copy_from_slice(b"TESTVOL    ");
+    label[11] = 0x08;
+    builder.push_raw(root, &label);
+
+    builder.add_file(root, "KEPT.TXT", b"kept");
+    let image = builder.build();
+    let volume = Volume::open(&image).unwrap();
+
+    assert_eq!(names(&volume, "/"), ["KEPT.TXT"]);
+}
+
+#[test]
+fn lowercase_short_names() {
+    let mut builder = Builder::new(CLUSTERS, 1, false);
+    let root = builder.root();
+    let mut raw = [0u8; 32];
+    raw[..11].copy_from_slice(b"CONFIG  TXT");
+    // lower case base and extension
+    raw[12] = 0x18;
+    builder.push_raw(root, &raw);
+    let image = builder.build();
+    let volume = Volume::open(&image).unwrap();
+
+    assert_eq!(names(&volume, "/"), ["config.txt"]);
+}
+
+#[test]
+fn corrupted_chain() {
+    let mut builder = Builder::new(CLUSTERS, 1, false);
+    let root = builder.root();
+    let cluster = builder.add_file(root, "FILE.BIN", &pattern(2000, 0));
+    // cut the chain after the first cluster
+    builder.set_fat(cluster, 0x0FFF_FFFF);
+    let image = builder.build();
+    let volume = Volume::open(&image).unwrap();
+
+    let file = volume.find("/FILE.BIN").unwrap();
+    assert!(matches!(
+        volume.read(&file, 0, &mut [0; 2000]),
+        Err(Error::Corrupted(_))
+    ));
+}
+
+/// An image made by the real `mkfs.vfat`: `./make_test_image.sh` then
+/// `FAT32_TEST_IMAGE=target/fat32-test.img cargo test -p fat32 -- --ignored`
+#[test]
+#[ignore = "needs an image made by make_test_image.sh"]
+fn mkfs_vfat_image() {
+    let path = std::env::var("FAT32_TEST_IMAGE").expect("FAT32_TEST_IMAGE not set");
+    let image = Image {
+        data: std::fs::read(path).unwrap(),
+    };
+    let volume = Volume::open(&image).unwrap();
+
+    assert_eq!(volume.label(), "MATIAOS");
+    let mut root = names(&volume, "/");
+    root.sort();
+    assert_eq!(root, ["A long file name.txt", "README.TXT", "boot"]);
+    assert_eq!(names(&volume, "/boot"), ["config.txt"]);
+
+    let readme = volume.find("/README.TXT").unwrap();
+    assert_eq!(read_all(&volume, &readme), b"hello FAT32\n");
+
+    let long = volume.find("/a long file name.txt").unwrap();
+    assert_eq!(read_all(&volume, &long), pattern(100_000, 1));
+}
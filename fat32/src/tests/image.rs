@@ -0,0 +1,264 @@
+//! In-memory FAT32 images, laid out the way `mkfs.vfat -F 32` does it.
+
+use std::{collections::HashMap, vec, vec::Vec};
+
+use crate::{BlockDevice, SECTOR_SIZE};
+
+const RESERVED_SECTORS: u32 = 32;
+const NUM_FATS: u32 = 2;
+const ROOT_CLUSTER: u32 = 2;
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+
+/// Partition start when the image has an MBR
+pub const PARTITION_START: u32 = 2048;
+
+pub struct Image {
+    pub data: Vec<u8>,
+}
+
+impl BlockDevice for Image {
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        let start = lba as usize * SECTOR_SIZE;
+        if !buf.len().is_multiple_of(SECTOR_SIZE) || start + buf.len() > self.data.len() {
+            return Err("Read out of the image");
+        }
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+
+        Ok(())
+    }
+}
+
+/// Adds files and directories to an empty volume
+pub struct Builder {
+    data: Vec<u8>,
+    /// Byte offset of the volume in the image
+    start: usize,
+    sectors_per_cluster: u32,
+    fat_size: u32,
+    cluster_count: u32,
+    next_free: u32,
+    /// Leave a free cluster between the clusters of a file
+    pub fragment: bool,
+    /// Directory first cluster -> (last cluster, next free entry in it)
+    dirs: HashMap<u32, (u32, usize)>,
+    /// Short names taken, for the ~N tails
+    short_names: usize,
+}
+
+impl Builder {
+    pub fn new(cluster_count: u32, sectors_per_cluster: u32, with_mbr: bool) -> Self {
+        let fat_size = ((cluster_count + 2) * 4).div_ceil(SECTOR_SIZE as u32);
+        let total_sectors =
+            RESERVED_SECTORS + NUM_FATS * fat_size + cluster_count * sectors_per_cluster;
+        let start = if with_mbr { PARTITION_START } else { 0 };
+        let mut data = vec![0; (start + total_sectors) as usize * SECTOR_SIZE];
+
+        if with_mbr {
+            let entry = &mut data[446..462];
+            entry[0] = 0x80;
+            entry[4] = 0x0C;
+            entry[8..12].copy_from_slice(&start.to_le_bytes());
+            entry[12..16].copy_from_slice(&total_sectors.to_le_bytes());
+            data[510] = 0x55;
+            data[511] = 0xAA;
+        }
+
+        let boot = &mut data[start as usize * SECTOR_SIZE..][..SECTOR_SIZE];
+        boot[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[3..11].copy_from_slice(b"mkfs.fat");
+        boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = sectors_per_cluster as u8;
+        boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        boot[16] = NUM_FATS as u8;
+        boot[21] = 0xF8;
+        boot[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+        boot[36..40].copy_from_slice(&fat_size.to_le_bytes());
+        boot[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[66] = 0x29;
+        boot[71..82].copy_from_slice(b"TESTVOL    ");
+        boot[82..90].copy_from_slice(b"FAT32   ");
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+
+        let mut builder = Self {
+            data,
+            start: start as usize * SECTOR_SIZE,
+            sectors_per_cluster,
+            fat_size,
+            cluster_count,
+            next_free: ROOT_CLUSTER,
+            fragment: false,
+            dirs: HashMap::new(),
+            short_names: 0,
+        };
+
+        builder.set_fat(0, 0x0FFF_FFF8);
+        builder.set_fat(1, END_OF_CHAIN);
+        let root = builder.allocate();
+        builder.dirs.insert(root, (root, 0));
+
+        builder
+    }
+
+    pub fn root(&self) -> u32 {
+        ROOT_CLUSTER
+    }
+
+    pub fn build(self) -> Image {
+        Image { data: self.data }
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        let first_data_sector = RESERVED_SECTORS + NUM_FATS * self.fat_size;
+        self.start
+            + (first_data_sector + (cluster - 2) * self.sectors_per_cluster) as usize * SECTOR_SIZE
+    }
+
+    pub fn set_fat(&mut self, cluster: u32, value: u32) {
+        for fat in 0..NUM_FATS {
+            let offset = self.start
+                + (RESERVED_SECTORS + fat * self.fat_size) as usize * SECTOR_SIZE
+                + cluster as usize * 4;
+            self.data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn allocate(&mut self) -> u32 {
+        let cluster = self.next_free;
+        assert!(cluster < self.cluster_count + 2, "image full");
+        self.next_free += if self.fragment { 2 } else { 1 };
+        self.set_fat(cluster, END_OF_CHAIN);
+
+        cluster
+    }
+
+    /// Write `content` to a new cluster chain, returns the first cluster (0 if empty)
+    fn write_chain(&mut self, content: &[u8]) -> u32 {
+        let mut first = 0;
+        let mut previous = 0;
+        for chunk in content.chunks(self.cluster_size()) {
+            let cluster = self.allocate();
+            if previous == 0 {
+                first = cluster;
+            } else {
+                self.set_fat(previous, cluster);
+            }
+            let offset = self.cluster_offset(cluster);
+            self.data[offset..offset + chunk.len()].copy_from_slice(chunk);
+            previous = cluster;
+        }
+
+        first
+    }
+
+    /// Append a raw 32 bytes entry to a directory, growing it by a cluster when full
+    pub fn push_raw(&mut self, dir: u32, raw: &[u8; 32]) {
+        let (mut last, mut index) = self.dirs[&dir];
+        if index == self.cluster_size() / 32 {
+            let cluster = self.allocate();
+            self.set_fat(last, cluster);
+            last = cluster;
+            index = 0;
+        }
+        let offset = self.cluster_offset(last) + index * 32;
+        self.data[offset..offset + 32].copy_from_slice(raw);
+        self.dirs.insert(dir, (last, index + 1));
+    }
+
+    /// Add an entry: short only if `name` is a valid upper case 8.3 name, long and short otherwise
+    fn push_entry(&mut self, dir: u32, name: &str, attributes: u8, cluster: u32, size: u32) {
+        let (short, needs_long) = match short_name(name) {
+            Some(short) => (short, false),
+            None => {
+                self.short_names += 1;
+                let mut short = *b"LONGNAMETXT";
+                let tail = std::format!("~{}", self.short_names);
+                short[8 - tail.len()..8].copy_from_slice(tail.as_bytes());
+                (short, true)
+            }
+        };
+
+        if needs_long {
+            let units: Vec<u16> = name.encode_utf16().collect();
+            let count = units.len().div_ceil(13);
+            let checksum = short
+                .iter()
+                .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c));
+            for sequence in (1..=count).rev() {
+                let mut raw = [0u8; 32];
+                raw[0] = sequence as u8 | if sequence == count { 0x40 } else { 0 };
+                raw[11] = 0x0F;
+                raw[13] = checksum;
+                let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+                for (i, offset) in offsets.iter().enumerate() {
+                    let position = (sequence - 1) * 13 + i;
+                    let unit = match position.cmp(&units.len()) {
+                        std::cmp::Ordering::Less => units[position],
+                        std::cmp::Ordering::Equal => 0,
+                        std::cmp::Ordering::Greater => 0xFFFF,
+                    };
+                    raw[*offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+                }
+                self.push_raw(dir, &raw);
+            }
+        }
+
+        let mut raw = [0u8; 32];
+        raw[..11].copy_from_slice(&short);
+        raw[11] = attributes;
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        self.push_raw(dir, &raw);
+    }
+
+    pub fn add_file(&mut self, dir: u32, name: &str, content: &[u8]) -> u32 {
+        let cluster = self.write_chain(content);
+        self.push_entry(dir, name, 0x20, cluster, content.len() as u32);
+
+        cluster
+    }
+
+    pub fn add_dir(&mut self, parent: u32, name: &str) -> u32 {
+        let cluster = self.allocate();
+        self.dirs.insert(cluster, (cluster, 0));
+        self.push_entry(parent, name, 0x10, cluster, 0);
+
+        let parent_cluster = if parent == ROOT_CLUSTER { 0 } else { parent };
+        for (dots, target) in [(b".          ", cluster), (b"..         ", parent_cluster)] {
+            let mut raw = [0u8; 32];
+            raw[..11].copy_from_slice(dots);
+            raw[11] = 0x10;
+            raw[20..22].copy_from_slice(&((target >> 16) as u16).to_le_bytes());
+            raw[26..28].copy_from_slice(&(target as u16).to_le_bytes());
+            self.push_raw(cluster, &raw);
+        }
+
+        cluster
+    }
+}
+
+/// The on-disk form of an upper case 8.3 name
+pub fn short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    let valid = |s: &str, max: usize| {
+        s.len() <= max
+            && s.bytes()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_')
+    };
+
+    if base.is_empty() || !valid(base, 8) || !valid(ext, 3) {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+
+    Some(short)
+}
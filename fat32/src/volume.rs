@@ -0,0 +1,359 @@
+//! A mounted FAT32 volume.
+
+use crate::{
+    bpb::BiosParameterBlock,
+    dir::{self, DirEntry, LongName, Parsed, DIR_ENTRY_SIZE},
+    mbr::PartitionTable,
+    BlockDevice, Error, SECTOR_SIZE,
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+/// FAT entries are 28 bits, the top 4 are reserved
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+/// Anything from here on ends the chain
+const FAT_END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+const FAT_ENTRY_SIZE: u32 = 4;
+
+/// The first data cluster. 0 and 1 are reserved.
+const FIRST_CLUSTER: u32 = 2;
+
+const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / DIR_ENTRY_SIZE;
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+/// A FAT32 volume on a block device
+pub struct Volume<D: BlockDevice> {
+    device: D,
+    /// Partition start, all sector numbers below are relative to it
+    start: u64,
+    sectors_per_cluster: u32,
+    fat_start: u32,
+    first_data_sector: u32,
+    cluster_count: u32,
+    root_cluster: u32,
+    label: [u8; 11],
+}
+
+/// The entries of a directory. `.` and `..` are left out.
+pub struct DirIter<'a, D: BlockDevice> {
+    volume: &'a Volume<D>,
+    cluster: u32,
+    /// Sector within the cluster
+    sector_index: u32,
+    /// Entry within the sector, `ENTRIES_PER_SECTOR` when the next sector is due
+    entry_index: usize,
+    sector: [u8; SECTOR_SIZE],
+    long_name: LongName,
+    /// Clusters visited so far, a loop in the chain is a corrupted FAT
+    clusters_seen: u32,
+    with_dots: bool,
+    done: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<D: BlockDevice> Volume<D> {
+    /// Open the volume: either the whole device (a "superfloppy", like a bare `mkfs.vfat` image)
+    /// or its first FAT32 partition.
+    pub fn open(device: D) -> Result<Self, Error> {
+        let mut sector = [0; SECTOR_SIZE];
+        device.read_blocks(0, &mut sector).map_err(Error::Device)?;
+
+        if BiosParameterBlock::is_fat32_boot_sector(&sector) {
+            return Self::open_at(device, 0, &sector);
+        }
+
+        let start = PartitionTable::parse(&sector)
+            .and_then(|table| table.partitions().find(|p| p.is_fat32()).copied())
+            .ok_or(Error::NoFat32Volume)?
+            .start_lba;
+
+        device
+            .read_blocks(u64::from(start), &mut sector)
+            .map_err(Error::Device)?;
+
+        Self::open_at(device, u64::from(start), &sector)
+    }
+
+    fn open_at(device: D, start: u64, boot_sector: &[u8; SECTOR_SIZE]) -> Result<Self, Error> {
+        let bpb = BiosParameterBlock::parse(boot_sector)?;
+
+        let first_data_sector = bpb.reserved_sectors + bpb.num_fats * bpb.fat_size;
+        let data_sectors = bpb
+            .total_sectors
+            .checked_sub(first_data_sector)
+            .ok_or(Error::Corrupted("FATs larger than the volume"))?;
+
+        // The FAT may be larger than needed, never smaller
+        let cluster_count = (data_sectors / bpb.sectors_per_cluster)
+            .min(bpb.fat_size * (SECTOR_SIZE as u32 / FAT_ENTRY_SIZE) - FIRST_CLUSTER);
+
+        let volume = Self {
+            device,
+            start,
+            sectors_per_cluster: bpb.sectors_per_cluster,
+            fat_start: bpb.reserved_sectors,
+            first_data_sector,
+            cluster_count,
+            root_cluster: bpb.root_cluster,
+            label: bpb.volume_label,
+        };
+
+        if !volume.is_valid_cluster(volume.root_cluster) {
+            return Err(Error::Corrupted("Invalid root cluster"));
+        }
+
+        Ok(volume)
+    }
+
+    /// Volume label of the boot sector, trimmed
+    pub fn label(&self) -> &str {
+        let len = self
+            .label
+            .iter()
+            .rposition(|&c| c != b' ')
+            .map_or(0, |x| x + 1);
+
+        core::str::from_utf8(&self.label[..len]).unwrap_or("")
+    }
+
+    /// Cluster size in bytes
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    /// The root directory
+    pub fn root(&self) -> DirEntry {
+        DirEntry::root(self.root_cluster)
+    }
+
+    /// Look up an absolute path (`/` separated, case insensitive)
+    pub fn find(&self, path: &str) -> Result<DirEntry, Error> {
+        let mut current = self.root();
+
+        for component in path.split('/').filter(|x| !x.is_empty() && *x != ".") {
+            current = self.lookup(&current, component)?;
+        }
+
+        Ok(current)
+    }
+
+    /// Look up `name` in the directory `dir` (case insensitive). `..` works, `..` of the root is
+    /// the root.
+    pub fn lookup(&self, dir: &DirEntry, name: &str) -> Result<DirEntry, Error> {
+        if !dir.is_dir() {
+            return Err(Error::NotADirectory);
+        }
+
+        if name == ".." && dir.first_cluster() == self.root_cluster {
+            return Ok(self.root());
+        }
+
+        for entry in self.iter_dir(dir, true) {
+            let entry = entry?;
+            if entry.matches(name) {
+                // ".." of a first level directory points to cluster 0, meaning the root
+                if entry.is_dir() && entry.first_cluster() == 0 {
+                    return Ok(self.root());
+                }
+
+                return Ok(entry);
+            }
+        }
+
+        Err(Error::NotFound)
+    }
+
+    /// List a directory
+    pub fn read_dir(&self, dir: &DirEntry) -> Result<DirIter<'_, D>, Error> {
+        if !dir.is_dir() {
+            return Err(Error::NotADirectory);
+        }
+
+        Ok(self.iter_dir(dir, false))
+    }
+
+    /// Read from a file at `offset`. Returns the number of bytes read, 0 at the end of the file.
+    pub fn read(&self, file: &DirEntry, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        if file.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        let size = u64::from(file.size());
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+
+        // skip the clusters before the offset
+        let cluster_size = self.cluster_size() as u64;
+        let mut cluster = file.first_cluster();
+        for _ in 0..offset / cluster_size {
+            cluster = self
+                .next_cluster(cluster)?
+                .ok_or(Error::Corrupted("Cluster chain shorter than the file"))?;
+        }
+
+        let mut sector = [0; SECTOR_SIZE];
+        let mut position = offset % cluster_size;
+        let mut done = 0;
+        while done < len {
+            if !self.is_valid_cluster(cluster) {
+                return Err(Error::Corrupted("Invalid cluster in chain"));
+            }
+
+            let sector_index = (position / SECTOR_SIZE as u64) as u32;
+            let in_sector = (position % SECTOR_SIZE as u64) as usize;
+            let count = (SECTOR_SIZE - in_sector).min(len - done);
+
+            if in_sector == 0 && count == SECTOR_SIZE {
+                self.read_sector(
+                    self.cluster_sector(cluster) + sector_index,
+                    &mut buf[done..done + SECTOR_SIZE],
+                )?;
+            } else {
+                self.read_sector(self.cluster_sector(cluster) + sector_index, &mut sector)?;
+                buf[done..done + count].copy_from_slice(&sector[in_sector..in_sector + count]);
+            }
+
+            done += count;
+            position += count as u64;
+
+            if position == cluster_size && done < len {
+                cluster = self
+                    .next_cluster(cluster)?
+                    .ok_or(Error::Corrupted("Cluster chain shorter than the file"))?;
+                position = 0;
+            }
+        }
+
+        Ok(done)
+    }
+
+    fn iter_dir(&self, dir: &DirEntry, with_dots: bool) -> DirIter<'_, D> {
+        DirIter {
+            volume: self,
+            cluster: dir.first_cluster(),
+            sector_index: 0,
+            entry_index: ENTRIES_PER_SECTOR,
+            sector: [0; SECTOR_SIZE],
+            long_name: LongName::new(),
+            clusters_seen: 0,
+            with_dots,
+            done: false,
+        }
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..FIRST_CLUSTER + self.cluster_count).contains(&cluster)
+    }
+
+    /// First sector of a cluster
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.first_data_sector + (cluster - FIRST_CLUSTER) * self.sectors_per_cluster
+    }
+
+    fn read_sector(&self, sector: u32, buf: &mut [u8]) -> Result<(), Error> {
+        self.device
+            .read_blocks(self.start + u64::from(sector), buf)
+            .map_err(Error::Device)
+    }
+
+    /// Follow the chain in the (first) FAT, `None` at its end
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, Error> {
+        let offset = cluster * FAT_ENTRY_SIZE;
+        let mut sector = [0; SECTOR_SIZE];
+        self.read_sector(self.fat_start + offset / SECTOR_SIZE as u32, &mut sector)?;
+
+        let i = (offset % SECTOR_SIZE as u32) as usize;
+        let next = u32::from_le_bytes([sector[i], sector[i + 1], sector[i + 2], sector[i + 3]])
+            & FAT_ENTRY_MASK;
+
+        match next {
+            FAT_END_OF_CHAIN..=FAT_ENTRY_MASK => Ok(None),
+            FAT_BAD_CLUSTER => Err(Error::Corrupted("Bad cluster in chain")),
+            x if self.is_valid_cluster(x) => Ok(Some(x)),
+            _ => Err(Error::Corrupted("Invalid cluster in chain")),
+        }
+    }
+}
+
+impl<D: BlockDevice> DirIter<'_, D> {
+    /// Move to the next entry, loading sectors and following the chain. `false` at the end.
+    fn advance(&mut self) -> Result<bool, Error> {
+        self.entry_index += 1;
+        if self.entry_index < ENTRIES_PER_SECTOR {
+            return Ok(true);
+        }
+
+        // first call
+        if self.clusters_seen == 0 {
+            self.clusters_seen = 1;
+            self.sector_index = 0;
+        } else {
+            self.sector_index += 1;
+        }
+
+        if self.sector_index == self.volume.sectors_per_cluster {
+            match self.volume.next_cluster(self.cluster)? {
+                None => return Ok(false),
+                Some(next) => self.cluster = next,
+            }
+            self.sector_index = 0;
+            self.clusters_seen += 1;
+            if self.clusters_seen > self.volume.cluster_count {
+                return Err(Error::Corrupted("Loop in directory cluster chain"));
+            }
+        }
+
+        if !self.volume.is_valid_cluster(self.cluster) {
+            return Err(Error::Corrupted("Invalid directory cluster"));
+        }
+
+        self.volume.read_sector(
+            self.volume.cluster_sector(self.cluster) + self.sector_index,
+            &mut self.sector,
+        )?;
+        self.entry_index = 0;
+
+        Ok(true)
+    }
+}
+
+impl<D: BlockDevice> Iterator for DirIter<'_, D> {
+    type Item = Result<DirEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.advance() {
+                Ok(true) => (),
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            if self.done {
+                break;
+            }
+
+            let raw = &self.sector[self.entry_index * DIR_ENTRY_SIZE..][..DIR_ENTRY_SIZE];
+            match dir::parse(raw, &mut self.long_name) {
+                Parsed::End => self.done = true,
+                Parsed::Skip => (),
+                Parsed::Entry(entry) if entry.is_dot() && !self.with_dots => (),
+                Parsed::Entry(entry) => return Some(Ok(entry)),
+            }
+        }
+
+        None
+    }
+}
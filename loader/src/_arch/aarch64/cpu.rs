@@ -14,7 +14,8 @@
 //!
 //! crate::cpu::arch_cpu
 
-use aarch64_cpu::asm;
+use aarch64_cpu::{asm, asm::barrier, registers::*};
+use tock_registers::interfaces::Readable;
 
 pub use asm::nop; // export cpu::nop() for waiting
 
@@ -33,3 +34,15 @@ pub fn spin_for_cycles(cycles: usize) {
         asm::nop();
     }
 }
+
+/// The generic timer's counter (CNTPCT_EL0), it keeps running into the kernel.
+#[inline(always)]
+pub fn counter() -> u64 {
+    barrier::isb(barrier::SY);
+    CNTPCT_EL0.get()
+}
+
+/// Ticks per second of the counter (CNTFRQ_EL0).
+pub fn counter_frequency() -> u64 {
+    CNTFRQ_EL0.get()
+}
@@ -13,8 +13,9 @@ use crate::loader_init;
 core::arch::global_asm!(include_str!("boot.s"));
 
 
+/// The Rust entry point, called from `boot.s` with the firmware's device tree address.
 #[no_mangle]
-pub unsafe fn _start_rust() {
-    loader_init()
+pub unsafe extern "C" fn _start_rust(dtb_addr: u64) {
+    loader_init(dtb_addr)
 }
 
@@ -45,6 +45,9 @@ https://sourceware.org/binutils/docs-2.36/as/AArch64_002dRelocations.html
 
 // fn _start() -> do initialization work and call rust code
 _start:
+    // The firmware gives the device tree address in x0, keep it for the kernel
+    mov x19, x0
+
     // We have 4 cores. Only proceed with the boot core, core0.
     // move MPIDR_EL1 register content to general purpose register x1   
     mrs x1, MPIDR_EL1
@@ -80,8 +83,9 @@ _copy_loop:
 ADR_ABS x0, __boot_core_stack_end_exclusive
 mov sp, x0
 // let's begin!
-ADR_ABS x0, _start_rust
-br x0
+mov x0, x19
+ADR_ABS x1, _start_rust
+br x1
 
 _park_core:
     wfe // wait for event
@@ -10,8 +10,17 @@
 // This is just a way to define the start address of UART and the GPIO. The trick is to figure out that the specified addresses are bus addresses
 // that need to be mapped physically.
 
+use core::cell::UnsafeCell;
+
+// Symbols from the linker script.
+extern "Rust" {
+    static __binary_start: UnsafeCell<()>;
+}
+
 pub mod map {
     pub const BOARD_DEFAULT_LOAD_ADDRESS: usize =        0x8_0000;
+    /// Far above the kernel (and its heap) and the relocated loader
+    pub const INITRAMFS_LOAD_ADDRESS:     usize =   0x0400_0000;
 
     pub const GPIO_OFFSET:         usize = 0x0020_0000;
     pub const UART_OFFSET:         usize = 0x0020_1000;
@@ -41,3 +50,14 @@ pub mod map {
 pub fn board_default_load_address() -> *const u64 {
     map::BOARD_DEFAULT_LOAD_ADDRESS as _
 }
+
+#[inline(always)]
+pub fn initramfs_load_address() -> *const u64 {
+    map::INITRAMFS_LOAD_ADDRESS as _
+}
+
+/// Where the loader runs, once relocated: the kernel can start it again there.
+#[inline(always)]
+pub fn loader_entry() -> *const u64 {
+    unsafe { __binary_start.get() as _ }
+}
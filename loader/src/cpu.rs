@@ -13,5 +13,5 @@ mod boot;
 #[path = "_arch/aarch64/cpu.rs"]
 mod arch_cpu;
 
-pub use arch_cpu::{nop, wait_forever};
+pub use arch_cpu::{counter, counter_frequency, nop, wait_forever};
 pub use arch_cpu::spin_for_cycles;
\ No newline at end of file
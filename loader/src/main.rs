@@ -21,7 +21,7 @@ mod panic_handler;
 /// # Safety
 ///
 /// - Only a single core must be active and running this function.
-unsafe fn loader_init() -> ! {
+unsafe fn loader_init(dtb_addr: u64) -> ! {
     use crate::driver::interface::DeviceManager;
 
     for i in bsp::driver::driver_manager().all_device_drivers().iter() {
@@ -31,7 +31,7 @@ unsafe fn loader_init() -> ! {
     }
     bsp::driver::driver_manager().post_device_driver_init();
 
-    loader_main();
+    loader_main(dtb_addr);
 }
 
 const LOADER_LOGO: &str = r#"
@@ -44,7 +44,8 @@ const LOADER_LOGO: &str = r#"
     \|__|     \|__|\|_______|\|_______|\|_______|    \|__|
 "#;
 
-fn loader_main() -> ! {
+/// Receive the kernel (and the initramfs) and run it, with the firmware's device tree.
+fn loader_main(dtb_addr: u64) -> ! {
     use bsp::console::console;
     use console::interface::All;
 
@@ -61,7 +62,78 @@ fn loader_main() -> ! {
         console().write_char(3 as char);
     }
 
-    // Read the binary's size.
+    let kernel_addr = bsp::memory::board_default_load_address() as *mut u8;
+    receive_image(kernel_addr);
+    println!("[Loader] Received kernel");
+
+    // The initramfs follows, its size is 0 if there is none
+    let initramfs_addr = bsp::memory::initramfs_load_address() as *mut u8;
+    let initramfs_size = receive_image(initramfs_addr);
+    if initramfs_size != 0 {
+        println!("[Loader] Received initramfs ({} bytes)", initramfs_size);
+    }
+
+    // The time of the host, when it was received
+    let (seconds, nanos) = receive_time();
+    let received_at = cpu::counter();
+    println!("[Loader] Received the time: {} seconds since 1970", seconds);
+
+    println!("[Loader] Executing kernel now!");
+    console().flush();
+
+    let (seconds, nanos) = advance_time(seconds, nanos, cpu::counter() - received_at);
+
+    // The kernel is started like the firmware does, the device tree address in x0, and finds the
+    // initramfs in x1 (address) and x2 (size), the current time in x3 (seconds since 1970, the
+    // firmware leaves it zeroed) and x4 (nanoseconds), and where to start the loader again in x5.
+    let kernel: extern "C" fn(u64, u64, u64, u64, u64, u64) -> ! =
+        unsafe { core::mem::transmute(kernel_addr) };
+    kernel(
+        dtb_addr,
+        initramfs_addr as u64,
+        u64::from(initramfs_size),
+        seconds,
+        u64::from(nanos),
+        bsp::memory::loader_entry() as u64,
+    );
+}
+
+/// Receive the time from the pusher: seconds since 1970 (8 bytes, little endian), then
+/// nanoseconds (4 bytes, little endian).
+fn receive_time() -> (u64, u32) {
+    use bsp::console::console;
+    use console::interface::All;
+
+    let mut seconds: u64 = 0;
+    for i in 0..8 {
+        seconds |= u64::from(console().read_char() as u8) << (8 * i);
+    }
+    let mut nanos: u32 = 0;
+    for i in 0..4 {
+        nanos |= u32::from(console().read_char() as u8) << (8 * i);
+    }
+
+    (seconds, nanos)
+}
+
+/// `seconds` and `nanos`, `ticks` of the counter later
+fn advance_time(seconds: u64, nanos: u32, ticks: u64) -> (u64, u32) {
+    let elapsed_nanos =
+        u128::from(ticks) * 1_000_000_000 / u128::from(cpu::counter_frequency().max(1));
+    let total = u128::from(nanos) + elapsed_nanos;
+
+    (
+        seconds + (total / 1_000_000_000) as u64,
+        (total % 1_000_000_000) as u32,
+    )
+}
+
+/// Receive an image from the pusher into `addr`: its size (4 bytes, little endian), then, after
+/// we answer "OK", its content. Returns the size.
+fn receive_image(addr: *mut u8) -> u32 {
+    use bsp::console::console;
+    use console::interface::All;
+
     let mut size: u32 = u32::from(console().read_char() as u8);
     size |= u32::from(console().read_char() as u8) << 8;
     size |= u32::from(console().read_char() as u8) << 16;
@@ -70,17 +142,11 @@ fn loader_main() -> ! {
     console().write_char('O');
     console().write_char('K');
 
-    let kernel_addr = bsp::memory::board_default_load_address() as *mut u8;
-
     unsafe {
         for i in 0..size {
-            core::ptr::write_volatile(kernel_addr.offset(i as isize), console().read_char() as u8);
+            core::ptr::write_volatile(addr.offset(i as isize), console().read_char() as u8);
         }
     }
 
-    println!("[Loader] Received kernel, executing now!");
-    console().flush();
-
-    let kernel: fn() -> ! = unsafe { core::mem::transmute(kernel_addr) };
-    kernel();
+    size
 }
@@ -10,4 +10,15 @@ fn main() {
     // either the linker script has changed or the build script itself
     println!("cargo:rerun-if-changed={linker_file}");
     println!("cargo:rerun-if-changed=build.rs");
+
+    // An initramfs archive to embed in the kernel, exported by the Makefile (INITRAMFS)
+    println!("cargo:rustc-check-cfg=cfg(initramfs_embedded)");
+    println!("cargo:rerun-if-env-changed=MATIAOS_INITRAMFS");
+    if let Ok(initramfs) = env::var("MATIAOS_INITRAMFS") {
+        if !initramfs.is_empty() {
+            println!("cargo:rerun-if-changed={initramfs}");
+            println!("cargo:rustc-env=MATIAOS_INITRAMFS={initramfs}");
+            println!("cargo:rustc-cfg=initramfs_embedded");
+        }
+    }
 }
@@ -14,7 +14,8 @@
 //!
 //! crate::cpu::arch_cpu
 
-use aarch64_cpu::asm;
+use aarch64_cpu::{asm, registers::*};
+use tock_registers::interfaces::Readable;
 
 pub use asm::nop; // export cpu::nop() for waiting
 
@@ -26,3 +27,19 @@ pub fn wait_forever() -> ! {
     }
 }
 
+/// The part number of the core (i.e. 0xD03 for a Cortex-A53).
+pub fn core_part_number() -> u64 {
+    MIDR_EL1.read(MIDR_EL1::PartNum)
+}
+
+/// Jump to `entry` in EL2, with `arg` in x0, like the firmware starts an image.
+///
+/// The kernel runs in EL1: the `hvc` goes through the EL2 vectors the boot code left (see
+/// `boot.s`), which jump to `entry` with the interrupts masked.
+///
+/// # Safety
+///
+/// - `entry` must be the start of an image that can run over the current state of the board.
+pub unsafe fn enter_el2(entry: usize, arg: usize) -> ! {
+    core::arch::asm!("hvc #0", in("x0") arg, in("x5") entry, options(noreturn))
+}
@@ -8,13 +8,82 @@
  //! Include the assembly file that is responsible for booting the kernel
  //! for the aarch64 architecture.
 
-use crate::kernel_init;
+use crate::{fdt, initramfs, kernel_init, power, time};
+use aarch64_cpu::{asm, registers::*};
+use core::{cell::UnsafeCell, time::Duration};
+use tock_registers::interfaces::Writeable;
  
 core::arch::global_asm!(include_str!("boot.s"));
 
+/// Prepare the jump from EL2 to EL1.
+///
+/// The kernel runs in EL1, where the exception (and interrupt) handling lives. `eret` "returns"
+/// to the exception level and address found in SPSR_EL2 and ELR_EL2, so we fake an exception
+/// return into `kernel_init` in EL1.
+///
+/// # Safety
+///
+/// - The HW state of EL1 must be prepared in a sound way.
+#[inline(always)]
+unsafe fn prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr: u64) {
+    // Let EL1 access the timer counter registers (CNTPCT_EL0 is what the time module reads).
+    CNTHCTL_EL2.write(CNTHCTL_EL2::EL1PCEN::SET + CNTHCTL_EL2::EL1PCTEN::SET);
 
+    // No offset for reading the counters.
+    CNTVOFF_EL2.set(0);
+
+    // EL1 runs aarch64.
+    HCR_EL2.write(HCR_EL2::RW::EL1IsAarch64);
+
+    // The way back to EL2, for the kernel to start another image (see `cpu::enter_el2`).
+    extern "Rust" {
+        static __el2_vector_start: UnsafeCell<()>;
+    }
+    VBAR_EL2.set(__el2_vector_start.get() as u64);
+
+    // Enter EL1 with all the interrupts masked, using SP_EL1 as the stack pointer.
+    SPSR_EL2.write(
+        SPSR_EL2::D::Masked
+            + SPSR_EL2::A::Masked
+            + SPSR_EL2::I::Masked
+            + SPSR_EL2::F::Masked
+            + SPSR_EL2::M::EL1h,
+    );
+
+    // "Return" to kernel_init.
+    ELR_EL2.set(kernel_init as *const () as u64);
+
+    // EL1 uses the same stack the boot code set up for EL2.
+    SP_EL1.set(phys_boot_core_stack_end_exclusive_addr);
+}
+
+/// The Rust entry point, called from `boot.s` in EL2.
+///
+/// `dtb_addr` is the device tree the firmware passed (0 without one), `initramfs_start` and
+/// `initramfs_size` are what the loader received after the kernel (both 0 without one).
+/// `unix_seconds` and `nanos` are the time the loader got from the pusher (seconds are 0 without
+/// one, the nanoseconds are garbage then). `loader_entry` is where the loader can be started
+/// again, garbage without a loader.
 #[no_mangle]
-pub unsafe fn _start_rust() {
-    kernel_init()
+pub unsafe extern "C" fn _start_rust(
+    phys_boot_core_stack_end_exclusive_addr: u64,
+    dtb_addr: u64,
+    initramfs_start: u64,
+    initramfs_size: u64,
+    unix_seconds: u64,
+    nanos: u64,
+    loader_entry: u64,
+) -> ! {
+    fdt::set_boot_fdt(dtb_addr as usize);
+    initramfs::set_loader_archive(initramfs_start as usize, initramfs_size as usize);
+    if unix_seconds != 0 {
+        let now = Duration::new(unix_seconds, (nanos % 1_000_000_000) as u32);
+        time::time_manager().set_wall_clock(time::SystemTime::from_unix(now));
+    }
+    power::set_serial_loader_entry(loader_entry as usize);
+    prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr);
+
+    // Jump to EL1 (kernel_init)
+    asm::eret()
 }
 
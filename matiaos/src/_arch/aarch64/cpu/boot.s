@@ -29,11 +29,29 @@ https://sourceware.org/binutils/docs-2.36/as/AArch64_002dRelocations.html
 .endm
 
 .equ _core_id_mask, 0b11
+.equ _EL2, 0x8 // CurrentEL holds the exception level in bits [3:2]
 
 .section .text._start
 
 // fn _start() -> do initialization work and call rust code
 _start:
+    // The firmware (and our loader) give the device tree address in x0. Our loader also hands
+    // the initramfs over in x1 (address) and x2 (size), the time in x3 (seconds) and x4
+    // (nanoseconds), and its own entry in x5. The firmware leaves x1-x3 zeroed. Keep them for
+    // _start_rust.
+    mov x19, x0
+    mov x20, x1
+    mov x21, x2
+    mov x22, x3
+    mov x23, x4
+    mov x24, x5
+
+    // The kernel drops from EL2 to EL1 (see boot.rs), so it must be started in EL2.
+    // This is what the firmware (and our loader) do.
+    mrs x0, CurrentEL
+    cmp x0, _EL2
+    b.ne _park_core
+
     // We have 4 cores. Only proceed with the boot core, core0.
     // move MPIDR_EL1 register content to general purpose register x1   
     mrs x1, MPIDR_EL1
@@ -64,9 +82,15 @@ _prepare_rust:
     cmp x2, xzr
     b.eq _park_core
     str w2, [x1] // only the lower 32 bit are the clock frequency
-    // let's begin!
-    ADR_REL x0, _start_rust
-    br x0
+    // let's begin! x0 still holds the stack address, _start_rust gives it to EL1
+    mov x1, x19
+    mov x2, x20
+    mov x3, x21
+    mov x4, x22
+    mov x5, x23
+    mov x6, x24
+    ADR_REL x7, _start_rust
+    br x7
 
 _park_core:
     wfe // wait for event
@@ -75,3 +99,45 @@ _park_core:
 .size _start, . - _start // tells the linker the size of _start, doesn't look important
 .type _start, function // start is a function
 .global _start // _start is an external symbol ready to link
+
+/*
+EL2 exception vectors, installed before dropping to EL1 (see boot.rs).
+
+Nothing is routed to EL2 but the `hvc` of the kernel, to start another image in EL2 like the
+firmware does (see `cpu::enter_el2`): x0 is the image's argument, x5 its entry. Every other entry
+parks the core.
+*/
+.macro EL2_PARK
+1:	wfe
+	b	1b
+.endm
+
+.section .text
+
+// Align by 2^11 bytes, as demanded by ARMv8-A.
+.align 11
+
+__el2_vector_start:
+
+// Current exception level, with SP_EL0 then SP_ELx
+.rept 8
+	EL2_PARK
+	.balign 0x80
+.endr
+
+// Lower exception level, AArch64, synchronous: the hvc (exception class 0x16) of the kernel
+	mrs	x9, ESR_EL2
+	lsr	x9, x9, #26
+	cmp	x9, #0x16
+	b.ne	2f
+	br	x5
+2:	EL2_PARK
+	.balign 0x80
+
+// The rest of lower exception level AArch64, then AArch32
+.rept 7
+	EL2_PARK
+	.balign 0x80
+.endr
+
+.global __el2_vector_start
@@ -0,0 +1,143 @@
+//! aarch64 data cache maintenance, by virtual address and by set/way.
+//!
+//! The operations by address go to the point of coherency: the memory every observer sees, the
+//! VideoCore and the DMA included. The set/way ones only reach the caches of the core running
+//! them, they are for turning the caches on or off.
+//!
+//! The system registers are read with `mrs`, the aarch64-cpu crate doesn't have CTR_EL0.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path attribute, the path of this
+//! file is:
+//!
+//! crate::memory::cache::arch_cache
+
+#[path = "cache/geometry.rs"]
+mod geometry;
+
+use aarch64_cpu::asm::barrier;
+use core::{arch::asm, ops::Range};
+use geometry::CacheGeometry;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+enum Operation {
+    Clean,
+    Invalidate,
+    CleanInvalidate,
+}
+
+/// CLIDR_EL1.Ctype of the levels with a data cache: data only, split, unified
+const CTYPE_DATA: [u64; 3] = [0b010, 0b011, 0b100];
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn ctr_el0() -> u64 {
+    let value;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+fn clidr_el1() -> u64 {
+    let value;
+    unsafe { asm!("mrs {}, clidr_el1", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+/// The CCSIDR_EL1 of the data or unified cache of `level` (1 is L1)
+fn ccsidr_el1(level: u32) -> u64 {
+    let selection = u64::from(level - 1) << 1;
+    let value;
+
+    unsafe { asm!("msr csselr_el1, {}", in(reg) selection, options(nomem, nostack)) };
+    barrier::isb(barrier::SY);
+    unsafe { asm!("mrs {}, ccsidr_el1", out(reg) value, options(nomem, nostack)) };
+
+    value
+}
+
+/// Apply `operation` to the line at `addr`, to the point of coherency
+fn by_address(operation: Operation, addr: usize) {
+    unsafe {
+        match operation {
+            Operation::Clean => asm!("dc cvac, {}", in(reg) addr, options(nostack)),
+            Operation::Invalidate => asm!("dc ivac, {}", in(reg) addr, options(nostack)),
+            Operation::CleanInvalidate => asm!("dc civac, {}", in(reg) addr, options(nostack)),
+        }
+    }
+}
+
+fn on_range(range: Range<usize>, mut operation: impl FnMut(usize) -> Operation) {
+    for addr in geometry::lines(range, line_size()) {
+        by_address(operation(addr), addr);
+    }
+
+    // Done before whatever comes next, like handing the buffer to a device
+    barrier::dsb(barrier::SY);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest data cache line, in bytes.
+pub fn line_size() -> usize {
+    geometry::data_line_size(ctr_el0())
+}
+
+/// Write the dirty lines of `range` back to memory, for a device to read them.
+pub fn clean_range(range: Range<usize>) {
+    on_range(range, |_| Operation::Clean);
+}
+
+/// Drop the lines of `range`, for the next reads to get what a device wrote.
+///
+/// The lines only partly in the range are cleaned first: what the rest of them holds is kept.
+pub fn invalidate_range(range: Range<usize>) {
+    let line_size = line_size();
+    let (start, end) = (range.start, range.end);
+
+    on_range(range, |addr| {
+        if addr < start || addr + line_size > end {
+            Operation::CleanInvalidate
+        } else {
+            Operation::Invalidate
+        }
+    });
+}
+
+/// Write the dirty lines of `range` back, and drop them.
+pub fn clean_invalidate_range(range: Range<usize>) {
+    on_range(range, |_| Operation::CleanInvalidate);
+}
+
+/// Write back and drop every line of the data caches of this core, up to the level of coherency.
+pub fn clean_invalidate_all() {
+    let clidr = clidr_el1();
+    let level_of_coherency = ((clidr >> 24) & 0x7) as u32;
+
+    for level in 1..=level_of_coherency {
+        let ctype = (clidr >> (3 * (level - 1))) & 0x7;
+        if !CTYPE_DATA.contains(&ctype) {
+            continue;
+        }
+
+        let geometry = CacheGeometry::from_ccsidr(ccsidr_el1(level));
+        for way in 0..geometry.ways {
+            for set in 0..geometry.sets {
+                let operand = geometry.set_way(level, set, way);
+                unsafe { asm!("dc cisw, {}", in(reg) operand, options(nostack)) };
+            }
+        }
+        // A level is done before the next one gets what it wrote back
+        barrier::dsb(barrier::SY);
+    }
+
+    barrier::isb(barrier::SY);
+}
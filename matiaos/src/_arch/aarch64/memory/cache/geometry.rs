@@ -0,0 +1,129 @@
+//! The geometry of the caches: the line size in CTR_EL0, the sets and ways in CCSIDR_EL1, the
+//! lines a range covers, and the operand of the set/way instructions.
+//!
+//! Plain integer code, without registers or crates, so it is tested on the host (`make test`).
+
+use core::ops::Range;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A cache level, as CCSIDR_EL1 describes it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheGeometry {
+    /// log2 of the line size in bytes
+    pub log2_line_size: u32,
+    pub ways: u32,
+    pub sets: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest data cache line of the system, in bytes: CTR_EL0.DminLine is log2 of its words.
+pub fn data_line_size(ctr: u64) -> usize {
+    4 << ((ctr >> 16) & 0xF)
+}
+
+/// The start of every line `range` touches, `line_size` being a power of two.
+pub fn lines(range: Range<usize>, line_size: usize) -> impl Iterator<Item = usize> {
+    let start = range.start & !(line_size - 1);
+    let end = if range.is_empty() { start } else { range.end };
+
+    (start..end).step_by(line_size)
+}
+
+impl CacheGeometry {
+    /// Decode CCSIDR_EL1 (without FEAT_CCIDX, as on the Cortex-A53 and A72).
+    pub fn from_ccsidr(ccsidr: u64) -> Self {
+        Self {
+            log2_line_size: (ccsidr & 0x7) as u32 + 4,
+            ways: ((ccsidr >> 3) & 0x3FF) as u32 + 1,
+            sets: ((ccsidr >> 13) & 0x7FFF) as u32 + 1,
+        }
+    }
+
+    /// The operand of DC ISW/CSW/CISW for a set and a way of `level` (1 is L1). The way goes in
+    /// the top bits, the set above the line offset, the level above bit 0.
+    pub fn set_way(&self, level: u32, set: u32, way: u32) -> u64 {
+        // log2 of the ways, rounded up
+        let way_bits = u32::BITS - (self.ways - 1).leading_zeros();
+
+        u64::from(way) << (32 - way_bits)
+            | u64::from(set) << self.log2_line_size
+            | u64::from(level - 1) << 1
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The Cortex-A53 L1 data cache: 32 KiB, 4 ways, 64 bytes lines
+    const A53_L1D_CCSIDR: u64 = 0x700F_E01A;
+
+    #[test]
+    fn line_size_of_ctr() {
+        // A53 and A72: DminLine is 4, 16 words
+        assert_eq!(data_line_size(0x8444_C004), 64);
+        assert_eq!(data_line_size(0), 4);
+    }
+
+    #[test]
+    fn lines_of_a_range() {
+        let unaligned: Vec<usize> = lines(0x1010..0x1090, 64).collect();
+        assert_eq!(unaligned, [0x1000, 0x1040, 0x1080]);
+
+        let one_line: Vec<usize> = lines(0x1000..0x1040, 64).collect();
+        assert_eq!(one_line, [0x1000]);
+
+        assert_eq!(lines(0x1010..0x1010, 64).count(), 0);
+        assert_eq!(lines(0x103F..0x1041, 64).count(), 2);
+    }
+
+    #[test]
+    fn ccsidr_of_the_a53() {
+        let geometry = CacheGeometry::from_ccsidr(A53_L1D_CCSIDR);
+
+        assert_eq!(
+            geometry,
+            CacheGeometry {
+                log2_line_size: 6,
+                ways: 4,
+                sets: 128
+            }
+        );
+    }
+
+    #[test]
+    fn set_way_operand() {
+        let l1 = CacheGeometry::from_ccsidr(A53_L1D_CCSIDR);
+        assert_eq!(l1.set_way(1, 0, 0), 0);
+        assert_eq!(l1.set_way(1, 1, 0), 1 << 6);
+        assert_eq!(l1.set_way(1, 127, 3), (3 << 30) | (127 << 6));
+
+        // 16 ways at L2
+        let l2 = CacheGeometry {
+            log2_line_size: 6,
+            ways: 16,
+            sets: 512,
+        };
+        assert_eq!(l2.set_way(2, 5, 15), (15 << 28) | (5 << 6) | (1 << 1));
+    }
+
+    #[test]
+    fn set_way_of_a_direct_mapped_cache() {
+        let geometry = CacheGeometry {
+            log2_line_size: 6,
+            ways: 1,
+            sets: 64,
+        };
+        assert_eq!(geometry.set_way(1, 3, 0), 3 << 6);
+    }
+}
@@ -2,20 +2,16 @@
 //! Use CNTPCT_EL0 and CNTFRQ_EL0 to implement a simple timer.
 //!
 
-use crate::warn;
+#[path = "time/ticks.rs"]
+mod ticks;
+
+use crate::error::{Error, ErrorKind};
 use aarch64_cpu::{asm::barrier, registers::*};
-use core::{
-    num::{NonZeroU128, NonZeroU32, NonZeroU64}, // This is for optimization purposes
-    ops::{Add, Div},
-    time::Duration,
-};
+use core::{num::NonZeroU32, time::Duration};
 use tock_registers::interfaces::Readable;
 
-/// Number of nanoseconds per second
-const NANOSEC_PER_SEC: NonZeroU64 = NonZeroU64::new(1_000_000_000).unwrap();
-
 /// Internal counter type (CNTPCT_EL0)
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 struct GenericTimerCounterValue(u64);
 
 // This is a dummy value; the boot code in `boot.s` will overide it with the content of of the
@@ -31,69 +27,108 @@ fn get_arch_timer_frequency() -> NonZeroU32 {
     unsafe { core::ptr::read_volatile(&ARCH_TIMER_COUNTER_FREQUENCY) }
 }
 
-// Implementations for the internal counter type.
-impl GenericTimerCounterValue {
-    /// Max counter value
-    pub const MAX: Self = GenericTimerCounterValue(u64::MAX);
+impl From<GenericTimerCounterValue> for Duration {
+    // seconds = counter_value / frequency, and the remainder gives the nanoseconds (see `ticks`)
+    fn from(counter_value: GenericTimerCounterValue) -> Self {
+        ticks::to_duration(counter_value.0, get_arch_timer_frequency())
+    }
 }
 
-/// Implement add for GenericTimerCounterValue
-impl Add for GenericTimerCounterValue {
-    type Output = GenericTimerCounterValue;
+impl TryFrom<Duration> for GenericTimerCounterValue {
+    type Error = Error;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        GenericTimerCounterValue(self.0.wrapping_add(rhs.0))
+    // Durations below the resolution are 0 ticks
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        ticks::from_duration(duration, get_arch_timer_frequency())
+            .map(GenericTimerCounterValue)
+            .ok_or(Error::new(
+                ErrorKind::InvalidArgument,
+                "Convertion error. Duration overflowed max allowed value (u64::max)",
+            ))
     }
 }
 
-/// Get the max possible value of the duration object
-fn max_duration() -> Duration {
-    Duration::from(GenericTimerCounterValue::MAX)
+/// Read the timer value from the register (u64)
+#[inline(always)]
+fn read_cntpct() -> GenericTimerCounterValue {
+    barrier::isb(barrier::SY);
+    let cnt = CNTPCT_EL0.get();
+    GenericTimerCounterValue(cnt)
+}
+
+/// Ticks a duration is, if an instant can move that far
+fn step(duration: Duration) -> Option<u64> {
+    GenericTimerCounterValue::try_from(duration)
+        .ok()
+        .map(|x| x.0)
+        .filter(|&x| x <= ticks::MAX_STEP)
 }
 
-impl From<GenericTimerCounterValue> for Duration {
-    // To construct the duration object we need: seconds, nanoseconds.
-    // To calculate seconds: counter_value / frequency
-    //     - for example, if the frequency is 20hz, it means the counter is ticking in
-    //       20 ticks per seconds. GenericTimerCounterValue is the CNTPCT_EL0 value, so dividing it
-    //       will give us the seconds
-    // To calculate nanoseconds: get the remainder - counter_value % frequency and multiply it by
-    // nanoseconds per seconds to get the value in nanoseconds.
-    fn from(counter_value: GenericTimerCounterValue) -> Self {
-        if counter_value.0 == 0 {
-            return Duration::ZERO;
-        }
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
 
-        // Get the timer frequency (CNTFRQ_EL0)
-        let frequency: NonZeroU64 = get_arch_timer_frequency().into();
-        let seconds = counter_value.0.div(frequency);
+/// A point in time, a value of the counter (CNTPCT_EL0).
+///
+/// Comparisons and differences keep working when the counter wraps, as long as the instants are
+/// less than half the counter apart (thousands of years).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Instant(GenericTimerCounterValue);
 
-        // The remainder
-        let subsecond_counter = counter_value.0 % frequency;
-        let nanos = unsafe { subsecond_counter.unchecked_mul(u64::from(NANOSEC_PER_SEC)) }
-            .div(frequency) as u32;
-        Duration::new(seconds, nanos)
+/// The ARM generic timer as a clock source, always there
+pub struct GenericTimer;
+
+pub static GENERIC_TIMER: GenericTimer = GenericTimer;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Instant {
+    /// The current instant.
+    pub fn now() -> Self {
+        Self(read_cntpct())
     }
-}
 
-impl TryFrom<Duration> for GenericTimerCounterValue {
-    type Error = &'static str;
+    /// Time since `earlier`, zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        GenericTimerCounterValue(ticks::ticks_since(self.0 .0, earlier.0 .0)).into()
+    }
 
-    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
-        if duration < resolution() {
-            return Ok(GenericTimerCounterValue(0));
-        }
+    /// Time since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// The instant `duration` after this one, if it can be represented.
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        step(duration).map(|x| Self(GenericTimerCounterValue(self.0 .0.wrapping_add(x))))
+    }
+}
 
-        if duration > max_duration() {
-            return Err("Convertion error. Duration overflowed max allowed value (u64::max)");
+impl PartialOrd for Instant {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        if self == other {
+            Some(core::cmp::Ordering::Equal)
+        } else if ticks::is_before(self.0 .0, other.0 .0) {
+            Some(core::cmp::Ordering::Less)
+        } else {
+            Some(core::cmp::Ordering::Greater)
         }
+    }
+}
+
+impl super::interface::ClockSource for GenericTimer {
+    fn name(&self) -> &'static str {
+        "generic_timer"
+    }
 
-        let frequency = u32::from(get_arch_timer_frequency()) as u128;
-        let duration: u128 = duration.as_nanos();
-        let counter_value =
-            unsafe { duration.unchecked_mul(frequency) }.div(NonZeroU128::from(NANOSEC_PER_SEC));
+    fn now(&self) -> Duration {
+        uptime()
+    }
 
-        Ok(GenericTimerCounterValue(counter_value as u64))
+    fn resolution(&self) -> Duration {
+        resolution()
     }
 }
 
@@ -104,33 +139,25 @@ pub fn resolution() -> Duration {
     Duration::from(GenericTimerCounterValue(1))
 }
 
-/// Read the timer value from the register (u64)
-#[inline(always)]
-fn read_cntpct() -> GenericTimerCounterValue {
-    barrier::isb(barrier::SY);
-    let cnt = CNTPCT_EL0.get();
-    GenericTimerCounterValue(cnt)
-}
-
 /// Get the system uptime (basically read the counter divided by the frequency)
 pub fn uptime() -> Duration {
     read_cntpct().into()
 }
 
-/// Spin for Duration
-pub fn spin_for_duration(duration: Duration) {
-    let current_timer_value = read_cntpct();
-
-    let counter_value_delta: GenericTimerCounterValue = match duration.try_into() {
-        Err(msg) => {
-            warn!("spin_for_duration error: {}", msg);
-            return;
-        }
-        Ok(val) => val,
-    };
-
-    // The target timer value to spin: current timer value + the request time(r) delta
-    let timer_target = current_timer_value + counter_value_delta;
+/// Spin until `deadline`
+pub fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {}
+}
 
-    while (GenericTimerCounterValue(CNTPCT_EL0.get())) < timer_target {}
+/// The deadline of a wait of at least `duration` from now, if it can be represented.
+///
+/// Unlike `Instant::checked_add`, the ticks are rounded up, plus one: now is already partway
+/// through its tick.
+pub fn deadline_after(duration: Duration) -> Option<Instant> {
+    let start = Instant::now();
+    let ticks = ticks::from_duration_ceil(duration, get_arch_timer_frequency())?
+        .checked_add(1)
+        .filter(|&x| x <= ticks::MAX_STEP)?;
+
+    Some(Instant(GenericTimerCounterValue(start.0 .0.wrapping_add(ticks))))
 }
@@ -0,0 +1,180 @@
+//! The math of the counter ticks: conversions from and to `Duration`, and comparisons that keep
+//! working when the counter wraps.
+//!
+//! Plain integer code, without registers or crates, so it is tested on the host (`make test`).
+
+use core::{num::NonZeroU32, time::Duration};
+
+/// Number of nanoseconds per second
+const NANOSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// The `Duration` of `ticks` at `frequency` (in Hz), rounded down to the nanosecond.
+pub fn to_duration(ticks: u64, frequency: NonZeroU32) -> Duration {
+    let frequency = u64::from(frequency.get());
+    let seconds = ticks / frequency;
+
+    // The remainder is below the frequency, so this fits: 2^32 * 10^9 < 2^64
+    let subsecond_ticks = ticks % frequency;
+    let nanos = subsecond_ticks * NANOSEC_PER_SEC / frequency;
+
+    Duration::new(seconds, nanos as u32)
+}
+
+/// The ticks of `duration` at `frequency`, rounded down. `None` if they don't fit in the counter.
+pub fn from_duration(duration: Duration, frequency: NonZeroU32) -> Option<u64> {
+    let ticks = duration.as_nanos() * u128::from(frequency.get()) / u128::from(NANOSEC_PER_SEC);
+
+    u64::try_from(ticks).ok()
+}
+
+/// The ticks of `duration` at `frequency`, rounded up: they last at least `duration`. `None` if
+/// they don't fit in the counter.
+pub fn from_duration_ceil(duration: Duration, frequency: NonZeroU32) -> Option<u64> {
+    let ticks =
+        (duration.as_nanos() * u128::from(frequency.get())).div_ceil(u128::from(NANOSEC_PER_SEC));
+
+    u64::try_from(ticks).ok()
+}
+
+/// Ticks from `earlier` to `later`, even across a wrap. Zero if `later` is actually before.
+///
+/// Like the comparisons, it is right as long as the two are less than half the counter apart.
+pub fn ticks_since(later: u64, earlier: u64) -> u64 {
+    if is_before(later, earlier) {
+        0
+    } else {
+        later.wrapping_sub(earlier)
+    }
+}
+
+/// `a` comes before `b`, even across a wrap.
+pub fn is_before(a: u64, b: u64) -> bool {
+    (a.wrapping_sub(b) as i64) < 0
+}
+
+/// The longest step an instant can move forward or backward, for the comparisons to still hold.
+pub const MAX_STEP: u64 = i64::MAX as u64;
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CNTFRQ_EL0 of the Raspberry Pi 4, and of QEMU's raspi3b
+    const RPI4_FREQUENCY: NonZeroU32 = NonZeroU32::new(54_000_000).unwrap();
+    const QEMU_FREQUENCY: NonZeroU32 = NonZeroU32::new(62_500_000).unwrap();
+
+    #[test]
+    fn zero_ticks_is_zero_duration() {
+        assert_eq!(to_duration(0, RPI4_FREQUENCY), Duration::ZERO);
+        assert_eq!(from_duration(Duration::ZERO, RPI4_FREQUENCY), Some(0));
+    }
+
+    #[test]
+    fn one_second_of_ticks() {
+        assert_eq!(to_duration(54_000_000, RPI4_FREQUENCY), Duration::from_secs(1));
+        assert_eq!(from_duration(Duration::from_secs(1), QEMU_FREQUENCY), Some(62_500_000));
+    }
+
+    #[test]
+    fn one_tick_rounds_down() {
+        // 1 / 54MHz = 18.52ns
+        assert_eq!(to_duration(1, RPI4_FREQUENCY), Duration::from_nanos(18));
+        assert_eq!(to_duration(1, QEMU_FREQUENCY), Duration::from_nanos(16));
+    }
+
+    #[test]
+    fn below_one_tick_is_zero_ticks() {
+        assert_eq!(from_duration(Duration::from_nanos(18), RPI4_FREQUENCY), Some(0));
+        assert_eq!(from_duration(Duration::from_nanos(19), RPI4_FREQUENCY), Some(1));
+    }
+
+    #[test]
+    fn below_one_tick_rounds_up_to_one_tick() {
+        assert_eq!(from_duration_ceil(Duration::ZERO, RPI4_FREQUENCY), Some(0));
+        assert_eq!(from_duration_ceil(Duration::from_nanos(1), RPI4_FREQUENCY), Some(1));
+        assert_eq!(from_duration_ceil(Duration::from_nanos(18), RPI4_FREQUENCY), Some(1));
+        assert_eq!(from_duration_ceil(Duration::from_nanos(19), RPI4_FREQUENCY), Some(2));
+    }
+
+    #[test]
+    fn exact_multiple_of_a_tick_is_not_rounded() {
+        // 16ns is exactly one tick at 62.5MHz
+        assert_eq!(from_duration_ceil(Duration::from_nanos(16), QEMU_FREQUENCY), Some(1));
+        assert_eq!(from_duration_ceil(Duration::from_nanos(17), QEMU_FREQUENCY), Some(2));
+        assert_eq!(from_duration_ceil(Duration::from_secs(1), RPI4_FREQUENCY), Some(54_000_000));
+        assert_eq!(
+            from_duration_ceil(Duration::from_micros(1), RPI4_FREQUENCY),
+            from_duration(Duration::from_micros(1), RPI4_FREQUENCY)
+        );
+    }
+
+    #[test]
+    fn rounded_up_ticks_last_at_least_the_duration() {
+        // CNTFRQ_EL0 of the Raspberry Pi 3: a tick is 52.08ns
+        let rpi3_frequency = NonZeroU32::new(19_200_000).unwrap();
+
+        for ns in [1, 17, 52, 53, 999, 1_001, 123_457] {
+            for frequency in [RPI4_FREQUENCY, QEMU_FREQUENCY, rpi3_frequency] {
+                let ticks = from_duration_ceil(Duration::from_nanos(ns), frequency).unwrap();
+                // In tick * ns units, to compare without rounding
+                let tick_time = u128::from(ticks) * u128::from(NANOSEC_PER_SEC);
+                let duration = u128::from(ns) * u128::from(frequency.get());
+
+                assert!(tick_time >= duration);
+                assert!(tick_time - duration < u128::from(NANOSEC_PER_SEC));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for ms in [1, 10, 999, 1_000, 123_456, 86_400_000] {
+            let duration = Duration::from_millis(ms);
+            for frequency in [RPI4_FREQUENCY, QEMU_FREQUENCY] {
+                let ticks = from_duration(duration, frequency).unwrap();
+                assert_eq!(to_duration(ticks, frequency), duration);
+            }
+        }
+    }
+
+    #[test]
+    fn subsecond_part_uses_the_remainder() {
+        let ticks = 3 * 54_000_000 + 27_000_000;
+        assert_eq!(to_duration(ticks, RPI4_FREQUENCY), Duration::from_millis(3_500));
+    }
+
+    #[test]
+    fn largest_counter_value_converts() {
+        let duration = to_duration(u64::MAX, NonZeroU32::MIN);
+        assert_eq!(duration, Duration::from_secs(u64::MAX));
+        assert!(to_duration(u64::MAX, RPI4_FREQUENCY) > Duration::from_secs(341_000_000_000));
+    }
+
+    #[test]
+    fn overflowing_duration_is_none() {
+        assert_eq!(from_duration(Duration::MAX, RPI4_FREQUENCY), None);
+        assert_eq!(from_duration_ceil(Duration::MAX, RPI4_FREQUENCY), None);
+        assert_eq!(from_duration(Duration::from_secs(u64::MAX), NonZeroU32::MIN), Some(u64::MAX));
+    }
+
+    #[test]
+    fn comparisons_across_the_wrap() {
+        assert!(is_before(1, 2));
+        assert!(!is_before(2, 1));
+        assert!(!is_before(5, 5));
+        assert!(is_before(u64::MAX - 5, 3));
+        assert!(!is_before(3, u64::MAX - 5));
+    }
+
+    #[test]
+    fn ticks_since_across_the_wrap() {
+        assert_eq!(ticks_since(10, 4), 6);
+        assert_eq!(ticks_since(3, u64::MAX - 5), 9);
+        assert_eq!(ticks_since(4, 10), 0);
+        assert_eq!(ticks_since(MAX_STEP, 0), MAX_STEP);
+    }
+}
@@ -0,0 +1,113 @@
+//! Block devices.
+//!
+//! A block device (the SD card for instance) is read and written in fixed size blocks, addressed by
+//! their index (LBA). Filesystems sit on top of this interface.
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    random,
+    synchronization::{interface::Mutex, NullLock},
+};
+use alloc::vec;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of a block in bytes. SD cards (in block addressing mode) and most disks use 512.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Blocks written by the self test: one alone, then all of them in a single write
+const SELF_TEST_BLOCKS: usize = 2;
+
+/// Block device traits
+pub mod interface {
+    use crate::error::Result;
+
+    /// Block device functions
+    pub trait BlockDevice {
+        /// Number of blocks of the device
+        fn num_blocks(&self) -> u64;
+
+        /// Read `buf.len() / BLOCK_SIZE` blocks, starting from block `lba`.
+        /// `buf.len()` must be a multiple of `BLOCK_SIZE`.
+        fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<()>;
+
+        /// Write `buf.len() / BLOCK_SIZE` blocks, starting from block `lba`.
+        /// `buf.len()` must be a multiple of `BLOCK_SIZE`.
+        fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<()>;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Write `pattern` from block `lba`, read it back and compare.
+fn write_read_back(
+    device: &dyn interface::BlockDevice,
+    lba: u64,
+    pattern: &[u8],
+    read_back: &mut [u8],
+) -> Result<()> {
+    device.write_blocks(lba, pattern)?;
+    device.read_blocks(lba, read_back)?;
+    if pattern != read_back {
+        return Err(Error::new(ErrorKind::Io, "Blocks read back differ from those written"));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static CUR_BLOCK_DEVICE: NullLock<Option<&'static (dyn interface::BlockDevice + Sync)>> =
+    NullLock::new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Register the boot block device (i.e. the SD card).
+pub fn register_block_device(new_device: &'static (dyn interface::BlockDevice + Sync)) {
+    CUR_BLOCK_DEVICE.lock(|device| *device = Some(new_device));
+}
+
+/// Return the boot block device, if there is one.
+pub fn block_device() -> Option<&'static (dyn interface::BlockDevice + Sync)> {
+    CUR_BLOCK_DEVICE.lock(|device| *device)
+}
+
+/// Check that a buffer holds a whole number of blocks and return how many.
+pub fn blocks_in(buf_len: usize) -> Result<usize> {
+    if !buf_len.is_multiple_of(BLOCK_SIZE) {
+        return Err(Error::new(
+            ErrorKind::InvalidArgument,
+            "Buffer size is not a multiple of the block size",
+        ));
+    }
+
+    Ok(buf_len / BLOCK_SIZE)
+}
+
+/// Write random blocks from `lba` (one block, then several in a single write), read them back and
+/// compare. The blocks are put back as they were, even if the test fails.
+pub fn self_test(device: &dyn interface::BlockDevice, lba: u64) -> Result<()> {
+    let len = SELF_TEST_BLOCKS * BLOCK_SIZE;
+    let mut saved = vec![0; len];
+    let mut pattern = vec![0; len];
+    let mut read_back = vec![0; len];
+    device.read_blocks(lba, &mut saved)?;
+
+    random::fill_bytes(&mut pattern);
+    let result = write_read_back(device, lba, &pattern[..BLOCK_SIZE], &mut read_back[..BLOCK_SIZE])
+        .and_then(|()| {
+            random::fill_bytes(&mut pattern);
+            write_read_back(device, lba, &pattern, &mut read_back)
+        });
+
+    device.write_blocks(lba, &saved)?;
+    result
+}
@@ -5,13 +5,11 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 //! board specific code
-//! reexport board specific code (only RPi 3 for now)
+//! reexport board specific code (RPi 3 and 4, detected at boot)
 
 mod device_driver;
 
 
-#[cfg(feature="bsp_rpi3")]
 mod raspberrypi;
 
-#[cfg(feature="bsp_rpi3")]
 pub use raspberrypi::*;
\ No newline at end of file
@@ -6,9 +6,9 @@
  */
 
 
-#[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
+mod arm;
 mod bcm;
 mod common;
 
-#[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
+pub use arm::*;
 pub use bcm::*;
@@ -0,0 +1,5 @@
+//! ARM drivers (the BCM2711's GIC-400)
+
+mod gicv2;
+
+pub use gicv2::*;
@@ -0,0 +1,311 @@
+//! GICv2 (GIC-400) interrupt controller driver.
+//!
+//! The BCM2711 routes its peripheral interrupts to a GIC-400. The 64 VideoCore peripheral IRQs
+//! of the legacy controller are the shared peripheral interrupts starting at `irq_base`, so the
+//! rest of the kernel keeps using the same IRQ numbers on both boards.
+//!
+//! Only core 0 is targeted, all the interrupts have the same priority.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    exception::asynchronous::{interface, IRQHandlerDescriptor, IRQNumber},
+    info, synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock,
+};
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+// GIC registers.
+//
+// Descriptions taken from
+// - https://developer.arm.com/documentation/ihi0048/b (ARM GIC architecture specification v2)
+//
+// The enable registers hold one bit per interrupt, the priority and target registers one byte.
+
+register_structs! {
+    #[allow(non_snake_case)]
+    DistributorRegisterBlock {
+        (0x000 => CTLR: ReadWrite<u32>),
+        (0x004 => TYPER: ReadOnly<u32>),
+        (0x008 => _reserved1),
+        (0x100 => ISENABLER: [WriteOnly<u32>; 32]),
+        (0x180 => ICENABLER: [WriteOnly<u32>; 32]),
+        (0x200 => _reserved2),
+        (0x400 => IPRIORITYR: [ReadWrite<u32>; 256]),
+        (0x800 => ITARGETSR: [ReadWrite<u32>; 256]),
+        (0xC00 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    CpuInterfaceRegisterBlock {
+        (0x00 => CTLR: ReadWrite<u32>),
+        (0x04 => PMR: ReadWrite<u32>),
+        (0x08 => _reserved1),
+        (0x0C => IAR: ReadOnly<u32>),
+        (0x10 => EOIR: WriteOnly<u32>),
+        (0x14 => @END),
+    }
+}
+
+// abtracts the register calling
+type DistributorRegisters = MMIODerefWrapper<DistributorRegisterBlock>;
+type CpuInterfaceRegisters = MMIODerefWrapper<CpuInterfaceRegisterBlock>;
+
+/// Number of peripheral IRQs, as with the legacy controller
+const NUM_IRQS: usize = 64;
+
+/// INTID of VideoCore IRQ 0 on the BCM2711 (SPI 64)
+const BCM2711_IRQ_BASE: usize = 96;
+
+/// INTIDs from here on are special, 1023 is "nothing pending"
+const SPURIOUS_INTID_START: u32 = 1020;
+const INTID_MASK: u32 = 0x3FF;
+
+/// Same priority for everyone, the lowest mask lets them all through
+const DEFAULT_PRIORITY: u32 = 0xA0;
+const PRIORITY_MASK_ALL: u32 = 0xFF;
+
+/// ITARGETSR byte of core 0
+const TARGET_CORE_0: u32 = 0x01;
+
+type HandlerTable = [Option<IRQHandlerDescriptor<IRQNumber>>; NUM_IRQS];
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+pub struct GICv2Inner {
+    gicd: DistributorRegisters,
+    gicc: CpuInterfaceRegisters,
+    handler_table: HandlerTable,
+    irq_base: usize,
+    // one bit per IRQ, to enable them again after a reset
+    enabled: u64,
+}
+
+/// Represent the GIC hardware.
+pub struct GICv2 {
+    // also used by the IRQ exception handler
+    inner: IRQSafeNullLock<GICv2Inner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl GICv2Inner {
+    /// Create GICv2Inner instance
+    ///
+    /// # Safety
+    ///
+    /// - verify mmio start addresses
+    pub const unsafe fn new(gicd_mmio_start_addr: usize, gicc_mmio_start_addr: usize) -> Self {
+        Self {
+            gicd: DistributorRegisters::new(gicd_mmio_start_addr),
+            gicc: CpuInterfaceRegisters::new(gicc_mmio_start_addr),
+            handler_table: [None; NUM_IRQS],
+            irq_base: BCM2711_IRQ_BASE,
+            enabled: 0,
+        }
+    }
+
+    /// Disable our interrupts, route them to core 0 and enable the distributor and the CPU
+    /// interface
+    fn init(&mut self) -> Result<(), Error> {
+        // TYPER.ITLinesNumber: 32 * (N + 1) interrupts
+        let num_intids = 32 * ((self.gicd.TYPER.get() as usize & 0x1F) + 1);
+        if self.irq_base + NUM_IRQS > num_intids {
+            return Err(Error::new(ErrorKind::Unsupported, "GIC has too few interrupts"));
+        }
+
+        self.gicd.CTLR.set(0);
+
+        for intid in self.irq_base..self.irq_base + NUM_IRQS {
+            self.gicd.ICENABLER[intid / 32].set(1 << (intid % 32));
+            set_byte(&self.gicd.IPRIORITYR[intid / 4], intid % 4, DEFAULT_PRIORITY);
+            set_byte(&self.gicd.ITARGETSR[intid / 4], intid % 4, TARGET_CORE_0);
+        }
+
+        self.gicd.CTLR.set(1);
+        self.gicc.PMR.set(PRIORITY_MASK_ALL);
+        self.gicc.CTLR.set(1);
+
+        Ok(())
+    }
+
+    /// Disable our interrupts and the distributor
+    fn shutdown(&mut self) {
+        self.gicd.CTLR.set(0);
+        for intid in self.irq_base..self.irq_base + NUM_IRQS {
+            self.gicd.ICENABLER[intid / 32].set(1 << (intid % 32));
+        }
+    }
+
+    /// Like `init`, but keep the IRQs that were enabled: the drivers don't register their
+    /// handlers again after a reset.
+    fn reset(&mut self) -> Result<(), Error> {
+        self.init()?;
+
+        let mut enabled = self.enabled;
+        while enabled != 0 {
+            let irq_number = enabled.trailing_zeros() as usize;
+            enabled &= !(1 << irq_number);
+            self.enable(irq_number);
+        }
+
+        Ok(())
+    }
+
+    /// Enable an IRQ
+    fn enable(&mut self, irq_number: IRQNumber) {
+        let intid = self.irq_base + irq_number;
+
+        self.gicd.ISENABLER[intid / 32].set(1 << (intid % 32));
+        self.enabled |= 1 << irq_number;
+    }
+}
+
+/// Write byte `index` of a byte-per-interrupt register
+fn set_byte(reg: &ReadWrite<u32>, index: usize, value: u32) {
+    let shift = index * 8;
+
+    reg.set((reg.get() & !(0xFF << shift)) | (value << shift));
+}
+
+impl GICv2 {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start addresses of the distributor and the CPU interface
+    pub const unsafe fn new(gicd_mmio_start_addr: usize, gicc_mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(GICv2Inner::new(gicd_mmio_start_addr, gicc_mmio_start_addr)),
+        }
+    }
+
+    /// Move the registers (i.e. found in the device tree). Call it before the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start addresses
+    pub unsafe fn set_mmio_start_addr(&self, gicd_mmio_start_addr: usize, gicc_mmio_start_addr: usize) {
+        self.inner.lock(|inner| {
+            inner.gicd = DistributorRegisters::new(gicd_mmio_start_addr);
+            inner.gicc = CpuInterfaceRegisters::new(gicc_mmio_start_addr);
+        });
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for GICv2 {
+    fn compatible(&self) -> &'static str {
+        "GICv2 (GIC-400) Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init())
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.shutdown());
+        Ok(())
+    }
+
+    // The enables are kept, the distributor just stops forwarding
+    fn suspend(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.gicd.CTLR.set(0));
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.gicd.CTLR.set(1));
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.reset())
+    }
+}
+
+impl interface::IRQManager for GICv2 {
+    type IRQNumberType = IRQNumber;
+
+    fn register_handler(
+        &self,
+        irq_handler_descriptor: IRQHandlerDescriptor<Self::IRQNumberType>,
+    ) -> Result<(), Error> {
+        let irq_number = irq_handler_descriptor.number();
+        if irq_number >= NUM_IRQS {
+            return Err(Error::new(ErrorKind::InvalidArgument, "IRQ number out of range"));
+        }
+
+        self.inner.lock(|inner| {
+            if inner.handler_table[irq_number].is_some() {
+                return Err(Error::new(ErrorKind::Busy, "IRQ handler already registered"));
+            }
+
+            inner.handler_table[irq_number] = Some(irq_handler_descriptor);
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq_number: &Self::IRQNumberType) {
+        self.inner.lock(|inner| inner.enable(*irq_number));
+    }
+
+    fn handle_pending_irqs(&self) {
+        // IRQs are already masked here (exception entry), so the handlers run under the lock
+        self.inner.lock(|inner| loop {
+            // Reading IAR acknowledges the interrupt, it must be written back to EOIR
+            let iar = inner.gicc.IAR.get();
+            let intid = iar & INTID_MASK;
+            if intid >= SPURIOUS_INTID_START {
+                break;
+            }
+
+            let descriptor = (intid as usize)
+                .checked_sub(inner.irq_base)
+                .and_then(|irq_number| inner.handler_table.get(irq_number).copied().flatten());
+            match descriptor {
+                None => panic!("No handler registered for GIC interrupt {}", intid),
+                Some(descriptor) => {
+                    // Call the IRQ handler. Panics on failure.
+                    descriptor.handler().handle().expect("Error handling IRQ");
+                }
+            }
+
+            inner.gicc.EOIR.set(iar);
+        })
+    }
+
+    fn print_handler(&self) {
+        info!("      Peripheral handler:");
+
+        self.inner.lock(|inner| {
+            for (i, descriptor) in inner
+                .handler_table
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.map(|x| (i, x)))
+            {
+                info!("            {: >3}. {}", i, descriptor.name());
+            }
+        });
+    }
+}
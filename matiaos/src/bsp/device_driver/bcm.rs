@@ -5,10 +5,32 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 
-//! BCM2xxx drivers (RPI3 is BCM2837)
+//! BCM2xxx drivers (RPI3 is BCM2837, RPI4 is BCM2711)
 
+mod bcm2xxx_dma;
+mod bcm2xxx_emmc;
+mod bcm2xxx_framebuffer;
 mod bcm2xxx_gpio;
+mod bcm2xxx_i2c;
+mod bcm2xxx_interrupt_controller;
+mod bcm2xxx_mailbox;
+mod bcm2xxx_mini_uart;
 mod bcm2xxx_pl011_uart;
+mod bcm2xxx_power_manager;
+mod bcm2xxx_rng;
+mod bcm2xxx_spi;
+mod bcm2xxx_system_timer;
 
+pub use bcm2xxx_dma::*;
+pub use bcm2xxx_emmc::*;
+pub use bcm2xxx_framebuffer::*;
 pub use bcm2xxx_gpio::*;
-pub use bcm2xxx_pl011_uart::*;
\ No newline at end of file
+pub use bcm2xxx_i2c::*;
+pub use bcm2xxx_interrupt_controller::*;
+pub use bcm2xxx_mailbox::*;
+pub use bcm2xxx_mini_uart::*;
+pub use bcm2xxx_pl011_uart::*;
+pub use bcm2xxx_power_manager::*;
+pub use bcm2xxx_rng::*;
+pub use bcm2xxx_spi::*;
+pub use bcm2xxx_system_timer::*;
\ No newline at end of file
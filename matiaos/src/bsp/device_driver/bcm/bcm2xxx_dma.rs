@@ -0,0 +1,681 @@
+//! BCM2xxx DMA controller driver.
+//!
+//! 15 channels, each one walking a chain of control blocks in memory: a control block says what
+//! to copy where, and gives the bus address of the next one (0 ends the chain). Channels 7 and up
+//! are "lite" ones: half the bandwidth, and 64 KiB at most per control block. The VideoCore keeps
+//! some channels, the ones the ARM can use are in the device tree's `brcm,dma-channel-mask`.
+//!
+//! The DMA sees the memory through its bus addresses: `memory::phys_to_bus` for the RAM,
+//! `memory::mmio_to_bus` for the peripheral registers. The control blocks are written back from
+//! the data cache before a start, the buffers are the caller's (`DmaBuffer`).
+//!
+//! Reference: BCM2837 ARM Peripherals, chapter 4 "DMA Controller"
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    bsp::memory,
+    driver,
+    error::{Error, ErrorKind},
+    exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    memory::{
+        cache,
+        dma_buffer::{Direction, DmaBuffer},
+    },
+    println, random,
+    synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock,
+    time,
+};
+use core::{
+    ops::Range,
+    sync::atomic::{fence, AtomicBool, Ordering},
+    time::Duration,
+};
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Control and status of a channel
+    CS [
+        RESET OFFSET(31) NUMBITS(1) [],
+        ABORT OFFSET(30) NUMBITS(1) [],
+        WAIT_FOR_OUTSTANDING_WRITES OFFSET(28) NUMBITS(1) [],
+        PANIC_PRIORITY OFFSET(20) NUMBITS(4) [],
+        PRIORITY OFFSET(16) NUMBITS(4) [],
+        ERROR OFFSET(8) NUMBITS(1) [],
+        WAITING_FOR_OUTSTANDING_WRITES OFFSET(6) NUMBITS(1) [],
+        PAUSED OFFSET(4) NUMBITS(1) [],
+        /// The channel's DREQ is asserted
+        DREQ OFFSET(3) NUMBITS(1) [],
+        /// Write 1 to clear
+        INT OFFSET(2) NUMBITS(1) [],
+        /// Write 1 to clear
+        END OFFSET(1) NUMBITS(1) [],
+        ACTIVE OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Transfer information, of a control block
+    TI [
+        NO_WIDE_BURSTS OFFSET(26) NUMBITS(1) [],
+        WAITS OFFSET(21) NUMBITS(5) [],
+        /// The peripheral whose DREQ paces the transfer
+        PERMAP OFFSET(16) NUMBITS(5) [],
+        BURST_LENGTH OFFSET(12) NUMBITS(4) [],
+        SRC_IGNORE OFFSET(11) NUMBITS(1) [],
+        SRC_DREQ OFFSET(10) NUMBITS(1) [],
+        /// 128-bit reads
+        SRC_WIDTH OFFSET(9) NUMBITS(1) [],
+        SRC_INC OFFSET(8) NUMBITS(1) [],
+        DEST_IGNORE OFFSET(7) NUMBITS(1) [],
+        DEST_DREQ OFFSET(6) NUMBITS(1) [],
+        /// 128-bit writes
+        DEST_WIDTH OFFSET(5) NUMBITS(1) [],
+        DEST_INC OFFSET(4) NUMBITS(1) [],
+        WAIT_RESP OFFSET(3) NUMBITS(1) [],
+        /// 2D mode, full channels only
+        TDMODE OFFSET(1) NUMBITS(1) [],
+        INTEN OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Errors of a channel, write 1 to clear
+    DEBUG [
+        LITE OFFSET(28) NUMBITS(1) [],
+        READ_ERROR OFFSET(2) NUMBITS(1) [],
+        FIFO_ERROR OFFSET(1) NUMBITS(1) [],
+        READ_LAST_NOT_SET_ERROR OFFSET(0) NUMBITS(1) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    ChannelRegisterBlock {
+        (0x00 => CS: ReadWrite<u32, CS::Register>),
+        (0x04 => CONBLK_AD: ReadWrite<u32>),
+        /// The control block being run
+        (0x08 => TI: ReadOnly<u32, TI::Register>),
+        (0x0C => SOURCE_AD: ReadOnly<u32>),
+        (0x10 => DEST_AD: ReadOnly<u32>),
+        (0x14 => TXFR_LEN: ReadOnly<u32>),
+        (0x18 => STRIDE: ReadOnly<u32>),
+        (0x1C => NEXTCONBK: ReadOnly<u32>),
+        (0x20 => DEBUG: ReadWrite<u32, DEBUG::Register>),
+        (0x24 => _reserved1),
+        (0x100 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x000 => CHANNELS: [ChannelRegisterBlock; NUM_CHANNELS]),
+        (0xF00 => _reserved1),
+        /// One bit per channel with its interrupt raised
+        (0xFE0 => INT_STATUS: ReadOnly<u32>),
+        (0xFE4 => _reserved2),
+        /// One bit per channel, set to enable it
+        (0xFF0 => ENABLE: ReadWrite<u32>),
+        (0xFF4 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+const NUM_CHANNELS: usize = 15;
+
+/// Channels from this one are lite ones
+const FIRST_LITE_CHANNEL: usize = 7;
+
+/// TXFR_LEN of a full channel is 30 bits, of a lite channel 16 bits
+const MAX_LEN: usize = (1 << 30) - 1;
+const LITE_MAX_LEN: usize = (1 << 16) - 1;
+
+/// Bursts of memory to memory copies, in words of the bus width
+const MEM_BURST_LENGTH: u32 = 4;
+
+/// The priority on the AXI bus, and the one while the VideoCore panics (what Linux sets)
+const PRIORITY: u32 = 8;
+const PANIC_PRIORITY: u32 = 15;
+
+/// A stopped channel finishes its writes in a handful of cycles
+const ABORT_TIMEOUT: Duration = Duration::from_millis(1);
+
+const SELF_TEST_LEN: usize = 4096;
+const SELF_TEST_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Set by the completion interrupt of the self-test
+static SELF_TEST_DONE: AtomicBool = AtomicBool::new(false);
+
+/// A completion callback to call, with the result of its channel
+type FiredCallback = (CompletionCallback, Result<(), Error>);
+
+struct DmaInner {
+    registers: Registers,
+    /// The channels the VideoCore left us, one bit per channel
+    channel_mask: u32,
+    /// The channels handed out
+    allocated: u32,
+    callbacks: [Option<CompletionCallback>; NUM_CHANNELS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Called from the DMA interrupt handler with the channel, when a control block with an
+/// interrupt is done (`ControlBlock::with_interrupt`).
+pub type CompletionCallback = fn(channel: usize, result: Result<(), Error>);
+
+/// The peripherals that can pace a transfer with their DREQ signal
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dreq {
+    PcmTx = 2,
+    PcmRx = 3,
+    Pwm = 5,
+    SpiTx = 6,
+    SpiRx = 7,
+    Emmc = 11,
+    UartTx = 12,
+    SdHost = 13,
+    UartRx = 14,
+}
+
+/// A control block, read by the DMA from memory.
+///
+/// The addresses are bus addresses: nothing keeps the buffers alive, they must outlive the
+/// transfer.
+#[repr(C, align(32))]
+#[derive(Clone, Copy)]
+pub struct ControlBlock {
+    ti: u32,
+    source_ad: u32,
+    dest_ad: u32,
+    txfr_len: u32,
+    stride: u32,
+    nextconbk: u32,
+    _reserved: [u32; 2],
+}
+
+/// A channel, taken from the controller until dropped.
+pub struct DmaChannel {
+    dma: &'static Dma,
+    id: usize,
+}
+
+/// Represent the DMA controller.
+pub struct Dma {
+    inner: IRQSafeNullLock<DmaInner>,
+    /// The interrupts of the channels, by channel number
+    irq_numbers: &'static [IRQNumber],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn check_len(len: usize) -> Result<u32, Error> {
+    if len == 0 || len > MAX_LEN {
+        return Err(Error::new(ErrorKind::InvalidArgument, "Bad DMA transfer length"));
+    }
+
+    Ok(len as u32)
+}
+
+fn is_lite(channel: usize) -> bool {
+    channel >= FIRST_LITE_CHANNEL
+}
+
+fn self_test_done(_channel: usize, _result: Result<(), Error>) {
+    SELF_TEST_DONE.store(true, Ordering::Release);
+}
+
+impl ControlBlock {
+    const fn new(ti: u32, source_ad: u32, dest_ad: u32, txfr_len: u32) -> Self {
+        Self {
+            ti,
+            source_ad,
+            dest_ad,
+            txfr_len,
+            stride: 0,
+            nextconbk: 0,
+            _reserved: [0; 2],
+        }
+    }
+
+    fn bus_address(&self) -> u32 {
+        memory::phys_to_bus(self as *const _ as usize)
+    }
+
+    /// Where the block is in memory
+    fn addresses(&self) -> Range<usize> {
+        let start = self as *const _ as usize;
+
+        start..start + core::mem::size_of::<Self>()
+    }
+}
+
+impl DmaInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            channel_mask: 0,
+            allocated: 0,
+            callbacks: [None; NUM_CHANNELS],
+        }
+    }
+
+    fn channel(&self, id: usize) -> &ChannelRegisterBlock {
+        &self.registers.CHANNELS[id]
+    }
+
+    fn reset(&self, id: usize) {
+        self.channel(id).CS.write(CS::RESET::SET);
+        self.channel(id).CS.write(CS::INT::SET + CS::END::SET);
+        self.channel(id).DEBUG.write(
+            DEBUG::READ_ERROR::SET + DEBUG::FIFO_ERROR::SET + DEBUG::READ_LAST_NOT_SET_ERROR::SET,
+        );
+    }
+
+    fn allocate(&mut self) -> Result<usize, Error> {
+        let free = self.channel_mask & !self.allocated;
+        // Full channels first, the lite ones are slower
+        let id = (0..NUM_CHANNELS)
+            .find(|&x| free & (1 << x) != 0)
+            .ok_or(Error::new(ErrorKind::Busy, "No free DMA channel"))?;
+
+        self.allocated |= 1 << id;
+        self.callbacks[id] = None;
+        self.registers.ENABLE.set(self.registers.ENABLE.get() | (1 << id));
+        self.reset(id);
+
+        Ok(id)
+    }
+
+    fn free(&mut self, id: usize) {
+        self.abort(id);
+        self.allocated &= !(1 << id);
+        self.callbacks[id] = None;
+    }
+
+    fn start(&mut self, id: usize, first: u32, on_done: Option<CompletionCallback>) {
+        self.callbacks[id] = on_done;
+        self.channel(id).CS.write(CS::INT::SET + CS::END::SET);
+        self.channel(id).CONBLK_AD.set(first);
+        self.channel(id).CS.write(
+            CS::ACTIVE::SET
+                + CS::WAIT_FOR_OUTSTANDING_WRITES::SET
+                + CS::PRIORITY.val(PRIORITY)
+                + CS::PANIC_PRIORITY.val(PANIC_PRIORITY),
+        );
+    }
+
+    fn is_active(&self, id: usize) -> bool {
+        self.channel(id).CS.is_set(CS::ACTIVE)
+    }
+
+    /// The error of the last transfer, cleared
+    fn take_result(&self, id: usize) -> Result<(), Error> {
+        if !self.channel(id).CS.is_set(CS::ERROR) {
+            return Ok(());
+        }
+
+        let debug = self.channel(id).DEBUG.get();
+        self.channel(id).DEBUG.set(debug);
+        Err(Error::new(ErrorKind::Io, "DMA transfer error"))
+    }
+
+    /// Stop the channel, once its writes are done
+    fn abort(&self, id: usize) {
+        if !self.is_active(id) {
+            return;
+        }
+
+        // Pause, and let the writes on their way land
+        self.channel(id).CS.set(0);
+        let _ = time::time_manager().wait_until(
+            || !self.channel(id).CS.is_set(CS::WAITING_FOR_OUTSTANDING_WRITES),
+            ABORT_TIMEOUT,
+        );
+        self.reset(id);
+    }
+
+    /// Acknowledge the interrupts, and take the callbacks they call
+    fn take_interrupts(&mut self) -> [Option<FiredCallback>; NUM_CHANNELS] {
+        let status = self.registers.INT_STATUS.get() & self.allocated;
+        let mut fired = [None; NUM_CHANNELS];
+
+        for id in (0..NUM_CHANNELS).filter(|x| status & (1 << x) != 0) {
+            // A chain still running must keep its ACTIVE bit
+            let active = self.channel(id).CS.read(CS::ACTIVE);
+            self.channel(id)
+                .CS
+                .write(CS::INT::SET + CS::END::SET + CS::ACTIVE.val(active));
+
+            fired[id] = self.callbacks[id].map(|x| (x, self.take_result(id)));
+        }
+
+        fired
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl ControlBlock {
+    /// Copy `src` to `dest`, they must have the same length.
+    pub fn mem_to_mem(dest: &mut [u8], src: &[u8]) -> Result<Self, Error> {
+        if dest.len() != src.len() {
+            return Err(Error::new(ErrorKind::InvalidArgument, "DMA buffers differ in size"));
+        }
+        let ti = TI::SRC_INC::SET
+            + TI::DEST_INC::SET
+            + TI::WAIT_RESP::SET
+            + TI::BURST_LENGTH.val(MEM_BURST_LENGTH);
+
+        Ok(Self::new(
+            ti.value,
+            memory::phys_to_bus(src.as_ptr() as usize),
+            memory::phys_to_bus(dest.as_mut_ptr() as usize),
+            check_len(src.len())?,
+        ))
+    }
+
+    /// Write `src` to the register at bus address `dest`, paced by `dreq`.
+    #[allow(dead_code)]
+    pub fn mem_to_peripheral(src: &[u8], dest: u32, dreq: Dreq) -> Result<Self, Error> {
+        let ti = TI::SRC_INC::SET
+            + TI::DEST_DREQ::SET
+            + TI::WAIT_RESP::SET
+            + TI::PERMAP.val(dreq as u32);
+
+        Ok(Self::new(
+            ti.value,
+            memory::phys_to_bus(src.as_ptr() as usize),
+            dest,
+            check_len(src.len())?,
+        ))
+    }
+
+    /// Read the register at bus address `src` into `dest`, paced by `dreq`.
+    #[allow(dead_code)]
+    pub fn peripheral_to_mem(src: u32, dest: &mut [u8], dreq: Dreq) -> Result<Self, Error> {
+        let ti = TI::DEST_INC::SET + TI::SRC_DREQ::SET + TI::PERMAP.val(dreq as u32);
+
+        Ok(Self::new(
+            ti.value,
+            src,
+            memory::phys_to_bus(dest.as_mut_ptr() as usize),
+            check_len(dest.len())?,
+        ))
+    }
+
+    /// Raise the channel's interrupt once this block is done.
+    pub fn with_interrupt(mut self) -> Self {
+        self.ti |= TI::INTEN::SET.value;
+        self
+    }
+
+    /// Bytes moved by the block
+    pub fn len(&self) -> usize {
+        self.txfr_len as usize
+    }
+
+    /// Chain `blocks` in order, the last one ends the transfer.
+    pub fn link(blocks: &mut [ControlBlock]) {
+        for i in 1..blocks.len() {
+            blocks[i - 1].nextconbk = blocks[i].bus_address();
+        }
+        if let Some(last) = blocks.last_mut() {
+            last.nextconbk = 0;
+        }
+    }
+}
+
+impl DmaChannel {
+    /// The longest control block the channel takes
+    pub fn max_len(&self) -> usize {
+        if is_lite(self.id) {
+            LITE_MAX_LEN
+        } else {
+            MAX_LEN
+        }
+    }
+
+    /// Start the chain of control blocks beginning at `first`. `on_done` is called for each block
+    /// with an interrupt.
+    ///
+    /// # Safety
+    ///
+    /// - The blocks and their buffers must live until the channel is done (`wait`) or dropped.
+    /// - On a lite channel, no block moves more than `max_len` bytes, nor is in 2D mode.
+    /// - The blocks after `first` are cleaned from the data cache (`memory::cache`).
+    pub unsafe fn start(
+        &self,
+        first: &ControlBlock,
+        on_done: Option<CompletionCallback>,
+    ) -> Result<(), Error> {
+        self.dma.inner.lock(|inner| {
+            if inner.is_active(self.id) {
+                return Err(Error::new(ErrorKind::Busy, "DMA channel running"));
+            }
+
+            // The control blocks and the buffers must be in memory before the DMA reads them
+            fence(Ordering::SeqCst);
+            cache::clean_range(first.addresses());
+            inner.start(self.id, first.bus_address(), on_done);
+
+            Ok(())
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.dma.inner.lock(|inner| inner.is_active(self.id))
+    }
+
+    /// Wait for the end of the chain. It is stopped if it doesn't end within `timeout`.
+    pub fn wait(&self, timeout: Duration) -> Result<(), Error> {
+        let done = time::time_manager().wait_until(|| !self.is_active(), timeout);
+
+        self.dma.inner.lock(|inner| {
+            if done.is_err() {
+                inner.abort(self.id);
+                return Err(Error::new(ErrorKind::Timeout, "DMA transfer timed out"));
+            }
+
+            fence(Ordering::SeqCst);
+            inner.take_result(self.id)
+        })
+    }
+
+    /// Link `blocks`, run them and wait for the end.
+    pub fn transfer(&self, blocks: &mut [ControlBlock], timeout: Duration) -> Result<(), Error> {
+        if blocks.iter().any(|x| x.len() > self.max_len()) {
+            return Err(Error::new(ErrorKind::InvalidArgument, "DMA block too long for channel"));
+        }
+        ControlBlock::link(blocks);
+        let Some(first) = blocks.first() else {
+            return Ok(());
+        };
+        let range = blocks.as_ptr_range();
+        cache::clean_range(range.start as usize..range.end as usize);
+
+        // Safety: `wait` returns once the channel is stopped, the borrows outlive it
+        unsafe { self.start(first, None)? };
+        self.wait(timeout)
+    }
+}
+
+impl Drop for DmaChannel {
+    fn drop(&mut self) {
+        self.dma.inner.lock(|inner| inner.free(self.id));
+    }
+}
+
+impl Dma {
+    /// Create an instance of the DMA controller driver
+    /// `irq_numbers` are the interrupts of the channels, by channel number
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize, irq_numbers: &'static [IRQNumber]) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(DmaInner::new(mmio_start_addr)),
+            irq_numbers,
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// The channels the ARM may use, one bit per channel. Only the ones with their own interrupt
+    /// are kept. Call it before the driver's init.
+    pub fn set_channel_mask(&self, mask: u32) {
+        let with_irq = (1 << self.irq_numbers.len()) - 1;
+        self.inner.lock(|inner| inner.channel_mask = mask & with_irq);
+    }
+
+    /// Take a free channel, full ones first.
+    pub fn allocate_channel(&'static self) -> Result<DmaChannel, Error> {
+        let id = self.inner.lock(|inner| inner.allocate())?;
+
+        Ok(DmaChannel { dma: self, id })
+    }
+
+    /// Print the channels, and who has them.
+    pub fn dump(&self) {
+        let (mask, allocated) = self.inner.lock(|inner| (inner.channel_mask, inner.allocated));
+
+        for id in (0..NUM_CHANNELS).filter(|x| mask & (1 << x) != 0) {
+            println!(
+                "channel {:2}: {}{}",
+                id,
+                if allocated & (1 << id) != 0 { "in use" } else { "free" },
+                if is_lite(id) { " (lite)" } else { "" }
+            );
+        }
+    }
+
+    /// Copy random bytes with a chain of two control blocks, then again with one block and its
+    /// completion interrupt, and check the copies.
+    pub fn self_test(&'static self) -> Result<(), Error> {
+        let channel = self.allocate_channel()?;
+        let mut src = DmaBuffer::new(SELF_TEST_LEN)?;
+        let mut dest = DmaBuffer::new(SELF_TEST_LEN)?;
+        random::fill_bytes(&mut src);
+        src.sync_for_device(Direction::ToDevice);
+        dest.sync_for_device(Direction::FromDevice);
+
+        let (src_low, src_high) = src.split_at(SELF_TEST_LEN / 2);
+        let (dest_low, dest_high) = dest.split_at_mut(SELF_TEST_LEN / 2);
+        let mut blocks = [
+            ControlBlock::mem_to_mem(dest_low, src_low)?,
+            ControlBlock::mem_to_mem(dest_high, src_high)?,
+        ];
+        channel.transfer(&mut blocks, SELF_TEST_TIMEOUT)?;
+        dest.sync_for_cpu(Direction::FromDevice);
+        if *src != *dest {
+            return Err(Error::new(ErrorKind::Io, "DMA chained copy differs"));
+        }
+
+        random::fill_bytes(&mut src);
+        src.sync_for_device(Direction::ToDevice);
+        dest.sync_for_device(Direction::FromDevice);
+        let block = ControlBlock::mem_to_mem(&mut dest, &src)?.with_interrupt();
+        SELF_TEST_DONE.store(false, Ordering::Release);
+        // Safety: `wait` returns once the channel is stopped, before the block goes
+        unsafe { channel.start(&block, Some(self_test_done))? };
+        channel.wait(SELF_TEST_TIMEOUT)?;
+
+        time::time_manager()
+            .wait_until(|| SELF_TEST_DONE.load(Ordering::Acquire), SELF_TEST_TIMEOUT)
+            .map_err(|_| Error::new(ErrorKind::Timeout, "No DMA completion interrupt"))?;
+        dest.sync_for_cpu(Direction::FromDevice);
+        if *src != *dest {
+            return Err(Error::new(ErrorKind::Io, "DMA copy differs"));
+        }
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Dma {
+    fn compatible(&self) -> &'static str {
+        "BCM DMA Controller Device driver version 1.0"
+    }
+
+    /// Stop what the firmware or a previous kernel left running on our channels
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| {
+            for id in (0..NUM_CHANNELS).filter(|x| inner.channel_mask & (1 << x) != 0) {
+                inner.abort(id);
+                inner.reset(id);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.init()
+    }
+
+    fn register_and_enable_irq_handler(&'static self) -> Result<(), Error> {
+        use exception::asynchronous::irq_manager;
+
+        let mask = self.inner.lock(|inner| inner.channel_mask);
+        for (id, irq_number) in self.irq_numbers.iter().enumerate() {
+            if mask & (1 << id) == 0 {
+                continue;
+            }
+            let descriptor = IRQHandlerDescriptor::new(*irq_number, "BCM DMA", self);
+
+            irq_manager().register_handler(descriptor)?;
+            irq_manager().enable(irq_number);
+        }
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for Dma {
+    fn handle(&self) -> Result<(), Error> {
+        let fired = self.inner.lock(|inner| inner.take_interrupts());
+
+        // The callbacks run outside of the lock, they may start the next transfer
+        for (channel, fired) in fired.into_iter().enumerate() {
+            if let Some((callback, result)) = fired {
+                callback(channel, result);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,850 @@
+//! BCM2xxx EMMC (SDHCI) driver for the SD card.
+//!
+//! The BCM2837 EMMC block is an Arasan SDHCI controller, the BCM2711 EMMC2 block (the one wired to
+//! the SD card slot on the Pi 4) is SDHCI compatible as well. The driver is polled: it identifies
+//! the card, switches it to 4-bit bus and high-speed mode when supported, and reads/writes blocks
+//! with single or multi-block transfers (CMD17/18, CMD24/25 with auto CMD12).
+//!
+//! Without a card (i.e. QEMU without `-drive if=sd,file=<img>`) `init()` succeeds but the block
+//! device is left unregistered.
+//!
+//! References:
+//! - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf (chapter 5)
+//! - SD Physical Layer Simplified Specification and SD Host Controller Simplified Specification
+//!   (https://www.sdcard.org/downloads/pls/)
+
+use super::{tag, Mailbox, PropertyMessage};
+use crate::{
+    block,
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    info, synchronization::interface::Mutex,
+    synchronization::NullLock,
+    time, warn,
+};
+use core::time::Duration;
+
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+// EMMC registers.
+//
+// Descriptions taken from the BCM2837 peripherals PDF (chapter 5). The layout follows the
+// standard SDHCI one.
+
+register_bitfields! {
+    u32, // 32 bits wide
+
+    /// Block size and count of data transfers
+    BLKSIZECNT [
+        /// Number of blocks to transfer
+        BLKCNT OFFSET(16) NUMBITS(16) [],
+
+        /// Block size in bytes
+        BLKSIZE OFFSET(0) NUMBITS(10) []
+    ],
+
+    /// Command and transfer mode
+    CMDTM [
+        /// Command index
+        CMD_INDEX OFFSET(24) NUMBITS(6) [],
+
+        /// The command involves a data transfer
+        CMD_ISDATA OFFSET(21) NUMBITS(1) [],
+
+        /// Check the response's command index
+        CMD_IXCHK_EN OFFSET(20) NUMBITS(1) [],
+
+        /// Check the response's CRC
+        CMD_CRCCHK_EN OFFSET(19) NUMBITS(1) [],
+
+        /// Response type
+        CMD_RSPNS_TYPE OFFSET(16) NUMBITS(2) [
+            None = 0b00,
+            Bits136 = 0b01,
+            Bits48 = 0b10,
+            Bits48Busy = 0b11
+        ],
+
+        /// Multiple block transfer
+        TM_MULTI_BLOCK OFFSET(5) NUMBITS(1) [],
+
+        /// Direction of the data transfer
+        TM_DAT_DIR OFFSET(4) NUMBITS(1) [
+            HostToCard = 0,
+            CardToHost = 1
+        ],
+
+        /// Command to send after the data transfer
+        TM_AUTO_CMD_EN OFFSET(2) NUMBITS(2) [
+            None = 0b00,
+            Cmd12 = 0b01
+        ],
+
+        /// Enable the block counter
+        TM_BLKCNT_EN OFFSET(1) NUMBITS(1) []
+    ],
+
+    /// Status
+    STATUS [
+        /// The data lines are in use
+        DAT_INHIBIT OFFSET(1) NUMBITS(1) [],
+
+        /// The command line is in use
+        CMD_INHIBIT OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Host configuration bits
+    CONTROL0 [
+        /// High speed mode
+        HCTL_HS_EN OFFSET(2) NUMBITS(1) [],
+
+        /// Use 4 data lines
+        HCTL_DWIDTH OFFSET(1) NUMBITS(1) []
+    ],
+
+    /// Host configuration bits
+    CONTROL1 [
+        /// Reset the data handling circuit
+        SRST_DATA OFFSET(26) NUMBITS(1) [],
+
+        /// Reset the command handling circuit
+        SRST_CMD OFFSET(25) NUMBITS(1) [],
+
+        /// Reset the complete host circuit
+        SRST_HC OFFSET(24) NUMBITS(1) [],
+
+        /// Data timeout unit exponent (TMCLK * 2^(x+13))
+        DATA_TOUNIT OFFSET(16) NUMBITS(4) [
+            Max = 0b1110
+        ],
+
+        /// Clock divider, lower 8 bits
+        CLK_FREQ8 OFFSET(8) NUMBITS(8) [],
+
+        /// Clock divider, upper 2 bits
+        CLK_FREQ_MS2 OFFSET(6) NUMBITS(2) [],
+
+        /// Enable the SD clock
+        CLK_EN OFFSET(2) NUMBITS(1) [],
+
+        /// The SD clock is stable
+        CLK_STABLE OFFSET(1) NUMBITS(1) [],
+
+        /// Enable the internal clock
+        CLK_INTLEN OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Interrupt flags (write 1 to clear)
+    INTERRUPT [
+        /// Command timeout
+        CTO_ERR OFFSET(16) NUMBITS(1) [],
+
+        /// An error happened (details in the upper 16 bits)
+        ERR OFFSET(15) NUMBITS(1) [],
+
+        /// DATA holds a block to read
+        READ_RDY OFFSET(5) NUMBITS(1) [],
+
+        /// DATA can take a block to write
+        WRITE_RDY OFFSET(4) NUMBITS(1) [],
+
+        /// Data transfer has finished
+        DATA_DONE OFFSET(1) NUMBITS(1) [],
+
+        /// Command has finished
+        CMD_DONE OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Slot interrupt status and version
+    SLOTISR_VER [
+        /// Host controller specification version (0: v1, 1: v2, 2: v3)
+        SDVERSION OFFSET(16) NUMBITS(8) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x04 => BLKSIZECNT: ReadWrite<u32, BLKSIZECNT::Register>),
+        (0x08 => ARG1: ReadWrite<u32>),
+        (0x0C => CMDTM: ReadWrite<u32, CMDTM::Register>),
+        (0x10 => RESP: [ReadOnly<u32>; 4]),
+        (0x20 => DATA: ReadWrite<u32>),
+        (0x24 => STATUS: ReadOnly<u32, STATUS::Register>),
+        (0x28 => CONTROL0: ReadWrite<u32, CONTROL0::Register>),
+        (0x2C => CONTROL1: ReadWrite<u32, CONTROL1::Register>),
+        (0x30 => INTERRUPT: ReadWrite<u32, INTERRUPT::Register>),
+        (0x34 => IRPT_MASK: ReadWrite<u32>),
+        (0x38 => IRPT_EN: ReadWrite<u32>),
+        (0x3C => _reserved2),
+        (0xFC => SLOTISR_VER: ReadOnly<u32, SLOTISR_VER::Register>),
+        (0x100 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Clock during card identification
+const IDENTIFICATION_CLOCK_HZ: u32 = 400_000;
+/// Clock in default speed mode
+const DEFAULT_SPEED_CLOCK_HZ: u32 = 25_000_000;
+/// Clock in high speed mode
+const HIGH_SPEED_CLOCK_HZ: u32 = 50_000_000;
+
+/// Timeouts
+const RESET_TIMEOUT: Duration = Duration::from_millis(100);
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(100);
+const DATA_TIMEOUT: Duration = Duration::from_millis(1000);
+const OP_COND_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// All the interrupt flags, for clearing
+const ALL_INTERRUPTS: u32 = u32::MAX;
+
+/// CMD8 argument: 2.7-3.6V and a check pattern the card echoes back
+const IF_COND_ARG: u32 = 0x1AA;
+/// ACMD41 argument: supported voltage window (2.7-3.6V)
+const OCR_VOLTAGE_WINDOW: u32 = 0x00FF_8000;
+/// ACMD41: host supports high capacity cards (request) / card is high capacity (response)
+const OCR_HCS: u32 = 1 << 30;
+/// ACMD41 response: the card finished its power up
+const OCR_READY: u32 = 1 << 31;
+/// CMD6 argument: switch function group 1 (access mode) to high speed
+const SWITCH_HIGH_SPEED_ARG: u32 = 0x80FF_FFF1;
+/// ACMD6 argument: 4-bit bus
+const BUS_WIDTH_4_ARG: u32 = 0b10;
+
+/// Size of the SCR register (ACMD51 data)
+const SCR_SIZE: usize = 8;
+/// Size of the CMD6 switch function status
+const SWITCH_STATUS_SIZE: usize = 64;
+
+/// Max blocks per transfer (BLKCNT is 16 bits)
+const MAX_BLOCKS_PER_TRANSFER: usize = 0xFFFF;
+
+/// Response type of a command
+#[derive(Clone, Copy, PartialEq)]
+enum Response {
+    None,
+    /// R2
+    Bits136,
+    /// R1, R6, R7
+    Bits48,
+    /// R3, no CRC or index to check
+    Bits48NoCheck,
+    /// R1b
+    Bits48Busy,
+}
+
+/// Data transfer of a command
+#[derive(Clone, Copy, PartialEq)]
+enum Transfer {
+    None,
+    Read,
+    ReadMulti,
+    Write,
+    WriteMulti,
+}
+
+/// A SD command
+struct Command {
+    index: u32,
+    response: Response,
+    transfer: Transfer,
+}
+
+impl Command {
+    const fn new(index: u32, response: Response, transfer: Transfer) -> Self {
+        Self {
+            index,
+            response,
+            transfer,
+        }
+    }
+}
+
+// The commands we use. ACMDs must follow an APP_CMD.
+const GO_IDLE_STATE: Command = Command::new(0, Response::None, Transfer::None);
+const ALL_SEND_CID: Command = Command::new(2, Response::Bits136, Transfer::None);
+const SEND_RELATIVE_ADDR: Command = Command::new(3, Response::Bits48, Transfer::None);
+const SWITCH_FUNC: Command = Command::new(6, Response::Bits48, Transfer::Read);
+const SELECT_CARD: Command = Command::new(7, Response::Bits48Busy, Transfer::None);
+const SEND_IF_COND: Command = Command::new(8, Response::Bits48, Transfer::None);
+const SEND_CSD: Command = Command::new(9, Response::Bits136, Transfer::None);
+const SET_BLOCKLEN: Command = Command::new(16, Response::Bits48, Transfer::None);
+const READ_SINGLE_BLOCK: Command = Command::new(17, Response::Bits48, Transfer::Read);
+const READ_MULTIPLE_BLOCK: Command = Command::new(18, Response::Bits48, Transfer::ReadMulti);
+const WRITE_BLOCK: Command = Command::new(24, Response::Bits48, Transfer::Write);
+const WRITE_MULTIPLE_BLOCK: Command = Command::new(25, Response::Bits48, Transfer::WriteMulti);
+const APP_CMD: Command = Command::new(55, Response::Bits48, Transfer::None);
+const SET_BUS_WIDTH: Command = Command::new(6, Response::Bits48, Transfer::None);
+const SD_SEND_OP_COND: Command = Command::new(41, Response::Bits48NoCheck, Transfer::None);
+const SEND_SCR: Command = Command::new(51, Response::Bits48, Transfer::Read);
+
+/// What we learned about the card during identification
+struct Card {
+    rca: u32,
+    // SDHC/SDXC cards are addressed in blocks, SDSC cards in bytes
+    high_capacity: bool,
+    num_blocks: u64,
+}
+
+/// Number of blocks from the CSD register (as read by SEND_CSD, without the CRC byte).
+fn csd_num_blocks(csd: u128) -> u64 {
+    // The SDHCI response registers hold CSD bits [127:8], shifted down by 8
+    let field = |msb: u32, lsb: u32| (csd >> (lsb - 8)) & ((1 << (msb - lsb + 1)) - 1);
+
+    match field(127, 126) {
+        // CSD version 1.0 (SDSC)
+        0 => {
+            let c_size = field(73, 62) as u64;
+            let c_size_mult = field(49, 47) as u32;
+            let read_bl_len = field(83, 80) as u32;
+
+            let bytes = (c_size + 1) << (c_size_mult + 2 + read_bl_len);
+            bytes / block::BLOCK_SIZE as u64
+        }
+        // CSD version 2.0 (SDHC/SDXC): capacity is (C_SIZE + 1) * 512KiB
+        _ => (field(69, 48) as u64 + 1) * 1024,
+    }
+}
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+pub struct EmmcInner {
+    registers: Registers,
+    mailbox: &'static Mailbox,
+    // mailbox clock id of the controller's base clock
+    clock_id: u32,
+    base_clock_hz: u32,
+    card: Option<Card>,
+}
+
+/// Represent the EMMC (SD card) hardware.
+pub struct Emmc {
+    inner: NullLock<EmmcInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl EmmcInner {
+    /// Create EmmcInner instance
+    ///
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    pub const unsafe fn new(mmio_start_addr: usize, mailbox: &'static Mailbox, clock_id: u32) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            mailbox,
+            clock_id,
+            base_clock_hz: 0,
+            card: None,
+        }
+    }
+
+    /// Reset the controller and identify the card
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.base_clock_hz = self.get_base_clock()?;
+        self.reset()?;
+
+        match self.init_card() {
+            Ok(card) => {
+                info!(
+                    "      SD card: {} MiB, {}",
+                    card.num_blocks / 2048,
+                    if card.high_capacity { "SDHC/SDXC" } else { "SDSC" }
+                );
+                self.card = Some(card);
+            }
+            // Not a driver failure, there is simply no (usable) card in the slot
+            Err(e) if e.kind() == ErrorKind::NotPresent => info!("      No SD card"),
+            Err(e) => warn!("SD card not available: {}", e),
+        }
+
+        Ok(())
+    }
+
+    /// Ask the firmware for the controller's base clock
+    fn get_base_clock(&self) -> Result<u32, Error> {
+        let mut message = PropertyMessage::new();
+        message.add_tag(tag::GET_CLOCK_RATE, 2, &[self.clock_id])?;
+        self.mailbox.call(&mut message)?;
+
+        match message.response(tag::GET_CLOCK_RATE) {
+            Some([_, rate]) if *rate != 0 => Ok(*rate),
+            _ => Err(Error::new(ErrorKind::Io, "EMMC base clock unknown")),
+        }
+    }
+
+    /// Reset the host controller, set the identification clock
+    fn reset(&mut self) -> Result<(), Error> {
+        self.registers.CONTROL0.set(0);
+        self.registers.CONTROL1.write(CONTROL1::SRST_HC::SET);
+        time::time_manager().wait_until(
+            || !self.registers.CONTROL1.is_set(CONTROL1::SRST_HC),
+            RESET_TIMEOUT,
+        )
+        .map_err(|_| Error::new(ErrorKind::Timeout, "EMMC reset timeout"))?;
+
+        self.registers
+            .CONTROL1
+            .write(CONTROL1::CLK_INTLEN::SET + CONTROL1::DATA_TOUNIT::Max);
+        self.set_clock(IDENTIFICATION_CLOCK_HZ)?;
+
+        // Polled driver: report every flag in INTERRUPT, but don't raise the IRQ line
+        self.registers.IRPT_EN.set(0);
+        self.registers.IRPT_MASK.set(ALL_INTERRUPTS);
+        self.registers.INTERRUPT.set(ALL_INTERRUPTS);
+
+        Ok(())
+    }
+
+    /// Reset the host controller, which stops the SD clock, and forget the card
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.card = None;
+        self.registers.CONTROL1.write(CONTROL1::SRST_HC::SET);
+        time::time_manager().wait_until(
+            || !self.registers.CONTROL1.is_set(CONTROL1::SRST_HC),
+            RESET_TIMEOUT,
+        )
+        .map_err(|_| Error::new(ErrorKind::Timeout, "EMMC reset timeout"))
+    }
+
+    /// Set the SD clock to the closest frequency at or below `hz`
+    fn set_clock(&mut self, hz: u32) -> Result<(), Error> {
+        time::time_manager().wait_until(
+            || {
+                !self.registers.STATUS.is_set(STATUS::CMD_INHIBIT)
+                    && !self.registers.STATUS.is_set(STATUS::DAT_INHIBIT)
+            },
+            COMMAND_TIMEOUT,
+        )
+        .map_err(|_| Error::new(ErrorKind::Busy, "EMMC busy, can't change the clock"))?;
+
+        self.registers.CONTROL1.modify(CONTROL1::CLK_EN::CLEAR);
+
+        // SD clock = base clock / (2 * divider), divider 0 is the base clock
+        let divider = if hz >= self.base_clock_hz {
+            0
+        } else {
+            self.base_clock_hz.div_ceil(2 * hz)
+        };
+        // v1/v2 controllers only have the 8 bit divider
+        let max_divider = match self.registers.SLOTISR_VER.read(SLOTISR_VER::SDVERSION) {
+            0 | 1 => 0xFF,
+            _ => 0x3FF,
+        };
+        let divider = divider.min(max_divider);
+
+        self.registers.CONTROL1.modify(
+            CONTROL1::CLK_FREQ8.val(divider & 0xFF) + CONTROL1::CLK_FREQ_MS2.val(divider >> 8),
+        );
+        time::time_manager().wait_until(
+            || self.registers.CONTROL1.is_set(CONTROL1::CLK_STABLE),
+            RESET_TIMEOUT,
+        )
+        .map_err(|_| Error::new(ErrorKind::Timeout, "EMMC clock not stable"))?;
+
+        self.registers.CONTROL1.modify(CONTROL1::CLK_EN::SET);
+
+        Ok(())
+    }
+
+    /// Reset the command (and data) circuit after an error
+    fn reset_lines(&mut self, data: bool) {
+        let lines = if data {
+            CONTROL1::SRST_CMD::SET + CONTROL1::SRST_DATA::SET
+        } else {
+            CONTROL1::SRST_CMD::SET
+        };
+
+        self.registers.CONTROL1.modify(lines);
+        // Nothing more we can do if it doesn't come back, the next command will fail
+        let _ = time::time_manager().wait_until(
+            || {
+                !self.registers.CONTROL1.is_set(CONTROL1::SRST_CMD)
+                    && !self.registers.CONTROL1.is_set(CONTROL1::SRST_DATA)
+            },
+            RESET_TIMEOUT,
+        );
+        self.registers.INTERRUPT.set(ALL_INTERRUPTS);
+    }
+
+    /// Wait for one of the `flags` in INTERRUPT and clear it
+    fn wait_interrupt(&mut self, flags: u32, timeout: Duration) -> Result<(), Error> {
+        let error = INTERRUPT::ERR::SET.value;
+
+        let result = time::time_manager().wait_until(
+            || self.registers.INTERRUPT.get() & (flags | error) != 0,
+            timeout,
+        );
+        let status = self.registers.INTERRUPT.get();
+
+        if result.is_err() || status & error != 0 {
+            let data = flags & INTERRUPT::CMD_DONE::SET.value == 0;
+            self.reset_lines(data);
+
+            return Err(match (result, status & INTERRUPT::CTO_ERR::SET.value != 0) {
+                (Err(_), _) => Error::new(ErrorKind::Timeout, "SD timeout"),
+                (_, true) => Error::new(ErrorKind::NotPresent, "SD command timeout (no card?)"),
+                _ => Error::new(ErrorKind::Io, "SD command error"),
+            });
+        }
+
+        self.registers.INTERRUPT.set(status & flags);
+        Ok(())
+    }
+
+    /// Send a command and wait for its response. Returns the first response word.
+    fn command(&mut self, cmd: &Command, arg: u32) -> Result<u32, Error> {
+        let uses_data = cmd.transfer != Transfer::None || cmd.response == Response::Bits48Busy;
+
+        time::time_manager().wait_until(
+            || {
+                !self.registers.STATUS.is_set(STATUS::CMD_INHIBIT)
+                    && !(uses_data && self.registers.STATUS.is_set(STATUS::DAT_INHIBIT))
+            },
+            COMMAND_TIMEOUT,
+        )
+        .map_err(|_| Error::new(ErrorKind::Busy, "SD card busy"))?;
+
+        let mut cmdtm = CMDTM::CMD_INDEX.val(cmd.index);
+        cmdtm += match cmd.response {
+            Response::None => CMDTM::CMD_RSPNS_TYPE::None,
+            Response::Bits136 => CMDTM::CMD_RSPNS_TYPE::Bits136 + CMDTM::CMD_CRCCHK_EN::SET,
+            Response::Bits48NoCheck => CMDTM::CMD_RSPNS_TYPE::Bits48,
+            Response::Bits48 => {
+                CMDTM::CMD_RSPNS_TYPE::Bits48 + CMDTM::CMD_CRCCHK_EN::SET + CMDTM::CMD_IXCHK_EN::SET
+            }
+            Response::Bits48Busy => {
+                CMDTM::CMD_RSPNS_TYPE::Bits48Busy
+                    + CMDTM::CMD_CRCCHK_EN::SET
+                    + CMDTM::CMD_IXCHK_EN::SET
+            }
+        };
+        if cmd.transfer != Transfer::None {
+            cmdtm += CMDTM::CMD_ISDATA::SET + CMDTM::TM_BLKCNT_EN::SET;
+        }
+        cmdtm += match cmd.transfer {
+            Transfer::None => CMDTM::TM_DAT_DIR::HostToCard,
+            Transfer::Read => CMDTM::TM_DAT_DIR::CardToHost,
+            Transfer::Write => CMDTM::TM_DAT_DIR::HostToCard,
+            Transfer::ReadMulti => {
+                CMDTM::TM_DAT_DIR::CardToHost
+                    + CMDTM::TM_MULTI_BLOCK::SET
+                    + CMDTM::TM_AUTO_CMD_EN::Cmd12
+            }
+            Transfer::WriteMulti => {
+                CMDTM::TM_DAT_DIR::HostToCard
+                    + CMDTM::TM_MULTI_BLOCK::SET
+                    + CMDTM::TM_AUTO_CMD_EN::Cmd12
+            }
+        };
+
+        self.registers.INTERRUPT.set(ALL_INTERRUPTS);
+        self.registers.ARG1.set(arg);
+        self.registers.CMDTM.write(cmdtm);
+
+        self.wait_interrupt(INTERRUPT::CMD_DONE::SET.value, COMMAND_TIMEOUT)?;
+
+        // R1b: the card holds DAT0 low while busy
+        if cmd.response == Response::Bits48Busy {
+            self.wait_interrupt(INTERRUPT::DATA_DONE::SET.value, DATA_TIMEOUT)?;
+        }
+
+        Ok(self.registers.RESP[0].get())
+    }
+
+    /// Send an application specific command (ACMD) that has no data
+    fn app_command(&mut self, cmd: &Command, arg: u32) -> Result<u32, Error> {
+        let rca = self.card.as_ref().map_or(0, |card| card.rca);
+
+        self.command(&APP_CMD, rca << 16)?;
+        self.command(cmd, arg)
+    }
+
+    /// The 136 bit response of the last command (without the CRC byte)
+    fn long_response(&self) -> u128 {
+        self.registers
+            .RESP
+            .iter()
+            .rev()
+            .fold(0, |acc, word| (acc << 32) | u128::from(word.get()))
+    }
+
+    /// Send a command that reads `buf.len() / block_size` blocks
+    fn read_data(
+        &mut self,
+        cmd: &Command,
+        arg: u32,
+        buf: &mut [u8],
+        block_size: usize,
+    ) -> Result<(), Error> {
+        let count = buf.len() / block_size;
+
+        self.registers.BLKSIZECNT.write(
+            BLKSIZECNT::BLKSIZE.val(block_size as u32) + BLKSIZECNT::BLKCNT.val(count as u32),
+        );
+        self.command(cmd, arg)?;
+
+        for block in buf.chunks_exact_mut(block_size) {
+            self.wait_interrupt(INTERRUPT::READ_RDY::SET.value, DATA_TIMEOUT)?;
+
+            for word in block.as_chunks_mut::<4>().0 {
+                *word = self.registers.DATA.get().to_le_bytes();
+            }
+        }
+
+        self.wait_interrupt(INTERRUPT::DATA_DONE::SET.value, DATA_TIMEOUT)
+    }
+
+    /// Send a command that writes `buf.len() / block_size` blocks
+    fn write_data(
+        &mut self,
+        cmd: &Command,
+        arg: u32,
+        buf: &[u8],
+        block_size: usize,
+    ) -> Result<(), Error> {
+        let count = buf.len() / block_size;
+
+        self.registers.BLKSIZECNT.write(
+            BLKSIZECNT::BLKSIZE.val(block_size as u32) + BLKSIZECNT::BLKCNT.val(count as u32),
+        );
+        self.command(cmd, arg)?;
+
+        for block in buf.chunks_exact(block_size) {
+            self.wait_interrupt(INTERRUPT::WRITE_RDY::SET.value, DATA_TIMEOUT)?;
+
+            for word in block.as_chunks::<4>().0 {
+                self.registers.DATA.set(u32::from_le_bytes(*word));
+            }
+        }
+
+        self.wait_interrupt(INTERRUPT::DATA_DONE::SET.value, DATA_TIMEOUT)
+    }
+
+    /// Card identification, then switch to the fastest mode both sides support
+    fn init_card(&mut self) -> Result<Card, Error> {
+        self.card = None;
+        self.command(&GO_IDLE_STATE, 0)?;
+
+        // Only v2.00+ cards answer CMD8, and only those may be high capacity
+        let v2 = match self.command(&SEND_IF_COND, IF_COND_ARG) {
+            Ok(response) if response & 0xFFF == IF_COND_ARG => true,
+            Ok(_) => {
+                return Err(Error::new(ErrorKind::Unsupported, "SD card voltage not supported"))
+            }
+            Err(_) => false,
+        };
+
+        // Wait for the card to power up
+        let hcs = if v2 { OCR_HCS } else { 0 };
+        let start = time::Instant::now();
+        let ocr = loop {
+            let ocr = self.app_command(&SD_SEND_OP_COND, OCR_VOLTAGE_WINDOW | hcs)?;
+            if ocr & OCR_READY != 0 {
+                break ocr;
+            }
+            if start.elapsed() > OP_COND_TIMEOUT {
+                return Err(Error::new(ErrorKind::Timeout, "SD card power up timeout"));
+            }
+            time::time_manager().spin_for_duration(Duration::from_millis(10));
+        };
+
+        self.command(&ALL_SEND_CID, 0)?;
+        let rca = self.command(&SEND_RELATIVE_ADDR, 0)? >> 16;
+
+        self.command(&SEND_CSD, rca << 16)?;
+        let num_blocks = csd_num_blocks(self.long_response());
+
+        self.command(&SELECT_CARD, rca << 16)?;
+        self.card = Some(Card {
+            rca,
+            high_capacity: ocr & OCR_HCS != 0,
+            num_blocks,
+        });
+        self.set_clock(DEFAULT_SPEED_CLOCK_HZ)?;
+
+        // SDSC cards may have another default block length
+        self.command(&SET_BLOCKLEN, block::BLOCK_SIZE as u32)?;
+
+        // The SCR tells the spec version and the supported bus widths (big endian)
+        let mut scr = [0u8; SCR_SIZE];
+        self.command(&APP_CMD, rca << 16)?;
+        self.read_data(&SEND_SCR, 0, &mut scr, SCR_SIZE)?;
+
+        if scr[1] & 0x4 != 0 {
+            self.app_command(&SET_BUS_WIDTH, BUS_WIDTH_4_ARG)?;
+            self.registers.CONTROL0.modify(CONTROL0::HCTL_DWIDTH::SET);
+        }
+
+        // CMD6 exists from spec version 1.10 on
+        if scr[0] & 0xF >= 1 {
+            let mut status = [0u8; SWITCH_STATUS_SIZE];
+            self.read_data(&SWITCH_FUNC, SWITCH_HIGH_SPEED_ARG, &mut status, SWITCH_STATUS_SIZE)?;
+
+            // function group 1 result, status bits [379:376]
+            if status[16] & 0xF == 1 {
+                self.registers.CONTROL0.modify(CONTROL0::HCTL_HS_EN::SET);
+                self.set_clock(HIGH_SPEED_CLOCK_HZ)?;
+            }
+        }
+
+        // init() puts it back once everything went well
+        self.card
+            .take()
+            .ok_or(Error::new(ErrorKind::NotPresent, "SD card lost"))
+    }
+
+    /// The command argument addressing a block
+    fn block_address(&self, lba: u64) -> Result<u32, Error> {
+        let high_capacity = self.card.as_ref().is_some_and(|card| card.high_capacity);
+        let address = if high_capacity {
+            lba
+        } else {
+            lba * block::BLOCK_SIZE as u64
+        };
+
+        u32::try_from(address)
+            .map_err(|_| Error::new(ErrorKind::InvalidArgument, "Block address out of range"))
+    }
+
+    /// Check that the card is there and the blocks are on it
+    fn check_range(&self, lba: u64, count: usize) -> Result<(), Error> {
+        let card = self
+            .card
+            .as_ref()
+            .ok_or(Error::new(ErrorKind::NotPresent, "No SD card"))?;
+
+        if lba
+            .checked_add(count as u64)
+            .is_none_or(|end| end > card.num_blocks)
+        {
+            return Err(Error::new(ErrorKind::InvalidArgument, "Block out of range"));
+        }
+
+        Ok(())
+    }
+
+    /// Read blocks, in transfers of up to 0xFFFF blocks
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(lba, block::blocks_in(buf.len())?)?;
+
+        let mut lba = lba;
+        for chunk in buf.chunks_mut(MAX_BLOCKS_PER_TRANSFER * block::BLOCK_SIZE) {
+            let cmd = if chunk.len() == block::BLOCK_SIZE {
+                &READ_SINGLE_BLOCK
+            } else {
+                &READ_MULTIPLE_BLOCK
+            };
+
+            let address = self.block_address(lba)?;
+            self.read_data(cmd, address, chunk, block::BLOCK_SIZE)?;
+            lba += (chunk.len() / block::BLOCK_SIZE) as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Write blocks, in transfers of up to 0xFFFF blocks
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), Error> {
+        self.check_range(lba, block::blocks_in(buf.len())?)?;
+
+        let mut lba = lba;
+        for chunk in buf.chunks(MAX_BLOCKS_PER_TRANSFER * block::BLOCK_SIZE) {
+            let cmd = if chunk.len() == block::BLOCK_SIZE {
+                &WRITE_BLOCK
+            } else {
+                &WRITE_MULTIPLE_BLOCK
+            };
+
+            let address = self.block_address(lba)?;
+            self.write_data(cmd, address, chunk, block::BLOCK_SIZE)?;
+            lba += (chunk.len() / block::BLOCK_SIZE) as u64;
+        }
+
+        Ok(())
+    }
+}
+
+impl Emmc {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    /// - `clock_id` is the mailbox clock id of the controller (EMMC or EMMC2)
+    pub const unsafe fn new(mmio_start_addr: usize, mailbox: &'static Mailbox, clock_id: u32) -> Self {
+        Self {
+            inner: NullLock::new(EmmcInner::new(mmio_start_addr, mailbox, clock_id)),
+        }
+    }
+
+    /// Select the controller of the SD card slot (EMMC or EMMC2) and its mailbox clock id. Call
+    /// it before the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_controller(&self, mmio_start_addr: usize, clock_id: u32) {
+        self.inner.lock(|inner| {
+            inner.registers = Registers::new(mmio_start_addr);
+            inner.clock_id = clock_id;
+        });
+    }
+
+    /// True if a card was found during init
+    pub fn has_card(&self) -> bool {
+        self.inner.lock(|inner| inner.card.is_some())
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Emmc {
+    fn compatible(&self) -> &'static str {
+        "BCM EMMC (SDHCI) Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init())
+    }
+
+    // The driver is synchronous, nothing is in flight when this runs
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.shutdown())
+    }
+}
+
+impl block::interface::BlockDevice for Emmc {
+    fn num_blocks(&self) -> u64 {
+        self.inner
+            .lock(|inner| inner.card.as_ref().map_or(0, |card| card.num_blocks))
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.read_blocks(lba, buf))
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.write_blocks(lba, buf))
+    }
+}
@@ -0,0 +1,463 @@
+//! VideoCore framebuffer driver with a text console.
+//!
+//! The framebuffer is allocated by the firmware through the mailbox property interface. On top
+//! of it we render text with a built-in bitmap font, scroll when the screen is full and
+//! understand a small subset of the ANSI escape sequences:
+//! - `ESC[...m`: reset, bold/bright, foreground and background colors (30-37, 40-47, 90-97, 100-107)
+//! - `ESC[2J`: clear the screen
+//! - `ESC[H`, `ESC[<row>;<col>H`: move the cursor
+//!
+//! On QEMU, check the output with the monitor's `screendump` command.
+
+mod font;
+
+use super::{tag, Mailbox, PropertyMessage};
+use crate::{
+    bsp::memory,
+    console, cpu, driver,
+    error::{Error, ErrorKind},
+    synchronization::interface::Mutex,
+    synchronization::NullLock,
+};
+use core::fmt;
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+/// Bits per pixel. We only support 32 bit, one `u32` per pixel.
+const DEPTH: u32 = 32;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Pixel order values of the SET_PIXEL_ORDER tag
+const PIXEL_ORDER_RGB: u32 = 1;
+
+/// Alignment requested for the framebuffer allocation
+const FRAMEBUFFER_ALIGNMENT: u32 = 4096;
+
+const TAB_WIDTH: usize = 8;
+
+/// The 16 ANSI colors (normal and bright), as 0xRRGGBB.
+const ANSI_PALETTE: [u32; 16] = [
+    0x00_00_00, 0xAA_00_00, 0x00_AA_00, 0xAA_55_00, 0x00_00_AA, 0xAA_00_AA, 0x00_AA_AA, 0xAA_AA_AA,
+    0x55_55_55, 0xFF_55_55, 0x55_FF_55, 0xFF_FF_55, 0x55_55_FF, 0xFF_55_FF, 0x55_FF_FF, 0xFF_FF_FF,
+];
+
+/// Default colors, palette indices
+const DEFAULT_FOREGROUND: usize = 7;
+const DEFAULT_BACKGROUND: usize = 0;
+
+/// Max number of numeric parameters in a CSI sequence we keep, the rest are dropped
+const MAX_ANSI_PARAMS: usize = 4;
+
+/// Escape sequence parser state
+#[derive(PartialEq)]
+enum AnsiState {
+    /// Regular characters
+    Normal,
+    /// Got ESC
+    Escape,
+    /// Got ESC[, collecting parameters
+    Csi,
+}
+
+/// What the firmware gave us
+struct FramebufferInfo {
+    base: usize,
+    width: usize,
+    height: usize,
+    pitch: usize,
+    rgb: bool,
+}
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+pub struct FramebufferInner {
+    mailbox: &'static Mailbox,
+    requested_width: u32,
+    requested_height: u32,
+    // None until the framebuffer is allocated
+    info: Option<FramebufferInfo>,
+    column: usize,
+    row: usize,
+    foreground: usize,
+    background: usize,
+    bright: bool,
+    ansi_state: AnsiState,
+    ansi_params: [u16; MAX_ANSI_PARAMS],
+    ansi_param_index: usize,
+    chars_written: usize,
+}
+
+/// Represent the framebuffer (HDMI output).
+pub struct Framebuffer {
+    inner: NullLock<FramebufferInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl FramebufferInner {
+    /// Create FramebufferInner instance. Nothing is allocated before `init()`.
+    pub const fn new(mailbox: &'static Mailbox, width: u32, height: u32) -> Self {
+        Self {
+            mailbox,
+            requested_width: width,
+            requested_height: height,
+            info: None,
+            column: 0,
+            row: 0,
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bright: false,
+            ansi_state: AnsiState::Normal,
+            ansi_params: [0; MAX_ANSI_PARAMS],
+            ansi_param_index: 0,
+            chars_written: 0,
+        }
+    }
+
+    /// Ask the firmware for a framebuffer and clear it.
+    pub fn init(&mut self) -> Result<(), Error> {
+        let (width, height) = (self.requested_width, self.requested_height);
+        let mut message = PropertyMessage::new();
+
+        message.add_tag(tag::SET_PHYSICAL_SIZE, 2, &[width, height])?;
+        message.add_tag(tag::SET_VIRTUAL_SIZE, 2, &[width, height])?;
+        message.add_tag(tag::SET_VIRTUAL_OFFSET, 2, &[0, 0])?;
+        message.add_tag(tag::SET_DEPTH, 1, &[DEPTH])?;
+        message.add_tag(tag::SET_PIXEL_ORDER, 1, &[PIXEL_ORDER_RGB])?;
+        message.add_tag(tag::ALLOCATE_BUFFER, 2, &[FRAMEBUFFER_ALIGNMENT])?;
+        message.add_tag(tag::GET_PITCH, 1, &[])?;
+
+        self.mailbox.call(&mut message)?;
+
+        let value = |tag: u32, index: usize| {
+            message
+                .response(tag)
+                .and_then(|values| values.get(index).copied())
+                .ok_or(Error::new(
+                    ErrorKind::Io,
+                    "Framebuffer: missing response from the firmware",
+                ))
+        };
+
+        if value(tag::SET_DEPTH, 0)? != DEPTH {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Framebuffer: 32 bit depth not supported",
+            ));
+        }
+
+        let base = value(tag::ALLOCATE_BUFFER, 0)?;
+        if base == 0 {
+            return Err(Error::new(ErrorKind::OutOfMemory, "Framebuffer: allocation failed"));
+        }
+
+        self.info = Some(FramebufferInfo {
+            base: memory::bus_to_phys(base),
+            width: value(tag::SET_VIRTUAL_SIZE, 0)? as usize,
+            height: value(tag::SET_VIRTUAL_SIZE, 1)? as usize,
+            pitch: value(tag::GET_PITCH, 0)? as usize,
+            rgb: value(tag::SET_PIXEL_ORDER, 0)? == PIXEL_ORDER_RGB,
+        });
+
+        self.clear_screen();
+        Ok(())
+    }
+
+    /// Number of text columns and rows
+    fn text_size(&self) -> (usize, usize) {
+        match &self.info {
+            Some(info) => (
+                info.width / font::GLYPH_WIDTH,
+                info.height / font::GLYPH_HEIGHT,
+            ),
+            None => (0, 0),
+        }
+    }
+
+    /// Convert a palette index to the framebuffer's pixel format
+    fn pixel(&self, palette_index: usize) -> u32 {
+        let color = ANSI_PALETTE[palette_index];
+
+        match &self.info {
+            // 0xRRGGBB in little endian is B, G, R in memory. Swap for RGB.
+            Some(info) if info.rgb => {
+                (color & 0x00_FF_00) | ((color >> 16) & 0xFF) | ((color & 0xFF) << 16)
+            }
+            _ => color,
+        }
+    }
+
+    /// The foreground color, bold makes the 8 normal colors bright
+    fn foreground_pixel(&self) -> u32 {
+        if self.bright && self.foreground < 8 {
+            self.pixel(self.foreground + 8)
+        } else {
+            self.pixel(self.foreground)
+        }
+    }
+
+    /// Fill a rectangle (in pixels) with a color
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, pixel: u32) {
+        let Some(info) = &self.info else {
+            return;
+        };
+
+        for line in y..y + height {
+            let line_start = info.base + line * info.pitch;
+            for column in x..x + width {
+                let address = line_start + column * BYTES_PER_PIXEL;
+                unsafe { core::ptr::write_volatile(address as *mut u32, pixel) };
+            }
+        }
+    }
+
+    fn clear_screen(&mut self) {
+        if let Some(info) = &self.info {
+            let (width, height) = (info.width, info.height);
+            self.fill(0, 0, width, height, self.pixel(self.background));
+        }
+        self.column = 0;
+        self.row = 0;
+    }
+
+    /// Draw a character at the cursor position
+    fn draw_glyph(&mut self, c: char) {
+        let Some(info) = &self.info else {
+            return;
+        };
+
+        let foreground = self.foreground_pixel();
+        let background = self.pixel(self.background);
+        let x = self.column * font::GLYPH_WIDTH;
+        let y = self.row * font::GLYPH_HEIGHT;
+
+        for (glyph_line, bits) in font::glyph(c).iter().enumerate() {
+            let line_start = info.base + (y + glyph_line) * info.pitch + x * BYTES_PER_PIXEL;
+            for glyph_column in 0..font::GLYPH_WIDTH {
+                let pixel = if bits & (0x80 >> glyph_column) != 0 {
+                    foreground
+                } else {
+                    background
+                };
+                let address = line_start + glyph_column * BYTES_PER_PIXEL;
+                unsafe { core::ptr::write_volatile(address as *mut u32, pixel) };
+            }
+        }
+    }
+
+    /// Move all text rows one up and clear the last one
+    fn scroll(&mut self) {
+        let (_, rows) = self.text_size();
+        let Some(info) = &self.info else {
+            return;
+        };
+        // a screen smaller than a glyph has no text row
+        if rows == 0 {
+            return;
+        }
+
+        let row_bytes = font::GLYPH_HEIGHT * info.pitch;
+        unsafe {
+            core::ptr::copy(
+                (info.base + row_bytes) as *const u8,
+                info.base as *mut u8,
+                (rows - 1) * row_bytes,
+            );
+        }
+
+        let width = info.width;
+        self.fill(
+            0,
+            (rows - 1) * font::GLYPH_HEIGHT,
+            width,
+            font::GLYPH_HEIGHT,
+            self.pixel(self.background),
+        );
+    }
+
+    fn new_line(&mut self) {
+        let (_, rows) = self.text_size();
+
+        self.column = 0;
+        self.row += 1;
+        if self.row >= rows {
+            self.scroll();
+            self.row = rows.saturating_sub(1);
+        }
+    }
+
+    /// Apply a "select graphic rendition" (`ESC[...m`) sequence
+    fn select_graphic_rendition(&mut self) {
+        for &param in &self.ansi_params[..=self.ansi_param_index] {
+            match param {
+                0 => {
+                    self.foreground = DEFAULT_FOREGROUND;
+                    self.background = DEFAULT_BACKGROUND;
+                    self.bright = false;
+                }
+                1 => self.bright = true,
+                22 => self.bright = false,
+                30..=37 => self.foreground = (param - 30) as usize,
+                39 => self.foreground = DEFAULT_FOREGROUND,
+                40..=47 => self.background = (param - 40) as usize,
+                49 => self.background = DEFAULT_BACKGROUND,
+                90..=97 => self.foreground = (param - 90) as usize + 8,
+                100..=107 => self.background = (param - 100) as usize + 8,
+                _ => {}
+            }
+        }
+    }
+
+    /// Handle the final character of a CSI sequence
+    fn finish_csi(&mut self, c: char) {
+        let (columns, rows) = self.text_size();
+
+        match c {
+            'm' => self.select_graphic_rendition(),
+            'J' if self.ansi_params[0] == 2 => self.clear_screen(),
+            'H' => {
+                // 1-based, 0 is the same as 1
+                self.row = (self.ansi_params[0].max(1) as usize - 1).min(rows.saturating_sub(1));
+                self.column =
+                    (self.ansi_params[1].max(1) as usize - 1).min(columns.saturating_sub(1));
+            }
+            _ => {}
+        }
+    }
+
+    /// Write Char
+    fn write_char(&mut self, c: char) {
+        // Nothing to draw on (yet), drop it
+        if self.info.is_none() {
+            return;
+        }
+
+        match self.ansi_state {
+            AnsiState::Normal => self.write_plain_char(c),
+            AnsiState::Escape => {
+                self.ansi_state = if c == '[' {
+                    self.ansi_params = [0; MAX_ANSI_PARAMS];
+                    self.ansi_param_index = 0;
+                    AnsiState::Csi
+                } else {
+                    AnsiState::Normal
+                };
+            }
+            AnsiState::Csi => match c {
+                '0'..='9' => {
+                    let param = &mut self.ansi_params[self.ansi_param_index];
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add(c as u16 - '0' as u16);
+                }
+                ';' => {
+                    self.ansi_param_index = (self.ansi_param_index + 1).min(MAX_ANSI_PARAMS - 1);
+                }
+                _ => {
+                    self.finish_csi(c);
+                    self.ansi_state = AnsiState::Normal;
+                }
+            },
+        }
+
+        self.chars_written += 1;
+    }
+
+    /// Write a character that is not part of an escape sequence
+    fn write_plain_char(&mut self, c: char) {
+        let (columns, _) = self.text_size();
+        // a screen narrower than a glyph has no text column
+        if columns == 0 {
+            return;
+        }
+
+        match c {
+            '\x1b' => self.ansi_state = AnsiState::Escape,
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => {
+                self.column = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                if self.column >= columns {
+                    self.new_line();
+                }
+            }
+            _ => {
+                self.draw_glyph(c);
+                self.column += 1;
+                if self.column >= columns {
+                    self.new_line();
+                }
+            }
+        }
+    }
+}
+
+/// See the PL011 driver: `write_str()` gives us `write_fmt()` for the print macros.
+impl fmt::Write for FramebufferInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+        Ok(())
+    }
+}
+
+impl Framebuffer {
+    /// Create new instance, the framebuffer will be `width`x`height` pixels.
+    pub const fn new(mailbox: &'static Mailbox, width: u32, height: u32) -> Self {
+        Self {
+            inner: NullLock::new(FramebufferInner::new(mailbox, width, height)),
+        }
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Framebuffer {
+    fn compatible(&self) -> &'static str {
+        "BCM VideoCore Framebuffer Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init())
+    }
+}
+
+impl console::interface::Write for Framebuffer {
+    fn write_char(&self, c: char) {
+        self.inner.lock(|inner| inner.write_char(c));
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+    }
+
+    // Pixels are written straight to memory, nothing is queued
+    fn flush(&self) {}
+}
+
+impl console::interface::Read for Framebuffer {
+    /// A screen has no input. Reading blocks forever.
+    fn read_char(&self) -> char {
+        cpu::wait_forever()
+    }
+
+    fn clear_rx(&self) {}
+}
+
+impl console::interface::Statistics for Framebuffer {
+    fn chars_written(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_written)
+    }
+}
+
+impl console::interface::All for Framebuffer {}
@@ -6,14 +6,20 @@
  */
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, driver, synchronization::interface::Mutex,
-    synchronization::NullLock,
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    println, synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock, time, warn,
 };
+use core::{fmt, time::Duration};
 
 use tock_registers::{
-    interfaces::{ReadWriteable, Writeable},
+    interfaces::{Readable, Writeable},
     register_bitfields, register_structs,
-    registers::ReadWrite,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
 };
 
 //----------------------------------------
@@ -24,30 +30,17 @@ use tock_registers::{
 //
 // Descriptions taken from 
 // - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf -> this is great
+// - https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf (pull up/down registers)
+//
+// Most of the registers hold one bit (GPSET, GPCLR, GPLEV...), two bits (GPIO_PUP_PDN_CNTRL_REG)
+// or three bits (GPFSEL) per pin and are split in banks, so they are accessed as arrays and the
+// pin's field is computed at runtime instead of being described with bitfields.
 
 register_bitfields! {
     u32, // 32 bits wide
 
-    // GPIO Function select register 1 (controls pins 14&15 among others)
-    GPFSEL1 [
-        // pin 14 (offset 12)
-        FSEL14 OFFSET(12) NUMBITS(3) [
-            // possible values for the field
-            input = 0b000,
-            output = 0b001,
-            altFunc0 = 0b100 // uart is in mode0
-        ],
-
-        FSEL15 OFFSET(15) NUMBITS(3) [
-            // possible values for the field
-            input = 0b000,
-            output = 0b001,
-            altFunc0 = 0b100 // uart is in mode0
-        ],
-    ],
-
     // GPIO Pull up/down register
-    // used in conjunction with GPPUDCLK0/1/2
+    // used in conjunction with GPPUDCLK0/1
     GPPUD [
         // pin up/down
         PUD OFFSET(0) NUMBITS(2) [
@@ -55,63 +48,123 @@ register_bitfields! {
             pullDown = 0b01,
             pullUp = 0b10
         ]
-    ],
-
-    // GPIO Pull Up/down clock register 0, to control pins 14&15
-    GPPUDCLK0 [
-        // assert clock on pin n
-        PUDCLK14 OFFSET(14) NUMBITS(1) [
-            noEffect = 0b0,
-            assertClock = 0b1
-        ],
-
-        PUDCLK15 OFFSET(15) NUMBITS(1) [
-            noEffect = 0b0,
-            assertClock = 0b1
-        ]
-    ],
-
-    /// GPIO Pull-up / Pull-down Register 0
-    ///
-    /// BCM2711 only.
-    GPIO_PUP_PDN_CNTRL_REG0 [
-        /// Pin 15
-        GPIO_PUP_PDN_CNTRL15 OFFSET(30) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ],
-
-        /// Pin 14
-        GPIO_PUP_PDN_CNTRL14 OFFSET(28) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ]
     ]
 }
 
 register_structs! {
     #[allow(non_snake_case)]
     RegisterBlock {
-        (0x00 => _reserved1),
-        (0x04 => GPFSEL1: ReadWrite<u32, GPFSEL1::Register>),
-        (0x08 => _reserved2),
+        // function select, 10 pins per register, 3 bits per pin
+        (0x00 => GPFSEL: [ReadWrite<u32>; 6]),
+        (0x18 => _reserved1),
+        // output set / clear, 32 pins per register
+        (0x1C => GPSET: [WriteOnly<u32>; 2]),
+        (0x24 => _reserved2),
+        (0x28 => GPCLR: [WriteOnly<u32>; 2]),
+        (0x30 => _reserved3),
+        // pin level, 32 pins per register
+        (0x34 => GPLEV: [ReadOnly<u32>; 2]),
+        (0x3C => _reserved4),
+        // event detect status, write 1 to clear
+        (0x40 => GPEDS: [ReadWrite<u32>; 2]),
+        (0x48 => _reserved5),
+        // event detect enables: rising/falling edge, high/low level, async rising/falling edge
+        (0x4C => GPREN: [ReadWrite<u32>; 2]),
+        (0x54 => _reserved6),
+        (0x58 => GPFEN: [ReadWrite<u32>; 2]),
+        (0x60 => _reserved7),
+        (0x64 => GPHEN: [ReadWrite<u32>; 2]),
+        (0x6C => _reserved8),
+        (0x70 => GPLEN: [ReadWrite<u32>; 2]),
+        (0x78 => _reserved9),
+        (0x7C => GPAREN: [ReadWrite<u32>; 2]),
+        (0x84 => _reserved10),
+        (0x88 => GPAFEN: [ReadWrite<u32>; 2]),
+        (0x90 => _reserved11),
+        // BCM2837 pull up/down
         (0x94 => GPPUD: ReadWrite<u32, GPPUD::Register>),
-        (0x98 => GPPUDCLK0: ReadWrite<u32, GPPUDCLK0::Register>),
-        (0x9C => _reserved3),
-        (0xE4 => GPIO_PUP_PDN_CNTRL_REG0: ReadWrite<u32, GPIO_PUP_PDN_CNTRL_REG0::Register>),
-        (0xE8 => @END),
+        (0x98 => GPPUDCLK: [ReadWrite<u32>; 2]),
+        (0xA0 => _reserved12),
+        // BCM2711 pull up/down, 16 pins per register, 2 bits per pin
+        (0xE4 => GPIO_PUP_PDN_CNTRL_REG: [ReadWrite<u32>; 4]),
+        (0xF4 => @END),
     }
 }
 
 // abtracts the register calling
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// Number of GPIO pins of the BCM2837/BCM2711 (bank 0 and 1)
+pub const NUM_PINS: u8 = 54;
+
 //----------------------------------------
 // public stuff
 //----------------------------------------
 
+// The pin API below is meant for the other drivers and the kernel, which do not use all of it (yet).
+
+/// The function of a pin, as encoded in GPFSEL.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PinFunction {
+    Input = 0b000,
+    Output = 0b001,
+    Alt0 = 0b100,
+    Alt1 = 0b101,
+    Alt2 = 0b110,
+    Alt3 = 0b111,
+    Alt4 = 0b011,
+    Alt5 = 0b010,
+}
+
+/// Pull resistor configuration of a pin.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+/// Pin events the GPIO can detect (and interrupt on).
+///
+/// The synchronous edge events sample the pin with the system clock, the asynchronous ones do not
+/// and can catch very short pulses. Level events keep firing as long as the level holds, so their
+/// callback should disable them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PinEvent {
+    RisingEdge,
+    FallingEdge,
+    High,
+    Low,
+    AsyncRisingEdge,
+    AsyncFallingEdge,
+}
+
+/// How the pull resistors are programmed. The BCM2837 clocks a control signal into the pads, the
+/// BCM2711 has a register with two bits per pin (and can read them back).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PullScheme {
+    Bcm2837,
+    Bcm2711,
+}
+
+/// Called from the GPIO interrupt handler with the number of the pin that had an event.
+pub type PinEventCallback = fn(pin: u8);
+
+/// A registered pin event callback
+#[derive(Clone, Copy)]
+struct PinEventHandler {
+    callback: PinEventCallback,
+    // events closer than this to the last accepted one are dropped
+    debounce: Duration,
+    last_event: Option<time::Instant>,
+}
+
 pub struct GPIOInner {
     registers: Registers,
+    pull_scheme: PullScheme,
+    // one bit per pin, set while the pin is owned by someone
+    claimed: u64,
+    handlers: [Option<PinEventHandler>; NUM_PINS as usize],
 }
 
 // Export the inner part for panic to use.
@@ -121,8 +174,60 @@ pub use GPIOInner as PanicGPIO;
 /// Repersent the GPIO Hardware.
 pub struct GPIO {
     // more than possible that two or more cores will try to access the gpio,
-    // so it is only logical to put a lock on it
-    inner: NullLock<GPIOInner>,
+    // so it is only logical to put a lock on it. The interrupt handler uses it too.
+    inner: IRQSafeNullLock<GPIOInner>,
+    irq_numbers: &'static [IRQNumber],
+}
+
+/// A claimed GPIO pin.
+///
+/// A pin can only be claimed once (see [`GPIO::claim`]), so holding a `Pin` means nobody else
+/// drives it. The claim is released when the handle is dropped.
+pub struct Pin {
+    number: u8,
+    gpio: &'static GPIO,
+}
+
+impl PinFunction {
+    fn from_bits(bits: u32) -> Self {
+        match bits & 0b111 {
+            0b000 => Self::Input,
+            0b001 => Self::Output,
+            0b100 => Self::Alt0,
+            0b101 => Self::Alt1,
+            0b110 => Self::Alt2,
+            0b111 => Self::Alt3,
+            0b011 => Self::Alt4,
+            _ => Self::Alt5,
+        }
+    }
+}
+
+impl fmt::Display for PinFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Input => "input",
+            Self::Output => "output",
+            Self::Alt0 => "alt0",
+            Self::Alt1 => "alt1",
+            Self::Alt2 => "alt2",
+            Self::Alt3 => "alt3",
+            Self::Alt4 => "alt4",
+            Self::Alt5 => "alt5",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for Pull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::None => "none",
+            Self::Up => "up",
+            Self::Down => "down",
+        };
+        f.pad(name)
+    }
 }
 
 // GPIO inner implementations
@@ -133,14 +238,44 @@ impl GPIOInner {
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
             registers: Registers::new(mmio_start_addr),
+            pull_scheme: PullScheme::Bcm2837,
+            claimed: 0,
+            handlers: [None; NUM_PINS as usize],
         }
     }
 
-    /// Disable pull-up/down on pins 14 and 15
-    #[cfg(feature = "bsp_rpi3")]
-    fn disable_pud_14_15_bcm2837(&mut self) {
-        use crate::time;
-        use core::time::Duration;
+    /// Select the function of a pin
+    fn set_function(&mut self, pin: u8, function: PinFunction) {
+        let reg = &self.registers.GPFSEL[pin as usize / 10];
+        let shift = (pin as u32 % 10) * 3;
+
+        reg.set((reg.get() & !(0b111 << shift)) | ((function as u32) << shift));
+    }
+
+    /// Read the current function of a pin
+    fn function(&self, pin: u8) -> PinFunction {
+        let shift = (pin as u32 % 10) * 3;
+
+        PinFunction::from_bits(self.registers.GPFSEL[pin as usize / 10].get() >> shift)
+    }
+
+    /// Drive an output pin high
+    fn set(&mut self, pin: u8) {
+        self.registers.GPSET[pin as usize / 32].set(1 << (pin % 32));
+    }
+
+    /// Drive an output pin low
+    fn clear(&mut self, pin: u8) {
+        self.registers.GPCLR[pin as usize / 32].set(1 << (pin % 32));
+    }
+
+    /// Read the level of a pin (true is high), whatever its function
+    fn level(&self, pin: u8) -> bool {
+        self.registers.GPLEV[pin as usize / 32].get() & (1 << (pin % 32)) != 0
+    }
+
+    /// Set the pull resistor of the pins in `mask` of bank `bank`
+    fn set_pull_bcm2837(&mut self, bank: usize, mask: u32, pull: Pull) {
         // 1. Write to GPPUD to set the required control signal (i.e. Pull-up or Pull-Down or neither
         // to remove the current Pull-up/down)
         // 2. Wait 150 cycles – this provides the required set-up time for the control signal
@@ -161,28 +296,170 @@ impl GPIOInner {
         // kernel does).
         const DELAY: Duration = Duration::from_micros(1);
 
-        // turn off pull up/down
-        self.registers.GPPUD.write(GPPUD::PUD::off);
+        let control = match pull {
+            Pull::None => GPPUD::PUD::off,
+            Pull::Up => GPPUD::PUD::pullUp,
+            Pull::Down => GPPUD::PUD::pullDown,
+        };
+
+        // set the control signal
+        self.registers.GPPUD.write(control);
         // wait
         time::time_manager().spin_for_duration(DELAY);
-        // assert clock on pins 14 and 15
-        self.registers
-            .GPPUDCLK0
-            .write(GPPUDCLK0::PUDCLK14::assertClock + GPPUDCLK0::PUDCLK15::assertClock);
+        // assert clock on the pins
+        self.registers.GPPUDCLK[bank].set(mask);
         // wait
         time::time_manager().spin_for_duration(DELAY);
-        // turn off pull up/down
+        // remove the control signal
         self.registers.GPPUD.write(GPPUD::PUD::off);
-        // write to GPPUDCLK0 to remove clock
-        self.registers.GPPUDCLK0.set(0);
+        // write to GPPUDCLK to remove clock
+        self.registers.GPPUDCLK[bank].set(0);
+    }
+
+    /// The register and the shift of a pin's 2 bits in GPIO_PUP_PDN_CNTRL_REG (16 pins each)
+    fn pull_field_bcm2711(&self, pin: u8) -> (&ReadWrite<u32>, u32) {
+        (
+            &self.registers.GPIO_PUP_PDN_CNTRL_REG[pin as usize / 16],
+            (pin as u32 % 16) * 2,
+        )
+    }
+
+    /// Set the pull resistor of a pin, and check it took.
+    ///
+    /// BCM2711 peripherals 5.2, GPIO_PUP_PDN_CNTRL_REG: 00 no resistor, 01 pull up, 10 pull down
+    /// (careful, not the same encoding as GPPUD). Unlike the BCM2837 there is no clocking
+    /// sequence, the write applies it.
+    fn set_pull_bcm2711(&mut self, pin: u8, pull: Pull) {
+        let (reg, shift) = self.pull_field_bcm2711(pin);
+        let bits = match pull {
+            Pull::None => 0b00,
+            Pull::Up => 0b01,
+            Pull::Down => 0b10,
+        };
+
+        reg.set((reg.get() & !(0b11 << shift)) | (bits << shift));
+
+        if self.pull(pin) != Some(pull) {
+            warn!("GPIO {}: pull {} did not take", pin, pull);
+        }
+    }
+
+    /// Read back the pull resistor of a pin. Only the BCM2711 can do that.
+    fn pull(&self, pin: u8) -> Option<Pull> {
+        if self.pull_scheme != PullScheme::Bcm2711 {
+            return None;
+        }
+        let (reg, shift) = self.pull_field_bcm2711(pin);
+
+        match (reg.get() >> shift) & 0b11 {
+            0b01 => Some(Pull::Up),
+            0b10 => Some(Pull::Down),
+            // 0b11 is reserved
+            _ => Some(Pull::None),
+        }
+    }
+
+    /// Set the pull resistor of a pin with the board's scheme
+    fn set_pull(&mut self, pin: u8, pull: Pull) {
+        match self.pull_scheme {
+            PullScheme::Bcm2837 => self.set_pull_bcm2837(pin as usize / 32, 1 << (pin % 32), pull),
+            PullScheme::Bcm2711 => self.set_pull_bcm2711(pin, pull),
+        }
+    }
+
+    /// The detect enable register of an event
+    fn event_register(&self, event: PinEvent) -> &[ReadWrite<u32>; 2] {
+        match event {
+            PinEvent::RisingEdge => &self.registers.GPREN,
+            PinEvent::FallingEdge => &self.registers.GPFEN,
+            PinEvent::High => &self.registers.GPHEN,
+            PinEvent::Low => &self.registers.GPLEN,
+            PinEvent::AsyncRisingEdge => &self.registers.GPAREN,
+            PinEvent::AsyncFallingEdge => &self.registers.GPAFEN,
+        }
+    }
+
+    /// Enable or disable the detection of an event on a pin
+    fn set_event_detect(&mut self, pin: u8, event: PinEvent, enable: bool) {
+        let reg = &self.event_register(event)[pin as usize / 32];
+        let mask = 1 << (pin % 32);
+
+        if enable {
+            reg.set(reg.get() | mask);
+        } else {
+            reg.set(reg.get() & !mask);
+        }
+    }
+
+    /// Clear a detected event of a pin
+    fn clear_event(&mut self, pin: u8) {
+        self.registers.GPEDS[pin as usize / 32].set(1 << (pin % 32));
+    }
+
+    /// Read and clear all the detected events, one bit per pin
+    fn take_events(&mut self) -> u64 {
+        let events = (u64::from(self.registers.GPEDS[1].get()) << 32)
+            | u64::from(self.registers.GPEDS[0].get());
+
+        // write 1 to clear
+        self.registers.GPEDS[0].set(events as u32);
+        self.registers.GPEDS[1].set((events >> 32) as u32);
+
+        events
+    }
+
+    /// Stop detecting events on a pin and forget its callback
+    fn reset_events(&mut self, pin: u8) {
+        for event in [
+            PinEvent::RisingEdge,
+            PinEvent::FallingEdge,
+            PinEvent::High,
+            PinEvent::Low,
+            PinEvent::AsyncRisingEdge,
+            PinEvent::AsyncFallingEdge,
+        ] {
+            self.set_event_detect(pin, event, false);
+        }
+        self.clear_event(pin);
+        self.handlers[pin as usize] = None;
+    }
+
+    /// Take the pending events and return the pins whose callback should run.
+    ///
+    /// Events of pins without a callback, or inside the debounce window, are dropped.
+    fn dispatch_events(&mut self, now: time::Instant) -> u64 {
+        let mut events = self.take_events();
+        let mut fired = 0;
+
+        while events != 0 {
+            let pin = events.trailing_zeros() as usize;
+            events &= !(1 << pin);
+
+            if let Some(handler) = self.handlers.get_mut(pin).and_then(|x| x.as_mut()) {
+                let bouncing = matches!(handler.last_event,
+                    Some(last) if now.duration_since(last) < handler.debounce);
+
+                if !bouncing {
+                    handler.last_event = Some(now);
+                    fired |= 1 << pin;
+                }
+            }
+        }
+
+        fired
     }
-    /// Disable pull-up/down on pins 14 and 15. - copied, not tested
-    #[cfg(feature = "bsp_rpi4")]
-    fn disable_pud_14_15_bcm2711(&mut self) {
-        self.registers.GPIO_PUP_PDN_CNTRL_REG0.write(
-            GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL15::PullUp
-                + GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL14::PullUp,
-        );
+
+    /// Mark a pin as owned
+    fn claim(&mut self, pin: u8) -> Result<(), Error> {
+        if pin >= NUM_PINS {
+            return Err(Error::new(ErrorKind::InvalidArgument, "GPIO pin number out of range"));
+        }
+        if self.claimed & (1 << pin) != 0 {
+            return Err(Error::new(ErrorKind::Busy, "GPIO pin already claimed"));
+        }
+
+        self.claimed |= 1 << pin;
+        Ok(())
     }
 
     /// Choose alt function 0 for pins 14, 15
@@ -190,33 +467,198 @@ impl GPIOInner {
     /// TX - pin 15
     /// RX - pin 14
     pub fn init_pl011_uart_pins(&mut self) {
-        self.registers
-            .GPFSEL1
-            .modify(GPFSEL1::FSEL14::altFunc0 + GPFSEL1::FSEL15::altFunc0);
+        self.init_uart_pins(PinFunction::Alt0);
+    }
 
-        #[cfg(feature = "bsp_rpi3")]
-        self.disable_pud_14_15_bcm2837();
+    /// Choose alt function 5 for pins 14, 15 (mini UART)
+    /// and disaple pull up/down
+    /// TX - pin 14
+    /// RX - pin 15
+    pub fn init_mini_uart_pins(&mut self) {
+        self.init_uart_pins(PinFunction::Alt5);
+    }
+
+    /// Route pins 14, 15 to a UART and take them so nobody can claim them later
+    fn init_uart_pins(&mut self, function: PinFunction) {
+        self.set_function(14, function);
+        self.set_function(15, function);
+
+        self.disable_pud_14_15();
+
+        self.claimed |= (1 << 14) | (1 << 15);
+    }
 
-        #[cfg(feature = "bsp_rpi4")]
-        self.disable_pud_14_15_bcm2711();
+    /// Disable pull-up/down on pins 14 and 15 with the board's scheme
+    fn disable_pud_14_15(&mut self) {
+        match self.pull_scheme {
+            PullScheme::Bcm2837 => {
+                self.set_pull_bcm2837(0, (1 << 14) | (1 << 15), Pull::None);
+            }
+            // The BCM2711 setup we copied pulls the lines up, keep it that way
+            PullScheme::Bcm2711 => {
+                for pin in [14, 15] {
+                    self.set_pull_bcm2711(pin, Pull::Up);
+                }
+            }
+        }
     }
 }
 
 impl GPIO {
     /// Create an instance of GPIO device driver
+    /// `irq_numbers` are the GPIO bank interrupts
     /// # Safety
     /// - User must ensure validity of the mmio start address
-    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+    pub const unsafe fn new(mmio_start_addr: usize, irq_numbers: &'static [IRQNumber]) -> Self {
         Self {
-            inner: NullLock::new(GPIOInner::new(mmio_start_addr)),
+            inner: IRQSafeNullLock::new(GPIOInner::new(mmio_start_addr)),
+            irq_numbers,
         }
     }
 
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// Select how the pull resistors are programmed, the board's SoC decides. Call it before
+    /// the driver's init.
+    pub fn set_pull_scheme(&self, pull_scheme: PullScheme) {
+        self.inner.lock(|inner| inner.pull_scheme = pull_scheme);
+    }
+
     /// init gpio uart pins
     /// same as the inner method, but with wrapping lock.
     pub fn init_gpio_uart_pins(&self) {
         self.inner.lock(|inner| inner.init_pl011_uart_pins());
     }
+
+    /// init gpio mini uart pins
+    /// same as the inner method, but with wrapping lock.
+    pub fn init_gpio_mini_uart_pins(&self) {
+        self.inner.lock(|inner| inner.init_mini_uart_pins());
+    }
+
+    /// Take ownership of a pin.
+    ///
+    /// Fails if the pin does not exist or is already owned (the console UART owns 14 and 15).
+    pub fn claim(&'static self, number: u8) -> Result<Pin, Error> {
+        self.inner.lock(|inner| inner.claim(number))?;
+
+        Ok(Pin { number, gpio: self })
+    }
+
+    /// Print the function, level and owner of every pin
+    pub fn dump(&self) {
+        println!("GPIO pins:");
+        for pin in 0..NUM_PINS {
+            let (function, level, pull, claimed) = self.inner.lock(|inner| {
+                (
+                    inner.function(pin),
+                    inner.level(pin),
+                    inner.pull(pin),
+                    inner.claimed & (1 << pin) != 0,
+                )
+            });
+            let level = if level { "high" } else { "low" };
+            let claimed = if claimed { "claimed" } else { "" };
+
+            match pull {
+                Some(pull) => println!(
+                    "  {:>2}: {:<6} {:<4} pull {:<4} {}",
+                    pin, function, level, pull, claimed
+                ),
+                None => println!("  {:>2}: {:<6} {:<4} {}", pin, function, level, claimed),
+            }
+        }
+    }
+}
+
+impl Pin {
+    /// Select the pin's function
+    pub fn set_function(&mut self, function: PinFunction) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_function(self.number, function));
+    }
+
+    /// Configure the pull resistor
+    pub fn set_pull(&mut self, pull: Pull) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_pull(self.number, pull));
+    }
+
+    /// Drive the pin high (the pin must be an output)
+    pub fn set_high(&mut self) {
+        self.gpio.inner.lock(|inner| inner.set(self.number));
+    }
+
+    /// Drive the pin low (the pin must be an output)
+    pub fn set_low(&mut self) {
+        self.gpio.inner.lock(|inner| inner.clear(self.number));
+    }
+
+    /// Read the pin's level
+    pub fn is_high(&self) -> bool {
+        self.gpio.inner.lock(|inner| inner.level(self.number))
+    }
+
+    /// Start detecting an event. Several events can be enabled at the same time.
+    pub fn enable_event(&mut self, event: PinEvent) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_event_detect(self.number, event, true));
+    }
+
+    /// Stop detecting an event
+    pub fn disable_event(&mut self, event: PinEvent) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.set_event_detect(self.number, event, false));
+    }
+
+    /// Drop an event that was already detected (i.e. a stale one, before enabling the callback)
+    pub fn clear_event(&mut self) {
+        self.gpio.inner.lock(|inner| inner.clear_event(self.number));
+    }
+
+    /// Call `callback` from the GPIO interrupt handler when one of the enabled events is detected.
+    ///
+    /// With a `debounce` duration, events closer than that to the last reported one are ignored
+    /// (mechanical buttons bounce for a few milliseconds).
+    pub fn on_event(&mut self, callback: PinEventCallback, debounce: Option<Duration>) {
+        let handler = PinEventHandler {
+            callback,
+            debounce: debounce.unwrap_or(Duration::ZERO),
+            last_event: None,
+        };
+
+        self.gpio
+            .inner
+            .lock(|inner| inner.handlers[self.number as usize] = Some(handler));
+    }
+
+    /// Remove the event callback. Detected events are dropped from now on.
+    pub fn remove_event_callback(&mut self) {
+        self.gpio
+            .inner
+            .lock(|inner| inner.handlers[self.number as usize] = None);
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        self.gpio.inner.lock(|inner| {
+            inner.reset_events(self.number);
+            inner.claimed &= !(1 << self.number);
+        });
+    }
 }
 
 // Interface code for the device driver trait (as specified in driver.rs)
@@ -224,4 +666,77 @@ impl driver::interface::DeviceDriver for GPIO {
     fn compatible(&self) -> &'static str {
         "BCM GPIO Device driver version 1.0"
     }
+
+    /// Stop detecting events, the pins keep their function and level
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| {
+            for pin in 0..NUM_PINS {
+                inner.reset_events(pin);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(&'static self) -> Result<(), Error> {
+        use exception::asynchronous::irq_manager;
+
+        for irq_number in self.irq_numbers {
+            let descriptor = IRQHandlerDescriptor::new(*irq_number, "BCM GPIO", self);
+
+            irq_manager().register_handler(descriptor)?;
+            irq_manager().enable(irq_number);
+        }
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for GPIO {
+    fn handle(&self) -> Result<(), Error> {
+        let now = time::Instant::now();
+        let mut fired = self.inner.lock(|inner| inner.dispatch_events(now));
+
+        // The callbacks run outside of the lock, they may use their pin
+        while fired != 0 {
+            let pin = fired.trailing_zeros() as u8;
+            fired &= !(1 << pin);
+
+            let callback = self
+                .inner
+                .lock(|inner| inner.handlers[pin as usize].map(|handler| handler.callback));
+            if let Some(callback) = callback {
+                callback(pin);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// embedded-hal: the pins of the ecosystem's drivers (chip selects, resets, data ready...)
+impl embedded_hal::digital::ErrorType for Pin {
+    type Error = Error;
+}
+
+impl embedded_hal::digital::OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Error> {
+        Pin::set_low(self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Error> {
+        Pin::set_high(self);
+        Ok(())
+    }
+}
+
+impl embedded_hal::digital::InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Error> {
+        Ok(Pin::is_high(self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Error> {
+        Ok(!Pin::is_high(self))
+    }
 }
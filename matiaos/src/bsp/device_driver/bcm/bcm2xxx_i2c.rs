@@ -0,0 +1,538 @@
+//! BCM2xxx BSC (Broadcom Serial Controller) driver, the I2C master of I2C1.
+//!
+//! I2C1 is on the header: SDA on GPIO 2, SCL on GPIO 3 (alt 0), with 1.8k pull-ups on the board.
+//! The driver claims the pins from the GPIO driver at init, and gives them back at shutdown.
+//!
+//! A transfer is a start, the 7-bit address, DLEN bytes one way, and a stop. The FIFO is 16
+//! bytes, the CPU serves it polling.
+//!
+//! The BSC has no repeated start of its own. A write-then-read gets one by starting the read
+//! while the write is still active: the controller chains it without a stop. The write part has
+//! to fit in the FIFO for that.
+//!
+//! Reference: BCM2837 ARM Peripherals, chapter 3 "BSC", and the Linux driver
+//! drivers/i2c/busses/i2c-bcm2835.c
+
+use super::{Pin, PinFunction, Pull, GPIO};
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    synchronization::interface::Mutex,
+    synchronization::NullLock,
+    time,
+};
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+use embedded_hal::i2c::Operation;
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::ReadWrite,
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Control
+    C [
+        I2CEN OFFSET(15) NUMBITS(1) [],
+        /// Start a transfer
+        ST OFFSET(7) NUMBITS(1) [],
+        CLEAR OFFSET(4) NUMBITS(2) [
+            Fifo = 0b11
+        ],
+        /// Read transfer
+        READ OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Status, CLKT, ERR and DONE are cleared by writing 1
+    S [
+        /// The slave held SCL low longer than CLKT.TOUT
+        CLKT OFFSET(9) NUMBITS(1) [],
+        /// The address or a byte was not acknowledged
+        ERR OFFSET(8) NUMBITS(1) [],
+        /// RX FIFO contains data
+        RXD OFFSET(5) NUMBITS(1) [],
+        /// TX FIFO can accept data
+        TXD OFFSET(4) NUMBITS(1) [],
+        DONE OFFSET(1) NUMBITS(1) [],
+        /// Transfer active
+        TA OFFSET(0) NUMBITS(1) []
+    ],
+
+    DLEN [
+        DLEN OFFSET(0) NUMBITS(16) []
+    ],
+
+    A [
+        ADDR OFFSET(0) NUMBITS(7) []
+    ],
+
+    DIV [
+        /// SCL = core clock / CDIV, even (odd values are rounded down), 0 is 32768
+        CDIV OFFSET(0) NUMBITS(16) []
+    ],
+
+    /// When SDA changes and is sampled after an SCL edge, in core clock cycles
+    DEL [
+        FEDL OFFSET(16) NUMBITS(16) [],
+        REDL OFFSET(0) NUMBITS(16) []
+    ],
+
+    CLKT [
+        /// SCL cycles a slave may stretch the clock for, 0 disables the timeout
+        TOUT OFFSET(0) NUMBITS(16) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => C: ReadWrite<u32, C::Register>),
+        (0x04 => S: ReadWrite<u32, S::Register>),
+        (0x08 => DLEN: ReadWrite<u32, DLEN::Register>),
+        (0x0C => A: ReadWrite<u32, A::Register>),
+        (0x10 => FIFO: ReadWrite<u32>),
+        (0x14 => DIV: ReadWrite<u32, DIV::Register>),
+        (0x18 => DEL: ReadWrite<u32, DEL::Register>),
+        (0x1C => CLKT: ReadWrite<u32, CLKT::Register>),
+        (0x20 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The depth of the FIFO, in bytes
+const FIFO_SIZE: usize = 16;
+
+/// SDA, SCL
+const PINS: [u8; 2] = [2, 3];
+
+const DEFAULT_CLOCK_HZ: u32 = 100_000;
+
+/// The most bytes of a transfer (DLEN)
+const MAX_LEN: usize = 0xFFFF;
+
+/// The clock stretching allowed, as Linux does
+const CLOCK_STRETCH_TIMEOUT_MS: u32 = 35;
+
+/// The 7-bit addresses of devices, the others are reserved
+const DEVICE_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+/// Until the stop. Generous: 1 kB takes 90 ms at 100 kHz.
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct I2cInner {
+    registers: Registers,
+    core_clock_hz: u32,
+    /// The SCL asked for
+    clock_hz: u32,
+    /// Claimed while the driver is up
+    pins: Vec<Pin>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Represent the I2C1 master.
+pub struct I2c {
+    inner: NullLock<I2cInner>,
+    gpio: &'static GPIO,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl I2cInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize, core_clock_hz: u32) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            core_clock_hz,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            pins: Vec::new(),
+        }
+    }
+
+    /// Program SCL as close as possible to `clock_hz` without going over it. Returns the actual
+    /// SCL.
+    fn set_clock_hz(&mut self, clock_hz: u32) -> Result<u32, Error> {
+        if clock_hz == 0 {
+            return Err(Error::new(ErrorKind::InvalidArgument, "I2C: no clock"));
+        }
+
+        let divider = self
+            .core_clock_hz
+            .div_ceil(clock_hz)
+            .next_multiple_of(2)
+            .clamp(2, 0xFFFE);
+        let actual_hz = self.core_clock_hz / divider;
+
+        self.registers.DIV.write(DIV::CDIV.val(divider));
+        // Sample and change SDA well away from the SCL edges
+        self.registers
+            .DEL
+            .write(DEL::FEDL.val((divider / 16).max(1)) + DEL::REDL.val((divider / 4).max(1)));
+        let timeout = (actual_hz / 1000 * CLOCK_STRETCH_TIMEOUT_MS).min(0xFFFF);
+        self.registers.CLKT.write(CLKT::TOUT.val(timeout));
+        self.clock_hz = clock_hz;
+
+        Ok(actual_hz)
+    }
+
+    /// Set a transfer up, with the FIFO empty and the old status cleared
+    fn prepare(&self, address: u8, len: usize) -> Result<(), Error> {
+        if !DEVICE_ADDRESSES.contains(&address) {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "I2C: reserved address",
+            ));
+        }
+        if len > MAX_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "I2C: transfer too long",
+            ));
+        }
+
+        self.registers.C.write(C::I2CEN::SET + C::CLEAR::Fifo);
+        self.registers
+            .S
+            .write(S::CLKT::SET + S::ERR::SET + S::DONE::SET);
+        self.registers.A.write(A::ADDR.val(u32::from(address)));
+        self.registers.DLEN.write(DLEN::DLEN.val(len as u32));
+
+        Ok(())
+    }
+
+    fn start(&self, read: bool) {
+        self.registers
+            .C
+            .write(C::I2CEN::SET + C::ST::SET + C::READ.val(u32::from(read)));
+    }
+
+    /// Fill the FIFO with the next bytes of `buf`
+    fn fill(&self, buf: &[u8], sent: &mut usize) {
+        while *sent < buf.len() && self.registers.S.is_set(S::TXD) {
+            self.registers.FIFO.set(u32::from(buf[*sent]));
+            *sent += 1;
+        }
+    }
+
+    /// Empty the FIFO into the next bytes of `buf`
+    fn drain(&self, buf: &mut [u8], received: &mut usize) {
+        while *received < buf.len() && self.registers.S.is_set(S::RXD) {
+            buf[*received] = self.registers.FIFO.get() as u8;
+            *received += 1;
+        }
+    }
+
+    /// The transfer stopped, or failed
+    fn ended(&self) -> bool {
+        self.registers.S.is_set(S::DONE) || self.failed()
+    }
+
+    /// No acknowledge, or a clock stretch timeout
+    fn failed(&self) -> bool {
+        let status = self.registers.S.extract();
+
+        status.is_set(S::ERR) || status.is_set(S::CLKT)
+    }
+
+    /// Wait for the stop, running `serve` on the FIFO meanwhile, and report how it went. The
+    /// controller is left disabled.
+    fn finish(&self, mut serve: impl FnMut(&Self)) -> Result<(), Error> {
+        let result = time::time_manager().wait_until(
+            || {
+                serve(self);
+                self.ended()
+            },
+            TRANSFER_TIMEOUT,
+        );
+        // The last bytes came with DONE
+        serve(self);
+
+        let status = self.registers.S.extract();
+        self.registers
+            .S
+            .write(S::CLKT::SET + S::ERR::SET + S::DONE::SET);
+        // Also aborts a transfer that timed out
+        self.registers.C.write(C::CLEAR::Fifo);
+
+        if status.is_set(S::CLKT) {
+            Err(Error::new(
+                ErrorKind::Timeout,
+                "I2C: clock stretched too long",
+            ))
+        } else if status.is_set(S::ERR) {
+            Err(Error::new(ErrorKind::NotPresent, "I2C: no acknowledge"))
+        } else {
+            result.map_err(|_| Error::new(ErrorKind::Timeout, "I2C: transfer timed out"))
+        }
+    }
+
+    fn write(&self, address: u8, buf: &[u8]) -> Result<(), Error> {
+        self.prepare(address, buf.len())?;
+
+        let mut sent = 0;
+        self.fill(buf, &mut sent);
+        self.start(false);
+
+        self.finish(|inner| inner.fill(buf, &mut sent))
+    }
+
+    fn read(&self, address: u8, buf: &mut [u8]) -> Result<(), Error> {
+        self.prepare(address, buf.len())?;
+        self.start(true);
+
+        let mut received = 0;
+        self.finish(|inner| inner.drain(buf, &mut received))?;
+        if received < buf.len() {
+            return Err(Error::new(ErrorKind::Io, "I2C: short read"));
+        }
+
+        Ok(())
+    }
+
+    /// Write `write`, then read `read` after a repeated start
+    fn write_read(&self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        if write.is_empty() {
+            return self.read(address, read);
+        }
+        if read.is_empty() {
+            return self.write(address, write);
+        }
+        if write.len() > FIFO_SIZE {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "I2C: write part over the FIFO size",
+            ));
+        }
+        if read.len() > MAX_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "I2C: transfer too long",
+            ));
+        }
+        self.prepare(address, write.len())?;
+
+        let mut sent = 0;
+        self.fill(write, &mut sent);
+        self.start(false);
+
+        // The read must be queued once the write is active, before its stop
+        let started = time::time_manager().wait_until(
+            || self.registers.S.is_set(S::TA) || self.ended(),
+            TRANSFER_TIMEOUT,
+        );
+        if started.is_ok() && !self.failed() {
+            self.registers.DLEN.write(DLEN::DLEN.val(read.len() as u32));
+            self.start(true);
+        }
+
+        let mut received = 0;
+        self.finish(|inner| inner.drain(read, &mut received))?;
+        if received < read.len() {
+            return Err(Error::new(ErrorKind::Io, "I2C: short read"));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl I2c {
+    /// Create new instance, the pins are taken from `gpio`. SCL is derived from `core_clock_hz`.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(
+        mmio_start_addr: usize,
+        gpio: &'static GPIO,
+        core_clock_hz: u32,
+    ) -> Self {
+        Self {
+            inner: NullLock::new(I2cInner::new(mmio_start_addr, core_clock_hz)),
+            gpio,
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner
+            .lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// Set the core clock the SCL is divided from. Call it before the driver's init.
+    pub fn set_core_clock_hz(&self, core_clock_hz: u32) {
+        self.inner.lock(|inner| inner.core_clock_hz = core_clock_hz);
+    }
+
+    /// Set the SCL frequency. Returns the actual one, at most `clock_hz`.
+    pub fn set_clock_hz(&self, clock_hz: u32) -> Result<u32, Error> {
+        self.inner.lock(|inner| inner.set_clock_hz(clock_hz))
+    }
+
+    /// Write `buf` to the device at `address`.
+    pub fn write(&self, address: u8, buf: &[u8]) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.write(address, buf))
+    }
+
+    /// Read `buf.len()` bytes from the device at `address`.
+    pub fn read(&self, address: u8, buf: &mut [u8]) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.read(address, buf))
+    }
+
+    /// Write `write` to the device at `address`, then read `read` from it after a repeated start
+    /// (i.e. a register number, then its value). `write` is at most 16 bytes.
+    pub fn write_read(&self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        self.inner
+            .lock(|inner| inner.write_read(address, write, read))
+    }
+
+    /// The addresses answering a one byte read.
+    ///
+    /// A read doesn't change the state of the usual devices, a write could.
+    pub fn scan(&self) -> Result<Vec<u8>, Error> {
+        let mut found = Vec::new();
+
+        for address in DEVICE_ADDRESSES {
+            match self.read(address, &mut [0]) {
+                Ok(()) => found.push(address),
+                Err(e) if e.kind() == ErrorKind::NotPresent => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for I2c {
+    fn compatible(&self) -> &'static str {
+        "BCM I2C (BSC) Device driver version 1.0"
+    }
+
+    /// Route the pins to the BSC, and program the clock
+    fn init(&self) -> Result<(), Error> {
+        let mut pins = Vec::with_capacity(PINS.len());
+        for number in PINS {
+            let mut pin = self.gpio.claim(number)?;
+            pin.set_function(PinFunction::Alt0);
+            // The board's pull-ups are enough
+            pin.set_pull(Pull::None);
+            pins.push(pin);
+        }
+
+        self.inner.lock(|inner| {
+            inner.pins = pins;
+            inner.set_clock_hz(inner.clock_hz)
+        })?;
+
+        Ok(())
+    }
+
+    /// Give the pins back
+    fn shutdown(&self) -> Result<(), Error> {
+        let pins = self.inner.lock(|inner| {
+            inner.registers.C.write(C::CLEAR::Fifo);
+            core::mem::take(&mut inner.pins)
+        });
+        drop(pins);
+
+        Ok(())
+    }
+}
+
+// embedded-hal: the bus of the ecosystem's I2C drivers
+impl embedded_hal::i2c::ErrorType for &I2c {
+    type Error = Error;
+}
+
+impl embedded_hal::i2c::I2c for &I2c {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Error> {
+        I2c::read(self, address, read)
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
+        I2c::write(self, address, write)
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        I2c::write_read(self, address, write, read)
+    }
+
+    /// The BSC chains one write and one read at most: a transaction is a write, a read, or a
+    /// write then a read. Adjacent operations of the same direction are merged, as the trait
+    /// asks.
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        // Nothing to put on the bus, not even the address
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let split = operations
+            .iter()
+            .position(|x| matches!(x, Operation::Read(_)))
+            .unwrap_or(operations.len());
+        let (writes, reads) = operations.split_at_mut(split);
+        if reads.iter().any(|x| matches!(x, Operation::Write(_))) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "I2C: a write after a read in a transaction",
+            ));
+        }
+
+        let mut write = Vec::new();
+        for operation in writes.iter() {
+            if let Operation::Write(bytes) = operation {
+                write.extend_from_slice(bytes);
+            }
+        }
+        let read_len = reads
+            .iter()
+            .filter_map(|x| match x {
+                Operation::Read(bytes) => Some(bytes.len()),
+                Operation::Write(_) => None,
+            })
+            .sum();
+        let mut read = vec![0; read_len];
+
+        I2c::write_read(self, address, &write, &mut read)?;
+
+        let mut received = read.as_slice();
+        for operation in reads.iter_mut() {
+            if let Operation::Read(bytes) = operation {
+                let (chunk, rest) = received.split_at(bytes.len());
+                bytes.copy_from_slice(chunk);
+                received = rest;
+            }
+        }
+
+        Ok(())
+    }
+}
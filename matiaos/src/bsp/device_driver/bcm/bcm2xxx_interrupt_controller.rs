@@ -0,0 +1,263 @@
+//! BCM2xxx (legacy) interrupt controller driver.
+//!
+//! The "ARM peripherals interrupts" block of the BCM2837. It multiplexes the 64 GPU peripheral
+//! interrupts (GPIO, system timer, UART, ...) onto the core's IRQ line. The BCM2711 keeps the same
+//! block (as ARMC) next to its GIC-400.
+//!
+//! The basic pending/enable registers (ARM timer, mailbox, doorbells) are not supported.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    exception::asynchronous::{interface, IRQHandlerDescriptor, IRQNumber},
+    info, synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock,
+};
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, WriteOnly},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+// Interrupt controller registers.
+//
+// Descriptions taken from
+// - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf (chapter 7)
+//
+// IRQs 0-31 live in the "1" registers, IRQs 32-63 in the "2" registers, one bit per IRQ.
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x04 => PENDING_1: ReadOnly<u32>),
+        (0x08 => PENDING_2: ReadOnly<u32>),
+        (0x0C => _reserved2),
+        (0x10 => ENABLE_1: WriteOnly<u32>),
+        (0x14 => ENABLE_2: WriteOnly<u32>),
+        (0x18 => _reserved3),
+        (0x1C => DISABLE_1: WriteOnly<u32>),
+        (0x20 => DISABLE_2: WriteOnly<u32>),
+        (0x24 => _reserved4),
+        (0x28 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Number of peripheral IRQs
+const NUM_IRQS: usize = 64;
+
+type HandlerTable = [Option<IRQHandlerDescriptor<IRQNumber>>; NUM_IRQS];
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+pub struct InterruptControllerInner {
+    registers: Registers,
+    handler_table: HandlerTable,
+    // the pending registers show disabled IRQs too, only look at the ones we enabled
+    enabled: u64,
+}
+
+/// Represent the interrupt controller hardware.
+pub struct InterruptController {
+    // also used by the IRQ exception handler
+    inner: IRQSafeNullLock<InterruptControllerInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl InterruptControllerInner {
+    /// Create InterruptControllerInner instance
+    ///
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            handler_table: [None; NUM_IRQS],
+            enabled: 0,
+        }
+    }
+
+    /// Disable all the peripheral IRQs, the firmware may have left some enabled
+    fn init(&mut self) {
+        self.registers.DISABLE_1.set(u32::MAX);
+        self.registers.DISABLE_2.set(u32::MAX);
+        self.enabled = 0;
+    }
+
+    /// Disable all the IRQs, but remember which ones were enabled
+    fn suspend(&mut self) {
+        self.registers.DISABLE_1.set(u32::MAX);
+        self.registers.DISABLE_2.set(u32::MAX);
+    }
+
+    /// Enable again the IRQs that were enabled before `suspend`
+    fn resume(&mut self) {
+        self.registers.ENABLE_1.set(self.enabled as u32);
+        self.registers.ENABLE_2.set((self.enabled >> 32) as u32);
+    }
+
+    /// Like `init`, but keep the IRQs that were enabled: the drivers don't register their
+    /// handlers again after a reset.
+    fn reset(&mut self) {
+        let enabled = self.enabled;
+
+        self.init();
+        self.enabled = enabled;
+        self.resume();
+    }
+
+    /// Enable an IRQ
+    fn enable(&mut self, irq_number: IRQNumber) {
+        let mask = 1 << (irq_number % 32);
+        if irq_number < 32 {
+            self.registers.ENABLE_1.set(mask);
+        } else {
+            self.registers.ENABLE_2.set(mask);
+        }
+
+        self.enabled |= 1 << irq_number;
+    }
+
+    /// The pending and enabled IRQs, one bit per IRQ
+    fn pending_irqs(&self) -> u64 {
+        let pending = (u64::from(self.registers.PENDING_2.get()) << 32)
+            | u64::from(self.registers.PENDING_1.get());
+
+        pending & self.enabled
+    }
+}
+
+impl InterruptController {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(InterruptControllerInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for InterruptController {
+    fn compatible(&self) -> &'static str {
+        "BCM Interrupt Controller Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init());
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init());
+        Ok(())
+    }
+
+    fn suspend(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.suspend());
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.resume());
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.reset());
+        Ok(())
+    }
+}
+
+impl interface::IRQManager for InterruptController {
+    type IRQNumberType = IRQNumber;
+
+    fn register_handler(
+        &self,
+        irq_handler_descriptor: IRQHandlerDescriptor<Self::IRQNumberType>,
+    ) -> Result<(), Error> {
+        let irq_number = irq_handler_descriptor.number();
+        if irq_number >= NUM_IRQS {
+            return Err(Error::new(ErrorKind::InvalidArgument, "IRQ number out of range"));
+        }
+
+        self.inner.lock(|inner| {
+            if inner.handler_table[irq_number].is_some() {
+                return Err(Error::new(ErrorKind::Busy, "IRQ handler already registered"));
+            }
+
+            inner.handler_table[irq_number] = Some(irq_handler_descriptor);
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq_number: &Self::IRQNumberType) {
+        self.inner.lock(|inner| inner.enable(*irq_number));
+    }
+
+    fn handle_pending_irqs(&self) {
+        // IRQs are already masked here (exception entry), so the handlers run under the lock
+        self.inner.lock(|inner| {
+            let mut pending = inner.pending_irqs();
+
+            while pending != 0 {
+                let irq_number = pending.trailing_zeros() as usize;
+                pending &= !(1 << irq_number);
+
+                match inner.handler_table[irq_number] {
+                    None => panic!("No handler registered for IRQ {}", irq_number),
+                    Some(descriptor) => {
+                        // Call the IRQ handler. Panics on failure.
+                        descriptor.handler().handle().expect("Error handling IRQ");
+                    }
+                }
+            }
+        })
+    }
+
+    fn print_handler(&self) {
+        info!("      Peripheral handler:");
+
+        self.inner.lock(|inner| {
+            for (i, descriptor) in inner
+                .handler_table
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.map(|x| (i, x)))
+            {
+                info!("            {: >3}. {}", i, descriptor.name());
+            }
+        });
+    }
+}
@@ -0,0 +1,286 @@
+//! VideoCore mailbox driver.
+//!
+//! The mailbox is how the ARM talks to the VideoCore firmware. We only use channel 8, the
+//! "property tags" interface (ARM -> VC): a 16-byte aligned buffer in memory holds a list of
+//! tags (get board revision, allocate framebuffer, ...), its bus address is written to the
+//! mailbox and the firmware answers in place.
+//!
+//! References:
+//! - https://github.com/raspberrypi/firmware/wiki/Mailboxes
+//! - https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    bsp::memory,
+    cpu, driver,
+    memory::cache,
+    error::{Error, ErrorKind},
+    synchronization::interface::Mutex,
+    synchronization::NullLock,
+};
+use core::sync::atomic::{fence, Ordering};
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, WriteOnly},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Mailbox status register
+    STATUS [
+        /// There is no space in the mailbox to write a message
+        FULL OFFSET(31) NUMBITS(1) [],
+
+        /// There is nothing to read from the mailbox
+        EMPTY OFFSET(30) NUMBITS(1) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => READ: ReadOnly<u32>),
+        (0x04 => _reserved1),
+        (0x18 => STATUS: ReadOnly<u32, STATUS::Register>),
+        (0x1C => _reserved2),
+        (0x20 => WRITE: WriteOnly<u32>),
+        (0x24 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Clock ids of the clock tags
+#[allow(dead_code)]
+pub mod clock_id {
+    pub const EMMC: u32 = 1;
+    pub const EMMC2: u32 = 12;
+}
+
+/// Property tags channel (ARM -> VC)
+const CHANNEL_PROPERTY_TAGS: u32 = 8;
+
+/// Buffer request code
+const REQUEST: u32 = 0x0000_0000;
+/// Buffer response code: request successful
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+/// Tag response code: bit 31 is set by the firmware once the tag was processed
+const TAG_RESPONSE: u32 = 0x8000_0000;
+/// The end tag
+const TAG_END: u32 = 0;
+
+/// Size of a property message in words. Big enough for a framebuffer allocation.
+const MESSAGE_WORDS: usize = 64;
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+/// Property tag identifiers.
+pub mod tag {
+    pub const GET_CLOCK_RATE: u32 = 0x0003_0002;
+    pub const ALLOCATE_BUFFER: u32 = 0x0004_0001;
+    pub const GET_PITCH: u32 = 0x0004_0008;
+    pub const SET_PHYSICAL_SIZE: u32 = 0x0004_8003;
+    pub const SET_VIRTUAL_SIZE: u32 = 0x0004_8004;
+    pub const SET_DEPTH: u32 = 0x0004_8005;
+    pub const SET_PIXEL_ORDER: u32 = 0x0004_8006;
+    pub const SET_VIRTUAL_OFFSET: u32 = 0x0004_8009;
+}
+
+/// A property tags message.
+///
+/// The firmware requires the buffer to be 16 byte aligned (the lower 4 bits of the address carry
+/// the channel number). It is aligned on cache lines, and the words fill whole ones: the cache
+/// maintenance around a call doesn't touch anything else.
+#[repr(C, align(64))]
+pub struct PropertyMessage {
+    words: [u32; MESSAGE_WORDS],
+    // index of the next free word (where the end tag will go)
+    len: usize,
+}
+
+pub struct MailboxInner {
+    registers: Registers,
+}
+
+/// Represent the VideoCore mailbox.
+pub struct Mailbox {
+    inner: NullLock<MailboxInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PropertyMessage {
+    /// Create an empty message (buffer size and request code only)
+    pub const fn new() -> Self {
+        Self {
+            words: [0; MESSAGE_WORDS],
+            len: 2,
+        }
+    }
+
+    /// Append a tag to the message.
+    ///
+    /// `value_words` is the size of the tag's value buffer, which must be big enough for both the
+    /// request and the response. `request` is copied to the start of the value buffer.
+    pub fn add_tag(
+        &mut self,
+        tag: u32,
+        value_words: usize,
+        request: &[u32],
+    ) -> Result<(), Error> {
+        // tag id, value buffer size, request/response code, values, and room for the end tag
+        if request.len() > value_words || self.len + 3 + value_words + 1 > MESSAGE_WORDS {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "Mailbox property message too long",
+            ));
+        }
+
+        self.words[self.len] = tag;
+        self.words[self.len + 1] = (value_words * 4) as u32;
+        self.words[self.len + 2] = REQUEST;
+        self.len += 3;
+
+        self.words[self.len..self.len + value_words].fill(0);
+        self.words[self.len..self.len + request.len()].copy_from_slice(request);
+        self.len += value_words;
+
+        Ok(())
+    }
+
+    /// Get the response values of a tag, once the message went through the mailbox.
+    pub fn response(&self, tag: u32) -> Option<&[u32]> {
+        let mut index = 2;
+
+        while index + 3 <= self.len {
+            let value_words = self.words[index + 1] as usize / 4;
+            let code = self.words[index + 2];
+
+            if self.words[index] == tag {
+                if code & TAG_RESPONSE == 0 {
+                    return None;
+                }
+                // the lower 31 bits hold the response length in bytes
+                let response_words = ((code & !TAG_RESPONSE) as usize / 4).min(value_words);
+
+                return Some(&self.words[index + 3..index + 3 + response_words]);
+            }
+
+            index += 3 + value_words;
+        }
+
+        None
+    }
+
+    /// Terminate the message and fill in its size
+    fn seal(&mut self) {
+        self.words[self.len] = TAG_END;
+        self.words[0] = ((self.len + 1) * 4) as u32;
+        self.words[1] = REQUEST;
+    }
+}
+
+impl MailboxInner {
+    /// Create MailboxInner instance
+    ///
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+        }
+    }
+
+    /// Send a message on a channel and wait for the firmware's answer.
+    fn call(&mut self, channel: u32, message: &mut PropertyMessage) -> Result<(), Error> {
+        message.seal();
+        let address = memory::phys_to_bus(message.words.as_ptr() as usize);
+
+        let words = message.words.as_ptr_range();
+        let words = words.start as usize..words.end as usize;
+
+        // The firmware must see the buffer content before it sees the mailbox write
+        fence(Ordering::SeqCst);
+        cache::clean_invalidate_range(words.clone());
+
+        while self.registers.STATUS.matches_all(STATUS::FULL::SET) {
+            cpu::nop();
+        }
+        self.registers.WRITE.set(address | channel);
+
+        loop {
+            while self.registers.STATUS.matches_all(STATUS::EMPTY::SET) {
+                cpu::nop();
+            }
+
+            // Answers to other requests (or channels) are not ours, drop them
+            if self.registers.READ.get() == address | channel {
+                break;
+            }
+        }
+
+        // ... and we must see the firmware's answer only after it said so
+        cache::invalidate_range(words);
+        fence(Ordering::SeqCst);
+
+        // The firmware wrote the response behind the compiler's back
+        let response_code = unsafe { core::ptr::read_volatile(&message.words[1]) };
+        if response_code != RESPONSE_SUCCESS {
+            return Err(Error::new(ErrorKind::Io, "Mailbox property request failed"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Mailbox {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: NullLock::new(MailboxInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// Send a property tags message to the firmware. The responses are written into `message`.
+    pub fn call(&self, message: &mut PropertyMessage) -> Result<(), Error> {
+        self.inner
+            .lock(|inner| inner.call(CHANNEL_PROPERTY_TAGS, message))
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Mailbox {
+    fn compatible(&self) -> &'static str {
+        "BCM VideoCore Mailbox Device driver version 1.0"
+    }
+}
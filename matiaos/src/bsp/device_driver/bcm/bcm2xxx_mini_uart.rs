@@ -0,0 +1,434 @@
+//! BCM2xxx auxiliary mini UART driver.
+//!
+//! The mini UART lives in the AUX peripheral block together with the two auxiliary SPI masters.
+//! On a stock configuration where Bluetooth owns the PL011, the mini UART is the one routed to
+//! GPIO 14/15 (alt function 5).
+//!
+//! Unlike the PL011, the mini UART is clocked by the VPU core clock, so its baudrate depends on
+//! `core_freq` in `config.txt`. `enable_uart=1` pins the core clock (250MHz on the Pi 3, 500MHz on
+//! the Pi 4) so the baudrate stays stable.
+
+use core::fmt;
+use core::fmt::Arguments;
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper, console, console::Utf8Decoder, cpu, driver,
+    error::Error, synchronization::interface::Mutex, synchronization::NullLock,
+};
+
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+// Mini UART registers.
+//
+// Descriptions taken from
+// - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf (chapter 2)
+// - https://elinux.org/BCM2835_datasheet_errata (the LCR data size field is two bits wide)
+
+register_bitfields! {
+    u32, // 32 bit wide
+
+    /// Auxiliary enables
+    AUX_ENABLES [
+        /// If set the mini UART is enabled. The UART will immediately start receiving data,
+        /// especially if the UART1_RX line is low.
+        /// If clear the mini UART is disabled. That also disables any mini UART register access.
+        MINI_UART_ENABLE OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Mini UART interrupt identify register
+    AUX_MU_IIR [
+        /// On write: clear the receive (bit 1) and/or the transmit (bit 2) FIFO.
+        FIFO_CLEAR OFFSET(1) NUMBITS(2) [
+            Rx = 0b01,
+            Tx = 0b10,
+            All = 0b11
+        ]
+    ],
+
+    /// Mini UART line control register
+    AUX_MU_LCR [
+        /// Data size. The datasheet only documents bit 0, but both bits must be set for 8 bits.
+        DATA_SIZE OFFSET(0) NUMBITS(2) [
+            SevenBit = 0b00,
+            EightBit = 0b11
+        ]
+    ],
+
+    /// Mini UART line status register
+    AUX_MU_LSR [
+        /// This bit is set if the transmitter is idle and the transmit FIFO is empty.
+        TX_IDLE OFFSET(6) NUMBITS(1) [],
+
+        /// This bit is set if the transmit FIFO can accept at least one byte.
+        TX_EMPTY OFFSET(5) NUMBITS(1) [],
+
+        /// This bit is set if the receive FIFO holds at least one symbol.
+        DATA_READY OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Mini UART extra control register
+    AUX_MU_CNTL [
+        /// If this bit is set the mini UART transmitter is enabled.
+        TX_ENABLE OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// If this bit is set the mini UART receiver is enabled.
+        RX_ENABLE OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART baudrate register
+    AUX_MU_BAUD [
+        /// baudrate = system_clock_freq / (8 * (BAUDRATE + 1))
+        BAUDRATE OFFSET(0) NUMBITS(16) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub RegisterBlock {
+        (0x00 => _reserved1),
+        (0x04 => AUX_ENABLES: ReadWrite<u32, AUX_ENABLES::Register>),
+        (0x08 => _reserved2),
+        (0x40 => AUX_MU_IO: ReadWrite<u32>),
+        (0x44 => AUX_MU_IER: ReadWrite<u32>),
+        (0x48 => AUX_MU_IIR: ReadWrite<u32, AUX_MU_IIR::Register>),
+        (0x4C => AUX_MU_LCR: ReadWrite<u32, AUX_MU_LCR::Register>),
+        (0x50 => AUX_MU_MCR: ReadWrite<u32>),
+        (0x54 => AUX_MU_LSR: ReadOnly<u32, AUX_MU_LSR::Register>),
+        (0x58 => _reserved3),
+        (0x60 => AUX_MU_CNTL: ReadWrite<u32, AUX_MU_CNTL::Register>),
+        (0x64 => _reserved4),
+        (0x68 => AUX_MU_BAUD: ReadWrite<u32, AUX_MU_BAUD::Register>),
+        (0x6C => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The baudrate used for the console, same as the PL011
+const BAUDRATE: u32 = 115_200;
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockingMode {
+    Blocking,
+    NonBlocking,
+}
+
+/// Compute the AUX_MU_BAUD value for the requested baudrate, rounded to the closest divisor.
+///
+/// baudrate = core_clock / (8 * (reg + 1))  =>  reg = core_clock / (8 * baudrate) - 1
+///
+/// For example, with a 250MHz core clock and 115200 baud:
+/// reg = 250,000,000 / (8 * 115200) - 1 = 270.27 - 1 (270)
+/// actual baudrate: 250,000,000 / (8 * 271) = ~115313, error: 0.1%
+const fn baud_divisor(core_clock_hz: u32, baudrate: u32) -> u32 {
+    let eight_baud = 8 * baudrate;
+
+    (core_clock_hz + eight_baud / 2) / eight_baud - 1
+}
+
+//----------------------------------------
+// Public Definitions
+//----------------------------------------
+
+pub struct MiniUartInner {
+    registers: Registers,
+    core_clock_hz: u32,
+    utf8: Utf8Decoder,
+    chars_written: usize,
+    chars_read: usize,
+}
+
+/// Represent the mini UART hardware.
+pub struct MiniUart {
+    inner: NullLock<MiniUartInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl MiniUartInner {
+    /// Create MiniUartInner instance
+    ///
+    /// # Safety
+    ///
+    /// - verify the AUX block mmio start address
+    pub const unsafe fn new(mmio_start_addr: usize, core_clock_hz: u32) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            core_clock_hz,
+            utf8: Utf8Decoder::new(),
+            chars_written: 0,
+            chars_read: 0,
+        }
+    }
+
+    /// Enable the mini UART in the AUX block, set 8N1 and the console baudrate.
+    pub fn init(&mut self) {
+        // AUX_ENABLES gates the register access of the whole mini UART, so enable it first.
+        // Flushing before that would spin on a register that reads as zero. The SPI1/SPI2 enables
+        // share the register, keep them.
+        self.registers
+            .AUX_ENABLES
+            .modify(AUX_ENABLES::MINI_UART_ENABLE::SET);
+
+        // Let queued characters go out before re-configuring (see the PL011 driver).
+        self.flush();
+
+        // disable transmitter and receiver while configuring
+        self.registers.AUX_MU_CNTL.set(0);
+
+        // no interrupts, no flow control
+        self.registers.AUX_MU_IER.set(0);
+        self.registers.AUX_MU_MCR.set(0);
+
+        // 8N1
+        self.registers
+            .AUX_MU_LCR
+            .write(AUX_MU_LCR::DATA_SIZE::EightBit);
+
+        self.registers.AUX_MU_BAUD.write(AUX_MU_BAUD::BAUDRATE.val(baud_divisor(
+            self.core_clock_hz,
+            BAUDRATE,
+        )));
+
+        // drop whatever is left in the FIFOs
+        self.registers.AUX_MU_IIR.write(AUX_MU_IIR::FIFO_CLEAR::All);
+
+        // turn mini UART on
+        self.registers
+            .AUX_MU_CNTL
+            .write(AUX_MU_CNTL::TX_ENABLE::Enabled + AUX_MU_CNTL::RX_ENABLE::Enabled);
+    }
+
+    /// Write one byte
+    fn write_byte(&mut self, byte: u8) {
+        // wait for an empty fifo slot!
+        while !self.registers.AUX_MU_LSR.matches_all(AUX_MU_LSR::TX_EMPTY::SET) {
+            cpu::nop();
+        }
+
+        // write
+        self.registers.AUX_MU_IO.set(byte.into());
+    }
+
+    /// Write Char, UTF-8 encoded
+    fn write_char(&mut self, c: char) {
+        for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+            self.write_byte(byte);
+        }
+
+        self.chars_written += 1;
+    }
+
+    /// Blocks execution until the transmit FIFO is empty and the last symbol was shifted out
+    fn flush(&self) {
+        while !self.registers.AUX_MU_LSR.matches_all(AUX_MU_LSR::TX_IDLE::SET) {
+            cpu::nop();
+        }
+    }
+
+    /// Read a byte Blocking / Non-Blocking mode
+    fn read_byte(&mut self, blocking_mode: BlockingMode) -> Option<u8> {
+        if !self.registers.AUX_MU_LSR.matches_all(AUX_MU_LSR::DATA_READY::SET) {
+            // return if non blocking mode
+            if blocking_mode == BlockingMode::NonBlocking {
+                return None;
+            }
+
+            // otherwise wait for a character
+            while !self.registers.AUX_MU_LSR.matches_all(AUX_MU_LSR::DATA_READY::SET) {
+                cpu::nop();
+            }
+        }
+
+        Some(self.registers.AUX_MU_IO.get() as u8)
+    }
+
+    /// Read a UTF-8 character Blocking / Non-Blocking mode (see the PL011 driver).
+    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+        let ret = match self.utf8.take_pending() {
+            Some(c) => c,
+            None => loop {
+                let byte = self.read_byte(blocking_mode)?;
+                if let Some(c) = self.utf8.push(byte) {
+                    break c;
+                }
+            },
+        };
+
+        self.chars_read += 1;
+        Some(ret)
+    }
+
+    /// Read what was received into `buf`, waiting for the first byte (see the PL011 driver).
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        let mut blocking_mode = BlockingMode::Blocking;
+
+        while count < buf.len() {
+            let Some(byte) = self.read_byte(blocking_mode) else {
+                break;
+            };
+            buf[count] = byte;
+            count += 1;
+            blocking_mode = BlockingMode::NonBlocking;
+        }
+
+        count
+    }
+}
+
+/// See the PL011 driver: `write_str()` gives us `write_fmt()` for the print macros.
+impl fmt::Write for MiniUartInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+        Ok(())
+    }
+}
+
+impl MiniUart {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address of the AUX block
+    /// - `core_clock_hz` must match the VPU core clock, otherwise the baudrate will be off
+    pub const unsafe fn new(mmio_start_addr: usize, core_clock_hz: u32) -> Self {
+        Self {
+            inner: NullLock::new(MiniUartInner::new(mmio_start_addr, core_clock_hz)),
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// Set the VPU core clock the baudrate is derived from. Call it before the driver's init.
+    pub fn set_core_clock_hz(&self, core_clock_hz: u32) {
+        self.inner.lock(|inner| inner.core_clock_hz = core_clock_hz);
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for MiniUart {
+    fn compatible(&self) -> &'static str {
+        "BCM AUX mini UART Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.init());
+        Ok(())
+    }
+
+    // The UART stays enabled, the last messages must get out
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+        Ok(())
+    }
+
+    fn suspend(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+        Ok(())
+    }
+}
+
+impl console::interface::Write for MiniUart {
+    fn write_char(&self, c: char) {
+        self.inner.lock(|inner| inner.write_char(c));
+    }
+
+    fn write_fmt(&self, args: Arguments) -> fmt::Result {
+        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+    }
+
+    fn flush(&self) {
+        self.inner.lock(|inner| inner.flush());
+    }
+}
+
+impl console::interface::Read for MiniUart {
+    fn read_char(&self) -> char {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::Blocking).unwrap())
+    }
+
+    fn try_read_char(&self) -> Option<char> {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
+    }
+
+    fn clear_rx(&self) {
+        while self
+            .inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking).is_some())
+        {}
+    }
+}
+
+impl console::interface::Statistics for MiniUart {
+    fn chars_written(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_written)
+    }
+
+    fn chars_read(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_read)
+    }
+}
+
+impl console::interface::All for MiniUart {}
+
+// embedded-io, see the PL011 driver
+impl embedded_io::ErrorType for &MiniUart {
+    type Error = Error;
+}
+
+impl embedded_io::Read for &MiniUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        Ok(self.inner.lock(|inner| inner.read_bytes(buf)))
+    }
+}
+
+impl embedded_io::Write for &MiniUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.inner.lock(|inner| {
+            for byte in buf {
+                inner.write_byte(*byte);
+            }
+        });
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+
+        Ok(())
+    }
+}
@@ -9,8 +9,9 @@ use core::fmt;
 use core::fmt::Arguments;
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, driver, synchronization::interface::Mutex,
-    synchronization::NullLock, cpu, console,
+    bsp::device_driver::common::MMIODerefWrapper, driver, error::Error,
+    synchronization::interface::Mutex, synchronization::NullLock, cpu, console,
+    console::Utf8Decoder,
 };
 
 use tock_registers::{
@@ -155,7 +156,7 @@ register_structs! {
 // abtracts the register calling
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum BlockingMode {
     Blocking,
     NonBlocking,
@@ -166,6 +167,7 @@ enum BlockingMode {
 
 pub struct PL011UartInner {
     registers: Registers,
+    utf8: Utf8Decoder,
     chars_written: usize,
     chars_read: usize,
 }
@@ -190,6 +192,7 @@ impl PL011UartInner {
     pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
             registers: Registers::new(mmio_start_addr),
+            utf8: Utf8Decoder::new(),
             chars_written: 0,
             chars_read: 0,
         }
@@ -237,15 +240,22 @@ impl PL011UartInner {
         self.registers.CR.write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
     }
 
-    /// Write Char
-    fn write_char(&mut self, c: char) {
+    /// Write one byte
+    fn write_byte(&mut self, byte: u8) {
         // wait for an empty fifo slot!
         while self.registers.FR.matches_all(FR::TXFF::SET) {
             cpu::nop();
         }
 
         // write
-        self.registers.DR.set(c as u32);
+        self.registers.DR.set(byte.into());
+    }
+
+    /// Write Char, UTF-8 encoded
+    fn write_char(&mut self, c: char) {
+        for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+            self.write_byte(byte);
+        }
 
         // increment chars_written
         self.chars_written += 1;
@@ -259,8 +269,8 @@ impl PL011UartInner {
         }
     }
 
-    /// Read a character Blocking / Non-Blocking mode
-    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+    /// Read a byte Blocking / Non-Blocking mode
+    fn read_byte(&mut self, blocking_mode: BlockingMode) -> Option<u8> {
         // What if RXF is empty
         if self.registers.FR.matches_all(FR::RXFE::SET) {
             // return if non blocking mode
@@ -274,12 +284,44 @@ impl PL011UartInner {
             }
         }
 
-        // read char
-        let ret = self.registers.DR.get() as u8 as char;
+        Some(self.registers.DR.get() as u8)
+    }
+
+    /// Read a UTF-8 character Blocking / Non-Blocking mode. The bytes of an incomplete one are
+    /// kept for the next call.
+    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+        let ret = match self.utf8.take_pending() {
+            Some(c) => c,
+            None => loop {
+                let byte = self.read_byte(blocking_mode)?;
+                if let Some(c) = self.utf8.push(byte) {
+                    break c;
+                }
+            },
+        };
 
         self.chars_read += 1;
         Some(ret)
     }
+
+    /// Read what was received into `buf`, waiting for the first byte. Returns the bytes read.
+    ///
+    /// Raw bytes, for `embedded-io`: they go around the UTF-8 decoding of the console.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        let mut blocking_mode = BlockingMode::Blocking;
+
+        while count < buf.len() {
+            let Some(byte) = self.read_byte(blocking_mode) else {
+                break;
+            };
+            buf[count] = byte;
+            count += 1;
+            blocking_mode = BlockingMode::NonBlocking;
+        }
+
+        count
+    }
 }
 
 /// Implementing `core::fmt::Write` enables usage of the `format_args!` macros, which in turn are
@@ -311,6 +353,16 @@ impl PL011Uart {
             inner: NullLock::new(PL011UartInner::new(mmio_start_addr)),
         }
     }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
 }
 
 // -----------------------------------------------
@@ -322,7 +374,18 @@ impl driver::interface::DeviceDriver for PL011Uart {
         "BCM PL011 UART Device driver version 1.0"
     }
     
-    fn init(&self) -> Result<(), &'static str> {
+    // The UART stays enabled, the last messages must get out
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+        Ok(())
+    }
+
+    fn suspend(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+        Ok(())
+    }
+
+    fn init(&self) -> Result<(), Error> {
         self.inner.lock(|inner| inner.init());
         Ok(())
     }
@@ -348,6 +411,10 @@ impl console::interface::Read for PL011Uart {
             .lock(|inner| inner.read_char(BlockingMode::Blocking)
             .unwrap())
     }
+    fn try_read_char(&self) -> Option<char> {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
+    }
     fn clear_rx(&self) {
         while self.inner
             .lock(|inner| inner.read_char(BlockingMode::NonBlocking)
@@ -365,3 +432,33 @@ impl console::interface::Statistics for PL011Uart {
 }
 
 impl console::interface::All for PL011Uart {}
+
+// embedded-io: the byte stream of the ecosystem's drivers (GPS, modems...). The console reads
+// the same UART, only one of them should.
+impl embedded_io::ErrorType for &PL011Uart {
+    type Error = Error;
+}
+
+impl embedded_io::Read for &PL011Uart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        Ok(self.inner.lock(|inner| inner.read_bytes(buf)))
+    }
+}
+
+impl embedded_io::Write for &PL011Uart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.inner.lock(|inner| {
+            for byte in buf {
+                inner.write_byte(*byte);
+            }
+        });
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.flush());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+//! BCM2xxx power manager driver: the watchdog, and the board resets it does.
+//!
+//! The watchdog counts PM_WDOG down, 65536 ticks a second, and resets the whole SoC when it gets
+//! to 0 (if PM_RSTC is set for a full reset). A reboot is the watchdog with a 10 ticks timeout.
+//! The registers only take writes carrying the 0x5A password in their top byte.
+//!
+//! PM_RSTS tells the firmware which partition to boot after the reset. Partition 63 is a special
+//! one: the firmware halts instead of booting (that is how Linux powers a Pi off).
+//!
+//! Reference: the Linux driver, drivers/watchdog/bcm2835_wdt.c
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    power,
+    synchronization::interface::Mutex,
+    synchronization::NullLock,
+};
+use core::time::Duration;
+
+use tock_registers::{
+    fields::FieldValue,
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::ReadWrite,
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Reset control
+    RSTC [
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Password = 0x5A
+        ],
+        /// Stops the watchdog, undocumented (the value Linux writes)
+        CONFIG OFFSET(0) NUMBITS(12) [
+            Stop = 0x102
+        ],
+        /// What the watchdog does when it expires
+        WRCFG OFFSET(4) NUMBITS(2) [
+            FullReset = 0b10
+        ]
+    ],
+
+    /// Reset status, and the partition the firmware boots after a reset (`PARTITION_MASK`)
+    RSTS [
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Password = 0x5A
+        ]
+    ],
+
+    /// Watchdog countdown
+    WDOG [
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Password = 0x5A
+        ],
+        TIME OFFSET(0) NUMBITS(20) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1C => RSTC: ReadWrite<u32, RSTC::Register>),
+        (0x20 => RSTS: ReadWrite<u32, RSTS::Register>),
+        (0x24 => WDOG: ReadWrite<u32, WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The 6 bits of the partition number in PM_RSTS, every other bit
+const PARTITION_MASK: u32 = 0x555;
+
+const TICKS_PER_SECOND: u64 = 1 << 16;
+
+/// The watchdog timeout of a reboot, about 150us
+const REBOOT_TICKS: u32 = 10;
+
+/// The partition of a normal boot, and the one the firmware halts at
+const BOOT_PARTITION: u32 = 0;
+const HALT_PARTITION: u32 = 63;
+
+struct PowerManagerInner {
+    registers: Registers,
+    /// Of the running watchdog, for `pet`
+    watchdog_ticks: Option<u32>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Represent the power manager.
+pub struct PowerManager {
+    inner: NullLock<PowerManagerInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// The PM_RSTS bits of a partition: its bit `n` goes to bit `2n`
+fn partition_bits(partition: u32) -> u32 {
+    (0..6).fold(0, |bits, n| bits | ((partition >> n) & 1) << (2 * n))
+}
+
+fn ticks_to_duration(ticks: u32) -> Duration {
+    Duration::from_micros(u64::from(ticks) * 1_000_000 / TICKS_PER_SECOND)
+}
+
+impl PowerManagerInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            watchdog_ticks: None,
+        }
+    }
+
+    /// Arm the watchdog for a full reset in `ticks`
+    fn arm(&self, ticks: u32) {
+        self.registers
+            .WDOG
+            .write(WDOG::PASSWD::Password + WDOG::TIME.val(ticks));
+        self.registers
+            .RSTC
+            .modify(RSTC::PASSWD::Password + RSTC::WRCFG::FullReset);
+    }
+
+    /// Reset the board, the firmware then boots `partition`
+    fn reset(&self, partition: u32) {
+        let partition = FieldValue::<u32, RSTS::Register>::new(
+            PARTITION_MASK,
+            0,
+            partition_bits(partition),
+        );
+
+        self.registers.RSTS.modify(RSTS::PASSWD::Password + partition);
+        self.arm(REBOOT_TICKS);
+    }
+
+    fn start_watchdog(&mut self, timeout: Duration) -> Result<(), Error> {
+        let ticks = timeout.as_micros() * u128::from(TICKS_PER_SECOND) / 1_000_000;
+        let ticks = u32::try_from(ticks)
+            .ok()
+            .filter(|&x| x <= WDOG::TIME.mask)
+            .ok_or(Error::new(
+                ErrorKind::InvalidArgument,
+                "Watchdog timeout too long",
+            ))?;
+
+        self.arm(ticks);
+        self.watchdog_ticks = Some(ticks);
+
+        Ok(())
+    }
+
+    fn stop_watchdog(&mut self) {
+        self.registers
+            .RSTC
+            .write(RSTC::PASSWD::Password + RSTC::CONFIG::Stop);
+        self.watchdog_ticks = None;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManager {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: NullLock::new(PowerManagerInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for PowerManager {
+    fn compatible(&self) -> &'static str {
+        "BCM Power Manager (watchdog) Device driver version 1.0"
+    }
+
+    /// The watchdog doesn't survive the kernel: another image (the serial loader) wouldn't pet it
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.stop_watchdog());
+
+        Ok(())
+    }
+}
+
+impl power::interface::PowerControl for PowerManager {
+    fn reboot(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.reset(BOOT_PARTITION));
+
+        Ok(())
+    }
+
+    fn power_off(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.reset(HALT_PARTITION));
+
+        Ok(())
+    }
+}
+
+impl power::interface::Watchdog for PowerManager {
+    fn max_timeout(&self) -> Duration {
+        ticks_to_duration(WDOG::TIME.mask)
+    }
+
+    fn start(&self, timeout: Duration) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.start_watchdog(timeout))
+    }
+
+    fn pet(&self) {
+        self.inner.lock(|inner| {
+            if let Some(ticks) = inner.watchdog_ticks {
+                inner.arm(ticks);
+            }
+        })
+    }
+
+    fn stop(&self) {
+        self.inner.lock(|inner| inner.stop_watchdog())
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.inner.lock(|inner| {
+            inner
+                .watchdog_ticks
+                .map(|_| ticks_to_duration(inner.registers.WDOG.read(WDOG::TIME)))
+        })
+    }
+}
@@ -0,0 +1,300 @@
+//! BCM2xxx hardware random number generator driver.
+//!
+//! Both boards have the RNG at the same place, but not the same block:
+//! - BCM2837: the bcm2835-rng, with the count of the words ready in the top byte of RNG_STATUS.
+//! - BCM2711: the RNG200, with a FIFO and its own count register.
+//!
+//! The first bits out of the generator are poor, each block is told to drop a warm-up count of
+//! them before it fills its FIFO.
+//!
+//! Reference: the Linux drivers, drivers/char/hw_random/bcm2835-rng.c and iproc-rng200.c
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    random,
+    synchronization::interface::Mutex,
+    synchronization::NullLock,
+    time,
+};
+use core::time::Duration;
+
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// bcm2835-rng control
+    RNG_CTRL [
+        RBGEN OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// bcm2835-rng status: write the warm-up count, read the words ready
+    RNG_STATUS [
+        WORDS OFFSET(24) NUMBITS(8) [],
+        WARMUP_COUNT OFFSET(0) NUMBITS(20) []
+    ],
+
+    RNG_INT_MASK [
+        INT_OFF OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// RNG200 control
+    RNG200_CTRL [
+        /// The sample rate divider
+        DIV_CTRL OFFSET(13) NUMBITS(2) [],
+        RBGEN OFFSET(0) NUMBITS(13) [
+            Enable = 0x1FFF
+        ]
+    ],
+
+    RNG200_FIFO_COUNT [
+        /// The count that raises the FIFO full interrupt
+        THRESHOLD OFFSET(8) NUMBITS(8) [],
+        COUNT OFFSET(0) NUMBITS(8) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => CTRL: ReadWrite<u32, RNG_CTRL::Register>),
+        (0x04 => STATUS: ReadWrite<u32, RNG_STATUS::Register>),
+        (0x08 => DATA: ReadOnly<u32>),
+        (0x0C => _reserved1),
+        (0x10 => INT_MASK: ReadWrite<u32, RNG_INT_MASK::Register>),
+        (0x14 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    Rng200RegisterBlock {
+        (0x00 => CTRL: ReadWrite<u32, RNG200_CTRL::Register>),
+        (0x04 => _reserved1),
+        (0x0C => TOTAL_BIT_COUNT: ReadOnly<u32>),
+        (0x10 => TOTAL_BIT_COUNT_THRESHOLD: ReadWrite<u32>),
+        (0x14 => _reserved2),
+        (0x20 => FIFO_DATA: ReadOnly<u32>),
+        (0x24 => FIFO_COUNT: ReadWrite<u32, RNG200_FIFO_COUNT::Register>),
+        (0x28 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+type Rng200Registers = MMIODerefWrapper<Rng200RegisterBlock>;
+
+/// The bits dropped before the first word (what Linux asks for)
+const WARMUP_COUNT: u32 = 0x4_0000;
+
+/// The RNG200 warms up at a 1 MHz sample rate
+const RNG200_DIV_CTRL: u32 = 3;
+
+/// The warm-up takes a fraction of this
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct RngInner {
+    registers: Registers,
+    rng200_registers: Rng200Registers,
+    variant: RngVariant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The random number generator blocks
+#[derive(Clone, Copy, PartialEq)]
+pub enum RngVariant {
+    /// BCM2835 to BCM2837
+    Bcm2835,
+    /// BCM2711
+    Rng200,
+}
+
+/// Represent the random number generator.
+pub struct Rng {
+    inner: NullLock<RngInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl RngInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            rng200_registers: Rng200Registers::new(mmio_start_addr),
+            variant: RngVariant::Bcm2835,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    unsafe fn set_mmio_start_addr(&mut self, mmio_start_addr: usize) {
+        self.registers = Registers::new(mmio_start_addr);
+        self.rng200_registers = Rng200Registers::new(mmio_start_addr);
+    }
+
+    /// Start the generator, unless the firmware already did (it then warmed up)
+    fn enable(&self) {
+        match self.variant {
+            RngVariant::Bcm2835 => {
+                if self.registers.CTRL.is_set(RNG_CTRL::RBGEN) {
+                    return;
+                }
+                // Polled, the interrupt is not used
+                self.registers.INT_MASK.modify(RNG_INT_MASK::INT_OFF::SET);
+                self.registers
+                    .STATUS
+                    .write(RNG_STATUS::WARMUP_COUNT.val(WARMUP_COUNT));
+                self.registers.CTRL.write(RNG_CTRL::RBGEN::SET);
+            }
+            RngVariant::Rng200 => {
+                if self.rng200_registers.CTRL.read(RNG200_CTRL::RBGEN) != 0 {
+                    return;
+                }
+                self.rng200_registers.TOTAL_BIT_COUNT_THRESHOLD.set(WARMUP_COUNT);
+                self.rng200_registers
+                    .FIFO_COUNT
+                    .write(RNG200_FIFO_COUNT::THRESHOLD.val(2));
+                self.rng200_registers.CTRL.write(
+                    RNG200_CTRL::DIV_CTRL.val(RNG200_DIV_CTRL) + RNG200_CTRL::RBGEN::Enable,
+                );
+            }
+        }
+    }
+
+    fn disable(&self) {
+        match self.variant {
+            RngVariant::Bcm2835 => self.registers.CTRL.set(0),
+            RngVariant::Rng200 => self.rng200_registers.CTRL.set(0),
+        }
+    }
+
+    /// The words ready to be read
+    fn words_ready(&self) -> usize {
+        match self.variant {
+            RngVariant::Bcm2835 => self.registers.STATUS.read(RNG_STATUS::WORDS) as usize,
+            RngVariant::Rng200 => {
+                // The FIFO fills with the warm-up bits first
+                if self.rng200_registers.TOTAL_BIT_COUNT.get() <= 16 {
+                    return 0;
+                }
+                self.rng200_registers
+                    .FIFO_COUNT
+                    .read(RNG200_FIFO_COUNT::COUNT) as usize
+            }
+        }
+    }
+
+    fn read_word(&self) -> u32 {
+        match self.variant {
+            RngVariant::Bcm2835 => self.registers.DATA.get(),
+            RngVariant::Rng200 => self.rng200_registers.FIFO_DATA.get(),
+        }
+    }
+
+    fn read_words(&self, buf: &mut [u32]) -> Result<(), Error> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let mut ready = 0;
+            time::time_manager()
+                .wait_until(
+                    || {
+                        ready = self.words_ready();
+                        ready > 0
+                    },
+                    READ_TIMEOUT,
+                )
+                .map_err(|_| Error::new(ErrorKind::Timeout, "RNG: no random words"))?;
+
+            for word in buf[filled..].iter_mut().take(ready) {
+                *word = self.read_word();
+                filled += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Rng {
+    /// Create new instance
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            inner: NullLock::new(RngInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.set_mmio_start_addr(mmio_start_addr));
+    }
+
+    /// Select the block of the board. Call it before the driver's init.
+    pub fn set_variant(&self, variant: RngVariant) {
+        self.inner.lock(|inner| inner.variant = variant);
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Rng {
+    fn compatible(&self) -> &'static str {
+        "BCM Random Number Generator Device driver version 1.0"
+    }
+
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.enable());
+
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.disable());
+
+        Ok(())
+    }
+}
+
+impl random::interface::EntropySource for Rng {
+    fn name(&self) -> &'static str {
+        "rng"
+    }
+
+    fn read_words(&self, buf: &mut [u32]) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.read_words(buf))
+    }
+}
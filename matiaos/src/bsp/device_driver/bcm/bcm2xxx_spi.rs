@@ -0,0 +1,555 @@
+//! BCM2xxx SPI0 master driver.
+//!
+//! SPI0 is on the header: CE1 on GPIO 7, CE0 on 8, MISO on 9, MOSI on 10, SCLK on 11 (alt 0).
+//! The driver claims the pins from the GPIO driver at init, and gives them back at shutdown.
+//!
+//! A transfer is full duplex: a byte is received for every byte sent. The FIFOs are 64 bytes
+//! each way. The CPU keeps them going, polling, or from the SPI interrupt (RX FIFO needs reading,
+//! transfer done).
+//!
+//! SCLK is the core clock divided by an even divider.
+//!
+//! Reference: BCM2837 ARM Peripherals, chapter 10 "SPI"
+
+use super::{Pin, PinFunction, Pull, GPIO};
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    println,
+    synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock,
+    time,
+};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::ReadWrite,
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Control and status
+    CS [
+        /// RX FIFO full, the transfer stalls until it is read
+        RXF OFFSET(20) NUMBITS(1) [],
+        /// RX FIFO needs reading (3/4 full)
+        RXR OFFSET(19) NUMBITS(1) [],
+        /// TX FIFO can accept data
+        TXD OFFSET(18) NUMBITS(1) [],
+        /// RX FIFO contains data
+        RXD OFFSET(17) NUMBITS(1) [],
+        /// Transfer done: TX FIFO empty, nothing being sent
+        DONE OFFSET(16) NUMBITS(1) [],
+        /// Interrupt on RXR
+        INTR OFFSET(10) NUMBITS(1) [],
+        /// Interrupt on DONE
+        INTD OFFSET(9) NUMBITS(1) [],
+        /// Transfer active, the chip select is asserted while it is set
+        TA OFFSET(7) NUMBITS(1) [],
+        /// Chip select active high
+        CSPOL OFFSET(6) NUMBITS(1) [],
+        CLEAR OFFSET(4) NUMBITS(2) [
+            Tx = 0b01,
+            Rx = 0b10,
+            Both = 0b11
+        ],
+        /// Clock polarity: idle high
+        CPOL OFFSET(3) NUMBITS(1) [],
+        /// Clock phase: sample on the second edge
+        CPHA OFFSET(2) NUMBITS(1) [],
+        CS OFFSET(0) NUMBITS(2) []
+    ],
+
+    CLK [
+        /// SCLK = core clock / CDIV, even (odd values are rounded down), 0 is 65536
+        CDIV OFFSET(0) NUMBITS(16) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => CS: ReadWrite<u32, CS::Register>),
+        (0x04 => FIFO: ReadWrite<u32>),
+        (0x08 => CLK: ReadWrite<u32, CLK::Register>),
+        (0x0C => DLEN: ReadWrite<u32>),
+        (0x10 => LTOH: ReadWrite<u32>),
+        (0x14 => DC: ReadWrite<u32>),
+        (0x18 => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// The depth of each FIFO, in bytes
+const FIFO_SIZE: usize = 64;
+
+/// CE1, CE0, MISO, MOSI, SCLK
+const PINS: [u8; 5] = [7, 8, 9, 10, 11];
+
+/// The largest even divider (0 in CDIV)
+const MAX_DIVIDER: u32 = 65536;
+
+/// Until the last byte is received. Generous: a full FIFO takes 8 ms at the slowest clock.
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A transfer under way. The buffers are the caller's, who waits for the end of the transfer:
+/// they outlive it. `read` and `write` may be the same buffer, a byte is sent before the one
+/// received in its place.
+struct Transfer {
+    write: *const u8,
+    write_len: usize,
+    read: *mut u8,
+    read_len: usize,
+    /// Bytes clocked: the longest of the two buffers
+    len: usize,
+    sent: usize,
+    received: usize,
+}
+
+// The buffer pointers are only used while the caller waits for the transfer
+unsafe impl Send for Transfer {}
+
+struct SpiInner {
+    registers: Registers,
+    core_clock_hz: u32,
+    config: SpiConfig,
+    /// Claimed while the driver is up
+    pins: Vec<Pin>,
+    /// The interrupt driven transfer, if one is under way
+    transfer: Option<Transfer>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Clock polarity (CPOL) and phase (CPHA), as numbered by everyone: mode = CPOL << 1 | CPHA.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SpiMode {
+    /// Idle low, sample on the rising edge
+    Mode0,
+    /// Idle low, sample on the falling edge
+    Mode1,
+    /// Idle high, sample on the falling edge
+    Mode2,
+    /// Idle high, sample on the rising edge
+    Mode3,
+}
+
+/// How the bus runs
+#[derive(Clone, Copy, Debug)]
+pub struct SpiConfig {
+    /// CE0 or CE1
+    pub chip_select: u8,
+    /// The SCLK asked for, the actual one is at most that
+    pub frequency_hz: u32,
+    pub mode: SpiMode,
+}
+
+/// Represent the SPI0 master.
+pub struct Spi {
+    inner: IRQSafeNullLock<SpiInner>,
+    gpio: &'static GPIO,
+    irq_number: IRQNumber,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl SpiMode {
+    fn cpol(self) -> bool {
+        matches!(self, Self::Mode2 | Self::Mode3)
+    }
+
+    fn cpha(self) -> bool {
+        matches!(self, Self::Mode1 | Self::Mode3)
+    }
+}
+
+impl SpiConfig {
+    const DEFAULT: Self = Self {
+        chip_select: 0,
+        frequency_hz: 1_000_000,
+        mode: SpiMode::Mode0,
+    };
+}
+
+impl Transfer {
+    fn new(read: *mut u8, read_len: usize, write: *const u8, write_len: usize) -> Self {
+        Self {
+            write,
+            write_len,
+            read,
+            read_len,
+            len: read_len.max(write_len),
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.received == self.len
+    }
+
+    /// Empty the RX FIFO and fill the TX one. Past the end of `write` zeros are sent, past the
+    /// end of `read` the bytes received are dropped.
+    fn pump(&mut self, registers: &Registers) {
+        while self.received < self.len && registers.CS.is_set(CS::RXD) {
+            let byte = registers.FIFO.get() as u8;
+            if self.received < self.read_len {
+                unsafe { self.read.add(self.received).write(byte) };
+            }
+            self.received += 1;
+        }
+
+        // No more in flight than the RX FIFO holds
+        while self.sent < self.len
+            && self.sent - self.received < FIFO_SIZE
+            && registers.CS.is_set(CS::TXD)
+        {
+            let byte = if self.sent < self.write_len {
+                unsafe { self.write.add(self.sent).read() }
+            } else {
+                0
+            };
+            registers.FIFO.set(u32::from(byte));
+            self.sent += 1;
+        }
+    }
+}
+
+impl SpiInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize, core_clock_hz: u32) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            core_clock_hz,
+            config: SpiConfig::DEFAULT,
+            pins: Vec::new(),
+            transfer: None,
+        }
+    }
+
+    /// The smallest even divider that doesn't go over `frequency_hz`
+    fn divider(&self, frequency_hz: u32) -> u32 {
+        let divider = self.core_clock_hz.div_ceil(frequency_hz.max(1));
+
+        divider.next_multiple_of(2).clamp(2, MAX_DIVIDER)
+    }
+
+    /// SCLK with `divider`
+    fn frequency_hz(&self, divider: u32) -> u32 {
+        self.core_clock_hz / divider
+    }
+
+    /// Program the clock, mode and chip select. The bus must be idle.
+    fn configure(&mut self, config: SpiConfig) -> Result<u32, Error> {
+        if config.chip_select > 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidArgument,
+                "SPI: no such chip select",
+            ));
+        }
+        if self.transfer.is_some() {
+            return Err(Error::new(ErrorKind::Busy, "SPI: transfer under way"));
+        }
+
+        let divider = self.divider(config.frequency_hz);
+        // 65536 is written as 0
+        self.registers
+            .CLK
+            .write(CLK::CDIV.val(divider % MAX_DIVIDER));
+        self.registers.CS.write(
+            CS::CS.val(u32::from(config.chip_select))
+                + CS::CPOL.val(u32::from(config.mode.cpol()))
+                + CS::CPHA.val(u32::from(config.mode.cpha()))
+                + CS::CLEAR::Both,
+        );
+        self.config = config;
+
+        Ok(self.frequency_hz(divider))
+    }
+
+    /// Empty the FIFOs and assert the chip select
+    fn start(&self, interrupts: bool) {
+        self.registers.CS.modify(
+            CS::CLEAR::Both
+                + CS::INTR.val(u32::from(interrupts))
+                + CS::INTD.val(u32::from(interrupts))
+                + CS::TA::SET,
+        );
+    }
+
+    /// Release the chip select, with the FIFOs emptied of what an aborted transfer left
+    fn stop(&self) {
+        self.registers
+            .CS
+            .modify(CS::TA::CLEAR + CS::INTR::CLEAR + CS::INTD::CLEAR + CS::CLEAR::Both);
+    }
+
+    fn transfer_polled(&self, mut transfer: Transfer) -> Result<(), Error> {
+        if self.transfer.is_some() {
+            return Err(Error::new(ErrorKind::Busy, "SPI: transfer under way"));
+        }
+
+        self.start(false);
+        let result = time::time_manager().wait_until(
+            || {
+                transfer.pump(&self.registers);
+                transfer.is_done()
+            },
+            TRANSFER_TIMEOUT,
+        );
+        self.stop();
+
+        result.map_err(|_| Error::new(ErrorKind::Timeout, "SPI: transfer timed out"))
+    }
+
+    /// Start an interrupt driven transfer: the first bytes go now, the handler does the rest.
+    fn start_transfer_irq(&mut self, mut transfer: Transfer) -> Result<(), Error> {
+        if self.transfer.is_some() {
+            return Err(Error::new(ErrorKind::Busy, "SPI: transfer under way"));
+        }
+
+        self.start(true);
+        transfer.pump(&self.registers);
+        self.transfer = Some(transfer);
+
+        Ok(())
+    }
+
+    /// Whether the interrupt driven transfer is over
+    fn transfer_irq_done(&self) -> bool {
+        self.transfer.as_ref().is_none_or(|x| x.is_done())
+    }
+
+    /// End the interrupt driven transfer, done or not
+    fn end_transfer_irq(&mut self) {
+        self.stop();
+        self.transfer = None;
+    }
+
+    /// Move the bytes of the interrupt driven transfer, and stop the interrupts at its end
+    fn handle_interrupt(&mut self) {
+        let registers = &self.registers;
+        match &mut self.transfer {
+            Some(transfer) => {
+                transfer.pump(registers);
+                if transfer.is_done() {
+                    registers.CS.modify(CS::INTR::CLEAR + CS::INTD::CLEAR);
+                }
+            }
+            // Nobody to give the bytes to
+            None => self.stop(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SpiMode {
+    /// The mode numbered `mode` (0 to 3)
+    pub fn from_number(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(Self::Mode0),
+            1 => Some(Self::Mode1),
+            2 => Some(Self::Mode2),
+            3 => Some(Self::Mode3),
+            _ => None,
+        }
+    }
+}
+
+impl Spi {
+    /// Create new instance, the pins are taken from `gpio`. SCLK is derived from `core_clock_hz`.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(
+        mmio_start_addr: usize,
+        gpio: &'static GPIO,
+        core_clock_hz: u32,
+        irq_number: IRQNumber,
+    ) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(SpiInner::new(mmio_start_addr, core_clock_hz)),
+            gpio,
+            irq_number,
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner
+            .lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// Set the core clock the SCLK is divided from. Call it before the driver's init.
+    pub fn set_core_clock_hz(&self, core_clock_hz: u32) {
+        self.inner.lock(|inner| inner.core_clock_hz = core_clock_hz);
+    }
+
+    /// Select the chip, clock and mode of the next transfers. Returns the actual SCLK.
+    pub fn configure(&self, config: SpiConfig) -> Result<u32, Error> {
+        self.inner.lock(|inner| inner.configure(config))
+    }
+
+    /// Send `write` while receiving into `read`, polling the FIFOs. The longest of the two sets
+    /// the length: zeros are sent past the end of `write`, the bytes past the end of `read` are
+    /// dropped.
+    pub fn transfer(&self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let transfer = Transfer::new(read.as_mut_ptr(), read.len(), write.as_ptr(), write.len());
+
+        self.inner.lock(|inner| inner.transfer_polled(transfer))
+    }
+
+    /// Send `buf`, replacing it with the bytes received, polling the FIFOs.
+    pub fn transfer_in_place(&self, buf: &mut [u8]) -> Result<(), Error> {
+        let ptr = buf.as_mut_ptr();
+        let transfer = Transfer::new(ptr, buf.len(), ptr, buf.len());
+
+        self.inner.lock(|inner| inner.transfer_polled(transfer))
+    }
+
+    /// Like [`Spi::transfer`], with the FIFOs served from the interrupt handler. The CPU waits
+    /// for the end of the transfer with the interrupts enabled.
+    pub fn transfer_irq(&self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let transfer = Transfer::new(read.as_mut_ptr(), read.len(), write.as_ptr(), write.len());
+        self.inner
+            .lock(|inner| inner.start_transfer_irq(transfer))?;
+
+        // `read` and `write` are borrowed until the transfer is ended, done or not
+        let result = time::time_manager().wait_until(
+            || self.inner.lock(|inner| inner.transfer_irq_done()),
+            TRANSFER_TIMEOUT,
+        );
+        self.inner.lock(|inner| inner.end_transfer_irq());
+
+        result.map_err(|_| Error::new(ErrorKind::Timeout, "SPI: transfer timed out"))
+    }
+
+    /// Print the configuration
+    pub fn dump(&self) {
+        let (config, divider, frequency_hz) = self.inner.lock(|inner| {
+            let divider = inner.divider(inner.config.frequency_hz);
+            (inner.config, divider, inner.frequency_hz(divider))
+        });
+
+        println!(
+            "SPI0: CE{}, mode {}, {} Hz (divider {})",
+            config.chip_select, config.mode as u8, frequency_hz, divider
+        );
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for Spi {
+    fn compatible(&self) -> &'static str {
+        "BCM SPI0 Device driver version 1.0"
+    }
+
+    /// Route the pins to SPI0, and program the default configuration
+    fn init(&self) -> Result<(), Error> {
+        let mut pins = Vec::with_capacity(PINS.len());
+        for number in PINS {
+            let mut pin = self.gpio.claim(number)?;
+            pin.set_function(PinFunction::Alt0);
+            pin.set_pull(Pull::None);
+            pins.push(pin);
+        }
+
+        self.inner.lock(|inner| {
+            inner.pins = pins;
+            inner.configure(inner.config)
+        })?;
+
+        Ok(())
+    }
+
+    /// Stop any transfer, and give the pins back
+    fn shutdown(&self) -> Result<(), Error> {
+        let pins = self.inner.lock(|inner| {
+            inner.end_transfer_irq();
+            core::mem::take(&mut inner.pins)
+        });
+        drop(pins);
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(&'static self) -> Result<(), Error> {
+        use exception::asynchronous::irq_manager;
+
+        let descriptor = IRQHandlerDescriptor::new(self.irq_number, "BCM SPI0", self);
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(&self.irq_number);
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for Spi {
+    fn handle(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| inner.handle_interrupt());
+
+        Ok(())
+    }
+}
+
+// embedded-hal: the bus of the ecosystem's SPI drivers. The chip select is the hardware's, it is
+// asserted for each call: a transaction of several operations needs a GPIO chip select (i.e.
+// embedded-hal-bus' `ExclusiveDevice`) with CE0/CE1 left unconnected.
+impl embedded_hal::spi::ErrorType for &Spi {
+    type Error = Error;
+}
+
+impl embedded_hal::spi::SpiBus for &Spi {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        Spi::transfer(self, words, &[])
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        Spi::transfer(self, &mut [], words)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        Spi::transfer(self, read, write)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        Spi::transfer_in_place(self, words)
+    }
+
+    /// The transfers are over when they return
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
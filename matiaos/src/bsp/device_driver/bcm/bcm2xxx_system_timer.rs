@@ -0,0 +1,288 @@
+//! BCM2xxx system timer driver.
+//!
+//! A free running 64-bit counter at 1 MHz, with four 32-bit compare channels: when the low half
+//! of the counter equals a channel's compare register, the channel's match bit is set and its
+//! interrupt fires. The VideoCore uses channels 0 and 2, the ARM gets 1 and 3.
+//!
+//! The counter is a second clock source next to the ARM generic timer (see `time`).
+//!
+//! Reference: BCM2837 ARM Peripherals, chapter 12 "System Timer"
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver,
+    error::{Error, ErrorKind},
+    exception,
+    exception::asynchronous::{IRQHandlerDescriptor, IRQNumber},
+    synchronization::interface::Mutex,
+    synchronization::IRQSafeNullLock,
+    time,
+};
+use core::time::Duration;
+
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//----------------------------------------
+// private stuff
+//----------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Control/Status: one match bit per channel, write 1 to clear it
+    CS [
+        M3 OFFSET(3) NUMBITS(1) [],
+        M2 OFFSET(2) NUMBITS(1) [],
+        M1 OFFSET(1) NUMBITS(1) [],
+        M0 OFFSET(0) NUMBITS(1) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => CS: ReadWrite<u32, CS::Register>),
+        (0x04 => CLO: ReadOnly<u32>),
+        (0x08 => CHI: ReadOnly<u32>),
+        (0x0C => C: [ReadWrite<u32>; NUM_CHANNELS]),
+        (0x1C => @END),
+    }
+}
+
+// abtracts the register calling
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+const NUM_CHANNELS: usize = 4;
+
+/// The counter runs at 1 MHz
+const COUNTER_RESOLUTION: Duration = Duration::from_micros(1);
+
+/// A compare value is only matched when the counter gets to it, one already passed waits for the
+/// next wrap (71 minutes). The setup takes well below that.
+const MIN_ALARM_US: u32 = 2;
+
+struct SystemTimerInner {
+    registers: Registers,
+    alarms: [Option<AlarmCallback>; NUM_CHANNELS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The compare channels the ARM can use
+pub const ARM_CHANNELS: [usize; 2] = [1, 3];
+
+/// Called from the interrupt handler with the channel of the alarm that went off.
+pub type AlarmCallback = fn(channel: usize);
+
+/// Represent the system timer.
+pub struct SystemTimer {
+    inner: IRQSafeNullLock<SystemTimerInner>,
+    irq_numbers: &'static [IRQNumber],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn check_channel(channel: usize) -> Result<(), Error> {
+    if ARM_CHANNELS.contains(&channel) {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidArgument,
+            "Not a system timer channel of the ARM",
+        ))
+    }
+}
+
+impl SystemTimerInner {
+    /// # Safety
+    ///
+    /// - verify mmio start address
+    const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            alarms: [None; NUM_CHANNELS],
+        }
+    }
+
+    /// The two halves are read apart, read again if the high one moved in between
+    fn counter(&self) -> u64 {
+        loop {
+            let high = self.registers.CHI.get();
+            let low = self.registers.CLO.get();
+
+            if self.registers.CHI.get() == high {
+                return (u64::from(high) << 32) | u64::from(low);
+            }
+        }
+    }
+
+    fn clear_match(&self, channel: usize) {
+        self.registers.CS.set(1 << channel);
+    }
+
+    fn set_alarm(&mut self, channel: usize, delay: Duration, callback: AlarmCallback) {
+        // The caller checked that the delay fits
+        let delay = (delay.as_micros() as u32).max(MIN_ALARM_US);
+
+        self.alarms[channel] = Some(callback);
+        self.clear_match(channel);
+        self.registers.C[channel].set(self.registers.CLO.get().wrapping_add(delay));
+    }
+
+    fn cancel_alarm(&mut self, channel: usize) {
+        self.alarms[channel] = None;
+        self.clear_match(channel);
+    }
+
+    /// Acknowledge the matches, and take the alarms they set off
+    fn take_matches(&mut self) -> [Option<AlarmCallback>; NUM_CHANNELS] {
+        let status = self.registers.CS.get();
+        let mut fired = [None; NUM_CHANNELS];
+
+        for channel in ARM_CHANNELS {
+            if status & (1 << channel) != 0 {
+                self.clear_match(channel);
+                fired[channel] = self.alarms[channel].take();
+            }
+        }
+
+        fired
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimer {
+    /// Create an instance of the system timer driver
+    /// `irq_numbers` are the interrupts of the ARM channels
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub const unsafe fn new(mmio_start_addr: usize, irq_numbers: &'static [IRQNumber]) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(SystemTimerInner::new(mmio_start_addr)),
+            irq_numbers,
+        }
+    }
+
+    /// Move the registers to `mmio_start_addr` (i.e. found in the device tree). Call it before
+    /// the driver's init.
+    ///
+    /// # Safety
+    ///
+    /// - Provide correct MMIO start address
+    pub unsafe fn set_mmio_start_addr(&self, mmio_start_addr: usize) {
+        self.inner.lock(|inner| inner.registers = Registers::new(mmio_start_addr));
+    }
+
+    /// The counter, in microseconds since the timer started (power-on).
+    pub fn counter(&self) -> u64 {
+        self.inner.lock(|inner| inner.counter())
+    }
+
+    /// Call `callback` from the timer's interrupt once `delay` passed, on one of the
+    /// `ARM_CHANNELS`. It replaces the channel's pending alarm.
+    ///
+    /// The compare registers are 32-bit: the longest delay is about 71 minutes.
+    pub fn set_alarm(
+        &self,
+        channel: usize,
+        delay: Duration,
+        callback: AlarmCallback,
+    ) -> Result<(), Error> {
+        check_channel(channel)?;
+        if delay.as_micros() > u128::from(u32::MAX) {
+            return Err(Error::new(ErrorKind::InvalidArgument, "Alarm too far away"));
+        }
+
+        self.inner.lock(|inner| inner.set_alarm(channel, delay, callback));
+
+        Ok(())
+    }
+
+    /// Drop the pending alarm of a channel, if any.
+    pub fn cancel_alarm(&self, channel: usize) -> Result<(), Error> {
+        check_channel(channel)?;
+        self.inner.lock(|inner| inner.cancel_alarm(channel));
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------
+// Interface code
+// -----------------------------------------------
+
+impl driver::interface::DeviceDriver for SystemTimer {
+    fn compatible(&self) -> &'static str {
+        "BCM System Timer Device driver version 1.0"
+    }
+
+    /// Drop the matches left by the firmware, the counter itself never stops
+    fn init(&self) -> Result<(), Error> {
+        self.inner.lock(|inner| {
+            for channel in ARM_CHANNELS {
+                inner.cancel_alarm(channel);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.init()
+    }
+
+    fn register_and_enable_irq_handler(&'static self) -> Result<(), Error> {
+        use exception::asynchronous::irq_manager;
+
+        for irq_number in self.irq_numbers {
+            let descriptor = IRQHandlerDescriptor::new(*irq_number, "BCM System Timer", self);
+
+            irq_manager().register_handler(descriptor)?;
+            irq_manager().enable(irq_number);
+        }
+
+        Ok(())
+    }
+}
+
+impl exception::asynchronous::interface::IRQHandler for SystemTimer {
+    fn handle(&self) -> Result<(), Error> {
+        let fired = self.inner.lock(|inner| inner.take_matches());
+
+        // The callbacks run outside of the lock, they may set the next alarm
+        for (channel, callback) in fired.into_iter().enumerate() {
+            if let Some(callback) = callback {
+                callback(channel);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl time::interface::ClockSource for SystemTimer {
+    fn name(&self) -> &'static str {
+        "system_timer"
+    }
+
+    fn now(&self) -> Duration {
+        Duration::from_micros(self.counter())
+    }
+
+    fn resolution(&self) -> Duration {
+        COUNTER_RESOLUTION
+    }
+}
@@ -5,22 +5,16 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 
-//! board specific code for Raspberry Pi (currently 3) 
+//! board specific code for Raspberry Pi 3 and 4. The board is detected at boot.
 
+pub mod board;
 pub mod cpu;
+pub mod devicetree;
 pub mod driver;
+pub mod exception;
 pub mod memory;
 
 /// Returns the board's name (rpi3, rpi4)
 pub fn board_name() -> &'static str {
-    #[cfg(feature="bsp_rpi3")]
-    {
-        "Raspberry pi 3"
-    }
-    
-    #[cfg(feature="bsp_rpi4")]
-    {
-
-        "Raspberry pi 4"
-    }
+    board::board().name()
 }
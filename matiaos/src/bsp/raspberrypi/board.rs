@@ -0,0 +1,85 @@
+//! Which Raspberry Pi the kernel runs on, detected at boot.
+//!
+//! The root `compatible` of the device tree says it. Without a device tree, the cores do: the
+//! BCM2837 (RPi3) has Cortex-A53 cores, the BCM2711 (RPi4) Cortex-A72 ones.
+
+use crate::{
+    cpu, fdt,
+    synchronization::{interface::Mutex, NullLock},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// MIDR_EL1 part number of the Cortex-A72
+const CORTEX_A72_PART_NUMBER: u64 = 0xD08;
+
+/// The board, and what told us
+static BOARD: NullLock<(Board, &'static str)> = NullLock::new((Board::RPi3, "default"));
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The supported boards
+#[derive(Clone, Copy, PartialEq)]
+pub enum Board {
+    /// Raspberry Pi 3 (BCM2837)
+    RPi3,
+    /// Raspberry Pi 4 (BCM2711)
+    RPi4,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn detect() -> (Board, &'static str) {
+    let root = fdt::boot_fdt().ok().and_then(|fdt| fdt.find_node("/"));
+    if let Some(root) = root {
+        if root.is_compatible("brcm,bcm2711") {
+            return (Board::RPi4, "device tree");
+        }
+        if root.is_compatible("brcm,bcm2837") {
+            return (Board::RPi3, "device tree");
+        }
+    }
+
+    if cpu::core_part_number() == CORTEX_A72_PART_NUMBER {
+        (Board::RPi4, "CPU part number")
+    } else {
+        (Board::RPi3, "CPU part number")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Board {
+    /// Board name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Board::RPi3 => "Raspberry pi 3",
+            Board::RPi4 => "Raspberry pi 4",
+        }
+    }
+}
+
+/// Detect the board. Called first thing, the drivers' addresses depend on it.
+pub fn init() {
+    let board = detect();
+
+    BOARD.lock(|x| *x = board);
+}
+
+/// The board we run on.
+pub fn board() -> Board {
+    BOARD.lock(|x| x.0)
+}
+
+/// How the board was detected.
+pub fn detected_by() -> &'static str {
+    BOARD.lock(|x| x.1)
+}
@@ -0,0 +1,231 @@
+//! Hardware discovery from the device tree the firmware passes.
+//!
+//! The memory ranges, the base addresses of the devices, the kernel command line
+//! (`/chosen/bootargs`) and the console (`/chosen/stdout-path`) come from the device tree.
+//! Whatever it doesn't have comes from the compiled-in map of the board (`memory::map`), and so
+//! does everything when the kernel is started without one (QEMU without `-dtb`).
+
+use super::{board, memory::map::Mmio};
+use crate::{
+    fdt,
+    synchronization::{interface::Mutex, NullLock},
+};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// `compatible` strings of the devices we look up
+const PL011_UART_COMPATIBLE: &[&str] = &["arm,pl011"];
+const MINI_UART_COMPATIBLE: &[&str] = &["brcm,bcm2835-aux-uart"];
+const GPIO_COMPATIBLE: &[&str] = &["brcm,bcm2835-gpio", "brcm,bcm2711-gpio"];
+const INTERRUPT_CONTROLLER_COMPATIBLE: &[&str] = &[
+    "brcm,bcm2836-armctrl-ic",
+    "brcm,bcm2835-armctrl-ic",
+    "brcm,bcm2711-armctrl-ic",
+];
+const MAILBOX_COMPATIBLE: &[&str] = &["brcm,bcm2835-mbox"];
+/// The controller of the SD card slot: EMMC2 on the BCM2711, the Arasan one before
+const EMMC_COMPATIBLE: &[&str] = &["brcm,bcm2711-emmc2", "brcm,bcm2835-sdhci"];
+const SYSTEM_TIMER_COMPATIBLE: &[&str] = &["brcm,bcm2835-system-timer"];
+/// The first `reg` is the power manager (with the watchdog)
+const POWER_MANAGER_COMPATIBLE: &[&str] = &["brcm,bcm2835-pm", "brcm,bcm2835-pm-wdt"];
+/// Has the `brcm,dma-channel-mask` of the channels left to the ARM
+const DMA_COMPATIBLE: &[&str] = &["brcm,bcm2835-dma"];
+const RNG_COMPATIBLE: &[&str] = &["brcm,bcm2835-rng", "brcm,bcm2711-rng200"];
+/// SPI0 comes first in the tree, the BCM2711's SPI3 to SPI6 are the same block
+const SPI_COMPATIBLE: &[&str] = &["brcm,bcm2835-spi"];
+/// The alias of the I2C master on the header, the BSCs are all "brcm,bcm2835-i2c"
+const I2C1_ALIAS: &str = "i2c1";
+/// `reg` is the distributor, then the CPU interface
+const GIC_COMPATIBLE: &[&str] = &["arm,gic-400", "arm,cortex-a15-gic"];
+
+struct Platform {
+    /// Size of the device tree, or why there is none
+    device_tree: Result<usize, &'static str>,
+    memory: Vec<Range<usize>>,
+    mmio: Mmio,
+    bootargs: Option<&'static str>,
+    stdout_path: Option<&'static str>,
+    serial_console: Option<SerialConsole>,
+    dma_channel_mask: Option<u32>,
+}
+
+static PLATFORM: NullLock<Platform> = NullLock::new(Platform::COMPILED_IN);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The UART `/chosen/stdout-path` designates
+#[derive(Clone, Copy, PartialEq)]
+pub enum SerialConsole {
+    /// The PL011 UART
+    PL011,
+    /// The mini UART
+    MiniUart,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Platform {
+    /// Without device tree. The memory range and the map of the board are filled by `init`.
+    const COMPILED_IN: Self = Self {
+        device_tree: Err("Not parsed yet"),
+        memory: Vec::new(),
+        mmio: Mmio::of(board::Board::RPi3),
+        bootargs: None,
+        stdout_path: None,
+        serial_console: None,
+        dma_channel_mask: None,
+    };
+}
+
+/// `reg` address `index` of the first node compatible with one of `compatible`
+fn device_address(fdt: &fdt::Fdt, compatible: &[&str], index: usize) -> Option<usize> {
+    compatible
+        .iter()
+        .find_map(|x| fdt.find_compatible(x))
+        .and_then(|node| node.address(index))
+}
+
+/// The ranges of the `memory` nodes
+fn find_memory_ranges(fdt: &fdt::Fdt) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    fdt.for_each_node(|node| {
+        if node.property_str("device_type") != Some("memory") {
+            return;
+        }
+
+        for (address, size) in node.reg().into_iter().flatten() {
+            ranges.push(address as usize..(address + size) as usize);
+        }
+    });
+
+    ranges
+}
+
+fn parse(fdt: &fdt::Fdt, compiled_in: Mmio) -> Platform {
+    let mmio = Mmio {
+        start: compiled_in.start,
+        gpio_start: device_address(fdt, GPIO_COMPATIBLE, 0).unwrap_or(compiled_in.gpio_start),
+        pl011_uart_start: device_address(fdt, PL011_UART_COMPATIBLE, 0)
+            .unwrap_or(compiled_in.pl011_uart_start),
+        mini_uart_start: device_address(fdt, MINI_UART_COMPATIBLE, 0)
+            // The node is the UART, the driver wants the AUX block it is in
+            .map(|x| x & !0xFFF)
+            .unwrap_or(compiled_in.mini_uart_start),
+        mailbox_start: device_address(fdt, MAILBOX_COMPATIBLE, 0)
+            .unwrap_or(compiled_in.mailbox_start),
+        interrupt_controller_start: device_address(fdt, INTERRUPT_CONTROLLER_COMPATIBLE, 0)
+            .unwrap_or(compiled_in.interrupt_controller_start),
+        emmc_start: device_address(fdt, EMMC_COMPATIBLE, 0).unwrap_or(compiled_in.emmc_start),
+        system_timer_start: device_address(fdt, SYSTEM_TIMER_COMPATIBLE, 0)
+            .unwrap_or(compiled_in.system_timer_start),
+        power_manager_start: device_address(fdt, POWER_MANAGER_COMPATIBLE, 0)
+            .unwrap_or(compiled_in.power_manager_start),
+        rng_start: device_address(fdt, RNG_COMPATIBLE, 0).unwrap_or(compiled_in.rng_start),
+        dma_start: device_address(fdt, DMA_COMPATIBLE, 0).unwrap_or(compiled_in.dma_start),
+        spi0_start: device_address(fdt, SPI_COMPATIBLE, 0).unwrap_or(compiled_in.spi0_start),
+        i2c1_start: fdt
+            .resolve(I2C1_ALIAS)
+            .and_then(|node| node.address(0))
+            .unwrap_or(compiled_in.i2c1_start),
+        gicd_start: device_address(fdt, GIC_COMPATIBLE, 0).unwrap_or(compiled_in.gicd_start),
+        gicc_start: device_address(fdt, GIC_COMPATIBLE, 1).unwrap_or(compiled_in.gicc_start),
+    };
+
+    let chosen = fdt.find_node("/chosen");
+    // "serial0:115200n8", an alias or a path, then the options
+    let stdout_path = chosen.and_then(|x| x.property_str("stdout-path"));
+    let serial_console = stdout_path
+        .and_then(|x| fdt.resolve(x.split(':').next()?))
+        .and_then(|node| {
+            if MINI_UART_COMPATIBLE.iter().any(|x| node.is_compatible(x)) {
+                Some(SerialConsole::MiniUart)
+            } else if PL011_UART_COMPATIBLE.iter().any(|x| node.is_compatible(x)) {
+                Some(SerialConsole::PL011)
+            } else {
+                None
+            }
+        });
+
+    let dma_channel_mask = DMA_COMPATIBLE
+        .iter()
+        .find_map(|x| fdt.find_compatible(x))
+        .and_then(|node| node.property("brcm,dma-channel-mask"))
+        .and_then(|x| Some(u32::from_be_bytes(x.get(..4)?.try_into().ok()?)));
+
+    Platform {
+        device_tree: Ok(fdt.size()),
+        memory: find_memory_ranges(fdt),
+        mmio,
+        bootargs: chosen.and_then(|x| x.property_str("bootargs")),
+        stdout_path,
+        serial_console,
+        dma_channel_mask,
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Read the device tree the firmware passed, if any. Called before the drivers are set up, after
+/// the board is detected.
+pub fn init() {
+    let compiled_in = Mmio::of(board::board());
+    let mut platform = match fdt::boot_fdt() {
+        Ok(fdt) => parse(&fdt, compiled_in),
+        Err(e) => Platform {
+            device_tree: Err(e),
+            mmio: compiled_in,
+            ..Platform::COMPILED_IN
+        },
+    };
+    // Everything below the peripherals
+    if platform.memory.is_empty() {
+        platform.memory.push(0..platform.mmio.start);
+    }
+
+    PLATFORM.lock(|x| *x = platform);
+}
+
+/// Size of the device tree, or why there is none.
+pub fn device_tree() -> Result<usize, &'static str> {
+    PLATFORM.lock(|x| x.device_tree)
+}
+
+/// The RAM of the ARM cores.
+pub fn memory_ranges() -> Vec<Range<usize>> {
+    PLATFORM.lock(|x| x.memory.clone())
+}
+
+/// Where the devices are.
+pub fn mmio() -> Mmio {
+    PLATFORM.lock(|x| x.mmio)
+}
+
+/// The kernel command line.
+pub fn bootargs() -> Option<&'static str> {
+    PLATFORM.lock(|x| x.bootargs)
+}
+
+/// The console path, as the device tree has it (i.e. `serial0:115200n8`).
+pub fn stdout_path() -> Option<&'static str> {
+    PLATFORM.lock(|x| x.stdout_path)
+}
+
+/// The UART the console path designates.
+pub fn serial_console() -> Option<SerialConsole> {
+    PLATFORM.lock(|x| x.serial_console)
+}
+
+/// The DMA channels the firmware leaves to the ARM, one bit per channel.
+pub fn dma_channel_mask() -> Option<u32> {
+    PLATFORM.lock(|x| x.dma_channel_mask)
+}
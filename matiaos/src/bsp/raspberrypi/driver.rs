@@ -5,45 +5,521 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 
+use super::board::{self, Board};
+use super::devicetree;
 use crate::bsp::device_driver;
-use crate::bsp::memory::map;
+use crate::bsp::exception::asynchronous::irq_map;
+use crate::block;
+use crate::bsp::memory::map::Mmio;
 use crate::console;
 use crate::driver as generic_driver;
+use crate::error::{Error, ErrorKind};
+use crate::exception;
+use crate::power;
+use crate::random;
+use crate::time;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 
-// Global instances of the drivers, created first at boot (`kernel_init`)
+// Global instances of the drivers, created first at boot (`kernel_init`). They start with the
+// RPi3 map and are moved to the detected board's devices before their init.
 static PL011_UART: device_driver::PL011Uart =
-    unsafe { device_driver::PL011Uart::new(map::mmio::PL011_UART_START) };
-static GPIO: device_driver::GPIO = unsafe { device_driver::GPIO::new(map::mmio::GPIO_START) };
+    unsafe { device_driver::PL011Uart::new(DEFAULT_MMIO.pl011_uart_start) };
+static MINI_UART: device_driver::MiniUart = unsafe {
+    device_driver::MiniUart::new(DEFAULT_MMIO.mini_uart_start, core_clock_hz(Board::RPi3))
+};
+static GPIO: device_driver::GPIO =
+    unsafe { device_driver::GPIO::new(DEFAULT_MMIO.gpio_start, &irq_map::GPIO) };
+static MAILBOX: device_driver::Mailbox =
+    unsafe { device_driver::Mailbox::new(DEFAULT_MMIO.mailbox_start) };
+static FRAMEBUFFER: device_driver::Framebuffer =
+    device_driver::Framebuffer::new(&MAILBOX, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT);
+static EMMC: device_driver::Emmc = unsafe {
+    device_driver::Emmc::new(DEFAULT_MMIO.emmc_start, &MAILBOX, emmc_clock_id(Board::RPi3))
+};
+static POWER_MANAGER: device_driver::PowerManager =
+    unsafe { device_driver::PowerManager::new(DEFAULT_MMIO.power_manager_start) };
+static DMA: device_driver::Dma =
+    unsafe { device_driver::Dma::new(DEFAULT_MMIO.dma_start, &irq_map::DMA) };
+static SPI: device_driver::Spi = unsafe {
+    device_driver::Spi::new(
+        DEFAULT_MMIO.spi0_start,
+        &GPIO,
+        core_clock_hz(Board::RPi3),
+        irq_map::SPI,
+    )
+};
+static I2C: device_driver::I2c = unsafe {
+    device_driver::I2c::new(DEFAULT_MMIO.i2c1_start, &GPIO, core_clock_hz(Board::RPi3))
+};
+static RNG: device_driver::Rng = unsafe { device_driver::Rng::new(DEFAULT_MMIO.rng_start) };
+static SYSTEM_TIMER: device_driver::SystemTimer = unsafe {
+    device_driver::SystemTimer::new(DEFAULT_MMIO.system_timer_start, &irq_map::SYSTEM_TIMER)
+};
+static INTERRUPT_CONTROLLER: device_driver::InterruptController =
+    unsafe { device_driver::InterruptController::new(DEFAULT_MMIO.interrupt_controller_start) };
+static GIC: device_driver::GICv2 = unsafe {
+    device_driver::GICv2::new(
+        Mmio::of(Board::RPi4).gicd_start,
+        Mmio::of(Board::RPi4).gicc_start,
+    )
+};
+
+const DEFAULT_MMIO: Mmio = Mmio::of(Board::RPi3);
+
+/// The VPU core clock the mini UART baudrate and the SPI and I2C clocks are derived from
+/// (`core_freq` with `enable_uart=1`).
+const fn core_clock_hz(board: Board) -> u32 {
+    match board {
+        Board::RPi3 => 250_000_000,
+        Board::RPi4 => 500_000_000,
+    }
+}
+
+/// The mailbox clock of the SD card controller
+const fn emmc_clock_id(board: Board) -> u32 {
+    match board {
+        Board::RPi3 => device_driver::clock_id::EMMC,
+        Board::RPi4 => device_driver::clock_id::EMMC2,
+    }
+}
+
+/// How the GPIO pull resistors are set
+const fn pull_scheme(board: Board) -> device_driver::PullScheme {
+    match board {
+        Board::RPi3 => device_driver::PullScheme::Bcm2837,
+        Board::RPi4 => device_driver::PullScheme::Bcm2711,
+    }
+}
+
+/// The DMA channels the firmware leaves to the ARM, when the device tree doesn't say
+const fn dma_channel_mask(board: Board) -> u32 {
+    match board {
+        Board::RPi3 => 0x7F35,
+        Board::RPi4 => 0x31F5,
+    }
+}
+
+/// The random number generator block
+const fn rng_variant(board: Board) -> device_driver::RngVariant {
+    match board {
+        Board::RPi3 => device_driver::RngVariant::Bcm2835,
+        Board::RPi4 => device_driver::RngVariant::Rng200,
+    }
+}
+
+/// HDMI resolution
+const FRAMEBUFFER_WIDTH: u32 = 1024;
+const FRAMEBUFFER_HEIGHT: u32 = 768;
+
+/// The UARTs that can back the console.
+#[derive(PartialEq)]
+enum ConsoleUart {
+    PL011,
+    MiniUart,
+}
+
+/// The PL011 and the mini UART are both wired to GPIO 14/15, but with a different alt function,
+/// so only one of them is used. The mini UART is the one on the header when Bluetooth owns the
+/// PL011 (stock config).
+///
+/// The console features decide, otherwise the device tree's console path does (it knows who
+/// owns the PL011), otherwise it is the PL011.
+fn console_uart() -> ConsoleUart {
+    if cfg!(feature = "console_mini_uart") {
+        ConsoleUart::MiniUart
+    } else {
+        match devicetree::serial_console() {
+            Some(devicetree::SerialConsole::MiniUart) => ConsoleUart::MiniUart,
+            _ => ConsoleUart::PL011,
+        }
+    }
+}
 
 /// This must be called only after successful init of the UART driver.
-fn post_init_uart() -> Result<(), &'static str> {
-    console::register_console(&PL011_UART);
+fn post_init_uart() -> Result<(), Error> {
+    console::register_console("uart", &PL011_UART)
+}
+
+/// This must be called only after successful init of the mini UART driver.
+fn post_init_mini_uart() -> Result<(), Error> {
+    console::register_console("uart", &MINI_UART)
+}
+
+/// This must be called only after successful init of the framebuffer driver.
+///
+/// The screen is always a console, it is only muted when not asked for.
+fn post_init_framebuffer() -> Result<(), Error> {
+    console::register_console("framebuffer", &FRAMEBUFFER)?;
+    if !cfg!(feature = "console_framebuffer") {
+        console::set_muted("framebuffer", true)?;
+    }
 
     Ok(())
 }
 
 /// This must be called only after successful init of the GPIO driver.
-fn post_init_gpio() -> Result<(), &'static str> {
-    GPIO.init_gpio_uart_pins();
+fn post_init_gpio() -> Result<(), Error> {
+    match console_uart() {
+        ConsoleUart::MiniUart => GPIO.init_gpio_mini_uart_pins(),
+        _ => GPIO.init_gpio_uart_pins(),
+    }
+
     Ok(())
 }
 
-fn driver_uart() -> Result<(), &'static str> {
-    let uart_descriptor =
-        generic_driver::DeviceDriverDescriptor::new(&PL011_UART, Some(post_init_uart));
+/// This must be called only after successful init of the EMMC driver.
+fn post_init_emmc() -> Result<(), Error> {
+    // The driver is fine without a card, but there is no block device then
+    if EMMC.has_card() {
+        block::register_block_device(&EMMC);
+    }
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the system timer driver.
+fn post_init_system_timer() -> Result<(), Error> {
+    time::time_manager().register_clock_source(&SYSTEM_TIMER)
+}
+
+/// This must be called only after successful init of the power manager driver.
+fn post_init_power_manager() -> Result<(), Error> {
+    power::register_power_control(&POWER_MANAGER);
+    power::register_watchdog(&POWER_MANAGER);
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the RNG driver.
+fn post_init_rng() -> Result<(), Error> {
+    random::register_entropy_source(&RNG);
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the interrupt controller driver.
+fn post_init_interrupt_controller() -> Result<(), Error> {
+    exception::asynchronous::register_irq_manager(&INTERRUPT_CONTROLLER);
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the GIC driver.
+fn post_init_gic() -> Result<(), Error> {
+    exception::asynchronous::register_irq_manager(&GIC);
+
+    Ok(())
+}
+
+/// The GPIO routes pins 14/15 to the UART in its post-init, the UART comes after it.
+fn driver_uart() -> Result<(), Error> {
+    let uart_descriptor = match console_uart() {
+        ConsoleUart::MiniUart => generic_driver::DeviceDriverDescriptor::new(
+            "mini_uart",
+            &MINI_UART,
+            Some(post_init_mini_uart),
+        ),
+        _ => generic_driver::DeviceDriverDescriptor::new(
+            "pl011_uart",
+            &PL011_UART,
+            Some(post_init_uart),
+        ),
+    }
+    .depends_on(&["gpio"]);
     generic_driver::driver_manager().register_driver(uart_descriptor);
 
     Ok(())
 }
 
-fn driver_gpio() -> Result<(), &'static str> {
-    let gpio_descriptor = generic_driver::DeviceDriverDescriptor::new(&GPIO, Some(post_init_gpio));
+fn driver_mailbox() -> Result<(), Error> {
+    let mailbox_descriptor = generic_driver::DeviceDriverDescriptor::new("mailbox", &MAILBOX, None);
+    generic_driver::driver_manager().register_driver(mailbox_descriptor);
+
+    Ok(())
+}
+
+/// The framebuffer is allocated through the mailbox.
+fn driver_framebuffer() -> Result<(), Error> {
+    let framebuffer_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        "framebuffer",
+        &FRAMEBUFFER,
+        Some(post_init_framebuffer),
+    )
+    .depends_on(&["mailbox"]);
+    generic_driver::driver_manager().register_driver(framebuffer_descriptor);
+
+    Ok(())
+}
+
+/// The EMMC gets its base clock through the mailbox.
+fn driver_emmc() -> Result<(), Error> {
+    let emmc_descriptor =
+        generic_driver::DeviceDriverDescriptor::new("emmc", &EMMC, Some(post_init_emmc))
+            .depends_on(&["mailbox"]);
+    generic_driver::driver_manager().register_driver(emmc_descriptor);
+
+    Ok(())
+}
+
+fn driver_system_timer() -> Result<(), Error> {
+    let system_timer_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        "system_timer",
+        &SYSTEM_TIMER,
+        Some(post_init_system_timer),
+    );
+    generic_driver::driver_manager().register_driver(system_timer_descriptor);
+
+    Ok(())
+}
+
+fn driver_power_manager() -> Result<(), Error> {
+    let power_manager_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        "power_manager",
+        &POWER_MANAGER,
+        Some(post_init_power_manager),
+    );
+    generic_driver::driver_manager().register_driver(power_manager_descriptor);
+
+    Ok(())
+}
+
+fn driver_dma() -> Result<(), Error> {
+    let dma_descriptor = generic_driver::DeviceDriverDescriptor::new("dma", &DMA, None);
+    generic_driver::driver_manager().register_driver(dma_descriptor);
+
+    Ok(())
+}
+
+fn driver_rng() -> Result<(), Error> {
+    let rng_descriptor =
+        generic_driver::DeviceDriverDescriptor::new("rng", &RNG, Some(post_init_rng));
+    generic_driver::driver_manager().register_driver(rng_descriptor);
+
+    Ok(())
+}
+
+/// SPI0 takes its pins from the GPIO driver at init.
+fn driver_spi() -> Result<(), Error> {
+    let spi_descriptor =
+        generic_driver::DeviceDriverDescriptor::new("spi0", &SPI, None).depends_on(&["gpio"]);
+    generic_driver::driver_manager().register_driver(spi_descriptor);
+
+    Ok(())
+}
+
+/// I2C1 takes its pins from the GPIO driver at init.
+fn driver_i2c() -> Result<(), Error> {
+    let i2c_descriptor =
+        generic_driver::DeviceDriverDescriptor::new("i2c1", &I2C, None).depends_on(&["gpio"]);
+    generic_driver::driver_manager().register_driver(i2c_descriptor);
+
+    Ok(())
+}
+
+/// The RPi4 peripherals interrupt through the GIC, the RPi3 ones through the BCM controller.
+fn driver_interrupt_controller() -> Result<(), Error> {
+    let interrupt_controller_descriptor = match board::board() {
+        Board::RPi3 => generic_driver::DeviceDriverDescriptor::new(
+            "interrupt_controller",
+            &INTERRUPT_CONTROLLER,
+            Some(post_init_interrupt_controller),
+        ),
+        Board::RPi4 => generic_driver::DeviceDriverDescriptor::new(
+            "interrupt_controller",
+            &GIC,
+            Some(post_init_gic),
+        ),
+    };
+    generic_driver::driver_manager().register_driver(interrupt_controller_descriptor);
+
+    Ok(())
+}
+
+fn driver_gpio() -> Result<(), Error> {
+    let gpio_descriptor =
+        generic_driver::DeviceDriverDescriptor::new("gpio", &GPIO, Some(post_init_gpio));
     generic_driver::driver_manager().register_driver(gpio_descriptor);
 
     Ok(())
 }
 
+/// Set the drivers up for the detected board, at the addresses found in the device tree.
+///
+/// # Safety
+///
+/// - Before the drivers' init.
+unsafe fn configure_drivers() {
+    let board = board::board();
+    let mmio = devicetree::mmio();
+
+    PL011_UART.set_mmio_start_addr(mmio.pl011_uart_start);
+    MINI_UART.set_mmio_start_addr(mmio.mini_uart_start);
+    MINI_UART.set_core_clock_hz(core_clock_hz(board));
+    GPIO.set_mmio_start_addr(mmio.gpio_start);
+    GPIO.set_pull_scheme(pull_scheme(board));
+    MAILBOX.set_mmio_start_addr(mmio.mailbox_start);
+    EMMC.set_controller(mmio.emmc_start, emmc_clock_id(board));
+    SYSTEM_TIMER.set_mmio_start_addr(mmio.system_timer_start);
+    POWER_MANAGER.set_mmio_start_addr(mmio.power_manager_start);
+    RNG.set_mmio_start_addr(mmio.rng_start);
+    RNG.set_variant(rng_variant(board));
+    DMA.set_mmio_start_addr(mmio.dma_start);
+    DMA.set_channel_mask(devicetree::dma_channel_mask().unwrap_or(dma_channel_mask(board)));
+    SPI.set_mmio_start_addr(mmio.spi0_start);
+    SPI.set_core_clock_hz(core_clock_hz(board));
+    I2C.set_mmio_start_addr(mmio.i2c1_start);
+    I2C.set_core_clock_hz(core_clock_hz(board));
+    INTERRUPT_CONTROLLER.set_mmio_start_addr(mmio.interrupt_controller_start);
+    GIC.set_mmio_start_addr(mmio.gicd_start, mmio.gicc_start);
+}
+
+/// Print the state of every GPIO pin.
+pub fn print_gpio_state() {
+    GPIO.dump();
+}
+
+/// Make a GPIO pin an input, with a pull up (`Some(true)`), pull down (`Some(false)`) or neither.
+///
+/// Fails if the pin is owned by a driver. The pin keeps its configuration when released.
+pub fn gpio_input(number: u8, pull_up: Option<bool>) -> Result<(), Error> {
+    let mut pin = gpio_pin(number)?;
+
+    pin.set_function(device_driver::PinFunction::Input);
+    pin.set_pull(match pull_up {
+        Some(true) => device_driver::Pull::Up,
+        Some(false) => device_driver::Pull::Down,
+        None => device_driver::Pull::None,
+    });
+
+    Ok(())
+}
+
+/// Make a GPIO pin an output, driven high or low if `level` is given.
+///
+/// Fails if the pin is owned by a driver. The pin keeps its configuration when released.
+pub fn gpio_output(number: u8, level: Option<bool>) -> Result<(), Error> {
+    let mut pin = gpio_pin(number)?;
+
+    // Latch the level first, so the pin does not glitch when it becomes an output
+    match level {
+        Some(true) => pin.set_high(),
+        Some(false) => pin.set_low(),
+        None => (),
+    }
+    pin.set_function(device_driver::PinFunction::Output);
+
+    Ok(())
+}
+
+/// Print the DMA channels the ARM can use.
+pub fn print_dma_channels() {
+    DMA.dump();
+}
+
+/// Copy a buffer with the DMA, and check the copy.
+pub fn dma_self_test() -> Result<(), Error> {
+    DMA.self_test()
+}
+
+/// Check that the interrupts get through (i.e. after a driver reset): set a short system timer
+/// alarm and wait for its callback.
+pub fn irq_self_test() -> Result<(), Error> {
+    static FIRED: AtomicBool = AtomicBool::new(false);
+
+    let channel = device_driver::ARM_CHANNELS[0];
+    FIRED.store(false, Ordering::Relaxed);
+    SYSTEM_TIMER.set_alarm(channel, Duration::from_millis(1), |_| {
+        FIRED.store(true, Ordering::Relaxed)
+    })?;
+
+    let result = time::time_manager()
+        .wait_until(|| FIRED.load(Ordering::Relaxed), Duration::from_millis(100));
+    if result.is_err() {
+        SYSTEM_TIMER.cancel_alarm(channel)?;
+        return Err(Error::new(ErrorKind::Timeout, "IRQ self-test: the timer alarm never fired"));
+    }
+
+    Ok(())
+}
+
+/// Print the SPI0 configuration.
+pub fn print_spi_config() {
+    SPI.dump();
+}
+
+/// Set the SPI0 chip select (0 or 1), clock and mode (0 to 3). Returns the actual clock.
+pub fn spi_configure(chip_select: u8, frequency_hz: u32, mode: u8) -> Result<u32, Error> {
+    let mode = device_driver::SpiMode::from_number(mode)
+        .ok_or(Error::new(ErrorKind::InvalidArgument, "SPI: no such mode"))?;
+
+    SPI.configure(device_driver::SpiConfig {
+        chip_select,
+        frequency_hz,
+        mode,
+    })
+}
+
+/// Send `write` on SPI0 while receiving into `read`, polled or interrupt driven.
+pub fn spi_transfer(read: &mut [u8], write: &[u8], interrupts: bool) -> Result<(), Error> {
+    if interrupts {
+        SPI.transfer_irq(read, write)
+    } else {
+        SPI.transfer(read, write)
+    }
+}
+
+/// The addresses of the devices on I2C1.
+pub fn i2c_scan() -> Result<Vec<u8>, Error> {
+    I2C.scan()
+}
+
+/// Set the I2C1 clock. Returns the actual one.
+pub fn i2c_set_clock_hz(clock_hz: u32) -> Result<u32, Error> {
+    I2C.set_clock_hz(clock_hz)
+}
+
+/// Read from the device at `address` on I2C1, after writing `write` (i.e. a register number)
+/// with a repeated start if it isn't empty.
+pub fn i2c_read(address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+    I2C.write_read(address, write, read)
+}
+
+/// Write to the device at `address` on I2C1.
+pub fn i2c_write(address: u8, write: &[u8]) -> Result<(), Error> {
+    I2C.write(address, write)
+}
+
+/// A GPIO pin, for the `embedded-hal` drivers.
+pub fn gpio_pin(number: u8) -> Result<device_driver::Pin, Error> {
+    GPIO.claim(number)
+}
+
+/// The SPI0 master, for the `embedded-hal` drivers.
+#[allow(dead_code)]
+pub fn spi0() -> &'static device_driver::Spi {
+    &SPI
+}
+
+/// The I2C1 master, for the `embedded-hal` drivers.
+#[allow(dead_code)]
+pub fn i2c1() -> &'static device_driver::I2c {
+    &I2C
+}
+
+/// The UART on GPIO 14/15, for the `embedded-io` drivers. It is the console's too.
+#[allow(dead_code)]
+pub fn pl011_uart() -> &'static device_driver::PL011Uart {
+    &PL011_UART
+}
+
+/// The mini UART, for the `embedded-io` drivers. Only on the header when it is the console.
+#[allow(dead_code)]
+pub fn mini_uart() -> &'static device_driver::MiniUart {
+    &MINI_UART
+}
+
 /// Initialize the driver subsystem.
 ///
 /// # Safety
@@ -60,15 +536,29 @@ fn driver_gpio() -> Result<(), &'static str> {
 ///
 /// [`AtomicBool::load`]: core::sync::atomic::AtomicBool::load
 /// [`AtomicBool::store`]: core::sync::atomic::AtomicBool::store
-pub unsafe fn init() -> Result<(), &'static str> {
+pub unsafe fn init() -> Result<(), Error> {
     static INIT_DONE: AtomicBool = AtomicBool::new(false);
     // Regular atmoic operation, nothing fancy - relxed
     if INIT_DONE.load(Ordering::Relaxed) {
-        return Err("Init already done");
+        return Err(Error::new(ErrorKind::Busy, "Init already done"));
     }
 
+    board::init();
+    devicetree::init();
+    configure_drivers();
+
     driver_uart()?;
     driver_gpio()?;
+    driver_mailbox()?;
+    driver_framebuffer()?;
+    driver_system_timer()?;
+    driver_power_manager()?;
+    driver_rng()?;
+    driver_dma()?;
+    driver_spi()?;
+    driver_i2c()?;
+    driver_interrupt_controller()?;
+    driver_emmc()?;
 
     INIT_DONE.store(true, Ordering::Relaxed);
     Ok(())
@@ -0,0 +1,38 @@
+//! BSP asynchronous exception handling.
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Interrupt number of the 64 "GPU" peripheral IRQs, on the BCM controller (RPi3) as on the GIC
+/// (RPi4).
+pub type IRQNumber = usize;
+
+/// Board IRQ numbers.
+pub mod irq_map {
+    use super::IRQNumber;
+
+    /// System timer compare channel 1 (0 and 2 belong to the VideoCore)
+    pub const SYSTEM_TIMER_1: IRQNumber = 1;
+    /// System timer compare channel 3
+    pub const SYSTEM_TIMER_3: IRQNumber = 3;
+
+    /// The system timer channels of the ARM
+    pub const SYSTEM_TIMER: [IRQNumber; 2] = [SYSTEM_TIMER_1, SYSTEM_TIMER_3];
+
+    /// The DMA channels 0 to 10, by channel (11 to 14 share one)
+    pub const DMA: [IRQNumber; 11] = [16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26];
+
+    /// GPIO bank 0 (pins 0-27)
+    pub const GPIO_BANK_0: IRQNumber = 49;
+    /// GPIO bank 1 (pins 28-45)
+    pub const GPIO_BANK_1: IRQNumber = 50;
+    /// GPIO bank 2 (pins 46-53)
+    pub const GPIO_BANK_2: IRQNumber = 51;
+
+    /// All the GPIO bank interrupts
+    pub const GPIO: [IRQNumber; 3] = [GPIO_BANK_0, GPIO_BANK_1, GPIO_BANK_2];
+
+    /// SPI0
+    pub const SPI: IRQNumber = 54;
+}
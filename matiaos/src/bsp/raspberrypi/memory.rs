@@ -10,34 +10,150 @@
 // This is just a way to define the start address of UART and the GPIO. The trick is to figure out that the specified addresses are bus addresses
 // that need to be mapped physically.
 
+use core::{cell::UnsafeCell, ops::Range};
+
+// Symbols from the linker script.
+extern "Rust" {
+    static __heap_start: UnsafeCell<()>;
+    static __heap_end_exclusive: UnsafeCell<()>;
+}
+
 pub mod map {
+    use super::super::board::Board;
+
     #[allow(dead_code)]
     pub const BOARD_DEFAULT_LOAD_ADDRESS: usize =        0x8_0000;
+    /// Where the serial loader relocates itself, it waits there for a kernel
+    pub const SERIAL_LOADER_ENTRY:        usize =   0x0208_0000;
 
     pub const GPIO_OFFSET:         usize = 0x0020_0000;
     pub const UART_OFFSET:         usize = 0x0020_1000;
+    /// The SPI master on the header
+    pub const SPI0_OFFSET:         usize = 0x0020_4000;
+    /// The I2C master on the header (BSC1)
+    pub const I2C1_OFFSET:         usize = 0x0080_4000;
+    pub const AUX_OFFSET:          usize = 0x0021_5000;
+    pub const MAILBOX_OFFSET:      usize = 0x0000_B880;
+    pub const INTERRUPT_CONTROLLER_OFFSET: usize = 0x0000_B200;
+    pub const SYSTEM_TIMER_OFFSET: usize = 0x0000_3000;
+    /// DMA channels 0 to 14
+    pub const DMA_OFFSET:          usize = 0x0000_7000;
+    /// The power manager, and its watchdog
+    pub const POWER_MANAGER_OFFSET: usize = 0x0010_0000;
+    /// The random number generator
+    pub const RNG_OFFSET:          usize = 0x0010_4000;
+    pub const EMMC_OFFSET:         usize = 0x0030_0000;
+    /// BCM2711 only, the SD card slot's controller
+    pub const EMMC2_OFFSET:        usize = 0x0034_0000;
 
-    /// Physical devices.
-    #[cfg(feature = "bsp_rpi3")]
-    pub mod mmio {
-        use super::*;
+    /// The VideoCore sees the ARM memory through its bus addresses. The 0xC000_0000 alias is
+    /// the uncached (L2 bypassing) one, so the firmware and the ARM agree on the content.
+    pub const BUS_ADDRESS_ALIAS:   usize = 0xC000_0000;
+    /// Where the VideoCore (and the DMA) sees the peripherals
+    pub const PERIPHERAL_BUS_START: usize = 0x7E00_0000;
 
-        pub const START:            usize =         0x3F00_0000;
-        pub const GPIO_START:       usize = START + GPIO_OFFSET;
-        pub const PL011_UART_START: usize = START + UART_OFFSET;
-    }
+    /// Start of the BCM2837 (RPi3) peripherals
+    pub const BCM2837_START:       usize = 0x3F00_0000;
+    /// Start of the BCM2711 (RPi4) peripherals, in the "low peripheral" mode the firmware sets
+    pub const BCM2711_START:       usize = 0xFE00_0000;
+    /// The BCM2711's GIC-400 distributor and CPU interface, past the peripherals
+    pub const BCM2711_GICD_START:  usize = 0xFF84_1000;
+    pub const BCM2711_GICC_START:  usize = 0xFF84_2000;
 
     /// Physical devices.
-    #[cfg(feature = "bsp_rpi4")]
-    pub mod mmio {
-        use super::*;
+    #[derive(Clone, Copy)]
+    pub struct Mmio {
+        /// Start of the peripherals, the end of the ARM memory
+        pub start: usize,
+        pub gpio_start: usize,
+        pub pl011_uart_start: usize,
+        /// AUX block of the mini UART
+        pub mini_uart_start: usize,
+        pub mailbox_start: usize,
+        /// Legacy interrupt controller
+        pub interrupt_controller_start: usize,
+        /// The controller of the SD card slot
+        pub emmc_start: usize,
+        /// The 1 MHz free running counter
+        pub system_timer_start: usize,
+        pub power_manager_start: usize,
+        pub rng_start: usize,
+        pub dma_start: usize,
+        pub spi0_start: usize,
+        pub i2c1_start: usize,
+        /// GIC-400 distributor, BCM2711 only
+        pub gicd_start: usize,
+        /// GIC-400 CPU interface, BCM2711 only
+        pub gicc_start: usize,
+    }
 
-        pub const START:            usize =         0xFE00_0000;
-        pub const GPIO_START:       usize = START + GPIO_OFFSET;
-        pub const PL011_UART_START: usize = START + UART_OFFSET;
+    impl Mmio {
+        /// The compiled-in map of `board`.
+        pub const fn of(board: Board) -> Self {
+            match board {
+                Board::RPi3 => Self {
+                    start:                      BCM2837_START,
+                    gpio_start:                 BCM2837_START + GPIO_OFFSET,
+                    pl011_uart_start:           BCM2837_START + UART_OFFSET,
+                    mini_uart_start:            BCM2837_START + AUX_OFFSET,
+                    mailbox_start:              BCM2837_START + MAILBOX_OFFSET,
+                    interrupt_controller_start: BCM2837_START + INTERRUPT_CONTROLLER_OFFSET,
+                    emmc_start:                 BCM2837_START + EMMC_OFFSET,
+                    system_timer_start:         BCM2837_START + SYSTEM_TIMER_OFFSET,
+                    power_manager_start:        BCM2837_START + POWER_MANAGER_OFFSET,
+                    rng_start:                  BCM2837_START + RNG_OFFSET,
+                    dma_start:                  BCM2837_START + DMA_OFFSET,
+                    spi0_start:                 BCM2837_START + SPI0_OFFSET,
+                    i2c1_start:                 BCM2837_START + I2C1_OFFSET,
+                    gicd_start:                 0,
+                    gicc_start:                 0,
+                },
+                Board::RPi4 => Self {
+                    start:                      BCM2711_START,
+                    gpio_start:                 BCM2711_START + GPIO_OFFSET,
+                    pl011_uart_start:           BCM2711_START + UART_OFFSET,
+                    mini_uart_start:            BCM2711_START + AUX_OFFSET,
+                    mailbox_start:              BCM2711_START + MAILBOX_OFFSET,
+                    interrupt_controller_start: BCM2711_START + INTERRUPT_CONTROLLER_OFFSET,
+                    emmc_start:                 BCM2711_START + EMMC2_OFFSET,
+                    system_timer_start:         BCM2711_START + SYSTEM_TIMER_OFFSET,
+                    power_manager_start:        BCM2711_START + POWER_MANAGER_OFFSET,
+                    rng_start:                  BCM2711_START + RNG_OFFSET,
+                    dma_start:                  BCM2711_START + DMA_OFFSET,
+                    spi0_start:                 BCM2711_START + SPI0_OFFSET,
+                    i2c1_start:                 BCM2711_START + I2C1_OFFSET,
+                    gicd_start:                 BCM2711_GICD_START,
+                    gicc_start:                 BCM2711_GICC_START,
+                },
+            }
+        }
     }
 }
 
+/// Translate an ARM physical address to a VideoCore bus address (mailbox, DMA).
+#[inline(always)]
+pub fn phys_to_bus(addr: usize) -> u32 {
+    (addr | map::BUS_ADDRESS_ALIAS) as u32
+}
+
+/// Translate a VideoCore bus address (i.e. returned by the firmware) to an ARM physical address.
+#[inline(always)]
+pub fn bus_to_phys(addr: u32) -> usize {
+    addr as usize & !map::BUS_ADDRESS_ALIAS
+}
+
+/// Translate the address of a peripheral register to its bus address (DMA).
+#[inline(always)]
+#[allow(dead_code)]
+pub fn mmio_to_bus(addr: usize) -> u32 {
+    (addr - super::devicetree::mmio().start + map::PERIPHERAL_BUS_START) as u32
+}
+
+/// The kernel heap, reserved by the linker script after the bss.
+pub fn heap_region() -> Range<usize> {
+    unsafe { __heap_start.get() as usize..__heap_end_exclusive.get() as usize }
+}
+
 #[inline(always)]
 #[allow(dead_code)]
 pub fn board_default_load_address() -> *const u64 {
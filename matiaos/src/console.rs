@@ -5,8 +5,24 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 
-mod null_console;
-use crate::synchronization::{NullLock, interface::Mutex};
+//! The console: every registered console device gets the output, input comes from any of them.
+//!
+//! Consoles (the UART, the framebuffer, the log buffer...) are registered by name and can be
+//! removed or muted at runtime. A muted console gets no output but can still be typed on.
+
+mod log_buffer;
+pub mod tty;
+mod utf8;
+
+use crate::{
+    cpu,
+    error::{Error, ErrorKind},
+    synchronization::{interface::Mutex, NullLock},
+};
+use core::fmt;
+
+pub use log_buffer::LOG_BUFFER;
+pub use utf8::Utf8Decoder;
 
 pub mod interface {
     pub use core::fmt;
@@ -27,6 +43,10 @@ pub mod interface {
         fn read_char(&self) -> char {
             ' '
         }
+        /// Read one character if there is one waiting
+        fn try_read_char(&self) -> Option<char> {
+            None
+        }
         /// Clear RX buffers
         fn clear_rx(&self);
     }
@@ -47,23 +67,238 @@ pub mod interface {
 }
 
 //--------------------------------------------------------------------------------------------------
-// Public definitions
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Most consoles at the same time
+const MAX_CONSOLES: usize = 4;
+
+#[derive(Clone, Copy)]
+struct ConsoleEntry {
+    name: &'static str,
+    console: &'static (dyn interface::All + Sync),
+    muted: bool,
+}
+
+/// No heap here, the console must work from the first instruction
+struct MultiplexerInner {
+    consoles: [Option<ConsoleEntry>; MAX_CONSOLES],
+}
+
+/// The console everybody prints to, it fans out to the registered ones
+struct Multiplexer {
+    inner: NullLock<MultiplexerInner>,
+}
+
+/// A console with the output translation of the line discipline
+struct Output<'a> {
+    console: &'a (dyn interface::All + Sync),
+    crlf: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
 //--------------------------------------------------------------------------------------------------
 
-static CUR_CONSOLE: NullLock<&'static (dyn interface::All + Sync)> = NullLock::new(&null_console::NULL_CONSOLE);
+// The log buffer is there from the start, it catches what is printed before the UART is up
+static MULTIPLEXER: Multiplexer = Multiplexer {
+    inner: NullLock::new(MultiplexerInner {
+        consoles: [
+            Some(ConsoleEntry {
+                name: "log",
+                console: &LOG_BUFFER,
+                muted: false,
+            }),
+            None,
+            None,
+            None,
+        ],
+    }),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl MultiplexerInner {
+    fn entries(&self) -> impl Iterator<Item = &ConsoleEntry> {
+        self.consoles.iter().flatten()
+    }
+
+    fn entry_mut(&mut self, name: &str) -> Result<&mut Option<ConsoleEntry>, Error> {
+        self.consoles
+            .iter_mut()
+            .find(|x| x.is_some_and(|entry| entry.name == name))
+            .ok_or(Error::new(ErrorKind::NotPresent, "No such console"))
+    }
+
+    /// The consoles that get the output
+    fn outputs(&self) -> impl Iterator<Item = &ConsoleEntry> {
+        self.entries().filter(|entry| !entry.muted)
+    }
+}
+
+impl fmt::Write for Output<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.crlf {
+            return self.console.write_fmt(format_args!("{}", s));
+        }
+
+        let mut lines = s.split('\n');
+        if let Some(first) = lines.next() {
+            self.console.write_fmt(format_args!("{}", first))?;
+        }
+        for line in lines {
+            self.console.write_fmt(format_args!("\r\n{}", line))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl interface::Write for Multiplexer {
+    fn write_char(&self, c: char) {
+        let crlf = tty::config().output_crlf;
+        self.inner.lock(|inner| {
+            for entry in inner.outputs() {
+                if c == '\n' && crlf {
+                    entry.console.write_char('\r');
+                }
+                entry.console.write_char(c);
+            }
+        })
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        let crlf = tty::config().output_crlf;
+        // One failing console doesn't keep the others from printing
+        self.inner.lock(|inner| {
+            let mut result = Ok(());
+            for entry in inner.outputs() {
+                let mut output = Output {
+                    console: entry.console,
+                    crlf,
+                };
+                result = result.and(fmt::Write::write_fmt(&mut output, args));
+            }
+
+            result
+        })
+    }
+
+    fn flush(&self) {
+        self.inner.lock(|inner| {
+            for entry in inner.outputs() {
+                entry.console.flush();
+            }
+        })
+    }
+}
+
+impl interface::Read for Multiplexer {
+    /// Wait for a character from any console
+    fn read_char(&self) -> char {
+        loop {
+            if let Some(c) = self.try_read_char() {
+                return c;
+            }
+            cpu::nop();
+        }
+    }
+
+    fn try_read_char(&self) -> Option<char> {
+        self.inner
+            .lock(|inner| inner.entries().find_map(|entry| entry.console.try_read_char()))
+    }
+
+    fn clear_rx(&self) {
+        self.inner.lock(|inner| {
+            for entry in inner.entries() {
+                entry.console.clear_rx();
+            }
+        })
+    }
+}
+
+/// The characters of all the consoles together
+impl interface::Statistics for Multiplexer {
+    fn chars_written(&self) -> usize {
+        self.inner
+            .lock(|inner| inner.entries().map(|entry| entry.console.chars_written()).sum())
+    }
+
+    fn chars_read(&self) -> usize {
+        self.inner
+            .lock(|inner| inner.entries().map(|entry| entry.console.chars_read()).sum())
+    }
+}
+
+impl interface::All for Multiplexer {}
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-/// Register a new console.
-pub fn register_console(new_console: &'static (dyn interface::All + Sync)) {
-    CUR_CONSOLE.lock(|con| *con = new_console);
+/// Add a console. It gets the output from now on.
+pub fn register_console(
+    name: &'static str,
+    new_console: &'static (dyn interface::All + Sync),
+) -> Result<(), Error> {
+    MULTIPLEXER.inner.lock(|inner| {
+        if inner.entries().any(|entry| entry.name == name) {
+            return Err(Error::new(ErrorKind::Busy, "Console already registered"));
+        }
+
+        let slot = inner
+            .consoles
+            .iter_mut()
+            .find(|x| x.is_none())
+            .ok_or(Error::new(ErrorKind::OutOfMemory, "Too many consoles"))?;
+        *slot = Some(ConsoleEntry {
+            name,
+            console: new_console,
+            muted: false,
+        });
+
+        Ok(())
+    })
+}
+
+/// Remove a console, after flushing it.
+pub fn remove_console(name: &str) -> Result<(), Error> {
+    let entry = MULTIPLEXER
+        .inner
+        .lock(|inner| inner.entry_mut(name).map(|x| x.take()))?;
+    if let Some(entry) = entry {
+        entry.console.flush();
+    }
+
+    Ok(())
 }
 
-/// Return a reference to the currently registered console.
+/// Stop or restart sending the output to a console.
+pub fn set_muted(name: &str, muted: bool) -> Result<(), Error> {
+    MULTIPLEXER.inner.lock(|inner| {
+        if let Some(entry) = inner.entry_mut(name)? {
+            entry.muted = muted;
+        }
+
+        Ok(())
+    })
+}
+
+/// Call `f` with the name of every console and whether it is muted.
+pub fn for_each_console(mut f: impl FnMut(&'static str, bool)) {
+    MULTIPLEXER.inner.lock(|inner| {
+        for entry in inner.entries() {
+            f(entry.name, entry.muted);
+        }
+    })
+}
+
+/// Return a reference to the console.
 ///
 /// This is the global console used by all printing macros.
 pub fn console() -> &'static dyn interface::All {
-    CUR_CONSOLE.lock(|con| *con)
-} 
+    &MULTIPLEXER
+}
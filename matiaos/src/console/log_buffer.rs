@@ -0,0 +1,118 @@
+//! A console that keeps the last output in memory, to read it back later (`dmesg`).
+//!
+//! It has no input. When full, the oldest output is overwritten. The `\r` of the newline
+//! translation (see `tty`) are dropped, the lines end with `\n`.
+
+use super::interface;
+use crate::synchronization::{interface::Mutex, NullLock};
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+struct LogBufferInner {
+    buffer: [u8; LOG_BUFFER_SIZE],
+    /// Bytes written since boot, the buffer holds the last `LOG_BUFFER_SIZE` of them
+    written: usize,
+    chars_written: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The output of the kernel, in memory
+pub struct LogBuffer {
+    inner: NullLock<LogBufferInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+pub static LOG_BUFFER: LogBuffer = LogBuffer {
+    inner: NullLock::new(LogBufferInner {
+        buffer: [0; LOG_BUFFER_SIZE],
+        written: 0,
+        chars_written: 0,
+    }),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl LogBufferInner {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().filter(|&&x| x != b'\r') {
+            self.buffer[self.written % LOG_BUFFER_SIZE] = byte;
+            self.written += 1;
+        }
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.push(c.encode_utf8(&mut [0; 4]).as_bytes());
+        self.chars_written += 1;
+    }
+}
+
+impl fmt::Write for LogBufferInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        self.chars_written += s.chars().count();
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl LogBuffer {
+    /// The buffered output, oldest first. A character cut by the wrap around comes out as `�`.
+    pub fn contents(&self) -> String {
+        let bytes = self.inner.lock(|inner| {
+            let mut bytes = Vec::with_capacity(inner.written.min(LOG_BUFFER_SIZE));
+            if inner.written > LOG_BUFFER_SIZE {
+                let start = inner.written % LOG_BUFFER_SIZE;
+                bytes.extend_from_slice(&inner.buffer[start..]);
+                bytes.extend_from_slice(&inner.buffer[..start]);
+            } else {
+                bytes.extend_from_slice(&inner.buffer[..inner.written]);
+            }
+
+            bytes
+        });
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl interface::Write for LogBuffer {
+    fn write_char(&self, c: char) {
+        self.inner.lock(|inner| inner.write_char(c))
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+    }
+
+    fn flush(&self) {}
+}
+
+impl interface::Read for LogBuffer {
+    fn clear_rx(&self) {}
+}
+
+impl interface::Statistics for LogBuffer {
+    fn chars_written(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_written)
+    }
+}
+
+impl interface::All for LogBuffer {}
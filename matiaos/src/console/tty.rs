@@ -0,0 +1,90 @@
+//! The line discipline between the console devices and their readers.
+//!
+//! It translates newlines, echoes what is typed and, in canonical mode, buffers the input a line
+//! at a time with editing (backspace, Ctrl-U to kill the line). In raw mode the characters are
+//! read as they come.
+
+mod discipline;
+
+use crate::{
+    console, cpu,
+    error::{Error, ErrorKind},
+    synchronization::{interface::Mutex, NullLock},
+    time::Instant,
+};
+use alloc::string::String;
+use core::time::Duration;
+use discipline::{Input, Terminal};
+
+pub use discipline::Config;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// All the consoles, until the deadline of the read
+struct Consoles {
+    deadline: Option<Instant>,
+}
+
+static CONFIG: NullLock<Config> = NullLock::new(Config::DEFAULT);
+static INPUT: NullLock<Input> = NullLock::new(Input::new());
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    // Too long to represent is forever
+    timeout.and_then(|x| Instant::now().checked_add(x))
+}
+
+impl Terminal for Consoles {
+    /// A character from any console, as it comes
+    fn receive(&mut self) -> Option<char> {
+        let Some(deadline) = self.deadline else {
+            return Some(console::console().read_char());
+        };
+
+        loop {
+            if let Some(c) = console::console().try_read_char() {
+                return Some(c);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            cpu::nop();
+        }
+    }
+
+    fn echo(&mut self, c: char) {
+        console::console().write_char(c);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The current settings.
+pub fn config() -> Config {
+    CONFIG.lock(|x| *x)
+}
+
+/// Change the settings. Lines already finished stay readable.
+pub fn set_config(config: Config) {
+    CONFIG.lock(|x| *x = config);
+}
+
+/// Read a line, without its `\n`, waiting at most `timeout`. On timeout, what was typed so far is
+/// kept for the next read.
+pub fn read_line(timeout: Option<Duration>) -> Result<String, Error> {
+    let config = config();
+    let mut consoles = Consoles {
+        deadline: deadline(timeout),
+    };
+
+    INPUT
+        .lock(|input| input.read_line(&config, &mut consoles))
+        .map_err(|_| Error::new(ErrorKind::Timeout, "No input"))
+}
@@ -0,0 +1,348 @@
+//! The input side of the line discipline: newline translation, echo and line editing.
+//!
+//! The characters come from a [`Terminal`], so this is plain code without devices, locks or
+//! clocks, and it is tested on the host (`make test`).
+
+// The kernel's crate root has it too, this one lets the file build alone on the host
+extern crate alloc;
+
+use alloc::{collections::VecDeque, string::String};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Longest line in canonical mode, in characters
+const MAX_LINE_LEN: usize = 256;
+
+const BACKSPACE: char = '\x08';
+const DELETE: char = '\x7f';
+/// Ctrl-U
+const KILL: char = '\x15';
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The settings of the line discipline
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Input is edited a line at a time, otherwise characters are read as they come
+    pub canonical: bool,
+    /// Print what is typed
+    pub echo: bool,
+    /// Output `\n` as `\r\n`
+    pub output_crlf: bool,
+    /// Read `\r` and `\r\n` as `\n`
+    pub input_cr_to_nl: bool,
+}
+
+/// Where a read gets its characters and echoes them
+pub trait Terminal {
+    /// The next character, `None` once the read is out of time
+    fn receive(&mut self) -> Option<char>;
+
+    /// Print a character
+    fn echo(&mut self, c: char);
+}
+
+/// The read ran out of time
+#[derive(Debug, PartialEq)]
+pub struct TimedOut;
+
+/// The input state, kept from one read to the next
+pub struct Input {
+    /// The line being typed
+    line: String,
+    /// Characters of finished lines, not read yet
+    ready: VecDeque<char>,
+    /// The last character was a `\r` read as `\n`
+    after_cr: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn echo(config: &Config, terminal: &mut impl Terminal, c: char) {
+    if config.echo {
+        terminal.echo(c);
+    }
+}
+
+fn echo_erase(config: &Config, terminal: &mut impl Terminal) {
+    for c in [BACKSPACE, ' ', BACKSPACE] {
+        echo(config, terminal, c);
+    }
+}
+
+impl Input {
+    /// Next character from the terminal, with the input translation
+    fn receive(&mut self, config: &Config, terminal: &mut impl Terminal) -> Result<char, TimedOut> {
+        loop {
+            match terminal.receive().ok_or(TimedOut)? {
+                '\n' if self.after_cr => self.after_cr = false,
+                '\r' if config.input_cr_to_nl => {
+                    self.after_cr = true;
+                    return Ok('\n');
+                }
+                c => {
+                    self.after_cr = false;
+                    return Ok(c);
+                }
+            }
+        }
+    }
+
+    /// Edit the line until it is finished, then make it readable
+    fn edit_line(&mut self, config: &Config, terminal: &mut impl Terminal) -> Result<(), TimedOut> {
+        loop {
+            match self.receive(config, terminal)? {
+                '\n' => {
+                    echo(config, terminal, '\n');
+                    self.ready.extend(self.line.drain(..));
+                    self.ready.push_back('\n');
+
+                    return Ok(());
+                }
+                BACKSPACE | DELETE => {
+                    if self.line.pop().is_some() {
+                        echo_erase(config, terminal);
+                    }
+                }
+                KILL => {
+                    while self.line.pop().is_some() {
+                        echo_erase(config, terminal);
+                    }
+                }
+                c if c.is_control() || self.line.chars().count() >= MAX_LINE_LEN => (),
+                c => {
+                    self.line.push(c);
+                    echo(config, terminal, c);
+                }
+            }
+        }
+    }
+
+    fn read_char(
+        &mut self,
+        config: &Config,
+        terminal: &mut impl Terminal,
+    ) -> Result<char, TimedOut> {
+        if let Some(c) = self.ready.pop_front() {
+            return Ok(c);
+        }
+
+        if config.canonical {
+            self.edit_line(config, terminal)?;
+            Ok(self.ready.pop_front().unwrap_or('\n'))
+        } else {
+            let c = self.receive(config, terminal)?;
+            echo(config, terminal, c);
+            Ok(c)
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Config {
+    pub const DEFAULT: Self = Self {
+        canonical: true,
+        echo: true,
+        output_crlf: true,
+        input_cr_to_nl: true,
+    };
+}
+
+impl Input {
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            ready: VecDeque::new(),
+            after_cr: false,
+        }
+    }
+
+    /// Read a line, without its `\n`. When the terminal runs out of time, what was typed so far
+    /// is kept for the next read.
+    pub fn read_line(
+        &mut self,
+        config: &Config,
+        terminal: &mut impl Terminal,
+    ) -> Result<String, TimedOut> {
+        // What is typed is kept in `line` until the `\n`, so a timeout loses nothing
+        loop {
+            match self.read_char(config, terminal)? {
+                '\n' => return Ok(core::mem::take(&mut self.line)),
+                c => self.line.push(c),
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `input`, then runs out of time
+    struct Script {
+        input: VecDeque<char>,
+        echoed: String,
+    }
+
+    impl Script {
+        fn new(input: &str) -> Self {
+            Self {
+                input: input.chars().collect(),
+                echoed: String::new(),
+            }
+        }
+
+        fn more(&mut self, input: &str) {
+            self.input.extend(input.chars());
+        }
+    }
+
+    impl Terminal for Script {
+        fn receive(&mut self) -> Option<char> {
+            self.input.pop_front()
+        }
+
+        fn echo(&mut self, c: char) {
+            self.echoed.push(c);
+        }
+    }
+
+    fn read_lines(config: &Config, input: &str) -> Vec<String> {
+        let mut terminal = Script::new(input);
+        let mut state = Input::new();
+        let mut lines = Vec::new();
+
+        while let Ok(line) = state.read_line(config, &mut terminal) {
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    #[test]
+    fn lf_ends_a_line() {
+        assert_eq!(read_lines(&Config::DEFAULT, "ab\ncd\n"), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn cr_ends_a_line() {
+        assert_eq!(read_lines(&Config::DEFAULT, "ab\rcd\r"), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn crlf_is_one_newline() {
+        assert_eq!(
+            read_lines(&Config::DEFAULT, "ab\r\ncd\r\n\r\n"),
+            ["ab", "cd", ""]
+        );
+    }
+
+    #[test]
+    fn cr_is_dropped_without_translation() {
+        let config = Config {
+            input_cr_to_nl: false,
+            ..Config::DEFAULT
+        };
+
+        assert_eq!(read_lines(&config, "ab\r\ncd\n"), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn backspace_erases_a_multibyte_character() {
+        let mut terminal = Script::new("aé\x08€\x7f\x08b\n");
+        let line = Input::new().read_line(&Config::DEFAULT, &mut terminal);
+
+        assert_eq!(line, Ok(String::from("b")));
+        assert_eq!(terminal.echoed, "aé\x08 \x08€\x08 \x08\x08 \x08b\n");
+    }
+
+    #[test]
+    fn backspace_on_an_empty_line_echoes_nothing() {
+        let mut terminal = Script::new("\x08a\n");
+        let line = Input::new().read_line(&Config::DEFAULT, &mut terminal);
+
+        assert_eq!(line, Ok(String::from("a")));
+        assert_eq!(terminal.echoed, "a\n");
+    }
+
+    #[test]
+    fn kill_erases_the_line() {
+        let mut terminal = Script::new("aé€\x15b\n");
+        let line = Input::new().read_line(&Config::DEFAULT, &mut terminal);
+
+        assert_eq!(line, Ok(String::from("b")));
+        assert_eq!(terminal.echoed, "aé€\x08 \x08\x08 \x08\x08 \x08b\n");
+    }
+
+    #[test]
+    fn line_length_is_limited() {
+        let input = "x".repeat(MAX_LINE_LEN + 10) + "\n";
+        let lines = read_lines(&Config::DEFAULT, &input);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].chars().count(), MAX_LINE_LEN);
+    }
+
+    #[test]
+    fn timeout_keeps_what_was_typed() {
+        let mut terminal = Script::new("ab");
+        let mut state = Input::new();
+
+        assert_eq!(
+            state.read_line(&Config::DEFAULT, &mut terminal),
+            Err(TimedOut)
+        );
+        terminal.more("\x08c\n");
+        assert_eq!(
+            state.read_line(&Config::DEFAULT, &mut terminal),
+            Ok(String::from("ac"))
+        );
+    }
+
+    #[test]
+    fn timeout_keeps_a_pending_crlf() {
+        let mut terminal = Script::new("ab\r");
+        let mut state = Input::new();
+
+        assert_eq!(
+            state.read_line(&Config::DEFAULT, &mut terminal),
+            Ok(String::from("ab"))
+        );
+        assert_eq!(
+            state.read_line(&Config::DEFAULT, &mut terminal),
+            Err(TimedOut)
+        );
+        terminal.more("\ncd\n");
+        assert_eq!(
+            state.read_line(&Config::DEFAULT, &mut terminal),
+            Ok(String::from("cd"))
+        );
+    }
+
+    #[test]
+    fn raw_mode_reads_characters_as_they_come() {
+        let config = Config {
+            canonical: false,
+            echo: false,
+            ..Config::DEFAULT
+        };
+        let mut terminal = Script::new("a\x08b\n");
+        let line = Input::new().read_line(&config, &mut terminal);
+
+        assert_eq!(line, Ok(String::from("a\x08b")));
+        assert_eq!(terminal.echoed, "");
+    }
+}
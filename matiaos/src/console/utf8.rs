@@ -0,0 +1,177 @@
+//! UTF-8 decoding of the bytes the serial devices receive, one byte at a time.
+
+use core::str;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Collects the bytes of a character until it is complete
+pub struct Utf8Decoder {
+    bytes: [u8; 4],
+    len: usize,
+    /// Completed by the byte that cut the previous sequence short
+    pending: Option<char>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Length of the sequence `lead` starts, `None` if it can't start one
+fn sequence_len(lead: u8) -> Option<usize> {
+    match lead {
+        0x00..=0x7F => Some(1),
+        0xC2..=0xDF => Some(2),
+        0xE0..=0xEF => Some(3),
+        0xF0..=0xF4 => Some(4),
+        _ => None,
+    }
+}
+
+impl Utf8Decoder {
+    fn start(&mut self, byte: u8) -> Option<char> {
+        match sequence_len(byte) {
+            Some(1) => Some(byte as char),
+            Some(_) => {
+                self.bytes[0] = byte;
+                self.len = 1;
+                None
+            }
+            None => Some(char::REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Utf8Decoder {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; 4],
+            len: 0,
+            pending: None,
+        }
+    }
+
+    /// Feed one byte, get the character it completes. Invalid input gives `U+FFFD`.
+    pub fn push(&mut self, byte: u8) -> Option<char> {
+        if self.len == 0 {
+            return self.start(byte);
+        }
+
+        // Not a continuation byte: the sequence was cut short, and this one starts over
+        if byte & 0xC0 != 0x80 {
+            self.len = 0;
+            self.pending = self.start(byte);
+            return Some(char::REPLACEMENT_CHARACTER);
+        }
+
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        let len = sequence_len(self.bytes[0]).unwrap_or(1);
+        if self.len < len {
+            return None;
+        }
+        self.len = 0;
+
+        // Catches the overlong encodings and the surrogates
+        Some(
+            str::from_utf8(&self.bytes[..len])
+                .ok()
+                .and_then(|x| x.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
+    }
+
+    /// A character left by the last `push`, to take before feeding more bytes.
+    pub fn take_pending(&mut self) -> Option<char> {
+        self.pending.take()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything the decoder gives for `bytes`, pending characters included
+    fn decode(bytes: &[u8]) -> String {
+        let mut decoder = Utf8Decoder::new();
+        let mut text = String::new();
+
+        for &byte in bytes {
+            text.extend(decoder.push(byte));
+            text.extend(decoder.take_pending());
+        }
+
+        text
+    }
+
+    #[test]
+    fn valid_sequences() {
+        let text = "a\u{e9}\u{20ac}\u{1f600}\u{10ffff}";
+
+        assert_eq!(decode(text.as_bytes()), text);
+    }
+
+    #[test]
+    fn four_bytes_split_across_pushes() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(decoder.push(0xF0), None);
+        assert_eq!(decoder.push(0x9F), None);
+        assert_eq!(decoder.push(0x98), None);
+        assert_eq!(decoder.push(0x80), Some('\u{1f600}'));
+        assert_eq!(decoder.take_pending(), None);
+    }
+
+    #[test]
+    fn truncated_sequence_keeps_the_next_character() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(decoder.push(0xE2), None);
+        assert_eq!(decoder.push(0x82), None);
+        assert_eq!(decoder.push(b'a'), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(decoder.take_pending(), Some('a'));
+    }
+
+    #[test]
+    fn truncated_sequence_before_another_one() {
+        assert_eq!(decode(&[0xC3, 0xE2, 0x82, 0xAC]), "\u{fffd}\u{20ac}");
+    }
+
+    #[test]
+    fn overlong_encodings() {
+        // '/' in two, three and four bytes
+        assert_eq!(decode(&[0xC0, 0xAF]), "\u{fffd}\u{fffd}");
+        assert_eq!(decode(&[0xE0, 0x80, 0xAF]), "\u{fffd}");
+        assert_eq!(decode(&[0xF0, 0x80, 0x80, 0xAF]), "\u{fffd}");
+    }
+
+    #[test]
+    fn surrogates() {
+        assert_eq!(decode(&[0xED, 0xA0, 0x80]), "\u{fffd}");
+        assert_eq!(decode(&[0xED, 0xBF, 0xBF]), "\u{fffd}");
+    }
+
+    #[test]
+    fn above_the_last_code_point() {
+        assert_eq!(decode(&[0xF4, 0x90, 0x80, 0x80]), "\u{fffd}");
+        // Not even a lead byte, each byte is replaced
+        assert_eq!(
+            decode(&[0xF5, 0x80, 0x80, 0x80]),
+            "\u{fffd}\u{fffd}\u{fffd}\u{fffd}"
+        );
+    }
+
+    #[test]
+    fn stray_continuation_byte() {
+        assert_eq!(decode(&[b'a', 0x80, b'b']), "a\u{fffd}b");
+    }
+}
@@ -13,4 +13,4 @@ mod boot;
 #[path = "_arch/aarch64/cpu.rs"]
 mod arch_cpu;
 
-pub use arch_cpu::{nop, wait_forever};
+pub use arch_cpu::{core_part_number, enter_el2, nop, wait_forever};
@@ -5,29 +5,89 @@
 * Author: Elad Matia (elad.matia@gmail.com)
 */
 
-use crate::info;
+//! Driver manager: the BSP registers its drivers, the kernel initializes them.
+//!
+//! Drivers name the drivers they depend on and are initialized after them. A driver whose init
+//! fails is reported and left out, and so are the drivers depending on it: the kernel boots with
+//! whatever works.
+//!
+//! Shutting down and suspending go the other way, a driver before its dependencies.
+
+use crate::error::{Error, ErrorKind};
 use crate::synchronization::interface::Mutex;
 use crate::synchronization::NullLock;
+use crate::{info, warn};
+use alloc::{vec, vec::Vec};
+use core::fmt;
 
-const MAX_SUPPORTED_DRIVERS: usize = 10;
+/// A registered driver and how it went
+struct DriverEntry {
+    descriptor: DeviceDriverDescriptor,
+    status: DriverStatus,
+}
 
 /// Implementation of a device driver manager
 struct DriverManagerInner {
-    next_driver_index: usize,
-    drivers: [Option<DeviceDriverDescriptor>; MAX_SUPPORTED_DRIVERS],
+    drivers: Vec<DriverEntry>,
+    /// Indices of the drivers in the order they were initialized
+    init_order: Vec<usize>,
 }
 
 impl DriverManagerInner {
     const fn new() -> Self {
         Self {
-            next_driver_index: 0,
-            drivers: [None; MAX_SUPPORTED_DRIVERS],
+            drivers: Vec::new(),
+            init_order: Vec::new(),
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.drivers
+            .iter()
+            .position(|entry| entry.descriptor.name == name)
+    }
+
+    /// The first dependency of a driver that is not usable (failed, or not registered at all)
+    fn missing_dependency(&self, index: usize) -> Option<&'static str> {
+        self.drivers[index]
+            .descriptor
+            .dependencies
+            .iter()
+            .copied()
+            .find(|dependency| match self.index_of(dependency) {
+                Some(i) => self.drivers[i].status != DriverStatus::Probed,
+                None => true,
+            })
+    }
+
+    /// Indices of the drivers, each after its dependencies, in registration order otherwise.
+    /// The drivers of a dependency cycle are left out.
+    fn dependency_order(&self) -> Vec<usize> {
+        let mut placed = vec![false; self.drivers.len()];
+        let mut order = Vec::with_capacity(self.drivers.len());
+
+        // Only a handful of drivers, no need for anything smarter
+        while let Some(next) = (0..self.drivers.len()).find(|&i| {
+            !placed[i]
+                && self.drivers[i]
+                    .descriptor
+                    .dependencies
+                    .iter()
+                    // unregistered dependencies are reported at init
+                    .all(|dependency| self.index_of(dependency).is_none_or(|j| placed[j]))
+        }) {
+            placed[next] = true;
+            order.push(next);
         }
+
+        order
     }
 }
 
 /// Driver-related traits (DeviceDriver, Driver manager)
 pub mod interface {
+    use crate::error::Result;
+
     /// Device driver trait - each driver has to implement this
     pub trait DeviceDriver {
         /// Return a string identifying the driver
@@ -35,20 +95,84 @@ pub mod interface {
 
         /// Called by kernel on startup to initialize the driver.
         /// Devices can only be used after their driver has been initialized
-        fn init(&self) -> Result<(), &'static str> {
+        fn init(&self) -> Result<()> {
+            Ok(())
+        }
+
+        /// Called by kernel after all the drivers were initialized, to register and enable the
+        /// driver's IRQ handlers (if it has any).
+        fn register_and_enable_irq_handler(&'static self) -> Result<()> {
+            Ok(())
+        }
+
+        /// Quiesce the device before a reboot or before another image takes over: finish or
+        /// abort the transfers, stop the interrupts. The device is not used afterwards.
+        fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        /// Stop the device's activity, keeping what `resume` needs to restart it.
+        fn suspend(&self) -> Result<()> {
+            Ok(())
+        }
+
+        /// Restart the device after `suspend`.
+        fn resume(&self) -> Result<()> {
             Ok(())
         }
+
+        /// Put the device back in its just initialized state.
+        ///
+        /// The post-init callback and the IRQ registration are not run again: a driver whose
+        /// `init` undoes them (i.e. an interrupt controller) must keep them here.
+        fn reset(&self) -> Result<()> {
+            self.shutdown()?;
+            self.init()
+        }
     }
 }
 
 /// Callback type for device drivers that need a post-init callback
-pub type DeviceDriverPostInitCB = unsafe fn() -> Result<(), &'static str>;
+pub type DeviceDriverPostInitCB = unsafe fn() -> Result<(), Error>;
 
 /// Describes a device driver
 #[derive(Copy, Clone)]
 pub struct DeviceDriverDescriptor {
+    /// Short name, the one dependencies refer to
+    name: &'static str,
     device_driver: &'static (dyn interface::DeviceDriver + Sync),
     post_init_cb: Option<DeviceDriverPostInitCB>,
+    /// Names of the drivers to initialize first
+    dependencies: &'static [&'static str],
+}
+
+/// Where a registered driver stands
+#[derive(Copy, Clone, PartialEq)]
+pub enum DriverStatus {
+    /// Not initialized yet
+    Registered,
+    /// Initialized, the device can be used
+    Probed,
+    /// The init, the post-init callback or the IRQ registration failed
+    Failed(Error),
+    /// Not initialized, a dependency is not usable
+    Disabled {
+        /// The dependency
+        missing: &'static str,
+    },
+    /// Suspended, until resumed
+    Suspended,
+    /// Shut down, not usable anymore
+    ShutDown,
+}
+
+/// The lifecycle hooks the driver manager runs across the drivers
+#[derive(Copy, Clone)]
+enum Hook {
+    Shutdown,
+    Suspend,
+    Resume,
+    Reset,
 }
 
 /// Driver manager
@@ -66,12 +190,77 @@ pub fn driver_manager() -> &'static DriverManager {
 
 impl DeviceDriverDescriptor {
     pub fn new(
+        name: &'static str,
         device_driver: &'static (dyn interface::DeviceDriver + Sync),
         post_init_cb: Option<DeviceDriverPostInitCB>,
     ) -> Self {
         Self {
+            name,
             device_driver,
             post_init_cb,
+            dependencies: &[],
+        }
+    }
+
+    /// Initialize the driver after the drivers named in `dependencies`.
+    pub fn depends_on(self, dependencies: &'static [&'static str]) -> Self {
+        Self {
+            dependencies,
+            ..self
+        }
+    }
+
+    /// Init the driver, then run its post-init callback
+    unsafe fn init(&self) -> DriverStatus {
+        if let Err(e) = self.device_driver.init() {
+            return DriverStatus::Failed(e);
+        }
+
+        // call post-init cb if needed
+        if let Some(callback) = self.post_init_cb {
+            if let Err(e) = callback() {
+                return DriverStatus::Failed(e);
+            }
+        }
+
+        DriverStatus::Probed
+    }
+}
+
+impl fmt::Display for DriverStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Registered => write!(f, "not initialized"),
+            Self::Probed => write!(f, "ok"),
+            Self::Failed(e) => write!(f, "failed: {}", e),
+            Self::Disabled { missing } => write!(f, "disabled: needs {}", missing),
+            Self::Suspended => write!(f, "suspended"),
+            Self::ShutDown => write!(f, "shut down"),
+        }
+    }
+}
+
+impl Hook {
+    /// The drivers the hook applies to
+    fn applies_to(&self, status: DriverStatus) -> bool {
+        match self {
+            Hook::Shutdown => matches!(status, DriverStatus::Probed | DriverStatus::Suspended),
+            Hook::Suspend | Hook::Reset => status == DriverStatus::Probed,
+            Hook::Resume => status == DriverStatus::Suspended,
+        }
+    }
+
+    /// Dependents first to stop them, dependencies first to bring them back up
+    fn reverse_order(&self) -> bool {
+        matches!(self, Hook::Shutdown | Hook::Suspend)
+    }
+
+    fn run(&self, driver: &dyn interface::DeviceDriver) -> Result<DriverStatus, Error> {
+        match self {
+            Hook::Shutdown => driver.shutdown().map(|_| DriverStatus::ShutDown),
+            Hook::Suspend => driver.suspend().map(|_| DriverStatus::Suspended),
+            Hook::Resume => driver.resume().map(|_| DriverStatus::Probed),
+            Hook::Reset => driver.reset().map(|_| DriverStatus::Probed),
         }
     }
 }
@@ -82,56 +271,140 @@ impl DriverManager {
             inner: NullLock::new(DriverManagerInner::new()),
         }
     }
+
     /// Register a device descriptor with the kernel's device-driver manager
     pub fn register_driver(&self, device_descriptor: DeviceDriverDescriptor) {
         self.inner.lock(|inner| {
-            inner.drivers[inner.next_driver_index] = Some(device_descriptor);
-            inner.next_driver_index += 1;
+            inner.drivers.push(DriverEntry {
+                descriptor: device_descriptor,
+                status: DriverStatus::Registered,
+            })
         })
     }
 
-    /// Run a function on all drivers
-    pub fn for_each_descriptor(&self, f: impl FnMut(&DeviceDriverDescriptor)) {
-        self.inner.lock(|inner| {
-            inner
-                .drivers
-                .iter()
-                .filter_map(|x| x.as_ref())
-                .for_each(f)
-        })
+    fn set_status(&self, index: usize, status: DriverStatus) {
+        self.inner.lock(|inner| inner.drivers[index].status = status);
     }
 
-    /// Initialize all registed drivers
-    pub unsafe fn init_drivers(&self) {
-        // Call init on all drivers
-        self.for_each_descriptor(|driver| {
-            if let Err(x) = driver.device_driver.init() {
-                panic!(
-                    "Error initializing device driver: {}: {}",
-                    driver.device_driver.compatible(),
-                    x
-                )
+    /// Run `hook` on every driver it applies to. All the drivers get their turn, the first error
+    /// is returned and the failed drivers are marked as such.
+    fn run_hook(&self, hook: Hook) -> Result<(), Error> {
+        let mut order = self.inner.lock(|inner| inner.init_order.clone());
+        if hook.reverse_order() {
+            order.reverse();
+        }
+
+        let mut result = Ok(());
+        for index in order {
+            let (descriptor, status) = self
+                .inner
+                .lock(|inner| (inner.drivers[index].descriptor, inner.drivers[index].status));
+            if !hook.applies_to(status) {
+                continue;
             }
 
-            // call post-init cb if needed
-            if let Some(callback) = driver.post_init_cb {
-                if let Err(e) = callback() {
-                    panic!(
-                        "Error executing post init callback for driver: {}: {}",
-                        driver.device_driver.compatible(),
-                        e
-                    )
+            match hook.run(descriptor.device_driver) {
+                Ok(status) => self.set_status(index, status),
+                Err(e) => {
+                    self.set_status(index, DriverStatus::Failed(e));
+                    result = result.and(Err(e));
                 }
             }
-        })
+        }
+
+        result
+    }
+
+    /// Initialize all registed drivers, dependencies first, then register their IRQ handlers.
+    ///
+    /// The IRQ handlers go last: the interrupt controller must be up before anyone registers.
+    /// Failures don't stop the boot, they are reported and the drivers depending on the failed
+    /// one are disabled.
+    pub unsafe fn init_drivers_and_irqs(&self) {
+        let order = self.inner.lock(|inner| {
+            inner.init_order = inner.dependency_order();
+            inner.init_order.clone()
+        });
+
+        // The driver's code runs outside of the lock, a post-init callback may register things
+        for &index in &order {
+            let (descriptor, missing) = self
+                .inner
+                .lock(|inner| (inner.drivers[index].descriptor, inner.missing_dependency(index)));
+
+            let status = match missing {
+                Some(missing) => DriverStatus::Disabled { missing },
+                None => descriptor.init(),
+            };
+            self.set_status(index, status);
+        }
+
+        self.inner.lock(|inner| {
+            for entry in &mut inner.drivers {
+                if entry.status == DriverStatus::Registered {
+                    entry.status = DriverStatus::Failed(Error::new(
+                        ErrorKind::InvalidArgument,
+                        "dependency cycle",
+                    ));
+                }
+            }
+        });
+
+        for &index in &order {
+            let (descriptor, status) = self
+                .inner
+                .lock(|inner| (inner.drivers[index].descriptor, inner.drivers[index].status));
+            if status != DriverStatus::Probed {
+                continue;
+            }
+
+            if let Err(e) = descriptor.device_driver.register_and_enable_irq_handler() {
+                self.set_status(index, DriverStatus::Failed(e));
+            }
+        }
+
+        self.inner.lock(|inner| {
+            for entry in &inner.drivers {
+                if entry.status != DriverStatus::Probed {
+                    warn!("Driver {}: {}", entry.descriptor.name, entry.status);
+                }
+            }
+        });
+    }
+
+    /// Shut all the drivers down, dependents first. Call it last before a reboot or before
+    /// jumping to another image; the console UART is flushed but stays usable.
+    pub fn shutdown_drivers(&self) -> Result<(), Error> {
+        self.run_hook(Hook::Shutdown)
+    }
+
+    /// Suspend the running drivers, dependents first.
+    pub fn suspend_drivers(&self) -> Result<(), Error> {
+        self.run_hook(Hook::Suspend)
+    }
+
+    /// Resume the suspended drivers, dependencies first.
+    pub fn resume_drivers(&self) -> Result<(), Error> {
+        self.run_hook(Hook::Resume)
+    }
+
+    /// Reset the running drivers, dependencies first.
+    pub fn reset_drivers(&self) -> Result<(), Error> {
+        self.run_hook(Hook::Reset)
     }
 
     /// Enumerate all registered drivers
     pub fn enumerate(&self) {
-        let mut index = 0;
-        self.for_each_descriptor(|descriptor| {
-            info!("[{}] - {}", index, descriptor.device_driver.compatible());
-            index += 1;
+        self.inner.lock(|inner| {
+            for (index, entry) in inner.drivers.iter().enumerate() {
+                info!(
+                    "[{}] - {} ({}): {}",
+                    index,
+                    entry.descriptor.name,
+                    entry.descriptor.device_driver.compatible(),
+                    entry.status
+                );
+            }
         })
     }
 }
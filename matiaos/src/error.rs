@@ -0,0 +1,154 @@
+//! Kernel errors.
+//!
+//! An error has a kind callers can match on (retry on `Timeout`, go without the device on
+//! `NotPresent`...) and a message saying what failed, printed after the kind.
+//!
+//! Modules still on `&'static str` errors (filesystems, the shell) get the message with
+//! [`Error::as_str`].
+//!
+//! It is also the error of the drivers' `embedded-hal` and `embedded-io` implementations, the
+//! kind is translated to theirs.
+
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Category of an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A parameter is out of range or malformed
+    InvalidArgument,
+    /// The hardware didn't answer in time
+    Timeout,
+    /// The device, or what was asked of it, isn't there
+    NotPresent,
+    /// An allocation failed, on the heap or by the firmware
+    OutOfMemory,
+    /// Already taken, or not in a state to do it
+    Busy,
+    /// The device or the firmware reported a failure
+    Io,
+    /// The hardware or the driver can't do it
+    Unsupported,
+}
+
+/// A kernel error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    context: Option<&'static str>,
+}
+
+/// Result with a kernel error
+pub type Result<T> = core::result::Result<T, Error>;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl ErrorKind {
+    /// Short description
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidArgument => "invalid argument",
+            Self::Timeout => "timeout",
+            Self::NotPresent => "not present",
+            Self::OutOfMemory => "out of memory",
+            Self::Busy => "busy",
+            Self::Io => "I/O error",
+            Self::Unsupported => "unsupported",
+        }
+    }
+}
+
+impl Error {
+    /// An error of `kind`, `context` says what failed.
+    pub const fn new(kind: ErrorKind, context: &'static str) -> Self {
+        Self {
+            kind,
+            context: Some(context),
+        }
+    }
+
+    /// The category
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Short description, for the kernel's `&'static str` errors: the context if there is one.
+    pub const fn as_str(&self) -> &'static str {
+        match self.context {
+            Some(context) => context,
+            None => self.kind.as_str(),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            context: None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.context {
+            Some(context) => write!(f, "{}: {}", self.kind, context),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// embedded-hal and embedded-io
+//--------------------------------------------------------------------------------------------------
+
+impl embedded_hal::digital::Error for Error {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
+
+impl embedded_hal::spi::Error for Error {
+    fn kind(&self) -> embedded_hal::spi::ErrorKind {
+        embedded_hal::spi::ErrorKind::Other
+    }
+}
+
+impl embedded_hal::i2c::Error for Error {
+    fn kind(&self) -> embedded_hal::i2c::ErrorKind {
+        use embedded_hal::i2c::{ErrorKind as I2cErrorKind, NoAcknowledgeSource};
+
+        match self.kind {
+            // The BSC doesn't say if it was the address or a data byte
+            ErrorKind::NotPresent => I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            _ => I2cErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind as IoErrorKind;
+
+        match self.kind {
+            ErrorKind::InvalidArgument => IoErrorKind::InvalidInput,
+            ErrorKind::Timeout => IoErrorKind::TimedOut,
+            ErrorKind::NotPresent => IoErrorKind::NotFound,
+            ErrorKind::OutOfMemory => IoErrorKind::OutOfMemory,
+            ErrorKind::Unsupported => IoErrorKind::Unsupported,
+            ErrorKind::Busy | ErrorKind::Io => IoErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! A dummy IRQ manager, used until the BSP registers the real interrupt controller.
+
+use super::{interface, IRQHandlerDescriptor, IRQNumber};
+use crate::error::{Error, ErrorKind};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub struct NullIRQManager;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+pub static NULL_IRQ_MANAGER: NullIRQManager = NullIRQManager {};
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl interface::IRQManager for NullIRQManager {
+    type IRQNumberType = IRQNumber;
+
+    fn register_handler(
+        &self,
+        _descriptor: IRQHandlerDescriptor<Self::IRQNumberType>,
+    ) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::NotPresent, "No IRQ manager registered yet"))
+    }
+
+    fn enable(&self, _irq_number: &Self::IRQNumberType) {}
+
+    fn handle_pending_irqs(&self) {
+        panic!("No IRQ manager registered yet");
+    }
+}
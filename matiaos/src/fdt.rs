@@ -0,0 +1,37 @@
+//! Flattened device tree (DTB): the one the firmware passed, and its parser.
+
+mod parser;
+
+use crate::synchronization::{interface::Mutex, NullLock};
+
+pub use parser::*;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The device tree the firmware passed
+static BOOT_FDT: NullLock<usize> = NullLock::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Record the device tree address the firmware passed (called from the boot code, before
+/// anything else runs).
+pub fn set_boot_fdt(addr: usize) {
+    BOOT_FDT.lock(|fdt| *fdt = addr);
+}
+
+/// The address of the device tree the firmware passed, 0 without one.
+pub fn boot_fdt_addr() -> usize {
+    BOOT_FDT.lock(|fdt| *fdt)
+}
+
+/// The device tree the firmware passed, if it passed a valid one.
+pub fn boot_fdt() -> Result<Fdt, &'static str> {
+    let addr = boot_fdt_addr();
+
+    // The firmware leaves it where the kernel doesn't go, and nobody writes there
+    unsafe { Fdt::from_addr(addr) }
+}
@@ -0,0 +1,724 @@
+//! Flattened device tree (DTB) parser.
+//!
+//! The firmware describes the board in a device tree blob and passes its address in x0. This
+//! reads it in place, without allocating: nodes are found by path or `compatible` string, and
+//! their `reg` addresses are translated to CPU physical addresses through the `ranges` of their
+//! parent buses.
+//!
+//! Format: <https://devicetree-specification.readthedocs.io/en/stable/flattened-format.html>
+//!
+//! Plain code over a byte slice, without registers or crates, so it is tested on the host
+//! (`make test`).
+
+use core::{slice, str};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const HEADER_SIZE: usize = 40;
+/// Oldest format version we read (the one with `size_dt_struct`)
+const MIN_VERSION: u32 = 16;
+
+/// Structure block tokens. The block ends with an `END` (9) one.
+mod token {
+    pub const BEGIN_NODE: u32 = 1;
+    pub const END_NODE: u32 = 2;
+    pub const PROP: u32 = 3;
+    pub const NOP: u32 = 4;
+}
+
+/// Deepest node we handle, the Raspberry Pi trees are 4 levels deep at most
+const MAX_DEPTH: usize = 8;
+
+/// Defaults when a node has no `#address-cells`/`#size-cells`
+const DEFAULT_ADDRESS_CELLS: usize = 2;
+const DEFAULT_SIZE_CELLS: usize = 1;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A device tree blob
+#[derive(Clone, Copy)]
+pub struct Fdt {
+    blob: &'static [u8],
+    structs: &'static [u8],
+    strings: &'static [u8],
+}
+
+/// A node of the tree. It remembers its ancestors, to find its parent bus.
+#[derive(Clone, Copy)]
+pub struct Node {
+    fdt: Fdt,
+    /// Offset of its `BEGIN_NODE` token in the structure block
+    offset: usize,
+    ancestors: [usize; MAX_DEPTH],
+    depth: usize,
+}
+
+/// A property of a node
+pub struct Property {
+    /// Property name
+    pub name: &'static str,
+    /// Raw value, big endian cells and NUL terminated strings
+    pub value: &'static [u8],
+}
+
+/// The properties of a node
+pub struct Properties {
+    fdt: Fdt,
+    offset: usize,
+}
+
+/// The (address, size) entries of a `reg` property, in the parent bus address space
+pub struct Reg {
+    value: &'static [u8],
+    address_cells: usize,
+    size_cells: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// A number of `cells` 32 bits big endian cells
+fn read_cells(bytes: &[u8], cells: usize) -> Option<u64> {
+    if cells > 2 {
+        return None;
+    }
+
+    (0..cells).try_fold(0u64, |acc, i| Some((acc << 32) | u64::from(be_u32(bytes, i * 4)?)))
+}
+
+fn align4(offset: usize) -> usize {
+    offset.next_multiple_of(4)
+}
+
+/// A NUL terminated string at `offset`
+fn c_str(bytes: &'static [u8], offset: usize) -> Option<&'static str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|&c| c == 0)?;
+
+    str::from_utf8(&bytes[..len]).ok()
+}
+
+/// Whether the node name `name` is `component`. The unit address (`@...`) can be omitted.
+fn name_matches(name: &str, component: &str) -> bool {
+    name == component || (!component.contains('@') && name.split('@').next() == Some(component))
+}
+
+impl Fdt {
+    /// Visit the nodes in order, until `f` returns true for one
+    fn find(&self, mut f: impl FnMut(&Node) -> bool) -> Option<Node> {
+        let mut ancestors = [0; MAX_DEPTH];
+        let mut depth = 0;
+        let mut offset = 0;
+
+        loop {
+            let token = be_u32(self.structs, offset)?;
+            match token {
+                token::BEGIN_NODE => {
+                    let node = Node {
+                        fdt: *self,
+                        offset,
+                        ancestors,
+                        depth,
+                    };
+                    if f(&node) {
+                        return Some(node);
+                    }
+
+                    *ancestors.get_mut(depth)? = offset;
+                    depth += 1;
+                    offset = align4(offset + 4 + node.name().len() + 1);
+                }
+                token::END_NODE => {
+                    depth = depth.checked_sub(1)?;
+                    offset += 4;
+                }
+                token::PROP => {
+                    let len = be_u32(self.structs, offset + 4)? as usize;
+                    offset = align4(offset + 12 + len);
+                }
+                token::NOP => offset += 4,
+                // END, or a corrupted tree
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl Node {
+    fn node_at(&self, depth: usize) -> Node {
+        Node {
+            fdt: self.fdt,
+            offset: self.ancestors[depth],
+            ancestors: self.ancestors,
+            depth,
+        }
+    }
+
+    /// `#address-cells` and `#size-cells` of the children of this node
+    fn child_cells(&self) -> (usize, usize) {
+        let cells = |name, default| {
+            self.property(name)
+                .and_then(|x| be_u32(x, 0))
+                .map_or(default, |x| x as usize)
+        };
+
+        (
+            cells("#address-cells", DEFAULT_ADDRESS_CELLS),
+            cells("#size-cells", DEFAULT_SIZE_CELLS),
+        )
+    }
+
+    /// Translate `address` of this bus's address space to its parent's, with `ranges`
+    fn translate(&self, address: u64) -> Option<u64> {
+        // No ranges means the bus is not memory mapped, an empty one is an identity mapping
+        let ranges = self.property("ranges")?;
+        if ranges.is_empty() {
+            return Some(address);
+        }
+
+        let (child_cells, size_cells) = self.child_cells();
+        let (parent_cells, _) = self.parent()?.child_cells();
+        let entry_size = (child_cells + parent_cells + size_cells) * 4;
+        if entry_size == 0 {
+            return None;
+        }
+
+        ranges.chunks_exact(entry_size).find_map(|entry| {
+            let child = read_cells(entry, child_cells)?;
+            let parent = read_cells(&entry[child_cells * 4..], parent_cells)?;
+            let size = read_cells(&entry[(child_cells + parent_cells) * 4..], size_cells)?;
+
+            // A range can't wrap around the end of the address space
+            if !(child..child.checked_add(size)?).contains(&address) {
+                return None;
+            }
+
+            (address - child).checked_add(parent)
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Fdt {
+    /// Check the header of the blob at `addr` and make an instance of it.
+    ///
+    /// # Safety
+    ///
+    /// - `addr` must point to readable memory that stays untouched for the kernel's lifetime.
+    pub unsafe fn from_addr(addr: usize) -> Result<Self, &'static str> {
+        if addr == 0 {
+            return Err("No device tree");
+        }
+
+        let header = slice::from_raw_parts(addr as *const u8, HEADER_SIZE);
+        if be_u32(header, 0) != Some(FDT_MAGIC) {
+            return Err("Bad device tree magic");
+        }
+        let total_size = be_u32(header, 4).unwrap() as usize;
+
+        Self::from_bytes(slice::from_raw_parts(addr as *const u8, total_size))
+    }
+
+    /// Make an instance of a blob.
+    pub fn from_bytes(blob: &'static [u8]) -> Result<Self, &'static str> {
+        let field = |i: usize| be_u32(blob, i * 4).ok_or("Truncated device tree");
+        if field(0)? != FDT_MAGIC {
+            return Err("Bad device tree magic");
+        }
+        if field(5)? < MIN_VERSION {
+            return Err("Unsupported device tree version");
+        }
+
+        let section = |offset: u32, size: u32| {
+            blob.get(offset as usize..offset as usize + size as usize)
+                .ok_or("Truncated device tree")
+        };
+
+        Ok(Self {
+            blob,
+            structs: section(field(2)?, field(9)?)?,
+            strings: section(field(3)?, field(8)?)?,
+        })
+    }
+
+    /// Size of the blob, in bytes
+    pub fn size(&self) -> usize {
+        self.blob.len()
+    }
+
+    /// The node at `path` (i.e. `/soc/serial@7e201000`). Unit addresses can be omitted when
+    /// they are not ambiguous.
+    pub fn find_node(&self, path: &str) -> Option<Node> {
+        let path = path.strip_prefix('/')?;
+        let components = || path.split('/').filter(|x| !x.is_empty());
+        let depth = components().count();
+
+        self.find(|node| {
+            node.depth == depth
+                && components()
+                    .enumerate()
+                    .all(|(i, component)| {
+                        // ancestors[0] is the root, the path starts with its children
+                        let name = if i + 1 == depth {
+                            node.name()
+                        } else {
+                            node.node_at(i + 1).name()
+                        };
+                        name_matches(name, component)
+                    })
+        })
+    }
+
+    /// The first node compatible with `compatible`
+    pub fn find_compatible(&self, compatible: &str) -> Option<Node> {
+        self.find(|node| node.is_compatible(compatible))
+    }
+
+    /// Call `f` with every node, in order
+    pub fn for_each_node(&self, mut f: impl FnMut(&Node)) {
+        self.find(|node| {
+            f(node);
+            false
+        });
+    }
+
+    /// The node an `/aliases` entry, or a path, designates
+    pub fn resolve(&self, alias_or_path: &str) -> Option<Node> {
+        if alias_or_path.starts_with('/') {
+            return self.find_node(alias_or_path);
+        }
+
+        let path = self.find_node("/aliases")?.property_str(alias_or_path)?;
+        self.find_node(path)
+    }
+}
+
+impl Node {
+    /// Node name, with its unit address (empty for the root)
+    pub fn name(&self) -> &'static str {
+        c_str(self.fdt.structs, self.offset + 4).unwrap_or("")
+    }
+
+    /// The parent node, `None` for the root
+    pub fn parent(&self) -> Option<Node> {
+        self.depth.checked_sub(1).map(|depth| self.node_at(depth))
+    }
+
+    /// The properties of the node
+    pub fn properties(&self) -> Properties {
+        Properties {
+            fdt: self.fdt,
+            offset: align4(self.offset + 4 + self.name().len() + 1),
+        }
+    }
+
+    /// The value of the property `name`
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        self.properties()
+            .find(|x| x.name == name)
+            .map(|x| x.value)
+    }
+
+    /// The value of a string property (the first one of a string list)
+    pub fn property_str(&self, name: &str) -> Option<&'static str> {
+        c_str(self.property(name)?, 0)
+    }
+
+    /// Whether `compatible` is one of the strings of the `compatible` property
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|x| {
+            x.split(|&c| c == 0)
+                .any(|x| x == compatible.as_bytes())
+        })
+    }
+
+    /// The entries of the `reg` property, in the parent bus address space
+    pub fn reg(&self) -> Option<Reg> {
+        let (address_cells, size_cells) = self.parent()?.child_cells();
+
+        Some(Reg {
+            value: self.property("reg")?,
+            address_cells,
+            size_cells,
+        })
+    }
+
+    /// The `index`th `reg` address as a CPU physical address, translated through the parent buses
+    pub fn address(&self, index: usize) -> Option<usize> {
+        let (mut address, _) = self.reg()?.nth(index)?;
+
+        // The root's children are in the CPU address space
+        let mut bus = self.parent()?;
+        while let Some(parent) = bus.parent() {
+            address = bus.translate(address)?;
+            bus = parent;
+        }
+
+        usize::try_from(address).ok()
+    }
+}
+
+impl Iterator for Properties {
+    type Item = Property;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match be_u32(self.fdt.structs, self.offset)? {
+                token::PROP => {
+                    let len = be_u32(self.fdt.structs, self.offset + 4)? as usize;
+                    let name_offset = be_u32(self.fdt.structs, self.offset + 8)? as usize;
+                    let value = self.fdt.structs.get(self.offset + 12..self.offset + 12 + len)?;
+                    self.offset = align4(self.offset + 12 + len);
+
+                    return Some(Property {
+                        name: c_str(self.fdt.strings, name_offset)?,
+                        value,
+                    });
+                }
+                token::NOP => self.offset += 4,
+                // Properties come before the children
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl Iterator for Reg {
+    /// (address, size)
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.value.is_empty() {
+            return None;
+        }
+
+        let address = read_cells(self.value, self.address_cells)?;
+        let size = read_cells(self.value.get(self.address_cells * 4..)?, self.size_cells)?;
+        self.value = self
+            .value
+            .get((self.address_cells + self.size_cells) * 4..)?;
+
+        Some((address, size))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a blob, a node at a time
+    struct Builder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Self {
+                structs: Vec::new(),
+                strings: Vec::new(),
+            }
+        }
+
+        fn word(&mut self, value: u32) {
+            self.structs.extend(value.to_be_bytes());
+        }
+
+        fn bytes(&mut self, bytes: &[u8]) {
+            self.structs.extend(bytes);
+            self.structs.resize(align4(self.structs.len()), 0);
+        }
+
+        fn begin(mut self, name: &str) -> Self {
+            self.word(token::BEGIN_NODE);
+            self.bytes(&[name.as_bytes(), &[0]].concat());
+            self
+        }
+
+        fn end(mut self) -> Self {
+            self.word(token::END_NODE);
+            self
+        }
+
+        fn prop(mut self, name: &str, value: &[u8]) -> Self {
+            let name_offset = self.strings.len();
+            self.strings.extend(name.as_bytes());
+            self.strings.push(0);
+
+            self.word(token::PROP);
+            self.word(value.len() as u32);
+            self.word(name_offset as u32);
+            self.bytes(value);
+            self
+        }
+
+        fn cells(self, name: &str, cells: &[u32]) -> Self {
+            let value: Vec<u8> = cells.iter().flat_map(|x| x.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        /// Header, empty memory reservation block, structure block and strings
+        fn build(mut self) -> &'static [u8] {
+            // END
+            self.word(9);
+
+            let structs_offset = HEADER_SIZE + 16;
+            let strings_offset = structs_offset + self.structs.len();
+            let total_size = strings_offset + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total_size as u32,
+                structs_offset as u32,
+                strings_offset as u32,
+                HEADER_SIZE as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ];
+
+            let mut blob: Vec<u8> = header.iter().flat_map(|x| x.to_be_bytes()).collect();
+            blob.extend([0; 16]);
+            blob.extend(self.structs);
+            blob.extend(self.strings);
+
+            Vec::leak(blob)
+        }
+    }
+
+    /// A Raspberry Pi like tree
+    fn blob() -> &'static [u8] {
+        Builder::new()
+            .begin("")
+            .cells("#address-cells", &[1])
+            .cells("#size-cells", &[1])
+            .begin("aliases")
+            .prop("serial0", b"/soc/serial@7e201000\0")
+            .prop("gpio", b"/soc/gpio@7e200000\0")
+            .end()
+            .begin("chosen")
+            .prop("bootargs", b"console=serial0,115200 quiet\0")
+            .end()
+            .begin("memory@0")
+            .cells("reg", &[0x0, 0x3B40_0000])
+            .end()
+            .begin("soc")
+            .prop("compatible", b"simple-bus\0")
+            .cells("#address-cells", &[1])
+            .cells("#size-cells", &[1])
+            .cells("ranges", &[0x7E00_0000, 0xFE00_0000, 0x0180_0000])
+            .begin("serial@7e201000")
+            .prop("compatible", b"arm,pl011\0arm,primecell\0")
+            .cells("reg", &[0x7E20_1000, 0x200])
+            .end()
+            .begin("outside@7f800000")
+            .cells("reg", &[0x7F80_0000, 0x100])
+            .end()
+            // No cells: its children use the defaults, not the ones of soc
+            .begin("bus")
+            .prop("ranges", &[])
+            .begin("device@0")
+            .cells("reg", &[0x0, 0x7E30_0000, 0x100])
+            .end()
+            .end()
+            .end()
+            // A range that would wrap around the end of the address space
+            .begin("wrap")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .cells("ranges", &[0xFFFF_FFFF, 0xFFFF_F000, 0x0, 0x0, 0x2000])
+            .begin("device@ffffffff_fffff800")
+            .cells("reg", &[0xFFFF_FFFF, 0xFFFF_F800, 0x0, 0x10])
+            .end()
+            .end()
+            .end()
+            .build()
+    }
+
+    fn fdt() -> Fdt {
+        Fdt::from_bytes(blob()).unwrap()
+    }
+
+    #[test]
+    fn truncated_header() {
+        assert_eq!(
+            Fdt::from_bytes(&blob()[..20]).err(),
+            Some("Truncated device tree")
+        );
+    }
+
+    #[test]
+    fn truncated_blocks() {
+        let blob = blob();
+
+        assert_eq!(
+            Fdt::from_bytes(&blob[..blob.len() - 1]).err(),
+            Some("Truncated device tree")
+        );
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut blob = blob().to_vec();
+        blob[0] = 0;
+
+        assert_eq!(
+            Fdt::from_bytes(Vec::leak(blob)).err(),
+            Some("Bad device tree magic")
+        );
+    }
+
+    #[test]
+    fn old_version() {
+        let mut blob = blob().to_vec();
+        blob[20..24].copy_from_slice(&15u32.to_be_bytes());
+
+        assert_eq!(
+            Fdt::from_bytes(Vec::leak(blob)).err(),
+            Some("Unsupported device tree version")
+        );
+    }
+
+    #[test]
+    fn chosen_bootargs() {
+        let chosen = fdt().find_node("/chosen").unwrap();
+
+        assert_eq!(
+            chosen.property_str("bootargs"),
+            Some("console=serial0,115200 quiet")
+        );
+        assert_eq!(chosen.property_str("stdout-path"), None);
+    }
+
+    #[test]
+    fn find_by_path() {
+        let fdt = fdt();
+
+        assert_eq!(
+            fdt.find_node("/soc/serial").unwrap().name(),
+            "serial@7e201000"
+        );
+        assert_eq!(
+            fdt.find_node("/soc/serial@7e201000").unwrap().name(),
+            "serial@7e201000"
+        );
+        assert!(fdt.find_node("/soc/serial@7e215040").is_none());
+        assert!(fdt.find_node("/serial").is_none());
+        assert_eq!(fdt.find_node("/").unwrap().name(), "");
+    }
+
+    #[test]
+    fn find_by_compatible() {
+        let node = fdt().find_compatible("arm,primecell").unwrap();
+
+        assert_eq!(node.name(), "serial@7e201000");
+        assert!(node.is_compatible("arm,pl011"));
+        assert!(!node.is_compatible("arm,pl0"));
+    }
+
+    #[test]
+    fn alias_resolution() {
+        let fdt = fdt();
+
+        assert_eq!(fdt.resolve("serial0").unwrap().name(), "serial@7e201000");
+        assert_eq!(
+            fdt.resolve("/soc/serial").unwrap().name(),
+            "serial@7e201000"
+        );
+        // The alias exists, but not its node
+        assert!(fdt.resolve("gpio").is_none());
+        assert!(fdt.resolve("serial1").is_none());
+    }
+
+    #[test]
+    fn ranges_translation() {
+        let fdt = fdt();
+
+        assert_eq!(
+            fdt.find_node("/soc/serial").unwrap().address(0),
+            Some(0xFE20_1000)
+        );
+        assert_eq!(fdt.find_node("/soc/serial").unwrap().address(1), None);
+        // Out of the ranges of soc
+        assert_eq!(fdt.find_node("/soc/outside").unwrap().address(0), None);
+        // Children of the root are not translated
+        assert_eq!(fdt.find_node("/memory").unwrap().address(0), Some(0));
+    }
+
+    #[test]
+    fn empty_ranges_is_identity() {
+        let node = fdt().find_node("/soc/bus/device").unwrap();
+
+        assert_eq!(node.address(0), Some(0xFE30_0000));
+    }
+
+    #[test]
+    fn wrapping_range_is_not_translated() {
+        let node = fdt().find_node("/wrap/device").unwrap();
+
+        assert_eq!(node.address(0), None);
+    }
+
+    #[test]
+    fn cells_come_from_the_parent() {
+        let fdt = fdt();
+
+        let memory: Vec<_> = fdt.find_node("/memory").unwrap().reg().unwrap().collect();
+        assert_eq!(memory, [(0x0, 0x3B40_0000)]);
+
+        // bus has no #address-cells/#size-cells: 2 and 1, whatever soc says
+        let device: Vec<_> = fdt
+            .find_node("/soc/bus/device")
+            .unwrap()
+            .reg()
+            .unwrap()
+            .collect();
+        assert_eq!(device, [(0x7E30_0000, 0x100)]);
+
+        let wrap: Vec<_> = fdt
+            .find_node("/wrap/device")
+            .unwrap()
+            .reg()
+            .unwrap()
+            .collect();
+        assert_eq!(wrap, [(0xFFFF_FFFF_FFFF_F800, 0x10)]);
+    }
+
+    #[test]
+    fn node_walk() {
+        let mut names = Vec::new();
+        fdt().for_each_node(|node| names.push(node.name()));
+
+        assert_eq!(names.len(), 11);
+        assert_eq!(names[..3], ["", "aliases", "chosen"]);
+        assert_eq!(
+            fdt()
+                .find_node("/soc/bus/device")
+                .unwrap()
+                .parent()
+                .unwrap()
+                .name(),
+            "bus"
+        );
+    }
+}
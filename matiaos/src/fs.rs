@@ -0,0 +1,392 @@
+//! Virtual filesystem.
+//!
+//! A filesystem is a tree of inodes (files and directories). The root one is a ramfs; others are
+//! mounted on its directories (the FAT32 boot partition on `/boot`, the initramfs on `/initrd`).
+//! Paths are resolved across mount points, and opened files are handles keeping their own offset.
+
+mod fat;
+mod initramfs;
+mod ramfs;
+
+use crate::{
+    block, initramfs as archive,
+    synchronization::{interface::Mutex, NullLock},
+};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Filesystem traits
+pub mod interface {
+    use super::{DirEntry, FileType, InodeRef, Metadata};
+    use alloc::vec::Vec;
+
+    /// A file or a directory. Filesystems only implement what they support, the rest fails.
+    pub trait Inode: Send + Sync {
+        /// Size and type
+        fn metadata(&self) -> Metadata;
+
+        /// Directories: the entry called `name` (never `.` or `..`, the VFS handles them)
+        fn lookup(&self, _name: &str) -> Result<InodeRef, &'static str> {
+            Err("Not a directory")
+        }
+
+        /// Directories: all the entries
+        fn read_dir(&self) -> Result<Vec<DirEntry>, &'static str> {
+            Err("Not a directory")
+        }
+
+        /// Directories: create an empty file or directory called `name`
+        fn create(&self, _name: &str, _file_type: FileType) -> Result<InodeRef, &'static str> {
+            Err("Read-only filesystem")
+        }
+
+        /// Directories: remove the entry called `name`
+        fn unlink(&self, _name: &str) -> Result<(), &'static str> {
+            Err("Read-only filesystem")
+        }
+
+        /// Files: read from `offset`. Returns the number of bytes read, 0 at the end of the file.
+        fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<usize, &'static str> {
+            Err("Is a directory")
+        }
+
+        /// Files: write at `offset`, growing the file if needed
+        fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize, &'static str> {
+            Err("Read-only filesystem")
+        }
+
+        /// Files: change the size
+        fn truncate(&self, _size: u64) -> Result<(), &'static str> {
+            Err("Read-only filesystem")
+        }
+    }
+
+    /// A mountable filesystem
+    pub trait FileSystem: Send + Sync {
+        /// Type name (ramfs, fat32...)
+        fn name(&self) -> &'static str;
+
+        /// The root directory
+        fn root(&self) -> InodeRef;
+    }
+}
+
+/// A reference counted inode
+pub type InodeRef = Arc<dyn interface::Inode>;
+
+/// Regular file or directory
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// A regular file
+    File,
+    /// A directory
+    Directory,
+}
+
+/// What we know about a file
+#[derive(Clone, Copy)]
+pub struct Metadata {
+    /// Regular file or directory
+    pub file_type: FileType,
+    /// Size in bytes, 0 for a directory
+    pub size: u64,
+}
+
+/// A directory entry
+pub struct DirEntry {
+    /// File name
+    pub name: String,
+    /// Its metadata
+    pub metadata: Metadata,
+}
+
+/// How a file is opened
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Read only
+    Read,
+    /// Write only, create the file or truncate it
+    Write,
+    /// Write only at the end, create the file if needed
+    Append,
+}
+
+/// An open file
+pub struct File {
+    inode: InodeRef,
+    mode: OpenMode,
+    offset: u64,
+}
+
+struct Mount {
+    /// Normalized path, "/" for the root
+    path: String,
+    fs: Arc<dyn interface::FileSystem>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Longest path first, so the first match is the innermost mount point
+static MOUNTS: NullLock<Vec<Mount>> = NullLock::new(Vec::new());
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// The components of an absolute path, with `.` and `..` resolved.
+fn components(path: &str) -> Result<Vec<&str>, &'static str> {
+    if !path.starts_with('/') {
+        return Err("Path is not absolute");
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            x => components.push(x),
+        }
+    }
+
+    Ok(components)
+}
+
+/// The inode at the end of `components`, starting from the innermost mount point on the way.
+fn resolve(path: &[&str]) -> Result<InodeRef, &'static str> {
+    let (depth, root) = MOUNTS
+        .lock(|mounts| {
+            mounts.iter().find_map(|mount| {
+                let mount_point = components(&mount.path).ok()?;
+
+                path.starts_with(&mount_point)
+                    .then(|| (mount_point.len(), mount.fs.root()))
+            })
+        })
+        .ok_or("Nothing mounted on /")?;
+
+    path[depth..]
+        .iter()
+        .try_fold(root, |inode, name| inode.lookup(name))
+}
+
+/// Create the directory `path` unless it exists
+fn create_mount_point(path: &str) -> Result<(), &'static str> {
+    match create_dir(path) {
+        Err(e) if e != "File exists" => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The parent directory and the name of the last component
+fn resolve_parent(path: &str) -> Result<(InodeRef, String), &'static str> {
+    let components = components(path)?;
+    let (name, parent) = components.split_last().ok_or("Invalid path")?;
+
+    Ok((resolve(parent)?, name.to_string()))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Metadata {
+    /// Is it a directory
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+impl File {
+    /// Read from the current offset. Returns the number of bytes read, 0 at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, &'static str> {
+        if self.mode != OpenMode::Read {
+            return Err("File not open for reading");
+        }
+
+        let len = self.inode.read_at(self.offset, buf)?;
+        self.offset += len as u64;
+
+        Ok(len)
+    }
+
+    /// Write at the current offset (the end in `Append` mode).
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, &'static str> {
+        match self.mode {
+            OpenMode::Read => return Err("File not open for writing"),
+            OpenMode::Append => self.offset = self.inode.metadata().size,
+            OpenMode::Write => (),
+        }
+
+        let len = self.inode.write_at(self.offset, buf)?;
+        self.offset += len as u64;
+
+        Ok(len)
+    }
+
+    /// Read everything from the current offset to the end.
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, &'static str> {
+        let mut chunk = [0; 512];
+        let mut total = 0;
+        loop {
+            let len = self.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(total);
+            }
+            buf.extend_from_slice(&chunk[..len]);
+            total += len;
+        }
+    }
+
+    /// Move the offset.
+    #[allow(dead_code)]
+    pub fn seek(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// Size and type
+    #[allow(dead_code)]
+    pub fn metadata(&self) -> Metadata {
+        self.inode.metadata()
+    }
+}
+
+/// The absolute `path` without `.`, `..` and repeated `/`.
+pub fn normalize(path: &str) -> Result<String, &'static str> {
+    Ok(String::from("/") + &components(path)?.join("/"))
+}
+
+/// Mount the root filesystem (a ramfs) on `/`.
+pub fn init() {
+    MOUNTS.lock(|mounts| {
+        mounts.push(Mount {
+            path: "/".to_string(),
+            fs: Arc::new(ramfs::RamFs::new()),
+        })
+    });
+}
+
+/// Mount `fs` on the directory `path`.
+pub fn mount(path: &str, fs: Arc<dyn interface::FileSystem>) -> Result<(), &'static str> {
+    let target = components(path)?;
+    if !resolve(&target)?.metadata().is_dir() {
+        return Err("Not a directory");
+    }
+
+    let path = normalize(path)?;
+    MOUNTS.lock(|mounts| {
+        if mounts.iter().any(|x| x.path == path) {
+            return Err("Already mounted");
+        }
+
+        let index = mounts
+            .iter()
+            .position(|x| x.path.len() < path.len())
+            .unwrap_or(mounts.len());
+        mounts.insert(index, Mount { path, fs });
+
+        Ok(())
+    })
+}
+
+/// Call `f` with the path and filesystem name of every mount point.
+pub fn mounts(mut f: impl FnMut(&str, &str)) {
+    MOUNTS.lock(|mounts| {
+        for mount in mounts.iter().rev() {
+            f(&mount.path, mount.fs.name());
+        }
+    })
+}
+
+/// Mount the FAT32 boot partition of the boot block device on `/boot`.
+pub fn mount_boot_partition() -> Result<(), &'static str> {
+    let device = block::block_device().ok_or("No block device")?;
+    let fs = fat::FatFileSystem::new(device)?;
+
+    create_mount_point("/boot")?;
+    mount("/boot", Arc::new(fs))
+}
+
+/// Mount the initramfs read-only on `/initrd`. Returns where the archive comes from and its
+/// number of files, or `None` if there is no initramfs.
+pub fn mount_initramfs() -> Result<Option<(&'static str, usize)>, &'static str> {
+    let Some((archive, origin)) = archive::archive() else {
+        return Ok(None);
+    };
+    let fs = initramfs::InitramFs::new(archive)?;
+    let files = fs.files();
+
+    create_mount_point("/initrd")?;
+    mount("/initrd", Arc::new(fs))?;
+
+    Ok(Some((origin, files)))
+}
+
+/// Size and type of the file or directory at `path`
+pub fn metadata(path: &str) -> Result<Metadata, &'static str> {
+    Ok(resolve(&components(path)?)?.metadata())
+}
+
+/// The entries of the directory at `path`
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, &'static str> {
+    resolve(&components(path)?)?.read_dir()
+}
+
+/// Open the file at `path`.
+pub fn open(path: &str, mode: OpenMode) -> Result<File, &'static str> {
+    let inode = match resolve(&components(path)?) {
+        Ok(inode) => inode,
+        Err(_) if mode != OpenMode::Read => {
+            let (parent, name) = resolve_parent(path)?;
+            parent.create(&name, FileType::File)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    if inode.metadata().is_dir() {
+        return Err("Is a directory");
+    }
+    if mode == OpenMode::Write {
+        inode.truncate(0)?;
+    }
+
+    Ok(File {
+        inode,
+        mode,
+        offset: 0,
+    })
+}
+
+/// Create the directory `path`.
+pub fn create_dir(path: &str) -> Result<(), &'static str> {
+    let (parent, name) = resolve_parent(path)?;
+    parent.create(&name, FileType::Directory)?;
+
+    Ok(())
+}
+
+/// Remove the file or empty directory `path`.
+pub fn remove(path: &str) -> Result<(), &'static str> {
+    let target = components(path)?;
+    let is_mount_point = MOUNTS.lock(|mounts| {
+        mounts
+            .iter()
+            .any(|x| components(&x.path).is_ok_and(|x| x == target))
+    });
+    if is_mount_point {
+        return Err("Device or resource busy");
+    }
+
+    let (parent, name) = resolve_parent(path)?;
+    parent.unlink(&name)
+}
@@ -0,0 +1,114 @@
+//! FAT32 on top of a block device, through the `fat32` crate. Read-only.
+
+use super::{interface, DirEntry, FileType, InodeRef, Metadata};
+use crate::block;
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The kernel's block devices, as the `fat32` crate wants them
+struct Device(&'static (dyn block::interface::BlockDevice + Sync));
+
+type Volume = fat32::Volume<Device>;
+
+struct FatInode {
+    volume: Arc<Volume>,
+    entry: fat32::DirEntry,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A FAT32 volume
+pub struct FatFileSystem {
+    volume: Arc<Volume>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl fat32::BlockDevice for Device {
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.0.read_blocks(lba, buf).map_err(|e| e.as_str())
+    }
+}
+
+fn metadata(entry: &fat32::DirEntry) -> Metadata {
+    Metadata {
+        file_type: if entry.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::File
+        },
+        size: u64::from(entry.size()),
+    }
+}
+
+impl interface::Inode for FatInode {
+    fn metadata(&self) -> Metadata {
+        metadata(&self.entry)
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, &'static str> {
+        let entry = self
+            .volume
+            .lookup(&self.entry, name)
+            .map_err(|e| e.as_str())?;
+
+        Ok(Arc::new(FatInode {
+            volume: self.volume.clone(),
+            entry,
+        }))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, &'static str> {
+        let mut entries = Vec::new();
+        for entry in self.volume.read_dir(&self.entry).map_err(|e| e.as_str())? {
+            let entry = entry.map_err(|e| e.as_str())?;
+            entries.push(DirEntry {
+                name: String::from(entry.name()),
+                metadata: metadata(&entry),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, &'static str> {
+        self.volume
+            .read(&self.entry, offset, buf)
+            .map_err(|e| e.as_str())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl FatFileSystem {
+    /// Open the FAT32 volume of `device` (first FAT32 partition, or the whole device).
+    pub fn new(device: &'static (dyn block::interface::BlockDevice + Sync)) -> Result<Self, &'static str> {
+        let volume = Volume::open(Device(device)).map_err(|e| e.as_str())?;
+
+        Ok(Self {
+            volume: Arc::new(volume),
+        })
+    }
+}
+
+impl interface::FileSystem for FatFileSystem {
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn root(&self) -> InodeRef {
+        Arc::new(FatInode {
+            volume: self.volume.clone(),
+            entry: self.volume.root(),
+        })
+    }
+}
@@ -0,0 +1,185 @@
+//! The initramfs archive as a read-only filesystem. File contents stay in the archive, only the
+//! directory tree is built on the heap.
+
+use super::{interface, DirEntry, FileType, InodeRef, Metadata};
+use crate::initramfs::{self, EntryKind};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+enum Node {
+    File(&'static [u8]),
+    Directory(BTreeMap<String, Node>),
+}
+
+/// Nodes are only built while mounting, after that the tree is immutable and shared
+struct InitramInode {
+    tree: Arc<Node>,
+    /// Path of the node from the root
+    path: Vec<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The content of an initramfs archive
+pub struct InitramFs {
+    tree: Arc<Node>,
+    files: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Node {
+    /// Insert `node` at `path`, creating the missing directories on the way
+    fn insert(&mut self, path: &str, node: Node) -> Result<(), &'static str> {
+        let Node::Directory(entries) = self else {
+            return Err("initramfs: file used as a directory");
+        };
+
+        match path.split_once('/') {
+            Some((name, rest)) => entries
+                .entry(String::from(name))
+                .or_insert_with(|| Node::Directory(BTreeMap::new()))
+                .insert(rest, node),
+            None => {
+                // Archives can list a directory after its content
+                let existing_dir = matches!(entries.get(path), Some(Node::Directory(_)));
+                if !(existing_dir && matches!(node, Node::Directory(_))) {
+                    entries.insert(String::from(path), node);
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::File(data) => Metadata {
+                file_type: FileType::File,
+                size: data.len() as u64,
+            },
+            Node::Directory(_) => Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+            },
+        }
+    }
+}
+
+impl InitramInode {
+    fn node(&self) -> &Node {
+        let mut node = &*self.tree;
+        for name in &self.path {
+            match node {
+                Node::Directory(entries) => node = &entries[name],
+                Node::File(_) => unreachable!(),
+            }
+        }
+
+        node
+    }
+
+    fn entries(&self) -> Result<&BTreeMap<String, Node>, &'static str> {
+        match self.node() {
+            Node::Directory(entries) => Ok(entries),
+            Node::File(_) => Err("Not a directory"),
+        }
+    }
+}
+
+impl interface::Inode for InitramInode {
+    fn metadata(&self) -> Metadata {
+        self.node().metadata()
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, &'static str> {
+        if !self.entries()?.contains_key(name) {
+            return Err("No such file or directory");
+        }
+
+        let mut path = self.path.clone();
+        path.push(String::from(name));
+
+        Ok(Arc::new(InitramInode {
+            tree: self.tree.clone(),
+            path,
+        }))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, &'static str> {
+        Ok(self
+            .entries()?
+            .iter()
+            .map(|(name, node)| DirEntry {
+                name: name.clone(),
+                metadata: node.metadata(),
+            })
+            .collect())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, &'static str> {
+        let Node::File(data) = self.node() else {
+            return Err("Is a directory");
+        };
+
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+
+        Ok(len)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl InitramFs {
+    /// Build the directory tree of a tar or cpio archive.
+    pub fn new(archive: &'static [u8]) -> Result<Self, &'static str> {
+        let mut root = Node::Directory(BTreeMap::new());
+        let mut files = 0;
+
+        for entry in initramfs::entries(archive)? {
+            let entry = entry?;
+            let node = match entry.kind {
+                EntryKind::File => {
+                    files += 1;
+                    Node::File(entry.data)
+                }
+                EntryKind::Directory => Node::Directory(BTreeMap::new()),
+            };
+            root.insert(entry.path, node)?;
+        }
+
+        Ok(Self {
+            tree: Arc::new(root),
+            files,
+        })
+    }
+
+    /// Number of regular files in the archive
+    pub fn files(&self) -> usize {
+        self.files
+    }
+}
+
+impl interface::FileSystem for InitramFs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn root(&self) -> InodeRef {
+        Arc::new(InitramInode {
+            tree: self.tree.clone(),
+            path: Vec::new(),
+        })
+    }
+}
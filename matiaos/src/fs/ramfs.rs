@@ -0,0 +1,193 @@
+//! A filesystem in RAM, on the kernel heap. The root filesystem.
+
+use super::{interface, DirEntry, FileType, InodeRef, Metadata};
+use crate::synchronization::{interface::Mutex, NullLock};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+enum Content {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, Arc<RamInode>>),
+}
+
+struct RamInode {
+    content: NullLock<Content>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An empty ramfs
+pub struct RamFs {
+    root: Arc<RamInode>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl RamInode {
+    fn new(file_type: FileType) -> Self {
+        let content = match file_type {
+            FileType::File => Content::File(Vec::new()),
+            FileType::Directory => Content::Directory(BTreeMap::new()),
+        };
+
+        Self {
+            content: NullLock::new(content),
+        }
+    }
+
+    fn with_dir<R>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, Arc<RamInode>>) -> Result<R, &'static str>,
+    ) -> Result<R, &'static str> {
+        self.content.lock(|content| match content {
+            Content::Directory(entries) => f(entries),
+            Content::File(_) => Err("Not a directory"),
+        })
+    }
+
+    fn with_file<R>(
+        &self,
+        f: impl FnOnce(&mut Vec<u8>) -> Result<R, &'static str>,
+    ) -> Result<R, &'static str> {
+        self.content.lock(|content| match content {
+            Content::File(data) => f(data),
+            Content::Directory(_) => Err("Is a directory"),
+        })
+    }
+}
+
+impl interface::Inode for RamInode {
+    fn metadata(&self) -> Metadata {
+        self.content.lock(|content| match content {
+            Content::File(data) => Metadata {
+                file_type: FileType::File,
+                size: data.len() as u64,
+            },
+            Content::Directory(_) => Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+            },
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, &'static str> {
+        self.with_dir(|entries| {
+            entries
+                .get(name)
+                .map(|x| x.clone() as InodeRef)
+                .ok_or("No such file or directory")
+        })
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, &'static str> {
+        // Collect the inodes first, their metadata takes their own lock
+        let inodes: Vec<_> = self.with_dir(|entries| {
+            Ok(entries
+                .iter()
+                .map(|(name, inode)| (name.clone(), inode.clone()))
+                .collect())
+        })?;
+
+        Ok(inodes
+            .into_iter()
+            .map(|(name, inode)| DirEntry {
+                name,
+                metadata: interface::Inode::metadata(&*inode),
+            })
+            .collect())
+    }
+
+    fn create(&self, name: &str, file_type: FileType) -> Result<InodeRef, &'static str> {
+        if name.is_empty() || name.contains('/') {
+            return Err("Invalid file name");
+        }
+
+        self.with_dir(|entries| {
+            if entries.contains_key(name) {
+                return Err("File exists");
+            }
+
+            let inode = Arc::new(RamInode::new(file_type));
+            entries.insert(String::from(name), inode.clone());
+
+            Ok(inode as InodeRef)
+        })
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), &'static str> {
+        self.with_dir(|entries| {
+            let inode = entries.get(name).ok_or("No such file or directory")?;
+            let not_empty = inode
+                .content
+                .lock(|content| matches!(content, Content::Directory(x) if !x.is_empty()));
+            if not_empty {
+                return Err("Directory not empty");
+            }
+
+            entries.remove(name);
+
+            Ok(())
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, &'static str> {
+        self.with_file(|data| {
+            let start = (offset as usize).min(data.len());
+            let len = buf.len().min(data.len() - start);
+            buf[..len].copy_from_slice(&data[start..start + len]);
+
+            Ok(len)
+        })
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, &'static str> {
+        self.with_file(|data| {
+            let offset = offset as usize;
+            let end = offset + buf.len();
+            if end > data.len() {
+                data.resize(end, 0);
+            }
+            data[offset..end].copy_from_slice(buf);
+
+            Ok(buf.len())
+        })
+    }
+
+    fn truncate(&self, size: u64) -> Result<(), &'static str> {
+        self.with_file(|data| {
+            data.resize(size as usize, 0);
+
+            Ok(())
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl RamFs {
+    /// Create an instance with an empty root directory
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(RamInode::new(FileType::Directory)),
+        }
+    }
+}
+
+impl interface::FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> InodeRef {
+        self.root.clone()
+    }
+}
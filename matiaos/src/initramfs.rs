@@ -0,0 +1,346 @@
+//! Initial ramdisk: a USTAR (tar) or newc (cpio) archive in memory.
+//!
+//! It ships files alongside the kernel without an SD card, and comes from either:
+//! - the loader, which receives it after the kernel and hands its location over in x1/x2,
+//! - the kernel image itself: `make kernel INITRAMFS=archive` puts it in the `.initramfs` section
+//!   (see `kernel.ld` and `build.rs`).
+//!
+//! Create one with `find . | cpio -o -H newc > ../initramfs.cpio` or
+//! `tar --format=ustar -cf ../initramfs.tar .`.
+
+use crate::synchronization::{interface::Mutex, NullLock};
+use core::{cell::UnsafeCell, slice, str};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Symbols from the linker script.
+extern "Rust" {
+    static __initramfs_start: UnsafeCell<()>;
+    static __initramfs_end_exclusive: UnsafeCell<()>;
+}
+
+/// The archive given to `make kernel INITRAMFS=...`, placed between the symbols above.
+#[cfg(initramfs_embedded)]
+#[link_section = ".initramfs"]
+#[used]
+static EMBEDDED_ARCHIVE: [u8; include_bytes!(env!("MATIAOS_INITRAMFS")).len()] =
+    *include_bytes!(env!("MATIAOS_INITRAMFS"));
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+/// Tar entry types we care about
+mod tar_type {
+    pub const FILE: u8 = b'0';
+    /// Old tars use NUL for regular files
+    pub const FILE_OLD: u8 = 0;
+    pub const DIRECTORY: u8 = b'5';
+    /// GNU: the data is the name of the next entry
+    pub const GNU_LONG_NAME: u8 = b'L';
+    /// POSIX: the data is `<len> key=value\n` records for the next entry
+    pub const PAX_HEADER: u8 = b'x';
+}
+
+const CPIO_MAGIC: &[u8] = b"070701";
+/// Same layout, with a checksum we don't check
+const CPIO_MAGIC_CRC: &[u8] = b"070702";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const CPIO_ALIGN: usize = 4;
+
+/// File type bits of the cpio mode
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Tar,
+    Cpio,
+}
+
+/// Where the loader put the archive
+static LOADER_ARCHIVE: NullLock<Option<(usize, usize)>> = NullLock::new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Type of an archive entry
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Regular file
+    File,
+    /// Directory
+    Directory,
+}
+
+/// A file or directory of the archive. Everything points into the archive, nothing is copied.
+pub struct Entry {
+    /// Path, without leading `/` or `./`
+    pub path: &'static str,
+    /// File or directory
+    pub kind: EntryKind,
+    /// File content, empty for a directory
+    pub data: &'static [u8],
+}
+
+/// The entries of an archive. Symlinks, devices and the like are skipped.
+pub struct Entries {
+    archive: &'static [u8],
+    format: Format,
+    offset: usize,
+    done: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Octal number of a tar header field (NUL or space terminated)
+fn parse_octal(field: &[u8]) -> Result<usize, &'static str> {
+    field
+        .iter()
+        .skip_while(|&&c| c == b' ')
+        .take_while(|&&c| c != 0 && c != b' ')
+        .try_fold(0usize, |acc, &c| match c {
+            b'0'..=b'7' => Ok(acc * 8 + usize::from(c - b'0')),
+            _ => Err("initramfs: invalid tar number"),
+        })
+}
+
+/// 8 digits hexadecimal number of a cpio header field
+fn parse_hex(field: &[u8]) -> Result<usize, &'static str> {
+    str::from_utf8(field)
+        .ok()
+        .and_then(|x| usize::from_str_radix(x, 16).ok())
+        .ok_or("initramfs: invalid cpio number")
+}
+
+/// A NUL terminated string of a header
+fn c_str(field: &'static [u8]) -> Result<&'static str, &'static str> {
+    let len = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+
+    str::from_utf8(&field[..len]).map_err(|_| "initramfs: file name is not UTF-8")
+}
+
+/// Strip the `/` and `./` archivers put in front of the paths, and the `/` of directories
+fn clean_path(path: &'static str) -> &'static str {
+    let mut path = path.trim_end_matches('/');
+    loop {
+        if let Some(x) = path.strip_prefix("./") {
+            path = x;
+        } else if let Some(x) = path.strip_prefix('/') {
+            path = x;
+        } else {
+            break;
+        }
+    }
+
+    if path == "." {
+        ""
+    } else {
+        path
+    }
+}
+
+/// The `path` record of a pax extended header
+fn pax_path(records: &'static [u8]) -> Option<&'static str> {
+    let mut rest = records;
+    while !rest.is_empty() {
+        // "<len> <key>=<value>\n", len counts the whole record
+        let space = rest.iter().position(|&c| c == b' ')?;
+        let len: usize = str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?;
+        if let Some(value) = record.strip_prefix(b"path=") {
+            return str::from_utf8(value.strip_suffix(b"\n")?).ok();
+        }
+        rest = &rest[len..];
+    }
+
+    None
+}
+
+fn slice(archive: &'static [u8], offset: usize, len: usize) -> Result<&'static [u8], &'static str> {
+    archive
+        .get(offset..offset + len)
+        .ok_or("initramfs: truncated archive")
+}
+
+impl Entries {
+    /// Next tar entry, `None` at the end of the archive (two zero blocks, or no more data)
+    fn next_tar(&mut self) -> Result<Option<Entry>, &'static str> {
+        let mut long_name = None;
+
+        loop {
+            if self.offset + TAR_BLOCK_SIZE > self.archive.len() {
+                return Ok(None);
+            }
+            let header = slice(self.archive, self.offset, TAR_BLOCK_SIZE)?;
+            if header.iter().all(|&c| c == 0) {
+                return Ok(None);
+            }
+            if &header[257..262] != TAR_MAGIC {
+                return Err("initramfs: not a ustar header");
+            }
+
+            let size = parse_octal(&header[124..136])?;
+            let data = slice(self.archive, self.offset + TAR_BLOCK_SIZE, size)?;
+            self.offset += TAR_BLOCK_SIZE + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+
+            let kind = match header[156] {
+                tar_type::GNU_LONG_NAME => {
+                    long_name = Some(c_str(data)?);
+                    continue;
+                }
+                tar_type::PAX_HEADER => {
+                    long_name = pax_path(data);
+                    continue;
+                }
+                tar_type::FILE | tar_type::FILE_OLD => EntryKind::File,
+                tar_type::DIRECTORY => EntryKind::Directory,
+                // links, devices...
+                _ => {
+                    long_name = None;
+                    continue;
+                }
+            };
+
+            let path = match long_name.take() {
+                Some(x) => x,
+                None => {
+                    // ustar splits long paths in a prefix and a name
+                    let name = c_str(&header[..100])?;
+                    let prefix = c_str(&header[345..500])?;
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        // they are contiguous in the header, but with NULs in between
+                        return Err("initramfs: ustar prefix paths are not supported");
+                    }
+                }
+            };
+
+            return Ok(Some(Entry {
+                path: clean_path(path),
+                kind,
+                data: if kind == EntryKind::File { data } else { &[] },
+            }));
+        }
+    }
+
+    /// Next cpio entry, `None` at the trailer
+    fn next_cpio(&mut self) -> Result<Option<Entry>, &'static str> {
+        loop {
+            let header = slice(self.archive, self.offset, CPIO_HEADER_SIZE)?;
+            if &header[..6] != CPIO_MAGIC && &header[..6] != CPIO_MAGIC_CRC {
+                return Err("initramfs: not a newc cpio header");
+            }
+
+            // 13 fields of 8 hex digits after the magic
+            let field = |i: usize| parse_hex(&header[6 + i * 8..6 + (i + 1) * 8]);
+            let mode = field(1)? as u32;
+            let file_size = field(6)?;
+            let name_size = field(11)?;
+
+            let name_offset = self.offset + CPIO_HEADER_SIZE;
+            let name = c_str(slice(self.archive, name_offset, name_size)?)?;
+            let data_offset = (name_offset + name_size).next_multiple_of(CPIO_ALIGN);
+            let data = slice(self.archive, data_offset, file_size)?;
+            self.offset = (data_offset + file_size).next_multiple_of(CPIO_ALIGN);
+
+            if name == CPIO_TRAILER {
+                return Ok(None);
+            }
+
+            let kind = match mode & S_IFMT {
+                S_IFREG => EntryKind::File,
+                S_IFDIR => EntryKind::Directory,
+                _ => continue,
+            };
+
+            return Ok(Some(Entry {
+                path: clean_path(name),
+                kind,
+                data,
+            }));
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Iterator for Entries {
+    type Item = Result<Entry, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let entry = match self.format {
+            Format::Tar => self.next_tar(),
+            Format::Cpio => self.next_cpio(),
+        };
+
+        match entry {
+            // "." is the root itself
+            Ok(Some(entry)) if entry.path.is_empty() => self.next(),
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Record the archive the loader placed in memory (called from the boot code, before anything
+/// else runs).
+pub fn set_loader_archive(start: usize, size: usize) {
+    if size != 0 {
+        LOADER_ARCHIVE.lock(|archive| *archive = Some((start, size)));
+    }
+}
+
+/// The archive, if there is one, and where it comes from. The loader's wins over the embedded one.
+pub fn archive() -> Option<(&'static [u8], &'static str)> {
+    if let Some((start, size)) = LOADER_ARCHIVE.lock(|archive| *archive) {
+        // The loader put it out of the way of the kernel, and nobody else uses this memory
+        return Some((unsafe { slice::from_raw_parts(start as *const u8, size) }, "loader"));
+    }
+
+    let embedded = unsafe {
+        let start = __initramfs_start.get() as usize;
+        let end = __initramfs_end_exclusive.get() as usize;
+        slice::from_raw_parts(start as *const u8, end - start)
+    };
+
+    (!embedded.is_empty()).then_some((embedded, "embedded"))
+}
+
+/// The entries of a tar or cpio archive.
+pub fn entries(archive: &'static [u8]) -> Result<Entries, &'static str> {
+    let format = if archive.starts_with(CPIO_MAGIC) || archive.starts_with(CPIO_MAGIC_CRC) {
+        Format::Cpio
+    } else if archive.get(257..262) == Some(TAR_MAGIC) {
+        Format::Tar
+    } else {
+        return Err("initramfs: unknown archive format");
+    };
+
+    Ok(Entries {
+        archive,
+        format,
+        offset: 0,
+        done: false,
+    })
+}
@@ -10,12 +10,24 @@
 #![no_main]
 #![no_std]
 
+extern crate alloc;
+
+mod block;
 mod bsp;
 mod console;
 mod cpu;
 mod driver;
+mod error;
+mod exception;
+mod fdt;
+mod fs;
+mod initramfs;
+mod memory;
 mod panic_handler;
+mod power;
 mod print;
+mod random;
+mod shell;
 mod synchronization;
 mod time;
 
@@ -25,13 +37,21 @@ mod time;
 ///
 /// - Only a single core must be active and running this function.
 unsafe fn kernel_init() -> ! {
+    exception::handling_init();
+    memory::heap_alloc::kernel_init_heap_allocator();
+
     if let Err(e) = bsp::driver::init() {
         panic!("Error initializing the driver subsystem !! {}", e)
     }
 
-    // Initialize driver
-    driver::driver_manager().init_drivers();
-    // Console should now be registered
+    // Initialize the drivers, the failed ones are reported and left out
+    driver::driver_manager().init_drivers_and_irqs();
+    // Console should now be registered, unless its driver failed
+
+    // Unmask interrupts on the boot CPU core.
+    exception::asynchronous::local_irq_unmask();
+
+    fs::init();
 
     kernel_main();
 }
@@ -47,20 +67,73 @@ const OS_LOGO: &str = r#"
 
 fn kernel_main() -> ! {
     println!("{OS_LOGO}");
-    info!("Booting on: {}", bsp::board_name());
+    info!(
+        "Booting on: {} (from the {})",
+        bsp::board_name(),
+        bsp::board::detected_by()
+    );
     info!("UART Console registered!");
 
-    info!("Loaded drivers:");
+    let (_, privilege_level) = exception::current_privilege_level();
+    info!("Current privilege level: {}", privilege_level);
+
+    match bsp::devicetree::device_tree() {
+        Ok(size) => info!("Device tree: {} bytes", size),
+        Err(e) => info!("{}, using the compiled-in memory map", e),
+    }
+    for range in bsp::devicetree::memory_ranges() {
+        info!(
+            "Memory: {:#010x} - {:#010x} ({} MiB)",
+            range.start,
+            range.end,
+            range.len() / (1024 * 1024)
+        );
+    }
+    if let Some(bootargs) = bsp::devicetree::bootargs() {
+        info!("Command line: {}", bootargs);
+    }
+    if let Some(path) = bsp::devicetree::stdout_path() {
+        info!("Console path: {}", path);
+    }
+
+    info!("Drivers:");
     driver::driver_manager().enumerate();
+
+    match block::block_device() {
+        Some(device) => info!("SD card: {} MiB", device.num_blocks() / 2048),
+        None => info!("No SD card"),
+    }
+
+    match fs::mount_boot_partition() {
+        Ok(()) => info!("Boot partition mounted on /boot"),
+        Err(e) => warn!("No boot partition: {}", e),
+    }
+
+    match fs::mount_initramfs() {
+        Ok(Some((origin, files))) => {
+            info!("Initramfs ({}): {} files mounted on /initrd", origin, files)
+        }
+        Ok(None) => info!("No initramfs"),
+        Err(e) => warn!("Bad initramfs: {}", e),
+    }
+
+    info!("Registered IRQ handlers:");
+    exception::asynchronous::irq_manager().print_handler();
+    time::time_manager().cross_check_clock_sources(core::time::Duration::from_millis(10));
+    info!(
+        "Clock source: {} ({} ns resolution)",
+        time::time_manager().clock_source().name(),
+        time::time_manager().resolution().as_nanos()
+    );
+    match random::entropy_source() {
+        Some(source) => info!("Random numbers seeded from: {}", source),
+        None => warn!("No entropy source, random numbers seeded from the timer's jitter only"),
+    }
     info!(
         "uptime: {} seconds",
         time::time_manager().uptime().as_secs()
     );
 
     info!("MatiaOS version {} is online", env!("CARGO_PKG_VERSION"));
-    info!("Echo mode is on");
-    loop {
-        let chr = console::console().read_char();
-        console::console().write_char(chr);
-    }
+    shell::run();
 }
@@ -0,0 +1,5 @@
+//! Memory management.
+
+pub mod cache;
+pub mod dma_buffer;
+pub mod heap_alloc;
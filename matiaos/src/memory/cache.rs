@@ -0,0 +1,11 @@
+//! Data cache maintenance, for the memory the kernel shares with devices (DMA, the VideoCore).
+//!
+//! Drivers rather go through `DmaBuffer`, which does the right one before and after the device.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../_arch/aarch64/memory/cache.rs"]
+mod arch_cache;
+
+pub use arch_cache::{
+    clean_invalidate_all, clean_invalidate_range, clean_range, invalidate_range, line_size,
+};
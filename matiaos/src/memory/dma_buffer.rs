@@ -0,0 +1,109 @@
+//! Buffers shared with a device, with the cache maintenance around the device's accesses.
+//!
+//! A buffer owns whole cache lines: its maintenance never touches another allocation, and the
+//! CPU never brings its lines back while writing something next to it.
+
+use super::cache;
+use crate::error::{Error, ErrorKind};
+use alloc::alloc::{alloc_zeroed, dealloc};
+use core::{
+    alloc::Layout,
+    ops::{Deref, DerefMut, Range},
+    ptr::NonNull,
+    slice,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Which way the data goes
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// The device reads what the CPU wrote
+    ToDevice,
+    /// The CPU reads what the device wrote
+    FromDevice,
+    Bidirectional,
+}
+
+/// A zeroed buffer, aligned on cache lines
+pub struct DmaBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    /// Whole lines
+    layout: Layout,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl DmaBuffer {
+    /// The lines of the buffer
+    fn lines(&self) -> Range<usize> {
+        let start = self.ptr.as_ptr() as usize;
+
+        start..start + self.layout.size()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl DmaBuffer {
+    /// Allocate `len` bytes, zeroed.
+    pub fn new(len: usize) -> Result<Self, Error> {
+        let line_size = cache::line_size();
+        let layout = Layout::from_size_align(len.max(1).next_multiple_of(line_size), line_size)
+            .map_err(|_| Error::new(ErrorKind::InvalidArgument, "DMA buffer too big"))?;
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .ok_or(Error::new(ErrorKind::OutOfMemory, "No memory for the DMA buffer"))?;
+
+        let buffer = Self { ptr, len, layout };
+        // The zeros go to memory: no dirty line is left to be written over the device's data
+        cache::clean_invalidate_range(buffer.lines());
+
+        Ok(buffer)
+    }
+
+    /// Before the device accesses the buffer: what the CPU wrote goes to memory, and the lines
+    /// the device writes leave the cache.
+    pub fn sync_for_device(&self, direction: Direction) {
+        match direction {
+            Direction::ToDevice => cache::clean_range(self.lines()),
+            Direction::FromDevice => cache::invalidate_range(self.lines()),
+            Direction::Bidirectional => cache::clean_invalidate_range(self.lines()),
+        }
+    }
+
+    /// Once the device is done, before the CPU reads: drop the lines the CPU may have loaded
+    /// (speculatively) in the meantime.
+    pub fn sync_for_cpu(&self, direction: Direction) {
+        if direction != Direction::ToDevice {
+            cache::invalidate_range(self.lines());
+        }
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
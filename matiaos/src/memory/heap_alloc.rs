@@ -0,0 +1,75 @@
+//! The kernel heap, so the kernel can use `alloc` (`Box`, `Vec`, `String`, `Arc`...).
+//!
+//! A linked list allocator over the region the linker script reserves after the bss.
+
+use crate::{
+    bsp,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+use linked_list_allocator::Heap;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The global allocator. IRQ safe, interrupt handlers may allocate too.
+pub struct HeapAllocator {
+    inner: IRQSafeNullLock<Heap>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+#[global_allocator]
+static KERNEL_HEAP_ALLOCATOR: HeapAllocator = HeapAllocator::new();
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl HeapAllocator {
+    /// Create an instance. Allocations fail until `kernel_init_heap_allocator()`.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(Heap::empty()),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for HeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner
+            .lock(|heap| heap.allocate_first_fit(layout))
+            .map_or(ptr::null_mut(), |x| x.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner
+            .lock(|heap| heap.deallocate(NonNull::new_unchecked(ptr), layout));
+    }
+}
+
+/// Hand the heap region to the allocator.
+///
+/// # Safety
+///
+/// - Call it once, before the first allocation.
+pub unsafe fn kernel_init_heap_allocator() {
+    let region = bsp::memory::heap_region();
+
+    KERNEL_HEAP_ALLOCATOR
+        .inner
+        .lock(|heap| heap.init(region.start as *mut u8, region.len()));
+}
+
+/// Bytes used and total size of the heap.
+pub fn usage() -> (usize, usize) {
+    KERNEL_HEAP_ALLOCATOR
+        .inner
+        .lock(|heap| (heap.used(), heap.size()))
+}
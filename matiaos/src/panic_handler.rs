@@ -1,4 +1,4 @@
-use crate::{cpu, println};
+use crate::{cpu, power, println};
 use core::panic::PanicInfo;
 
 //--------------------------------------------------------------------------------------------------
@@ -36,26 +36,30 @@ fn panic_prevent_reenter() {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    use crate::exception::asynchronous::local_irq_mask;
+
+    // Mask IRQs so that the panic messages won't be interrupted.
+    local_irq_mask();
+
     // Protect against panic infinite loops if any of the following code panics itself.
     panic_prevent_reenter();
 
-    let timestamp = crate::time::time_manager().uptime();
+    let timestamp = crate::print::__timestamp();
     let (location, line, column) = match info.location() {
         Some(loc) => (loc.file(), loc.line(), loc.column()),
         _ => ("???", 0, 0),
     };
 
     println!(
-        "[  {:>3}.{:06}] Kernel panic!\n\n\
+        "[  {}] Kernel panic!\n\n\
         Panic location:\n      File '{}', line {}, column {}\n\n\
         {}",
-        timestamp.as_secs(),
-        timestamp.subsec_micros(),
+        timestamp,
         location,
         line,
         column,
         info.message().unwrap_or(&format_args!("")),
     );
 
-    cpu::wait_forever()
+    power::after_panic()
 }
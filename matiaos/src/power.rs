@@ -0,0 +1,203 @@
+//! Restarting and stopping the board, the watchdog, and what a panic ends with.
+//!
+//! The BSP registers the device that resets the board and the watchdog. Rebooting, halting and
+//! going back to the serial loader shut the drivers down first (`DriverManager`), so the consoles
+//! are flushed and the devices quiet.
+
+use crate::{
+    bsp, console, cpu, driver,
+    error::{Error, ErrorKind},
+    exception, fdt, info, memory, println,
+    synchronization::{interface::Mutex, NullLock},
+    time, warn,
+};
+use core::{convert::Infallible, fmt, time::Duration};
+
+pub mod interface {
+    use crate::error::Error;
+    use core::time::Duration;
+
+    /// A device that restarts or stops the whole board
+    pub trait PowerControl {
+        /// Start a reset of the board. It takes effect shortly after this returns.
+        fn reboot(&self) -> Result<(), Error>;
+        /// Stop the board for good, until it is power cycled.
+        fn power_off(&self) -> Result<(), Error>;
+    }
+
+    /// Resets the board unless petted in time
+    pub trait Watchdog {
+        /// The longest timeout it takes
+        fn max_timeout(&self) -> Duration;
+        /// Reset the board if not petted within `timeout`. Restarts a running watchdog.
+        fn start(&self, timeout: Duration) -> Result<(), Error>;
+        /// Restart the countdown of a running watchdog.
+        fn pet(&self);
+        /// Stop the countdown, the board is not reset until the next `start`.
+        fn stop(&self);
+        /// The time left before the reset, `None` if stopped
+        fn remaining(&self) -> Option<Duration>;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What the kernel does once a panic is reported
+#[derive(Clone, Copy, PartialEq)]
+pub enum PanicPolicy {
+    /// Stop there, the message stays on the screen
+    Hang,
+    /// Reboot, after leaving some time to read the message
+    Reboot { after: Duration },
+    /// Go back to the serial loader, for the next kernel
+    SerialLoader,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static POWER_CONTROL: NullLock<Option<&'static (dyn interface::PowerControl + Sync)>> =
+    NullLock::new(None);
+static WATCHDOG: NullLock<Option<&'static (dyn interface::Watchdog + Sync)>> =
+    NullLock::new(None);
+static PANIC_POLICY: NullLock<PanicPolicy> = NullLock::new(PanicPolicy::Hang);
+/// Where the serial loader that started the kernel waits for the next one, 0 without it
+static SERIAL_LOADER_ENTRY: NullLock<usize> = NullLock::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Quiesce the drivers and the interrupts before the kernel goes away
+fn shutdown() {
+    if let Err(e) = driver::driver_manager().shutdown_drivers() {
+        warn!("Shutdown: {}", e);
+    }
+    exception::asynchronous::local_irq_mask();
+}
+
+impl fmt::Display for PanicPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hang => write!(f, "hang"),
+            Self::Reboot { after } => write!(f, "reboot after {} seconds", after.as_secs()),
+            Self::SerialLoader => write!(f, "go back to the serial loader"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Register the device that resets the board.
+pub fn register_power_control(new_device: &'static (dyn interface::PowerControl + Sync)) {
+    POWER_CONTROL.lock(|device| *device = Some(new_device));
+}
+
+/// Register the watchdog.
+pub fn register_watchdog(new_watchdog: &'static (dyn interface::Watchdog + Sync)) {
+    WATCHDOG.lock(|watchdog| *watchdog = Some(new_watchdog));
+}
+
+/// Return the watchdog, if there is one.
+pub fn watchdog() -> Option<&'static (dyn interface::Watchdog + Sync)> {
+    WATCHDOG.lock(|watchdog| *watchdog)
+}
+
+/// Restart the board. Without a device to do it, the kernel stops there.
+pub fn reboot() -> ! {
+    info!("Rebooting");
+    shutdown();
+
+    match POWER_CONTROL.lock(|device| *device) {
+        Some(device) => {
+            if let Err(e) = device.reboot() {
+                warn!("Reboot failed: {}", e);
+            }
+        }
+        None => warn!("Can't reboot, no power control device"),
+    }
+
+    cpu::wait_forever()
+}
+
+/// Stop the board, until it is power cycled.
+pub fn halt() -> ! {
+    shutdown();
+    println!("System halted");
+    console::console().flush();
+
+    if let Some(device) = POWER_CONTROL.lock(|device| *device) {
+        if let Err(e) = device.power_off() {
+            warn!("Power off failed: {}", e);
+        }
+    }
+
+    cpu::wait_forever()
+}
+
+/// Record where the serial loader that started the kernel can be entered again (called from the
+/// boot code). Anything else than where it relocates itself is not the loader.
+pub fn set_serial_loader_entry(entry: usize) {
+    if entry == bsp::memory::map::SERIAL_LOADER_ENTRY {
+        SERIAL_LOADER_ENTRY.lock(|x| *x = entry);
+    }
+}
+
+/// Go back to the serial loader, which waits for the next kernel. Only returns on error.
+pub fn enter_serial_loader() -> Result<Infallible, Error> {
+    let entry = SERIAL_LOADER_ENTRY.lock(|x| *x);
+    if entry == 0 {
+        return Err(Error::new(
+            ErrorKind::NotPresent,
+            "Not started by the serial loader",
+        ));
+    }
+    // The loader is not protected, the kernel and its heap must be below it
+    if bsp::memory::heap_region().end > entry {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "The kernel overlaps the serial loader",
+        ));
+    }
+
+    info!("Going back to the serial loader");
+    shutdown();
+    // The loader starts with the caches off, it must find the memory as the kernel left it
+    memory::cache::clean_invalidate_all();
+
+    // It gets the device tree the kernel got
+    unsafe { cpu::enter_el2(entry, fdt::boot_fdt_addr()) }
+}
+
+/// Choose what a panic ends with.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    PANIC_POLICY.lock(|x| *x = policy);
+}
+
+/// What a panic ends with.
+pub fn panic_policy() -> PanicPolicy {
+    PANIC_POLICY.lock(|x| *x)
+}
+
+/// Apply the panic policy, once the panic was reported.
+pub fn after_panic() -> ! {
+    match panic_policy() {
+        PanicPolicy::Hang => (),
+        PanicPolicy::Reboot { after } => {
+            println!("Rebooting in {} seconds", after.as_secs());
+            time::time_manager().spin_for_duration(after);
+            reboot();
+        }
+        PanicPolicy::SerialLoader => match enter_serial_loader() {
+            Ok(never) => match never {},
+            Err(e) => println!("Can't go back to the serial loader: {}", e),
+        },
+    }
+
+    cpu::wait_forever()
+}
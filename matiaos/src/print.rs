@@ -7,15 +7,57 @@
 
 //! Print functions
 
-use crate::console;
+use crate::{
+    console,
+    synchronization::{interface::Mutex, NullLock},
+    time,
+};
 use core::fmt;
 
+/// Log lines carry the wall-clock time instead of the uptime, once it is known
+static WALL_CLOCK_TIMESTAMPS: NullLock<bool> = NullLock::new(false);
+
+/// The time at the start of a log line
+pub enum Timestamp {
+    /// Seconds since power-on
+    Uptime(core::time::Duration),
+    /// UTC, in ISO-8601
+    WallClock(time::SystemTime),
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timestamp::Uptime(x) => write!(f, "{:>3}.{:06}", x.as_secs(), x.subsec_micros()),
+            Timestamp::WallClock(x) => write!(f, "{}", x),
+        }
+    }
+}
+
 // private, helper function
 pub fn __print(args: fmt::Arguments) {
     // This is just fmt::Write, but more readable (or is it?)
     console::console().write_fmt(args).unwrap();
 }
 
+// private, helper function
+pub fn __timestamp() -> Timestamp {
+    let wall_clock = WALL_CLOCK_TIMESTAMPS
+        .lock(|x| *x)
+        .then(|| time::time_manager().wall_clock())
+        .flatten();
+
+    match wall_clock {
+        Some(x) => Timestamp::WallClock(x),
+        None => Timestamp::Uptime(time::time_manager().uptime()),
+    }
+}
+
+/// Print the wall-clock time in the log lines, when it is set, or the uptime.
+pub fn set_wall_clock_timestamps(enabled: bool) {
+    WALL_CLOCK_TIMESTAMPS.lock(|x| *x = enabled);
+}
+
 // public usable macros: print, println
 
 /// Regular print, no endline
@@ -44,21 +86,15 @@ macro_rules! println {
 #[macro_export]
 macro_rules! info {
     ($string:expr) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::__print(format_args_nl!(
-            concat!("[  {:>3}.{:06}] ", $string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[  {}] ", $string),
+            $crate::print::__timestamp(),
         ));
     });
     ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::__print(format_args_nl!(
-            concat!("[  {:>3}.{:06}] ", $format_string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[  {}] ", $format_string),
+            $crate::print::__timestamp(),
             $($arg)*
         ));
     })
@@ -68,21 +104,15 @@ macro_rules! info {
 #[macro_export]
 macro_rules! warn {
     ($string:expr) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::__print(format_args_nl!(
-            concat!("[W {:>3}.{:06}] ", $string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[W {}] ", $string),
+            $crate::print::__timestamp(),
         ));
     });
     ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::time_manager().uptime();
-
         $crate::print::__print(format_args_nl!(
-            concat!("[W {:>3}.{:06}] ", $format_string),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
+            concat!("[W {}] ", $format_string),
+            $crate::print::__timestamp(),
             $($arg)*
         ));
     })
@@ -0,0 +1,140 @@
+//! Random numbers for the kernel.
+//!
+//! A ChaCha20 generator (`chacha`) is seeded with words of the hardware RNG, when the BSP
+//! registers one, and with the jitter of the timer. The jitter alone is a weak seed: without the
+//! hardware RNG the numbers are fine for hashing or backoffs, not for keys.
+//!
+//! The generator is seeded on first use, and again when an entropy source is registered.
+
+mod chacha;
+
+use crate::{
+    error::Error,
+    synchronization::{interface::Mutex, NullLock},
+    time::Instant,
+    warn,
+};
+use chacha::ChaChaRng;
+use core::hint::black_box;
+
+pub mod interface {
+    use crate::error::Error;
+
+    /// A device that gives random words
+    pub trait EntropySource {
+        fn name(&self) -> &'static str;
+        /// Fill `buf` with random words, waiting for the device as needed.
+        fn read_words(&self, buf: &mut [u32]) -> Result<(), Error>;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Words taken from the hardware RNG, and from the jitter, for a seed
+const SOURCE_WORDS: usize = 16;
+const JITTER_WORDS: usize = 16;
+
+/// Timings folded into a jitter word
+const JITTER_SAMPLES: usize = 32;
+
+struct Random {
+    generator: ChaChaRng,
+    seeded: bool,
+}
+
+static RANDOM: NullLock<Random> = NullLock::new(Random {
+    generator: ChaChaRng::new(),
+    seeded: false,
+});
+static ENTROPY_SOURCE: NullLock<Option<&'static (dyn interface::EntropySource + Sync)>> =
+    NullLock::new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// A word of the timer's jitter: how long some uneven work takes, down to the counter's tick.
+fn timer_jitter() -> u32 {
+    let mut scratch = [0u32; 16];
+    let mut word = 0u32;
+
+    for i in 0..JITTER_SAMPLES {
+        let start = Instant::now();
+        for j in 0..=(i + word as usize) % scratch.len() {
+            scratch[j] = black_box(scratch[j].wrapping_add(word).rotate_left(j as u32));
+        }
+        word = word.rotate_left(7) ^ start.elapsed().subsec_nanos();
+    }
+
+    word
+}
+
+/// The words of the entropy source, if there is one and it answers
+fn source_words() -> Result<Option<[u32; SOURCE_WORDS]>, Error> {
+    let Some(source) = ENTROPY_SOURCE.lock(|x| *x) else {
+        return Ok(None);
+    };
+
+    let mut words = [0; SOURCE_WORDS];
+    source.read_words(&mut words)?;
+
+    Ok(Some(words))
+}
+
+/// Mix a fresh seed into the generator
+fn reseed() {
+    let mut jitter = [0; JITTER_WORDS];
+    for word in jitter.iter_mut() {
+        *word = timer_jitter();
+    }
+
+    let words = source_words().unwrap_or_else(|e| {
+        warn!("Entropy source: {}", e);
+        None
+    });
+
+    RANDOM.lock(|x| {
+        if let Some(words) = &words {
+            x.generator.mix(words);
+        }
+        x.generator.mix(&jitter);
+        x.seeded = true;
+    });
+}
+
+/// Run `f` with the generator, seeded first if it was not yet
+fn with_generator<T>(f: impl FnOnce(&mut ChaChaRng) -> T) -> T {
+    if !RANDOM.lock(|x| x.seeded) {
+        reseed();
+    }
+
+    RANDOM.lock(|x| f(&mut x.generator))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Register the hardware entropy source, and seed the generator with it.
+pub fn register_entropy_source(new_source: &'static (dyn interface::EntropySource + Sync)) {
+    ENTROPY_SOURCE.lock(|source| *source = Some(new_source));
+    reseed();
+}
+
+/// The name of the entropy source, `None` when the seed is only the timer's jitter.
+pub fn entropy_source() -> Option<&'static str> {
+    ENTROPY_SOURCE.lock(|source| source.map(|x| x.name()))
+}
+
+/// Fill `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    with_generator(|x| x.fill_bytes(buf))
+}
+
+/// A random `u64`.
+#[allow(dead_code)]
+pub fn next_u64() -> u64 {
+    with_generator(|x| x.next_u64())
+}
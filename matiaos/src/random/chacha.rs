@@ -0,0 +1,236 @@
+//! The ChaCha20 block function (RFC 8439), and a generator built on it.
+//!
+//! The generator erases its key as it goes: every block gives the next key and 32 bytes of output,
+//! so a key found later says nothing about the output that came before.
+//!
+//! Plain integer code, without registers or crates, so it is tested on the host (`make test`).
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+const KEY_WORDS: usize = 8;
+const BLOCK_WORDS: usize = 16;
+/// What is left of a block once the next key is taken from it
+const OUTPUT_WORDS: usize = BLOCK_WORDS - KEY_WORDS;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A ChaCha20 random number generator
+pub struct ChaChaRng {
+    key: [u32; KEY_WORDS],
+    output: [u32; OUTPUT_WORDS],
+    /// Words of `output` already handed out
+    used: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn quarter_round(state: &mut [u32; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+impl ChaChaRng {
+    /// Take the next key and output from a block
+    fn refill(&mut self) {
+        let block = block(&self.key, 0, &[0; 3]);
+
+        self.key.copy_from_slice(&block[..KEY_WORDS]);
+        self.output.copy_from_slice(&block[KEY_WORDS..]);
+        self.used = 0;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The ChaCha20 block of `key`, `counter` and `nonce`.
+pub fn block(key: &[u32; KEY_WORDS], counter: u32, nonce: &[u32; 3]) -> [u32; BLOCK_WORDS] {
+    let mut input = [0; BLOCK_WORDS];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+    for _ in 0..10 {
+        // Columns, then diagonals
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+
+    state
+}
+
+impl ChaChaRng {
+    /// A generator with an all zero key, to `mix` a seed into.
+    pub const fn new() -> Self {
+        Self {
+            key: [0; KEY_WORDS],
+            output: [0; OUTPUT_WORDS],
+            used: OUTPUT_WORDS,
+        }
+    }
+
+    /// Add entropy to the key. The output that was not handed out yet is dropped.
+    pub fn mix(&mut self, entropy: &[u32]) {
+        for (i, word) in entropy.iter().enumerate() {
+            self.key[i % KEY_WORDS] ^= word;
+        }
+        self.refill();
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.used == OUTPUT_WORDS {
+            self.refill();
+        }
+        self.used += 1;
+
+        self.output[self.used - 1]
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        u64::from(self.next_u32()) | (u64::from(self.next_u32()) << 32)
+    }
+
+    /// Fill `buf` with random bytes, the words are taken in order (little endian).
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The key of the RFC 8439 examples: the bytes 0x00 to 0x1f
+    const RFC_KEY: [u32; KEY_WORDS] = [
+        0x0302_0100,
+        0x0706_0504,
+        0x0b0a_0908,
+        0x0f0e_0d0c,
+        0x1312_1110,
+        0x1716_1514,
+        0x1b1a_1918,
+        0x1f1e_1d1c,
+    ];
+
+    #[test]
+    fn quarter_round_vector() {
+        // RFC 8439, 2.1.1
+        let mut state = [0; BLOCK_WORDS];
+        state[..4].copy_from_slice(&[0x1111_1111, 0x0102_0304, 0x9b8d_6f43, 0x0123_4567]);
+
+        quarter_round(&mut state, 0, 1, 2, 3);
+        assert_eq!(state[..4], [0xea2a_92f4, 0xcb1c_f8ce, 0x4581_472e, 0x5881_c4bb]);
+    }
+
+    #[test]
+    fn block_vector() {
+        // RFC 8439, 2.3.2
+        let nonce = [0x0900_0000, 0x4a00_0000, 0x0000_0000];
+        let expected = [
+            0xe4e7_f110, 0x1559_3bd1, 0x1fdd_0f50, 0xc471_20a3, 0xc7f4_d1c7, 0x0368_c033,
+            0x9aaa_2204, 0x4e6c_d4c3, 0x4664_82d2, 0x09aa_9f07, 0x05d7_c214, 0xa202_8bd9,
+            0xd19c_12b5, 0xb94e_16de, 0xe883_d0cb, 0x4e3c_50a2,
+        ];
+
+        assert_eq!(block(&RFC_KEY, 1, &nonce), expected);
+    }
+
+    #[test]
+    fn zero_key_block() {
+        // RFC 8439, A.1 test vector #1: 76 b8 e0 ad a0 f1 3d 90 ...
+        let block = block(&[0; KEY_WORDS], 0, &[0; 3]);
+        assert_eq!(block[..4], [0xade0_b876, 0x903d_f1a0, 0xe56a_5d40, 0x28bd_8653]);
+    }
+
+    #[test]
+    fn output_is_the_rest_of_the_block() {
+        let mut rng = ChaChaRng::new();
+        rng.mix(&RFC_KEY);
+
+        let block = block(&RFC_KEY, 0, &[0; 3]);
+        let words: Vec<u32> = (0..OUTPUT_WORDS).map(|_| rng.next_u32()).collect();
+        assert_eq!(words, block[KEY_WORDS..]);
+        // ... and the next block has another key
+        assert_ne!(rng.next_u32(), block[KEY_WORDS]);
+    }
+
+    #[test]
+    fn fill_bytes_matches_the_words() {
+        let mut a = ChaChaRng::new();
+        let mut b = ChaChaRng::new();
+        a.mix(&RFC_KEY);
+        b.mix(&RFC_KEY);
+
+        let mut bytes = [0; 70];
+        a.fill_bytes(&mut bytes[..10]);
+        a.fill_bytes(&mut bytes[10..]);
+
+        // A partial word is dropped, the next fill starts with a new one
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&b.next_u32().to_le_bytes());
+        expected.extend_from_slice(&b.next_u32().to_le_bytes());
+        expected.extend_from_slice(&b.next_u32().to_le_bytes()[..2]);
+        for _ in 0..15 {
+            expected.extend_from_slice(&b.next_u32().to_le_bytes());
+        }
+        assert_eq!(bytes[..], expected[..]);
+    }
+
+    #[test]
+    fn mixing_changes_the_stream() {
+        let mut a = ChaChaRng::new();
+        let mut b = ChaChaRng::new();
+        a.mix(&RFC_KEY);
+        b.mix(&RFC_KEY);
+        b.mix(&[1]);
+
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn next_u64_is_two_words() {
+        let mut a = ChaChaRng::new();
+        let mut b = ChaChaRng::new();
+        a.mix(&RFC_KEY);
+        b.mix(&RFC_KEY);
+
+        let low = u64::from(b.next_u32());
+        let high = u64::from(b.next_u32());
+        assert_eq!(a.next_u64(), low | (high << 32));
+    }
+}
@@ -0,0 +1,577 @@
+//! A minimal shell on the console: line editing, a current directory and a few commands.
+
+use crate::{
+    block, bsp, console, console::tty, driver, fs, memory, power, print, println, random, time,
+};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+struct Shell {
+    /// Current directory, absolute
+    cwd: String,
+}
+
+type CommandFn = fn(&mut Shell, &[&str]) -> Result<(), &'static str>;
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    run: CommandFn,
+}
+
+const COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "help", run: help },
+    Command { name: "ls", usage: "ls [path]", run: ls },
+    Command { name: "cat", usage: "cat <file>...", run: cat },
+    Command { name: "write", usage: "write <file> <text>", run: write },
+    Command { name: "append", usage: "append <file> <text>", run: append },
+    Command { name: "mkdir", usage: "mkdir <dir>", run: mkdir },
+    Command { name: "rm", usage: "rm <path>", run: rm },
+    Command { name: "cd", usage: "cd [dir]", run: cd },
+    Command { name: "pwd", usage: "pwd", run: pwd },
+    Command { name: "mount", usage: "mount", run: mount },
+    Command { name: "blk", usage: "blk [test <lba>]", run: blk },
+    Command { name: "mem", usage: "mem", run: mem },
+    Command { name: "gpio", usage: "gpio [<pin> in [up|down] | <pin> out|high|low]", run: gpio },
+    Command { name: "drivers", usage: "drivers [reset | suspend <seconds>]", run: drivers },
+    Command { name: "console", usage: "console [mute | unmute | remove <name>]", run: console },
+    Command { name: "dma", usage: "dma [test]", run: dma },
+    Command { name: "spi", usage: "spi [set <cs> <hz> <mode> | poll|irq <hex>...]", run: spi },
+    Command {
+        name: "i2c",
+        usage: "i2c [scan | clock <hz> | read <addr> [<reg>] <n> | write <addr> <hex>...]",
+        run: i2c,
+    },
+    Command { name: "dmesg", usage: "dmesg", run: dmesg },
+    Command { name: "stty", usage: "stty [[-]icanon|[-]echo|[-]onlcr|[-]icrnl]...", run: stty },
+    Command { name: "date", usage: "date [<seconds since 1970> | log on|off]", run: date },
+    Command { name: "clocksource", usage: "clocksource [<name>]", run: clocksource },
+    Command { name: "random", usage: "random [<bytes>]", run: random },
+    Command { name: "watchdog", usage: "watchdog [start <seconds> | pet | stop]", run: watchdog },
+    Command { name: "onpanic", usage: "onpanic [hang | reboot <seconds> | loader]", run: onpanic },
+    Command { name: "loader", usage: "loader", run: loader },
+    Command { name: "reboot", usage: "reboot", run: reboot },
+    Command { name: "halt", usage: "halt", run: halt },
+];
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Shell {
+    /// Make `path` absolute
+    fn absolute(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            path.to_string()
+        } else {
+            self.cwd.clone() + "/" + path
+        }
+    }
+
+    fn execute(&mut self, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some(name) = args.first() else {
+            return;
+        };
+
+        match COMMANDS.iter().find(|x| x.name == *name) {
+            Some(command) => {
+                if let Err(e) = (command.run)(self, &args[1..]) {
+                    println!("{}: {}", name, e);
+                }
+            }
+            None => println!("{}: command not found", name),
+        }
+    }
+}
+
+fn help(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    for command in COMMANDS {
+        println!("  {}", command.usage);
+    }
+
+    Ok(())
+}
+
+fn ls(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let path = shell.absolute(args.first().unwrap_or(&"."));
+
+    if !fs::metadata(&path)?.is_dir() {
+        println!("{}", path);
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(&path)? {
+        if entry.metadata.is_dir() {
+            println!("{:>10}  {}/", "", entry.name);
+        } else {
+            println!("{:>10}  {}", entry.metadata.size, entry.name);
+        }
+    }
+
+    Ok(())
+}
+
+fn cat(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    if args.is_empty() {
+        return Err("missing file");
+    }
+
+    for path in args {
+        let mut content = Vec::new();
+        fs::open(&shell.absolute(path), fs::OpenMode::Read)?.read_to_end(&mut content)?;
+        print!("{}", String::from_utf8_lossy(&content));
+    }
+
+    Ok(())
+}
+
+/// Write the rest of the line to a file, as a line of text
+fn write_line(shell: &Shell, args: &[&str], mode: fs::OpenMode) -> Result<(), &'static str> {
+    let (path, words) = args.split_first().ok_or("missing file")?;
+    let text = words.join(" ") + "\n";
+
+    fs::open(&shell.absolute(path), mode)?.write(text.as_bytes())?;
+
+    Ok(())
+}
+
+fn write(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    write_line(shell, args, fs::OpenMode::Write)
+}
+
+fn append(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    write_line(shell, args, fs::OpenMode::Append)
+}
+
+fn mkdir(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    fs::create_dir(&shell.absolute(args.first().ok_or("missing directory")?))
+}
+
+fn rm(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    fs::remove(&shell.absolute(args.first().ok_or("missing path")?))
+}
+
+fn cd(shell: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let path = shell.absolute(args.first().unwrap_or(&"/"));
+    if !fs::metadata(&path)?.is_dir() {
+        return Err("Not a directory");
+    }
+
+    shell.cwd = fs::normalize(&path)?;
+
+    Ok(())
+}
+
+fn pwd(shell: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    println!("{}", shell.cwd);
+
+    Ok(())
+}
+
+fn mount(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    fs::mounts(|path, name| println!("{} on {}", name, path));
+
+    Ok(())
+}
+
+fn blk(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let device = block::block_device().ok_or("no block device")?;
+
+    match args {
+        [] => println!("{} blocks of {} bytes", device.num_blocks(), block::BLOCK_SIZE),
+        ["test", lba] => {
+            let lba = lba.parse().map_err(|_| "invalid block number")?;
+            block::self_test(device, lba).map_err(|e| e.as_str())?;
+            println!("Block write and read back OK");
+        }
+        _ => return Err("usage: blk [test <lba>]"),
+    }
+
+    Ok(())
+}
+
+fn mem(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    let (used, size) = memory::heap_alloc::usage();
+    println!("heap: {} KiB used of {} KiB", used / 1024, size / 1024);
+
+    Ok(())
+}
+
+fn gpio(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let (pin, action) = match args {
+        [] => {
+            bsp::driver::print_gpio_state();
+            return Ok(());
+        }
+        [pin, action @ ..] => (pin.parse().map_err(|_| "invalid pin number")?, action),
+    };
+
+    match action {
+        ["in"] => bsp::driver::gpio_input(pin, None),
+        ["in", "up"] => bsp::driver::gpio_input(pin, Some(true)),
+        ["in", "down"] => bsp::driver::gpio_input(pin, Some(false)),
+        ["out"] => bsp::driver::gpio_output(pin, None),
+        ["high"] => bsp::driver::gpio_output(pin, Some(true)),
+        ["low"] => bsp::driver::gpio_output(pin, Some(false)),
+        _ => return Err("usage: gpio [<pin> in [up|down] | <pin> out|high|low]"),
+    }
+    .map_err(|e| e.as_str())
+}
+
+fn drivers(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let manager = driver::driver_manager();
+
+    match args {
+        [] => manager.enumerate(),
+        ["reset"] => {
+            manager.reset_drivers().map_err(|e| e.as_str())?;
+            bsp::driver::irq_self_test().map_err(|e| e.as_str())?;
+            println!("IRQs OK");
+        }
+        ["suspend", seconds] => {
+            let seconds = seconds.parse().map_err(|_| "invalid number of seconds")?;
+            manager.suspend_drivers().map_err(|e| e.as_str())?;
+            time::time_manager().spin_for_duration(Duration::from_secs(seconds));
+            manager.resume_drivers().map_err(|e| e.as_str())?;
+        }
+        _ => return Err("usage: drivers [reset | suspend <seconds>]"),
+    }
+
+    Ok(())
+}
+
+fn console(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => console::for_each_console(|name, muted| {
+            println!("{}{}", name, if muted { " (muted)" } else { "" })
+        }),
+        ["mute", name] => console::set_muted(name, true).map_err(|e| e.as_str())?,
+        ["unmute", name] => console::set_muted(name, false).map_err(|e| e.as_str())?,
+        ["remove", name] => console::remove_console(name).map_err(|e| e.as_str())?,
+        _ => return Err("usage: console [mute | unmute | remove <name>]"),
+    }
+
+    Ok(())
+}
+
+fn dma(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => bsp::driver::print_dma_channels(),
+        ["test"] => {
+            bsp::driver::dma_self_test().map_err(|e| e.as_str())?;
+            println!("DMA copy OK");
+        }
+        _ => return Err("usage: dma [test]"),
+    }
+
+    Ok(())
+}
+
+/// A byte written in hex, with or without 0x
+fn parse_hex_byte(text: &str) -> Result<u8, &'static str> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+
+    u8::from_str_radix(digits, 16).map_err(|_| "invalid hex byte")
+}
+
+fn spi(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    const MAX_BYTES: usize = 64;
+
+    let (interrupts, bytes) = match args {
+        [] => {
+            bsp::driver::print_spi_config();
+            return Ok(());
+        }
+        ["set", chip_select, frequency_hz, mode] => {
+            let chip_select = chip_select.parse().map_err(|_| "invalid chip select")?;
+            let frequency_hz = frequency_hz.parse().map_err(|_| "invalid frequency")?;
+            let mode = mode.parse().map_err(|_| "invalid mode")?;
+            let actual_hz = bsp::driver::spi_configure(chip_select, frequency_hz, mode)
+                .map_err(|e| e.as_str())?;
+            println!("SPI clock {} Hz", actual_hz);
+            return Ok(());
+        }
+        ["poll", bytes @ ..] if !bytes.is_empty() => (false, bytes),
+        ["irq", bytes @ ..] if !bytes.is_empty() => (true, bytes),
+        _ => return Err("usage: spi [set <cs> <hz> <mode> | poll|irq <hex>...]"),
+    };
+    if bytes.len() > MAX_BYTES {
+        return Err("at most 64 bytes");
+    }
+
+    let mut write = [0; MAX_BYTES];
+    for (byte, text) in write.iter_mut().zip(bytes) {
+        *byte = parse_hex_byte(text)?;
+    }
+    let mut read = [0; MAX_BYTES];
+    bsp::driver::spi_transfer(&mut read[..bytes.len()], &write[..bytes.len()], interrupts)
+        .map_err(|e| e.as_str())?;
+
+    for byte in &read[..bytes.len()] {
+        print!("{:02x} ", byte);
+    }
+    println!();
+
+    Ok(())
+}
+
+fn i2c(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    const MAX_BYTES: usize = 64;
+
+    match args {
+        [] | ["scan"] => {
+            let found = bsp::driver::i2c_scan().map_err(|e| e.as_str())?;
+
+            // As i2cdetect prints it, the reserved addresses left blank
+            println!("     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f");
+            for row in (0..0x80u8).step_by(16) {
+                print!("{:02x}:", row);
+                for address in row..row + 16 {
+                    if !(0x08..=0x77).contains(&address) {
+                        print!("   ");
+                    } else if found.contains(&address) {
+                        print!(" {:02x}", address);
+                    } else {
+                        print!(" --");
+                    }
+                }
+                println!();
+            }
+        }
+        ["clock", clock_hz] => {
+            let clock_hz = clock_hz.parse().map_err(|_| "invalid frequency")?;
+            let actual_hz = bsp::driver::i2c_set_clock_hz(clock_hz).map_err(|e| e.as_str())?;
+            println!("I2C clock {} Hz", actual_hz);
+        }
+        ["read", address, register @ .., count] if register.len() <= 1 => {
+            let address = parse_hex_byte(address)?;
+            let register = match register {
+                [register] => Some(parse_hex_byte(register)?),
+                _ => None,
+            };
+            let count: usize = count.parse().map_err(|_| "invalid number of bytes")?;
+            if count > MAX_BYTES {
+                return Err("at most 64 bytes");
+            }
+
+            let mut read = [0; MAX_BYTES];
+            bsp::driver::i2c_read(address, register.as_slice(), &mut read[..count])
+                .map_err(|e| e.as_str())?;
+            for byte in &read[..count] {
+                print!("{:02x} ", byte);
+            }
+            println!();
+        }
+        ["write", address, bytes @ ..] if bytes.len() <= MAX_BYTES => {
+            let address = parse_hex_byte(address)?;
+            let mut write = [0; MAX_BYTES];
+            for (byte, text) in write.iter_mut().zip(bytes) {
+                *byte = parse_hex_byte(text)?;
+            }
+
+            bsp::driver::i2c_write(address, &write[..bytes.len()]).map_err(|e| e.as_str())?;
+        }
+        _ => {
+            return Err(
+                "usage: i2c [scan | clock <hz> | read <addr> [<reg>] <n> | write <addr> <hex>...]",
+            )
+        }
+    }
+
+    Ok(())
+}
+
+fn dmesg(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    // Copied first, printing it adds to it
+    print!("{}", console::LOG_BUFFER.contents());
+
+    Ok(())
+}
+
+fn stty(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let mut config = tty::config();
+
+    if args.is_empty() {
+        let flag = |set: bool, name| println!("{}{}", if set { "" } else { "-" }, name);
+        flag(config.canonical, "icanon");
+        flag(config.echo, "echo");
+        flag(config.output_crlf, "onlcr");
+        flag(config.input_cr_to_nl, "icrnl");
+
+        return Ok(());
+    }
+
+    for arg in args {
+        let (name, set) = match arg.strip_prefix('-') {
+            Some(name) => (name, false),
+            None => (*arg, true),
+        };
+        match name {
+            "icanon" => config.canonical = set,
+            "echo" => config.echo = set,
+            "onlcr" => config.output_crlf = set,
+            "icrnl" => config.input_cr_to_nl = set,
+            _ => return Err("unknown setting"),
+        }
+    }
+    tty::set_config(config);
+
+    Ok(())
+}
+
+fn date(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => match time::time_manager().wall_clock() {
+            Some(now) => {
+                println!("{} ({} seconds since 1970)", now, now.since_unix_epoch().as_secs())
+            }
+            None => return Err("the time is not set"),
+        },
+        ["log", "on"] => print::set_wall_clock_timestamps(true),
+        ["log", "off"] => print::set_wall_clock_timestamps(false),
+        [seconds] => {
+            let seconds = seconds.parse().map_err(|_| "invalid number of seconds")?;
+            let now = time::SystemTime::from_unix(Duration::from_secs(seconds));
+            time::time_manager().set_wall_clock(now);
+        }
+        _ => return Err("usage: date [<seconds since 1970> | log on|off]"),
+    }
+
+    Ok(())
+}
+
+fn clocksource(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let manager = time::time_manager();
+
+    match args {
+        [] => manager.for_each_clock_source(|source, selected| {
+            println!(
+                "{} ({} ns){}",
+                source.name(),
+                source.resolution().as_nanos(),
+                if selected { " (selected)" } else { "" }
+            )
+        }),
+        [name] => manager.set_clock_source(name).map_err(|e| e.as_str())?,
+        _ => return Err("usage: clocksource [<name>]"),
+    }
+
+    Ok(())
+}
+
+fn random(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    const MAX_BYTES: usize = 256;
+
+    let count = match args {
+        [] => 16,
+        [count] => count.parse().map_err(|_| "invalid number of bytes")?,
+        _ => return Err("usage: random [<bytes>]"),
+    };
+    if count > MAX_BYTES {
+        return Err("at most 256 bytes");
+    }
+
+    let mut bytes = [0; MAX_BYTES];
+    random::fill_bytes(&mut bytes[..count]);
+    for line in bytes[..count].chunks(16) {
+        for byte in line {
+            print!("{:02x} ", byte);
+        }
+        println!();
+    }
+    match random::entropy_source() {
+        Some(source) => println!("(seeded from {} and the timer's jitter)", source),
+        None => println!("(seeded from the timer's jitter only)"),
+    }
+
+    Ok(())
+}
+
+fn watchdog(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let watchdog = power::watchdog().ok_or("no watchdog")?;
+
+    match args {
+        [] => match watchdog.remaining() {
+            Some(remaining) => println!("running, {} ms left", remaining.as_millis()),
+            None => println!(
+                "stopped (longest timeout: {} seconds)",
+                watchdog.max_timeout().as_secs()
+            ),
+        },
+        ["start", seconds] => {
+            let seconds = seconds.parse().map_err(|_| "invalid number of seconds")?;
+            watchdog
+                .start(Duration::from_secs(seconds))
+                .map_err(|e| e.as_str())?;
+        }
+        ["pet"] => watchdog.pet(),
+        ["stop"] => watchdog.stop(),
+        _ => return Err("usage: watchdog [start <seconds> | pet | stop]"),
+    }
+
+    Ok(())
+}
+
+fn onpanic(_: &mut Shell, args: &[&str]) -> Result<(), &'static str> {
+    let policy = match args {
+        [] => {
+            println!("{}", power::panic_policy());
+            return Ok(());
+        }
+        ["hang"] => power::PanicPolicy::Hang,
+        ["reboot", seconds] => {
+            let seconds = seconds.parse().map_err(|_| "invalid number of seconds")?;
+            power::PanicPolicy::Reboot {
+                after: Duration::from_secs(seconds),
+            }
+        }
+        ["loader"] => power::PanicPolicy::SerialLoader,
+        _ => return Err("usage: onpanic [hang | reboot <seconds> | loader]"),
+    };
+    power::set_panic_policy(policy);
+
+    Ok(())
+}
+
+fn loader(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    match power::enter_serial_loader() {
+        Ok(never) => match never {},
+        Err(e) => Err(e.as_str()),
+    }
+}
+
+fn reboot(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    power::reboot()
+}
+
+fn halt(_: &mut Shell, _: &[&str]) -> Result<(), &'static str> {
+    power::halt()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Run the shell, forever.
+pub fn run() -> ! {
+    let mut shell = Shell {
+        cwd: String::from("/"),
+    };
+
+    println!("Type `help` for the list of commands");
+    loop {
+        print!("{} $ ", shell.cwd);
+        match tty::read_line(None) {
+            Ok(line) => shell.execute(&line),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
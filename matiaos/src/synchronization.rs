@@ -5,6 +5,7 @@
  * Author: Elad Matia (elad.matia@gmail.com)
  */
 
+use crate::exception;
 use core::cell::UnsafeCell;
 
 pub mod interface {
@@ -68,3 +69,39 @@ impl<T> interface::Mutex for NullLock<T> {
         func(data)
     }
 }
+
+/// A NullLock that masks IRQs while the data is accessed.
+///
+/// Data shared with interrupt handlers must use this one: an IRQ firing in the middle of a `lock`
+/// closure would otherwise hand out a second mutable reference to the same data.
+pub struct IRQSafeNullLock<T>
+where
+    T: ?Sized,
+{
+    data: UnsafeCell<T>,
+}
+
+impl<T> IRQSafeNullLock<T> {
+    /// Create IRQ safe null lock instance
+    pub const fn new(value: T) -> Self {
+        IRQSafeNullLock {
+            data: UnsafeCell::new(value),
+        }
+    }
+}
+
+unsafe impl<T> Send for IRQSafeNullLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for IRQSafeNullLock<T> where T: ?Sized + Send {}
+
+impl<T> interface::Mutex for IRQSafeNullLock<T> {
+    type Data = T;
+
+    fn lock<R, F>(&self, func: F) -> R
+    where
+        F: FnOnce(&mut Self::Data) -> R,
+    {
+        // Still single core, so masking the IRQs is enough for exclusive access.
+        let data = unsafe { &mut *self.data.get() };
+        exception::asynchronous::exec_with_irq_masked(|| func(data))
+    }
+}
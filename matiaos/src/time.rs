@@ -4,11 +4,53 @@
 #[cfg(target_arch = "aarch64")]
 #[path = "_arch/aarch64/time.rs"]
 mod arch_time;
+mod wall_clock;
 
+use crate::{
+    cpu,
+    error::{Error, ErrorKind},
+    info,
+    synchronization::{interface::Mutex, NullLock},
+    warn,
+};
 use core::time::Duration;
 
+pub use arch_time::Instant;
+pub use wall_clock::SystemTime;
+
+pub mod interface {
+    use core::time::Duration;
+
+    /// A free running counter the uptime can be read from
+    pub trait ClockSource {
+        /// A short name, to select it
+        fn name(&self) -> &'static str;
+        /// Time since the counter started, usually at power-on
+        fn now(&self) -> Duration;
+        /// The shortest duration the counter can measure
+        fn resolution(&self) -> Duration;
+    }
+}
+
+/// Most clock sources, the generic timer included
+const MAX_CLOCK_SOURCES: usize = 4;
+
+type ClockSourceRef = &'static (dyn interface::ClockSource + Sync);
+
+struct ClockSources {
+    sources: [Option<ClockSourceRef>; MAX_CLOCK_SOURCES],
+    /// The one `uptime` reads
+    selected: ClockSourceRef,
+}
+
 /// A generic time manager
-pub struct TimeManager;
+///
+/// The uptime comes from the selected clock source, the ARM generic timer unless another one is
+/// chosen. `Instant`, the spins and the wall clock always count with the generic timer: it has
+/// no MMIO and works from the first instruction.
+pub struct TimeManager {
+    clock_sources: NullLock<ClockSources>,
+}
 
 static TIME_MANAGER: TimeManager = TimeManager::new();
 
@@ -19,16 +61,164 @@ pub fn time_manager() -> &'static TimeManager {
 
 impl TimeManager {
     pub const fn new() -> Self {
-        Self
+        Self {
+            clock_sources: NullLock::new(ClockSources {
+                sources: [Some(&arch_time::GENERIC_TIMER), None, None, None],
+                selected: &arch_time::GENERIC_TIMER,
+            }),
+        }
+    }
+
+    /// The shortest duration the selected clock source can measure
+    pub fn resolution(&self) -> Duration {
+        self.clock_source().resolution()
     }
 
     /// The uptime of the device since power-on
     pub fn uptime(&self) -> Duration {
-        arch_time::uptime()
+        self.clock_source().now()
+    }
+
+    /// The selected clock source
+    pub fn clock_source(&self) -> ClockSourceRef {
+        self.clock_sources.lock(|x| x.selected)
+    }
+
+    /// Make a clock source available to `set_clock_source`
+    pub fn register_clock_source(&self, source: ClockSourceRef) -> Result<(), Error> {
+        self.clock_sources.lock(|x| {
+            if x.sources.iter().flatten().any(|y| y.name() == source.name()) {
+                return Err(Error::new(ErrorKind::Busy, "Clock source already registered"));
+            }
+
+            let slot = x
+                .sources
+                .iter_mut()
+                .find(|y| y.is_none())
+                .ok_or(Error::new(ErrorKind::OutOfMemory, "Too many clock sources"))?;
+            *slot = Some(source);
+
+            Ok(())
+        })
+    }
+
+    /// Read the uptime from the clock source called `name`
+    pub fn set_clock_source(&self, name: &str) -> Result<(), Error> {
+        self.clock_sources.lock(|x| {
+            x.selected = x
+                .sources
+                .iter()
+                .flatten()
+                .find(|y| y.name() == name)
+                .copied()
+                .ok_or(Error::new(ErrorKind::NotPresent, "No such clock source"))?;
+
+            Ok(())
+        })
+    }
+
+    /// Call `f` with every clock source and whether it is the selected one
+    pub fn for_each_clock_source(&self, mut f: impl FnMut(ClockSourceRef, bool)) {
+        let (sources, selected) = self.clock_sources.lock(|x| (x.sources, x.selected));
+
+        for source in sources.iter().flatten() {
+            f(*source, source.name() == selected.name());
+        }
     }
 
-    /// Spin for duration (i.e sleep/block the current task)
+    /// Check that every clock source measures the same `interval` as the generic timer, within
+    /// 1% and their resolutions. One that doesn't is not used for the uptime.
+    pub fn cross_check_clock_sources(&self, interval: Duration) {
+        let reference: ClockSourceRef = &arch_time::GENERIC_TIMER;
+
+        self.for_each_clock_source(|source, selected| {
+            if source.name() == reference.name() {
+                return;
+            }
+
+            let (reference_start, start) = (reference.now(), source.now());
+            self.spin_for_duration(interval);
+            let elapsed = source.now().saturating_sub(start);
+            let reference_elapsed = reference.now().saturating_sub(reference_start);
+
+            let tolerance = interval / 100 + source.resolution() + reference.resolution();
+            if elapsed.abs_diff(reference_elapsed) <= tolerance {
+                info!(
+                    "Clock source {}: {} us, {}: {} us",
+                    source.name(),
+                    elapsed.as_micros(),
+                    reference.name(),
+                    reference_elapsed.as_micros()
+                );
+                return;
+            }
+
+            warn!(
+                "Clock source {} disagrees: {} us, {}: {} us",
+                source.name(),
+                elapsed.as_micros(),
+                reference.name(),
+                reference_elapsed.as_micros()
+            );
+            if selected {
+                self.clock_sources.lock(|x| x.selected = reference);
+            }
+        });
+    }
+
+    /// Set the wall-clock time (UTC)
+    pub fn set_wall_clock(&self, now: SystemTime) {
+        wall_clock::set(now);
+    }
+
+    /// The wall-clock time (UTC), if it was set
+    pub fn wall_clock(&self) -> Option<SystemTime> {
+        wall_clock::now()
+    }
+
+    /// Spin for at least `duration` (i.e sleep/block the current task)
     pub fn spin_for_duration(&self, duration: Duration) {
-        arch_time::spin_for_duration(duration);
+        match arch_time::deadline_after(duration) {
+            Some(deadline) => self.spin_until(deadline),
+            None => warn!("spin_for_duration error: {:?} is too long", duration),
+        }
+    }
+
+    /// Spin until the `deadline` instant
+    pub fn spin_until(&self, deadline: Instant) {
+        arch_time::spin_until(deadline);
+    }
+
+    /// Spin until `condition` is true, or fail after `timeout`
+    pub fn wait_until(
+        &self,
+        mut condition: impl FnMut() -> bool,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+
+        while !condition() {
+            if start.elapsed() > timeout {
+                return Err(Error::new(ErrorKind::Timeout, "Condition not met in time"));
+            }
+            cpu::nop();
+        }
+
+        Ok(())
+    }
+}
+
+/// embedded-hal: the delays of the ecosystem's drivers
+impl embedded_hal::delay::DelayNs for &TimeManager {
+    fn delay_ns(&mut self, ns: u32) {
+        self.spin_for_duration(Duration::from_nanos(u64::from(ns)));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.spin_for_duration(Duration::from_micros(u64::from(us)));
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.spin_for_duration(Duration::from_millis(u64::from(ms)));
     }
 }
@@ -0,0 +1,106 @@
+//! Wall-clock time, in UTC.
+//!
+//! The Pi has no RTC: the time comes from the host (the pusher sends it along with the kernel, or
+//! `date` in the shell sets it), and the counter keeps it going from there.
+
+mod calendar;
+
+use super::Instant;
+use calendar::civil_from_days;
+use crate::synchronization::{interface::Mutex, NullLock};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// The time when it was set, and the instant it was
+static WALL_CLOCK: NullLock<Option<(SystemTime, Instant)>> = NullLock::new(None);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A UTC time: the time since 1970-01-01T00:00:00Z, leap seconds aside
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemTime(Duration);
+
+/// A UTC time broken down in calendar fields
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTime {
+    /// The time `since_epoch` after 1970-01-01T00:00:00Z.
+    pub const fn from_unix(since_epoch: Duration) -> Self {
+        Self(since_epoch)
+    }
+
+    /// The time since 1970-01-01T00:00:00Z.
+    pub fn since_unix_epoch(&self) -> Duration {
+        self.0
+    }
+
+    /// Year, month, day and time of the day.
+    pub fn date_time(&self) -> DateTime {
+        let seconds = self.0.as_secs();
+        let (year, month, day) = civil_from_days(seconds / SECS_PER_DAY);
+        let second_of_day = seconds % SECS_PER_DAY;
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (second_of_day / 3600) as u8,
+            minute: (second_of_day / 60 % 60) as u8,
+            second: (second_of_day % 60) as u8,
+            nanosecond: self.0.subsec_nanos(),
+        }
+    }
+}
+
+/// ISO-8601, to the microsecond: `2024-05-01T12:34:56.123456Z`
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = self.date_time();
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            x.year,
+            x.month,
+            x.day,
+            x.hour,
+            x.minute,
+            x.second,
+            x.nanosecond / 1_000
+        )
+    }
+}
+
+/// Set the current time.
+pub fn set(now: SystemTime) {
+    WALL_CLOCK.lock(|x| *x = Some((now, Instant::now())));
+}
+
+/// The current time, if it was set.
+pub fn now() -> Option<SystemTime> {
+    WALL_CLOCK
+        .lock(|x| *x)
+        .map(|(time, instant)| SystemTime(time.0 + instant.elapsed()))
+}
@@ -0,0 +1,74 @@
+//! The proleptic Gregorian calendar, for the dates of the wall clock.
+//!
+//! Plain integer code, without registers or crates, so it is tested on the host (`make test`).
+
+/// The date of a day since 1970-01-01, in the proleptic Gregorian calendar.
+///
+/// Howard Hinnant's `civil_from_days`: the years start in March, so the leap day ends them.
+pub fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    // Days since 0000-03-01
+    let days = days + 719_468;
+    // 400 years eras, of 146097 days
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // March is 0
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    (year, month as u8, day as u8)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Days in a 400 years era, after which the calendar repeats
+    const DAYS_PER_ERA: u64 = 146_097;
+
+    #[test]
+    fn epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(31), (1970, 2, 1));
+        assert_eq!(civil_from_days(364), (1970, 12, 31));
+        assert_eq!(civil_from_days(365), (1971, 1, 1));
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(civil_from_days(789), (1972, 2, 29));
+        assert_eq!(civil_from_days(790), (1972, 3, 1));
+    }
+
+    #[test]
+    fn leap_day_of_a_400th_year() {
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+    }
+
+    #[test]
+    fn no_leap_day_in_a_100th_year() {
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn far_future() {
+        assert_eq!(civil_from_days(2_932_896), (9999, 12, 31));
+        // 1461385103 eras later, still within the days of `u64::MAX` seconds
+        assert_eq!(
+            civil_from_days(2_932_896 + 1_461_385_103 * DAYS_PER_ERA),
+            (584_554_051_199, 12, 31)
+        );
+        // The last day of `u64::MAX` seconds
+        assert_eq!(civil_from_days(u64::MAX / 86_400), (584_554_051_223, 11, 9));
+    }
+}
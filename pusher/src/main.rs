@@ -40,9 +40,10 @@ __________             .__
 
 fn main() -> Result<()> {
     println!("{PUSHER_LOGO}\n[PUSHER] Pusher is waiting...");
-    let (serial_path, baudrate, kernel_path) = parse_input()?;
+    let (serial_path, baudrate, kernel_path, initramfs_path) = parse_input()?;
     println!("Baudrate: {baudrate}");
-    let mut pusher_session = SerialSession::init(serial_path, baudrate, kernel_path)?;
+    let mut pusher_session =
+        SerialSession::init(serial_path, baudrate, kernel_path, initramfs_path)?;
     pusher_session.start_pusher()?;
     Ok(())
 }
@@ -50,17 +51,19 @@ fn main() -> Result<()> {
 
 
 /// Parse command line arguments.
-/// Checks if the serial device exists and is a tty and if the kernel image exists
+/// Checks if the serial device exists and is a tty and if the kernel (and initramfs) image exists
 ///
 /// # Usage:
-/// pusher <tty_device> <baudrate> <kernel_to_push>
+/// pusher <tty_device> <baudrate> <kernel_to_push> [initramfs_to_push]
 ///
 /// # Return
-/// The tty device path and a path to the kernel image
-fn parse_input() -> Result<(String, u32, PathBuf)> {
+/// The tty device path, a path to the kernel image and maybe a path to the initramfs
+fn parse_input() -> Result<(String, u32, PathBuf, Option<PathBuf>)> {
     let supplied_arguments: Vec<String> = env::args().collect();
-    if supplied_arguments.len() != 4 {
-        return Err(anyhow!("Usage: pusher <device> <baudrate> <kernel>"));
+    if supplied_arguments.len() != 4 && supplied_arguments.len() != 5 {
+        return Err(anyhow!(
+            "Usage: pusher <device> <baudrate> <kernel> [initramfs]"
+        ));
     }
     // check if the supplied device exists
     if !Path::new(&supplied_arguments[1]).exists() {
@@ -91,9 +94,16 @@ fn parse_input() -> Result<(String, u32, PathBuf)> {
     if !Path::new(&supplied_arguments[3]).exists() {
         return Err(anyhow!(format!("{} doesn't exist", supplied_arguments[3])));
     }
+    let initramfs_path = supplied_arguments.get(4).map(PathBuf::from);
+    if let Some(path) = &initramfs_path {
+        if !path.exists() {
+            return Err(anyhow!(format!("{} doesn't exist", path.display())));
+        }
+    }
     Ok((
         supplied_arguments[1].clone(),
         supplied_arguments[2].parse::<u32>()?,
         PathBuf::from(&supplied_arguments[3]),
+        initramfs_path,
     ))
 }
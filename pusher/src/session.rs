@@ -5,6 +5,7 @@ use mio_serial::SerialStream;
 use std::fs;
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const SERIAL_TOKEN: Token = Token(0);
 const STDIN_TOKEN: Token = Token(1);
@@ -52,6 +53,8 @@ pub struct SerialSession {
     num_breaks: usize,
     /// The kernel image path
     kimage_path: PathBuf,
+    /// The initramfs path, if any
+    initramfs_path: Option<PathBuf>,
     /// Session state
     session_state: SessionState,
     /// Control character pressed
@@ -60,7 +63,12 @@ pub struct SerialSession {
 
 impl SerialSession {
     /// Create new serial (pusher) session.
-    pub fn init(serial_dev_path: String, baudrate: u32, kernel_path: PathBuf) -> Result<Self> {
+    pub fn init(
+        serial_dev_path: String,
+        baudrate: u32,
+        kernel_path: PathBuf,
+        initramfs_path: Option<PathBuf>,
+    ) -> Result<Self> {
         let args = mio_serial::new(serial_dev_path, baudrate);
         let serial_stream = match SerialStream::open(&args) {
             Ok(device) => device,
@@ -79,6 +87,7 @@ impl SerialSession {
             kernel_poll: Poll::new()?,
             num_breaks: 0,
             kimage_path: kernel_path,
+            initramfs_path,
             session_state: SessionState::WaitingForLoader,
             ctrl_character_pressed: true,
         })
@@ -184,11 +193,9 @@ impl SerialSession {
         self.clear_stdin_buffer();
         Ok(Action::Proceed)
     }
-    /// Send kernel image over serial connection
+    /// Send kernel image over serial connection, then the initramfs (an empty one if there is
+    /// none) and the time
     fn send_kernel(&mut self) -> Result<()> {
-        let mut bytes_sent = 0;
-        let mut kernel_events = Events::with_capacity(1024);
-
         // Patch: I am not sure if polling for writable event is necessary, as it isn't always
         // working.
         self.kernel_poll.registry().register(
@@ -197,17 +204,41 @@ impl SerialSession {
             Interest::READABLE | Interest::WRITABLE,
         )?;
 
-        let kernel_size = fs::metadata(&self.kimage_path)?.len() as u32;
-        write!(self.stdio, "[PUSHER] Kernel size: {kernel_size} bytes\r\n")?;
+        let kernel_image = fs::read(&self.kimage_path)?;
+        self.send_image("Kernel", &kernel_image)?;
+
+        let initramfs_image = match &self.initramfs_path {
+            Some(path) => fs::read(path)?,
+            None => Vec::new(),
+        };
+        self.send_image("Initramfs", &initramfs_image)?;
+        self.send_time()?;
+
+        write!(self.stdio, "[PUSHER] Done! Booting now\r\n")?;
+
+        self.stdio.flush()?;
+        self.clear_serial_buffer();
+        self.kernel_poll = Poll::new()?;
+        self.session_state = SessionState::EchoMode;
+        Ok(())
+    }
+
+    /// Send an image: its size, wait for the loader's "OK", then its content
+    fn send_image(&mut self, name: &str, image: &[u8]) -> Result<()> {
+        let mut bytes_sent = 0;
+        let mut kernel_events = Events::with_capacity(1024);
+
+        let image_size = image.len() as u32;
+        write!(self.stdio, "[PUSHER] {name} size: {image_size} bytes\r\n")?;
         self.stdio.flush()?;
         self.serial_stream.flush()?;
-        assert!(std::u32::MAX > kernel_size);
+        assert!(std::u32::MAX > image_size);
 
         while KERNEL_SIZE_CHUNKS != bytes_sent {
             self.kernel_poll.poll(&mut kernel_events, None)?;
             for kevent in kernel_events.iter() {
                 if kevent.token() == SERIAL_TOKEN && kevent.is_writable() {
-                    let byte = ((kernel_size >> (8 * bytes_sent)) & 0xff) as u8;
+                    let byte = ((image_size >> (8 * bytes_sent)) & 0xff) as u8;
                     self.serial_stream.write_all(&[byte])?;
                     self.serial_stream.flush()?;
                     bytes_sent += 1;
@@ -236,27 +267,32 @@ impl SerialSession {
 
         write!(
             self.stdio,
-            "[PUSHER] Got response: \"{}\", sending image now!\r\n",
-            String::from_utf8_lossy(&self.serial_buffer[..bytes_read])
+            "[PUSHER] Got response: \"{}\", sending {} now!\r\n",
+            String::from_utf8_lossy(&self.serial_buffer[..bytes_read]),
+            name.to_lowercase()
         )?;
         self.stdio.flush()?;
 
-        let kernel_image = fs::read(&self.kimage_path)?;
-        kernel_events.clear();
-        bytes_sent = 0;
-
-        while bytes_sent != kernel_size {
-            self.serial_stream
-                .write_all(&[kernel_image[bytes_sent as usize]])?;
+        for byte in image {
+            self.serial_stream.write_all(&[*byte])?;
             self.serial_stream.flush()?;
-            bytes_sent += 1;
         }
-        write!(self.stdio, "[PUSHER] Done! Booting now\r\n")?;
-
-        self.stdio.flush()?;
         self.clear_serial_buffer();
-        self.kernel_poll = Poll::new()?;
-        self.session_state = SessionState::EchoMode;
+
+        Ok(())
+    }
+
+    /// Send the current time, the Pi has no clock: the seconds since 1970 (8 bytes), then the
+    /// nanoseconds (4 bytes), little endian
+    fn send_time(&mut self) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let mut message = now.as_secs().to_le_bytes().to_vec();
+        message.extend_from_slice(&now.subsec_nanos().to_le_bytes());
+
+        self.serial_stream.write_all(&message)?;
+        self.serial_stream.flush()?;
+        write!(self.stdio, "[PUSHER] Sent the time: {} seconds since 1970\r\n", now.as_secs())?;
+
         Ok(())
     }
 